@@ -1,23 +1,436 @@
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{bail, Context, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use indoc::printdoc;
-use sd_crypto::header::file::FileHeader;
-use std::path::PathBuf;
-use tokio::fs::File;
+use sd_crypto::{
+	crypto::{Decryptor, Encryptor},
+	header::{file::FileHeader, keyslot::Keyslot},
+	primitives::{LATEST_FILE_HEADER, LATEST_KEYSLOT},
+	types::{Algorithm, HashingAlgorithm, Key, Params, Salt},
+	Protected,
+};
+use std::{
+	io::{stdin, BufRead},
+	path::{Path, PathBuf},
+};
+use tokio::{
+	fs::{self, File, OpenOptions},
+	io::{self, AsyncWriteExt},
+};
 
 #[derive(Parser)]
+#[command(about = "A tool for inspecting and repairing Spacedrive-encrypted files")]
 struct Args {
-	#[arg(help = "the file path to get details for")]
-	path: PathBuf,
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Print the details of an encrypted file's header
+	Info {
+		#[arg(help = "the file path to get details for")]
+		path: PathBuf,
+	},
+	/// Encrypt a file
+	Encrypt {
+		#[arg(help = "the file to encrypt")]
+		input: PathBuf,
+		#[arg(help = "the path to write the encrypted file to")]
+		output: PathBuf,
+		#[command(flatten)]
+		secret: Secret,
+		#[arg(long, value_enum, default_value_t = AlgorithmArg::XChaCha20Poly1305)]
+		algorithm: AlgorithmArg,
+		#[arg(long, value_enum, default_value_t = ParamsArg::Standard)]
+		hashing_params: ParamsArg,
+	},
+	/// Decrypt a file
+	Decrypt {
+		#[arg(help = "the file to decrypt")]
+		input: PathBuf,
+		#[arg(help = "the path to write the decrypted file to")]
+		output: PathBuf,
+		#[command(flatten)]
+		secret: Secret,
+	},
+	/// Verify the integrity of an encrypted file, without writing any plaintext
+	Verify {
+		#[arg(help = "the file to verify")]
+		path: PathBuf,
+		#[command(flatten)]
+		secret: Secret,
+	},
+	/// Decrypt and print the metadata attached to an encrypted file
+	Metadata {
+		#[arg(help = "the encrypted file")]
+		path: PathBuf,
+		#[command(flatten)]
+		secret: Secret,
+	},
+	/// Decrypt and extract the preview media attached to an encrypted file
+	PreviewMedia {
+		#[arg(help = "the encrypted file")]
+		path: PathBuf,
+		#[arg(help = "the path to write the preview media to")]
+		output: PathBuf,
+		#[command(flatten)]
+		secret: Secret,
+	},
+	/// Add a keyslot to an encrypted file, so that it may be decrypted with another password/keyfile
+	AddKeyslot {
+		#[arg(help = "the encrypted file")]
+		path: PathBuf,
+		#[command(flatten)]
+		secret: Secret,
+		#[arg(
+			long,
+			help = "the new keyfile (the new password is prompted for otherwise)"
+		)]
+		new_keyfile: Option<PathBuf>,
+		#[arg(long, value_enum, default_value_t = ParamsArg::Standard)]
+		hashing_params: ParamsArg,
+	},
+	/// Remove a keyslot from an encrypted file
+	RemoveKeyslot {
+		#[arg(help = "the encrypted file")]
+		path: PathBuf,
+		#[arg(help = "the keyslot to remove (as shown by `info`)")]
+		keyslot: usize,
+		#[command(flatten)]
+		secret: Secret,
+	},
+}
+
+/// The password or keyfile used to access an encrypted file
+///
+/// Passwords are never taken as arguments, as they'd end up in the shell's history and be visible to other processes.
+/// They're prompted for on the terminal, or read from the first line of stdin with `--password-stdin`.
+#[derive(ClapArgs)]
+#[group(multiple = false)]
+struct Secret {
+	#[arg(long, help = "read the password from the first line of stdin")]
+	password_stdin: bool,
+	#[arg(long, help = "a keyfile to use instead of a password")]
+	keyfile: Option<PathBuf>,
+}
+
+impl Secret {
+	async fn read(&self) -> Result<Protected<Vec<u8>>> {
+		let source = match &self.keyfile {
+			Some(keyfile) => SecretSource::Keyfile(keyfile),
+			None if self.password_stdin => SecretSource::Stdin,
+			None => SecretSource::Prompt("Password: "),
+		};
+
+		read_secret(source).await
+	}
+}
+
+/// Where a password or keyfile is read from
+enum SecretSource<'a> {
+	Keyfile(&'a Path),
+	/// The first line of stdin
+	Stdin,
+	/// A prompt on the terminal, which isn't echoed
+	Prompt(&'static str),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AlgorithmArg {
+	#[value(name = "xchacha20-poly1305")]
+	XChaCha20Poly1305,
+	#[value(name = "aes-256-gcm")]
+	Aes256Gcm,
+}
+
+impl From<AlgorithmArg> for Algorithm {
+	fn from(value: AlgorithmArg) -> Self {
+		match value {
+			AlgorithmArg::XChaCha20Poly1305 => Self::XChaCha20Poly1305,
+			AlgorithmArg::Aes256Gcm => Self::Aes256Gcm,
+		}
+	}
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ParamsArg {
+	Standard,
+	Hardened,
+	Paranoid,
+}
+
+impl From<ParamsArg> for HashingAlgorithm {
+	fn from(value: ParamsArg) -> Self {
+		Self::Argon2id(match value {
+			ParamsArg::Standard => Params::Standard,
+			ParamsArg::Hardened => Params::Hardened,
+			ParamsArg::Paranoid => Params::Paranoid,
+		})
+	}
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
 	let args = Args::parse();
 
-	let mut reader = File::open(args.path).await.context("unable to open file")?;
+	match args.command {
+		Command::Info { path } => {
+			let (header, aad) = read_header(&path).await?;
+			print_crypto_details(&header, &aad);
+		}
+		Command::Encrypt {
+			input,
+			output,
+			secret,
+			algorithm,
+			hashing_params,
+		} => {
+			encrypt(
+				&input,
+				&output,
+				secret.read().await?,
+				algorithm.into(),
+				hashing_params.into(),
+			)
+			.await?;
+		}
+		Command::Decrypt {
+			input,
+			output,
+			secret,
+		} => decrypt(&input, &output, secret.read().await?).await?,
+		Command::Verify { path, secret } => {
+			let mut reader = File::open(&path).await.context("unable to open file")?;
+			let (header, aad) = FileHeader::from_reader(&mut reader).await?;
+			let master_key = header.decrypt_master_key(secret.read().await?).await?;
+
+			if let Some(metadata) = &header.metadata {
+				Decryptor::decrypt_bytes(
+					master_key.clone(),
+					metadata.metadata_nonce,
+					metadata.algorithm,
+					&metadata.metadata,
+					&[],
+				)
+				.await
+				.context("the metadata failed authentication")?;
+			}
+
+			if let Some(preview_media) = &header.preview_media {
+				Decryptor::decrypt_bytes(
+					master_key.clone(),
+					preview_media.media_nonce,
+					preview_media.algorithm,
+					&preview_media.media,
+					&[],
+				)
+				.await
+				.context("the preview media failed authentication")?;
+			}
+
+			Decryptor::new(master_key, header.nonce, header.algorithm)?
+				.decrypt_streams(&mut reader, io::sink(), &aad)
+				.await
+				.context("the file contents failed authentication")?;
+
+			println!("{} is intact", path.display());
+		}
+		Command::Metadata { path, secret } => {
+			let (header, _) = read_header(&path).await?;
+			let metadata: serde_json::Value = header.decrypt_metadata(secret.read().await?).await?;
+
+			println!("{}", serde_json::to_string_pretty(&metadata)?);
+		}
+		Command::PreviewMedia {
+			path,
+			output,
+			secret,
+		} => {
+			let (header, _) = read_header(&path).await?;
+			let media = header.decrypt_preview_media(secret.read().await?).await?;
+
+			File::create(output)
+				.await
+				.context("unable to create the output file")?
+				.write_all(media.expose())
+				.await?;
+		}
+		Command::AddKeyslot {
+			path,
+			secret,
+			new_keyfile,
+			hashing_params,
+		} => {
+			let secret = secret.read().await?;
+			let new_secret = read_secret(match &new_keyfile {
+				Some(keyfile) => SecretSource::Keyfile(keyfile),
+				None => SecretSource::Prompt("New password: "),
+			})
+			.await?;
+
+			add_keyslot(&path, secret, new_secret, hashing_params.into()).await?;
+		}
+		Command::RemoveKeyslot {
+			path,
+			keyslot,
+			secret,
+		} => remove_keyslot(&path, keyslot, secret.read().await?).await?,
+	}
+
+	Ok(())
+}
+
+async fn encrypt(
+	input: &Path,
+	output: &Path,
+	secret: Protected<Vec<u8>>,
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+) -> Result<()> {
+	let master_key = Key::generate();
+
+	let header = FileHeader::new(
+		LATEST_FILE_HEADER,
+		algorithm,
+		vec![new_keyslot(secret, algorithm, hashing_algorithm, master_key.clone()).await?],
+	)?;
+
+	let mut reader = File::open(input).await.context("unable to open file")?;
+	let mut writer = File::create(output)
+		.await
+		.context("unable to create the output file")?;
+
+	header.write(&mut writer).await?;
+
+	Encryptor::new(master_key, header.nonce, header.algorithm)?
+		.encrypt_streams(&mut reader, &mut writer, &header.generate_aad())
+		.await?;
+
+	Ok(())
+}
+
+async fn decrypt(input: &Path, output: &Path, secret: Protected<Vec<u8>>) -> Result<()> {
+	let mut reader = File::open(input).await.context("unable to open file")?;
 	let (header, aad) = FileHeader::from_reader(&mut reader).await?;
-	print_crypto_details(&header, &aad);
+	let master_key = header.decrypt_master_key(secret).await?;
+
+	let mut writer = File::create(output)
+		.await
+		.context("unable to create the output file")?;
+
+	if let Err(e) = Decryptor::new(master_key, header.nonce, header.algorithm)?
+		.decrypt_streams(&mut reader, &mut writer, &aad)
+		.await
+	{
+		// don't leave partial plaintext lying around
+		drop(writer);
+		fs::remove_file(output).await.ok();
+		bail!("unable to decrypt the file, it may be damaged: {e}");
+	}
+
+	Ok(())
+}
+
+async fn read_header(path: &Path) -> Result<(FileHeader, Vec<u8>)> {
+	let mut reader = File::open(path).await.context("unable to open file")?;
+	Ok(FileHeader::from_reader(&mut reader).await?)
+}
+
+async fn read_secret(source: SecretSource<'_>) -> Result<Protected<Vec<u8>>> {
+	let password = match source {
+		SecretSource::Keyfile(keyfile) => {
+			return Ok(Protected::new(
+				fs::read(keyfile)
+					.await
+					.context("unable to read the keyfile")?,
+			))
+		}
+		SecretSource::Stdin => read_password_line(stdin().lock())?,
+		SecretSource::Prompt(prompt) => {
+			rpassword::prompt_password(prompt).context("unable to read the password")?
+		}
+	};
+
+	if password.is_empty() {
+		bail!("the password can't be empty");
+	}
+
+	Ok(Protected::new(password.into_bytes()))
+}
+
+/// Reads the first line, without its line ending.
+fn read_password_line(mut reader: impl BufRead) -> Result<String> {
+	let mut line = String::new();
+	reader
+		.read_line(&mut line)
+		.context("unable to read the password from stdin")?;
+
+	Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Adds a keyslot for `new_secret`, once `secret` has been checked against the existing keyslots.
+async fn add_keyslot(
+	path: &Path,
+	secret: Protected<Vec<u8>>,
+	new_secret: Protected<Vec<u8>>,
+	hashing_algorithm: HashingAlgorithm,
+) -> Result<()> {
+	let (mut header, _) = read_header(path).await?;
+	let master_key = header.decrypt_master_key(secret).await?;
+
+	let keyslot = new_keyslot(new_secret, header.algorithm, hashing_algorithm, master_key).await?;
+
+	header.add_keyslot(keyslot)?;
+	rewrite_header(path, &header).await
+}
+
+/// Removes the keyslot numbered `keyslot` (from 1, as shown by `info`).
+async fn remove_keyslot(path: &Path, keyslot: usize, secret: Protected<Vec<u8>>) -> Result<()> {
+	let (mut header, _) = read_header(path).await?;
+	let index = keyslot
+		.checked_sub(1)
+		.context("keyslots are numbered from 1")?;
+
+	// ensure the user is allowed to modify the file, and that they won't lock themselves out
+	let remaining = header.find_key_index(secret).await?;
+	if remaining == index {
+		bail!("the provided password/keyfile belongs to the keyslot being removed");
+	}
+
+	header.remove_keyslot(index)?;
+	rewrite_header(path, &header).await
+}
+
+async fn new_keyslot(
+	secret: Protected<Vec<u8>>,
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+	master_key: Key,
+) -> Result<Keyslot> {
+	let content_salt = Salt::generate();
+	let hashed_key = hashing_algorithm.hash(secret, content_salt, None)?;
+
+	Ok(Keyslot::new(
+		LATEST_KEYSLOT,
+		algorithm,
+		hashing_algorithm,
+		content_salt,
+		hashed_key,
+		master_key,
+	)
+	.await?)
+}
+
+/// The serialized header is always the same size (as space is reserved for two keyslots), so it can be overwritten in-place.
+async fn rewrite_header(path: &Path, header: &FileHeader) -> Result<()> {
+	let mut writer = OpenOptions::new()
+		.write(true)
+		.open(path)
+		.await
+		.context("unable to open file for writing")?;
+
+	header.write(&mut writer).await?;
+	writer.flush().await?;
 
 	Ok(())
 }
@@ -43,7 +456,7 @@ fn print_crypto_details(header: &FileHeader, aad: &[u8]) {
               Master Key (hex, encrypted): {master}
               Master key nonce (hex): {nonce}
         ",
-			index = i + i,
+			index = i + 1,
 			version = k.version,
 			algorithm = k.algorithm,
 			hashing_algorithm = k.hashing_algorithm,
@@ -83,3 +496,117 @@ fn print_crypto_details(header: &FileHeader, aad: &[u8]) {
 		};
 	});
 }
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use tempfile::tempdir;
+
+	use super::*;
+
+	const HASHING_ALGORITHM: HashingAlgorithm = HashingAlgorithm::Argon2id(Params::Standard);
+
+	fn password(password: &str) -> Protected<Vec<u8>> {
+		Protected::new(password.as_bytes().to_vec())
+	}
+
+	#[test]
+	fn reads_first_line_as_password() {
+		assert_eq!(
+			read_password_line(Cursor::new("hunter2\r\nsomething else\n")).unwrap(),
+			"hunter2"
+		);
+		assert_eq!(
+			read_password_line(Cursor::new("hunter2")).unwrap(),
+			"hunter2"
+		);
+	}
+
+	#[tokio::test]
+	async fn reads_secret_from_keyfile() {
+		let dir = tempdir().unwrap();
+		let keyfile = dir.path().join("keyfile");
+		fs::write(&keyfile, [0, 1, 2, 255]).await.unwrap();
+
+		let secret = read_secret(SecretSource::Keyfile(&keyfile)).await.unwrap();
+		assert_eq!(secret.expose(), &vec![0, 1, 2, 255]);
+
+		assert!(
+			read_secret(SecretSource::Keyfile(&dir.path().join("missing")))
+				.await
+				.is_err()
+		);
+	}
+
+	#[tokio::test]
+	async fn adds_and_removes_keyslots() {
+		let dir = tempdir().unwrap();
+		let plaintext_path = dir.path().join("plaintext");
+		let encrypted_path = dir.path().join("encrypted");
+		let decrypted_path = dir.path().join("decrypted");
+		fs::write(&plaintext_path, b"some very secret data")
+			.await
+			.unwrap();
+
+		encrypt(
+			&plaintext_path,
+			&encrypted_path,
+			password("first"),
+			Algorithm::XChaCha20Poly1305,
+			HASHING_ALGORITHM,
+		)
+		.await
+		.unwrap();
+
+		// a keyslot can only be added with a password which already decrypts the file
+		assert!(add_keyslot(
+			&encrypted_path,
+			password("wrong"),
+			password("second"),
+			HASHING_ALGORITHM
+		)
+		.await
+		.is_err());
+		add_keyslot(
+			&encrypted_path,
+			password("first"),
+			password("second"),
+			HASHING_ALGORITHM,
+		)
+		.await
+		.unwrap();
+		assert_eq!(
+			read_header(&encrypted_path).await.unwrap().0.keyslots.len(),
+			2
+		);
+
+		decrypt(&encrypted_path, &decrypted_path, password("second"))
+			.await
+			.unwrap();
+		assert_eq!(
+			fs::read(&decrypted_path).await.unwrap(),
+			b"some very secret data"
+		);
+
+		// the keyslot of the password that's used can't be removed
+		assert!(remove_keyslot(&encrypted_path, 2, password("second"))
+			.await
+			.is_err());
+		remove_keyslot(&encrypted_path, 1, password("second"))
+			.await
+			.unwrap();
+
+		let (header, _) = read_header(&encrypted_path).await.unwrap();
+		assert_eq!(header.keyslots.len(), 1);
+		assert!(header.decrypt_master_key(password("first")).await.is_err());
+
+		decrypt(&encrypted_path, &decrypted_path, password("second"))
+			.await
+			.unwrap();
+		assert_eq!(
+			fs::read(&decrypted_path).await.unwrap(),
+			b"some very secret data"
+		);
+	}
+}
@@ -1,17 +1,7 @@
 use std::net::{SocketAddr, TcpListener};
 
-use axum::{
-	extract::{Query, State, TypedHeader},
-	headers::authorization::{Authorization, Bearer},
-	http::{Request, StatusCode},
-	middleware::{self, Next},
-	response::Response,
-	routing::get,
-	RequestPartsExt,
-};
+use axum::routing::get;
 use httpz::{Endpoint, HttpEndpoint};
-use rand::{distributions::Alphanumeric, Rng};
-use serde::Deserialize;
 use tauri::{async_runtime::Receiver, plugin::TauriPlugin, Builder, Runtime};
 use tracing::debug;
 
@@ -20,19 +10,10 @@ pub(super) async fn setup<R: Runtime>(
 	mut rx: Receiver<()>,
 	endpoint: Endpoint<impl HttpEndpoint>,
 ) -> Builder<R> {
-	let auth_token: String = rand::thread_rng()
-		.sample_iter(&Alphanumeric)
-		.take(10)
-		.map(char::from)
-		.collect();
-
+	// requests to the endpoint are authorized by the core, using the token the frontend is given by `custom_uri_auth_plugin`
 	let axum_app = axum::Router::new()
 		.route("/", get(|| async { "Spacedrive Server!" }))
 		.nest("/spacedrive", endpoint.axum())
-		.route_layer(middleware::from_fn_with_state(
-			auth_token.clone(),
-			auth_middleware,
-		))
 		.fallback(|| async { "404 Not Found: We're past the event horizon..." });
 
 	// Only allow current device to access it and randomise port
@@ -54,47 +35,13 @@ pub(super) async fn setup<R: Runtime>(
 			.expect("Error with HTTP server!");
 	});
 
-	app.plugin(tauri_plugin(&auth_token, listen_addr))
-}
-
-#[derive(Deserialize)]
-struct QueryToken {
-	token: String,
-}
-
-async fn auth_middleware<B>(
-	Query(query): Query<QueryToken>,
-	State(auth_token): State<String>,
-	request: Request<B>,
-	next: Next<B>,
-) -> Result<Response, StatusCode>
-where
-	B: Send,
-{
-	let req = if query.token != auth_token {
-		let (mut parts, body) = request.into_parts();
-
-		let auth: TypedHeader<Authorization<Bearer>> = parts
-			.extract()
-			.await
-			.map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-		if auth.token() != auth_token {
-			return Err(StatusCode::UNAUTHORIZED);
-		}
-
-		Request::from_parts(parts, body)
-	} else {
-		request
-	};
-
-	Ok(next.run(req).await)
+	app.plugin(tauri_plugin(listen_addr))
 }
 
-fn tauri_plugin<R: Runtime>(auth_token: &str, listen_addr: SocketAddr) -> TauriPlugin<R> {
+fn tauri_plugin<R: Runtime>(listen_addr: SocketAddr) -> TauriPlugin<R> {
 	tauri::plugin::Builder::new("spacedrive-linux")
 		.js_init_script(format!(
-                r#"window.__SD_CUSTOM_SERVER_AUTH_TOKEN__ = "{auth_token}"; window.__SD_CUSTOM_URI_SERVER__ = "http://{listen_addr}";"#
+			r#"window.__SD_CUSTOM_URI_SERVER__ = "http://{listen_addr}";"#
 		))
 		.build()
 }
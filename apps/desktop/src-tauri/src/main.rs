@@ -23,6 +23,16 @@ async fn app_ready(app_handle: tauri::AppHandle) {
 	window.show().unwrap();
 }
 
+/// The frontend appends the session token to the URLs it requests from the custom URI server.
+fn custom_uri_auth_plugin<R: Runtime>(node: &Node) -> TauriPlugin<R> {
+	tauri::plugin::Builder::new("spacedrive-custom-uri")
+		.js_init_script(format!(
+			r#"window.__SD_CUSTOM_SERVER_AUTH_TOKEN__ = "{}";"#,
+			node.custom_uri_auth().session_token()
+		))
+		.build()
+}
+
 pub fn tauri_error_plugin<R: Runtime>(err: NodeError) -> TauriPlugin<R> {
 	tauri::plugin::Builder::new("spacedrive")
 		.js_init_script(format!(r#"window.__SD_ERROR__ = "{err}";"#))
@@ -48,6 +58,7 @@ async fn main() -> tauri::Result<()> {
 		Ok((node, router)) => {
 			// This is a super cringe workaround for: https://github.com/tauri-apps/tauri/issues/3725 & https://bugs.webkit.org/show_bug.cgi?id=146351#c5
 			let endpoint = create_custom_uri_endpoint(node.clone());
+			let app = app.plugin(custom_uri_auth_plugin(&node));
 
 			#[cfg(target_os = "linux")]
 			let app = app_linux::setup(app, rx, endpoint).await;
@@ -49,13 +49,12 @@ if (customUriServerUrl && !customUriServerUrl?.endsWith('/')) {
 }
 
 function getCustomUriURL(path: string): string {
+	const queryParams = customUriAuthToken ? `?token=${encodeURIComponent(customUriAuthToken)}` : '';
+
 	if (customUriServerUrl) {
-		const queryParams = customUriAuthToken
-			? `?token=${encodeURIComponent(customUriAuthToken)}`
-			: '';
 		return `${customUriServerUrl}spacedrive/${path}${queryParams}`;
 	} else {
-		return convertFileSrc(path, 'spacedrive');
+		return convertFileSrc(path, 'spacedrive') + queryParams;
 	}
 }
 
@@ -54,6 +54,7 @@ const EditLocationSettingsScreen = ({
 			sync_preview_media: data.syncPreviewMedia,
 			generate_preview_media: data.generatePreviewMedia,
 			hidden: data.hidden,
+			encrypt_at_rest: null,
 			indexer_rules_ids: []
 		})
 	);
@@ -1,6 +1,15 @@
-use std::{env, net::SocketAddr, path::Path};
+use std::{env, net::SocketAddr, path::Path, sync::Arc};
 
-use axum::routing::get;
+use axum::{
+	extract::State,
+	middleware::{from_fn_with_state, Next},
+	response::{IntoResponse, Response},
+	routing::get,
+};
+use http::{
+	header::{ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION},
+	Method, Request, StatusCode,
+};
 use sd_core::{custom_uri::create_custom_uri_endpoint, Node};
 use tracing::info;
 
@@ -30,6 +39,7 @@ async fn main() {
 
 	let (node, router) = Node::new(data_dir).await.expect("Unable to create node");
 	let signal = utils::axum_shutdown_signal(node.clone());
+	let auth_node = node.clone();
 
 	let app = axum::Router::new()
 		.route("/", get(|| async { "Spacedrive Server!" }))
@@ -38,15 +48,63 @@ async fn main() {
 			"/spacedrive",
 			create_custom_uri_endpoint(node.clone()).axum(),
 		)
-		.nest("/rspc", router.endpoint(move || node.clone()).axum())
+		.nest(
+			"/rspc",
+			router
+				.endpoint(move || node.clone())
+				.axum()
+				.layer(from_fn_with_state(auth_node.clone(), authorize_rspc)),
+		)
 		.fallback(|| async { "404 Not Found: We're past the event horizon..." });
 
 	let mut addr = "[::]:8080".parse::<SocketAddr>().unwrap(); // This listens on IPv6 and IPv4
 	addr.set_port(port);
 	info!("Listening on http://localhost:{}", port);
+	info!(
+		"Open the web interface with '?token={}' to authorize it",
+		auth_node.custom_uri_auth().session_token()
+	);
 	axum::Server::bind(&addr)
 		.serve(app.into_make_service())
 		.with_graceful_shutdown(signal)
 		.await
 		.expect("Error with HTTP server!");
 }
+
+/// The rspc router can read and change everything on the node, so it requires the same session token as the custom URI server,
+/// as `?token=` (which is how the WebSocket is authorized) or an `Authorization: Bearer` header.
+async fn authorize_rspc<B>(
+	State(node): State<Arc<Node>>,
+	req: Request<B>,
+	next: Next<B>,
+) -> Response {
+	let token = req
+		.uri()
+		.query()
+		.and_then(|query| {
+			query
+				.split('&')
+				.find_map(|param| param.strip_prefix("token="))
+		})
+		.or_else(|| {
+			req.headers()
+				.get(AUTHORIZATION)
+				.and_then(|value| value.to_str().ok())
+				.and_then(|value| value.strip_prefix("Bearer "))
+		});
+
+	// preflight requests never carry credentials
+	if req.method() == Method::OPTIONS
+		|| matches!(token, Some(token) if node.custom_uri_auth().is_session_token(token))
+	{
+		return next.run(req).await;
+	}
+
+	// without the CORS header, a browser reports the error as a network error
+	(
+		StatusCode::UNAUTHORIZED,
+		[(ACCESS_CONTROL_ALLOW_ORIGIN, "*")],
+		"Invalid token!",
+	)
+		.into_response()
+}
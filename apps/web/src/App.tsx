@@ -10,8 +10,14 @@ globalThis.isDev = import.meta.env.DEV;
 
 const serverOrigin = import.meta.env.VITE_SDSERVER_ORIGIN || 'localhost:8080';
 
+// The server logs its token when it starts, and it's given to the web interface as `?token=` so it's remembered after that
+const tokenParam = new URLSearchParams(window.location.search).get('token');
+if (tokenParam) localStorage.setItem('sd-server-token', tokenParam);
+const serverToken = tokenParam ?? localStorage.getItem('sd-server-token') ?? undefined;
+const tokenQuery = serverToken ? `?token=${encodeURIComponent(serverToken)}` : '';
+
 const wsClient = createWSClient({
-	url: `ws://${serverOrigin}/rspc/ws`
+	url: `ws://${serverOrigin}/rspc/ws${tokenQuery}`
 });
 
 const client = hooks.createClient({
@@ -28,14 +34,16 @@ const client = hooks.createClient({
 const http = isDev ? 'http' : 'https';
 const spacedriveProtocol = `${http}://${serverOrigin}/spacedrive`;
 
+// The custom URI server needs the same token on every request
+function getCustomUriURL(path: string): string {
+	return `${spacedriveProtocol}/${path}${tokenQuery}`;
+}
+
 const platform: Platform = {
 	platform: 'web',
-	getThumbnailUrlById: (casId) =>
-		`${spacedriveProtocol}/thumbnail/${encodeURIComponent(casId)}.webp`,
+	getThumbnailUrlById: (casId) => getCustomUriURL(`thumbnail/${encodeURIComponent(casId)}.webp`),
 	getFileUrl: (libraryId, locationLocalId, filePathId) =>
-		`${spacedriveProtocol}/file/${encodeURIComponent(libraryId)}/${encodeURIComponent(
-			locationLocalId
-		)}/${encodeURIComponent(filePathId)}`,
+		getCustomUriURL(`file/${encodeURIComponent(libraryId)}/${locationLocalId}/${filePathId}`),
 	openLink: (url) => window.open(url, '_blank')?.focus(),
 	demoMode: import.meta.env.VITE_SD_DEMO_MODE === 'true'
 };
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "encrypt_at_rest" BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "node" ADD COLUMN "acknowledged_timestamp" BIGINT;
@@ -0,0 +1,9 @@
+-- CreateTable
+CREATE TABLE "paired_peer" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "peer_id" TEXT NOT NULL,
+    "date_paired" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "paired_peer_peer_id_key" ON "paired_peer"("peer_id");
@@ -0,0 +1,17 @@
+-- AlterTable
+ALTER TABLE "node" ADD COLUMN "last_synced" DATETIME;
+
+-- CreateTable
+CREATE TABLE "sync_conflict" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "model" TEXT NOT NULL,
+    "record_id" BLOB NOT NULL,
+    "field" TEXT NOT NULL,
+    "winner_value" BLOB NOT NULL,
+    "winner_timestamp" BIGINT NOT NULL,
+    "winner_node" BLOB NOT NULL,
+    "loser_value" BLOB NOT NULL,
+    "loser_timestamp" BIGINT NOT NULL,
+    "loser_node" BLOB NOT NULL,
+    "date_resolved" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "mime_type" TEXT;
+ALTER TABLE "file_path" ADD COLUMN "verified_extension" TEXT;
+ALTER TABLE "file_path" ADD COLUMN "extension_mismatch" BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "blurhash" TEXT;
+ALTER TABLE "object" ADD COLUMN "dominant_color" TEXT;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "paired_peer" ADD COLUMN "node_pub_id" BLOB;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "paired_peer_node_pub_id_key" ON "paired_peer"("node_pub_id");
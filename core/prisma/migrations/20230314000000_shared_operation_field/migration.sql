@@ -0,0 +1,8 @@
+-- AlterTable
+ALTER TABLE "shared_operation" ADD COLUMN "field" TEXT;
+
+-- Backfill the field of existing updates
+UPDATE "shared_operation" SET "field" = json_extract(CAST("data" AS TEXT), '$.field') WHERE "kind" = 'u';
+
+-- CreateIndex
+CREATE INDEX "shared_operation_model_record_id_field_timestamp_idx" ON "shared_operation"("model", "record_id", "field", "timestamp");
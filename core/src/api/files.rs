@@ -2,20 +2,25 @@ use crate::{
 	invalidate_query,
 	library::Library,
 	location::{file_path_helper::MaterializedPath, find_location, LocationError},
-	object::fs::{
-		copy::FileCopierJobInit, cut::FileCutterJobInit, decrypt::FileDecryptorJobInit,
-		delete::FileDeleterJobInit, encrypt::FileEncryptorJobInit, erase::FileEraserJobInit,
+	object::{
+		fs::{
+			copy::FileCopierJobInit, cut::FileCutterJobInit, decrypt::FileDecryptorJobInit,
+			delete::FileDeleterJobInit, encrypt::FileEncryptorJobInit, erase::FileEraserJobInit,
+		},
+		preview::{similar_image_clusters, DEFAULT_SIMILARITY_DISTANCE},
 	},
 	prisma::{location, object},
 };
 
 use rspc::{ErrorCode, Type};
 use serde::Deserialize;
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 use tokio::fs;
 
 use super::{utils::LibraryRequest, RouterBuilder};
 
+const OBJECT_CHUNK_SIZE: usize = 500;
+
 pub(crate) fn mount() -> RouterBuilder {
 	<RouterBuilder>::new()
 		.library_query("get", |t| {
@@ -33,6 +38,69 @@ pub(crate) fn mount() -> RouterBuilder {
 					.await?)
 			})
 		})
+		.library_query("findSimilarImages", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct FindSimilarImagesArgs {
+				/// How many bits of their perceptual hashes two images can differ by to be considered similar.
+				pub max_distance: Option<u32>,
+			}
+
+			t(
+				|_, args: FindSimilarImagesArgs, library: Library| async move {
+					let hashes = library
+						.db
+						.object()
+						.find_many(vec![object::perceptual_hash::not(None)])
+						.select(object::select!({ id perceptual_hash }))
+						.exec()
+						.await?
+						.into_iter()
+						.filter_map(|object| Some((object.id, object.perceptual_hash?)))
+						.collect::<Vec<_>>();
+
+					let max_distance = args.max_distance.unwrap_or(DEFAULT_SIMILARITY_DISTANCE);
+					// Clustering every image in the library is CPU bound, so keep it off of the async runtime
+					let clusters = tokio::task::spawn_blocking(move || {
+						similar_image_clusters(&hashes, max_distance)
+					})
+					.await
+					.map_err(|e| {
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to find similar images".to_string(),
+							e,
+						)
+					})?;
+
+					let ids = clusters.iter().flatten().copied().collect::<Vec<_>>();
+					let mut objects = HashMap::with_capacity(ids.len());
+					// Fetched in chunks to stay under SQLite's limit on bound variables
+					for chunk in ids.chunks(OBJECT_CHUNK_SIZE) {
+						objects.extend(
+							library
+								.db
+								.object()
+								.find_many(vec![object::id::in_vec(chunk.to_vec())])
+								.include(object::include!({ file_paths media_data }))
+								.exec()
+								.await?
+								.into_iter()
+								.map(|object| (object.id, object)),
+						);
+					}
+
+					Ok(clusters
+						.into_iter()
+						.map(|cluster| {
+							cluster
+								.into_iter()
+								.filter_map(|id| objects.remove(&id))
+								.collect::<Vec<_>>()
+						})
+						.collect::<Vec<_>>())
+				},
+			)
+		})
 		.library_mutation("setNote", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct SetNoteArgs {
@@ -115,6 +183,13 @@ pub(crate) fn mount() -> RouterBuilder {
 		})
 		.library_mutation("eraseFiles", |t| {
 			t(|_, args: FileEraserJobInit, library: Library| async move {
+				if args.passes == 0 {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"At least one pass is required to erase files".to_string(),
+					));
+				}
+
 				library.spawn_job(args).await.map_err(Into::into)
 			})
 		})
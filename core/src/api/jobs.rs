@@ -36,6 +36,8 @@ pub(crate) fn mount() -> RouterBuilder {
 			pub struct GenerateThumbsForLocationArgs {
 				pub id: i32,
 				pub path: PathBuf,
+				#[serde(default)]
+				pub video_previews: bool,
 			}
 
 			t(
@@ -49,6 +51,7 @@ pub(crate) fn mount() -> RouterBuilder {
 							location,
 							sub_path: Some(args.path),
 							background: false,
+							video_previews: args.video_previews,
 						})
 						.await
 						.map_err(Into::into)
@@ -1,4 +1,6 @@
-use sd_crypto::keys::keymanager::{StoredKey, StoredKeyType};
+use sd_crypto::keys::keymanager::{KeystoreBackup, RecoverySecret, StoredKeyType};
+use sd_crypto::keys::recovery::RecoveryKit;
+use sd_crypto::keys::shamir::Share;
 use sd_crypto::primitives::SECRET_KEY_IDENTIFIER;
 use sd_crypto::types::{Algorithm, HashingAlgorithm, SecretKeyString};
 use sd_crypto::{Error, Protected};
@@ -36,6 +38,32 @@ pub struct RestoreBackupArgs {
 	path: PathBuf,
 }
 
+#[derive(Type, Deserialize)]
+pub enum RecoveryKitExportSecret {
+	Password(Protected<String>),
+	Shares { threshold: u8, shares: u8 },
+}
+
+#[derive(Type, Deserialize)]
+pub struct RecoveryKitExportArgs {
+	path: PathBuf,
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+	secret: RecoveryKitExportSecret,
+}
+
+#[derive(Type, Deserialize)]
+pub enum RecoveryKitImportSecret {
+	Password(Protected<String>),
+	Shares(Vec<String>),
+}
+
+#[derive(Type, Deserialize)]
+pub struct RecoveryKitImportArgs {
+	path: PathBuf,
+	secret: RecoveryKitImportSecret,
+}
+
 #[derive(Type, Deserialize)]
 pub struct MasterPasswordChangeArgs {
 	password: Protected<String>,
@@ -311,27 +339,63 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
-		.library_mutation("restoreKeystore", |t| {
-			t(|_, args: RestoreBackupArgs, library| async move {
-				let mut input_file = File::open(args.path).await.map_err(Error::Io)?;
-
-				let mut backup = Vec::new();
+		.library_mutation("exportRecoveryKit", |t| {
+			t(|_, args: RecoveryKitExportArgs, library| async move {
+				let (kit, shares) = match args.secret {
+					RecoveryKitExportSecret::Password(password) => (
+						library
+							.key_manager
+							.export_recovery_kit(
+								password.into(),
+								args.algorithm,
+								args.hashing_algorithm,
+							)
+							.await?,
+						vec![],
+					),
+					RecoveryKitExportSecret::Shares { threshold, shares } => {
+						library
+							.key_manager
+							.export_shared_recovery_kit(
+								threshold,
+								shares,
+								args.algorithm,
+								args.hashing_algorithm,
+							)
+							.await?
+					}
+				};
 
-				input_file
-					.read_to_end(&mut backup)
+				let mut output_file = File::create(args.path).await.map_err(Error::Io)?;
+				output_file
+					.write_all(&serde_json::to_vec(&kit).map_err(|_| Error::Serialization)?)
 					.await
 					.map_err(Error::Io)?;
 
-				let stored_keys: Vec<StoredKey> =
-					serde_json::from_slice(&backup).map_err(|_| Error::Serialization)?;
+				// these are only ever shown to the user once, so they can be handed out to trustees
+				Ok(shares.iter().map(ToString::to_string).collect::<Vec<_>>())
+			})
+		})
+		.library_mutation("importRecoveryKit", |t| {
+			t(|_, args: RecoveryKitImportArgs, library| async move {
+				let kit: RecoveryKit = serde_json::from_slice(&read_backup(args.path).await?)
+					.map_err(|_| Error::Serialization)?;
+
+				let secret = match args.secret {
+					RecoveryKitImportSecret::Password(password) => {
+						RecoverySecret::Password(password.into())
+					}
+					RecoveryKitImportSecret::Shares(shares) => RecoverySecret::Shares(
+						shares
+							.iter()
+							.map(|s| s.parse::<Share>())
+							.collect::<Result<_, _>>()?,
+					),
+				};
 
 				let updated_keys = library
 					.key_manager
-					.import_keystore_backup(
-						args.password,
-						SecretKeyString(args.secret_key),
-						&stored_keys,
-					)
+					.import_keystore_backup(KeystoreBackup::RecoveryKit { kit, secret })
 					.await?;
 
 				for key in &updated_keys {
@@ -341,6 +405,39 @@ pub(crate) fn mount() -> RouterBuilder {
 				invalidate_query!(library, "keys.list");
 				invalidate_query!(library, "keys.listMounted");
 
+				Ok(TryInto::<u32>::try_into(updated_keys.len()).unwrap())
+			})
+		})
+		.library_mutation("restoreKeystore", |t| {
+			t(|_, args: RestoreBackupArgs, library| async move {
+				let backup = read_backup(args.path).await?;
+
+				// recovery kits may also be opened with the master password and secret key at the time of export
+				let backup = match serde_json::from_slice::<RecoveryKit>(&backup) {
+					Ok(kit) => KeystoreBackup::RecoveryKit {
+						kit,
+						secret: RecoverySecret::MasterPassword {
+							master_password: args.password,
+							secret_key: SecretKeyString(args.secret_key),
+						},
+					},
+					Err(_) => KeystoreBackup::Keystore {
+						master_password: args.password,
+						secret_key: SecretKeyString(args.secret_key),
+						stored_keys: serde_json::from_slice(&backup)
+							.map_err(|_| Error::Serialization)?,
+					},
+				};
+
+				let updated_keys = library.key_manager.import_keystore_backup(backup).await?;
+
+				for key in &updated_keys {
+					write_storedkey_to_db(&library.db, key).await?;
+				}
+
+				invalidate_query!(library, "keys.list");
+				invalidate_query!(library, "keys.listMounted");
+
 				Ok(TryInto::<u32>::try_into(updated_keys.len()).unwrap()) // We convert from `usize` (bigint type) to `u32` (number type) because rspc doesn't support bigints.
 			})
 		})
@@ -375,3 +472,16 @@ pub(crate) fn mount() -> RouterBuilder {
 			})
 		})
 }
+
+async fn read_backup(path: PathBuf) -> Result<Vec<u8>, Error> {
+	let mut input_file = File::open(path).await.map_err(Error::Io)?;
+
+	let mut backup = Vec::new();
+
+	input_file
+		.read_to_end(&mut backup)
+		.await
+		.map_err(Error::Io)?;
+
+	Ok(backup)
+}
@@ -1,7 +1,8 @@
 use crate::{
 	invalidate_query,
 	library::{Library, LibraryConfig},
-	prisma::statistics,
+	object::preview::cleanup_job::ThumbnailCleanupJobInit,
+	prisma::{paired_peer, statistics},
 	volume::{get_volumes, save_volume},
 };
 
@@ -12,6 +13,7 @@ use sd_crypto::{
 
 use chrono::Utc;
 use rspc::{Error, ErrorCode, Type};
+use sd_p2p::PeerId;
 use serde::Deserialize;
 use tracing::debug;
 use uuid::Uuid;
@@ -168,41 +170,62 @@ pub(crate) fn mount() -> RouterBuilder {
 			})
 		})
 		.mutation("delete", |t| {
-			t(|ctx, id: Uuid| async move { Ok(ctx.library_manager.delete_library(id).await?) })
+			t(|ctx, id: Uuid| async move {
+				ctx.library_manager.delete_library(id).await?;
+
+				// the library's peers shouldn't be redialed unless they're paired with another library
+				ctx.p2p.prune_known_peers().await;
+
+				Ok(())
+			})
 		})
-	// .yolo_merge("peer.guest.", peer_guest_router())
-	// .yolo_merge("peer.host.", peer_host_router())
-}
+		.library_mutation("createPairingCode", |t| {
+			t(|ctx, _: (), library: Library| async move {
+				Ok(ctx.p2p.create_pairing_code(library.id).await)
+			})
+		})
+		.library_query("pairedPeers", |t| {
+			t(|_, _: (), library: Library| async move {
+				Ok(library.db.paired_peer().find_many(vec![]).exec().await?)
+			})
+		})
+		.library_mutation("unpair", |t| {
+			t(|ctx, peer_id: PeerId, library: Library| async move {
+				library
+					.db
+					.paired_peer()
+					.delete_many(vec![paired_peer::peer_id::equals(peer_id.to_string())])
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "library.pairedPeers");
+
+				ctx.p2p.prune_known_peers().await;
 
-// pub(crate) fn peer_guest_router() -> RouterBuilder {
-// 	<RouterBuilder>::new()
-// 		.subscription("request_peering", |t| {
-// 			t(|node, peer_id: String| {
-// 				async_stream::stream! {
-//                     let mut rx = node.begin_guest_peer_request(peer_id).await.unwrap();
-
-// 					while let Some(state) = rx.recv().await {
-// 						yield state;
-// 					}
-// 				}
-// 			})
-// 		})
-// 		.mutation("submit_password", |t| {
-// 			t(|node, password: String| async move {
-// 				let request = node.peer_request.lock().await;
-// 				let Some(peer_request::PeerRequest::Guest(request)) = &*request else {
-//                     return
-//                 };
-
-//                 request.submit_password(password).await;
-// 			})
-// 		})
-// }
-
-// pub(crate) fn peer_host_router() -> RouterBuilder {
-// 	<RouterBuilder>::new()
-// 		.subscription("request", |t| {
-// 			t(|node, _: ()| async_stream::stream! { yield (); })
-// 		})
-// 		.mutation("accept", |t| t(|node, _: ()| Ok(())))
-// }
+				Ok(())
+			})
+		})
+		// Removes the thumbnails which aren't referenced by any library, and evicts the least recently used ones if the cache is over its size limit
+		.library_mutation("thumbnails.cleanup", |t| {
+			t(|_, _: (), library: Library| async move {
+				library
+					.spawn_job(ThumbnailCleanupJobInit {})
+					.await
+					.map_err(Into::into)
+			})
+		})
+		.mutation("thumbnails.setCacheLimit", |t| {
+			t(|ctx, max_mb: Option<u32>| async move {
+				ctx.config
+					.write(|mut config| config.thumbnail_cache_max_mb = max_mb)
+					.await
+					.map(|_| ())
+					.map_err(|e| {
+						Error::new(
+							ErrorCode::InternalServerError,
+							format!("failed to save thumbnail cache limit: {e}"),
+						)
+					})
+			})
+		})
+}
@@ -1,10 +1,12 @@
 use crate::{
+	job::Job,
 	library::Library,
 	location::{
 		delete_location, find_location, indexer::rules::IndexerRuleCreateArgs, light_scan_location,
 		location_with_indexer_rules, relink_location, scan_location, LocationCreateArgs,
 		LocationError, LocationUpdateArgs,
 	},
+	object::{fs::at_rest::AtRestEncryptorJobInit, preview::ThumbnailSize},
 	prisma::{file_path, indexer_rule, indexer_rules_in_location, location, object, tag},
 };
 
@@ -46,6 +48,68 @@ pub struct ExplorerData {
 file_path::include!(file_path_with_object { object });
 object::include!(object_with_file_paths { file_paths });
 
+/// The contents of a directory within a location. This is used for both `locations.getExplorerData` and remote browsing over P2P.
+pub(crate) async fn get_explorer_data(
+	library: &Library,
+	location_id: i32,
+	mut path: String,
+) -> Result<ExplorerData, LocationError> {
+	let Library { db, .. } = library;
+
+	let location = find_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	if !path.ends_with(MAIN_SEPARATOR) {
+		path += MAIN_SEPARATOR_STR;
+	}
+
+	let directory = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location.id),
+			file_path::materialized_path::equals(path.clone()),
+			file_path::is_dir::equals(true),
+		])
+		.exec()
+		.await?
+		.ok_or(LocationError::DirectoryNotFound(path))?;
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location.id),
+			file_path::parent_id::equals(Some(directory.id)),
+		])
+		.include(file_path_with_object::include())
+		.exec()
+		.await?;
+
+	let mut items = Vec::with_capacity(file_paths.len());
+
+	for file_path in file_paths {
+		let has_thumbnail = if let Some(cas_id) = &file_path.cas_id {
+			library
+				.thumbnail_exists(cas_id, ThumbnailSize::Small)
+				.await
+				.map_err(LocationError::IOError)?
+		} else {
+			false
+		};
+
+		items.push(ExplorerItem::Path {
+			has_thumbnail,
+			item: file_path,
+		});
+	}
+
+	Ok(ExplorerData {
+		context: ExplorerContext::Location(location),
+		items,
+	})
+}
+
 pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 	<RouterBuilder>::new()
 		.library_query("list", |t| {
@@ -79,63 +143,8 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 				pub cursor: Option<String>,
 			}
 
-			t(|_, mut args: LocationExplorerArgs, library| async move {
-				let Library { db, .. } = &library;
-
-				let location = find_location(&library, args.location_id)
-					.exec()
-					.await?
-					.ok_or(LocationError::IdNotFound(args.location_id))?;
-
-				if !args.path.ends_with(MAIN_SEPARATOR) {
-					args.path += MAIN_SEPARATOR_STR;
-				}
-
-				let directory = db
-					.file_path()
-					.find_first(vec![
-						file_path::location_id::equals(location.id),
-						file_path::materialized_path::equals(args.path),
-						file_path::is_dir::equals(true),
-					])
-					.exec()
-					.await?
-					.ok_or_else(|| {
-						rspc::Error::new(ErrorCode::NotFound, "Directory not found".into())
-					})?;
-
-				let file_paths = db
-					.file_path()
-					.find_many(vec![
-						file_path::location_id::equals(location.id),
-						file_path::parent_id::equals(Some(directory.id)),
-					])
-					.include(file_path_with_object::include())
-					.exec()
-					.await?;
-
-				let mut items = Vec::with_capacity(file_paths.len());
-
-				for file_path in file_paths {
-					let has_thumbnail = if let Some(cas_id) = &file_path.cas_id {
-						library
-							.thumbnail_exists(cas_id)
-							.await
-							.map_err(LocationError::IOError)?
-					} else {
-						false
-					};
-
-					items.push(ExplorerItem::Path {
-						has_thumbnail,
-						item: file_path,
-					});
-				}
-
-				Ok(ExplorerData {
-					context: ExplorerContext::Location(location),
-					items,
-				})
+			t(|_, args: LocationExplorerArgs, library| async move {
+				Ok(get_explorer_data(&library, args.location_id, args.path).await?)
 			})
 		})
 		.library_mutation("create", |t| {
@@ -147,7 +156,30 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 		})
 		.library_mutation("update", |t| {
 			t(|_, args: LocationUpdateArgs, library| async move {
-				args.update(&library).await.map_err(Into::into)
+				let location_id = args.id;
+
+				let was_encrypted_at_rest = find_location(&library, location_id)
+					.select(location::select!({ encrypt_at_rest }))
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?
+					.encrypt_at_rest;
+				let encrypt_at_rest = args.encrypt_at_rest == Some(true);
+
+				args.update(&library).await?;
+
+				// files that are already within the location are encrypted once it's enabled, new ones are encrypted as they're added
+				if encrypt_at_rest && !was_encrypted_at_rest {
+					library
+						.spawn_job(Job::new(AtRestEncryptorJobInit {
+							location_id,
+							sub_path: None,
+							file_path_id: None,
+						}))
+						.await?;
+				}
+
+				Ok(())
 			})
 		})
 		.library_mutation("delete", |t| {
@@ -21,7 +21,7 @@ mod files;
 mod jobs;
 mod keys;
 mod libraries;
-mod locations;
+pub(crate) mod locations;
 mod nodes;
 mod p2p;
 mod sync;
@@ -1,24 +1,48 @@
+use crate::custom_uri::DEFAULT_SIGNED_URL_EXPIRY_SECS;
+
 use super::RouterBuilder;
+use chrono::{Duration, Utc};
 use rspc::Type;
 use serde::{Deserialize, Serialize};
 
 pub(crate) fn mount() -> RouterBuilder {
-	<RouterBuilder>::new().mutation("tokenizeSensitiveKey", |t| {
-		#[derive(Deserialize, Type)]
-		pub struct TokenizeKeyArgs {
-			pub secret_key: String,
-		}
-		#[derive(Serialize, Type)]
-		pub struct TokenizeResponse {
-			pub token: String,
-		}
+	<RouterBuilder>::new()
+		.query("signCustomUri", |t| {
+			#[derive(Deserialize, Type)]
+			pub struct SignCustomUriArgs {
+				/// The path on the custom URI server, such as `/file/<library_id>/<location_id>/<file_path_id>`.
+				pub path: String,
+				pub expires_in_secs: Option<u32>,
+			}
+
+			// A signed URL can be shared without giving away the session token, which authorizes every request
+			t(|ctx, args: SignCustomUriArgs| async move {
+				let expires_in = args
+					.expires_in_secs
+					.unwrap_or(DEFAULT_SIGNED_URL_EXPIRY_SECS);
+
+				Ok(ctx.custom_uri_auth.sign(
+					&args.path,
+					Utc::now() + Duration::seconds(expires_in.into()),
+				))
+			})
+		})
+		.mutation("tokenizeSensitiveKey", |t| {
+			#[derive(Deserialize, Type)]
+			pub struct TokenizeKeyArgs {
+				pub secret_key: String,
+			}
+			#[derive(Serialize, Type)]
+			pub struct TokenizeResponse {
+				pub token: String,
+			}
 
-		t(|ctx, args: TokenizeKeyArgs| async move {
-			let token = ctx.secure_temp_keystore.tokenize(args.secret_key);
+			t(|ctx, args: TokenizeKeyArgs| async move {
+				let token = ctx.secure_temp_keystore.tokenize(args.secret_key);
 
-			Ok(TokenizeResponse {
-				token: token.to_string(),
+				Ok(TokenizeResponse {
+					token: token.to_string(),
+				})
 			})
 		})
-	})
 }
@@ -1,7 +1,8 @@
-use rspc::Type;
-use sd_p2p::PeerId;
+use rspc::{ErrorCode, Type};
+use sd_p2p::{BandwidthLimits, PeerId};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
+use uuid::Uuid;
 
 use crate::p2p::P2PEvent;
 
@@ -33,17 +34,110 @@ pub(crate) fn mount() -> RouterBuilder {
 				}
 			})
 		})
+		.query("getRemoteExplorerData", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct RemoteExplorerArgs {
+				peer_id: PeerId,
+				library_id: Uuid,
+				location_id: i32,
+				path: String,
+			}
+
+			t(|ctx, args: RemoteExplorerArgs| async move {
+				Ok(ctx
+					.p2p
+					.request_explorer_data(
+						args.peer_id,
+						args.library_id,
+						args.location_id,
+						args.path,
+					)
+					.await?)
+			})
+		})
 		.mutation("spacedrop", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct SpacedropArgs {
 				peer_id: PeerId,
-				file_path: String,
+				file_paths: Vec<String>,
 			}
 
 			t(|ctx, args: SpacedropArgs| async move {
 				ctx.p2p
-					.big_bad_spacedrop(args.peer_id, PathBuf::from(args.file_path))
-					.await;
+					.spacedrop(
+						args.peer_id,
+						args.file_paths.into_iter().map(PathBuf::from).collect(),
+					)
+					.await
+					.map_err(|e| {
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							format!("failed to start Spacedrop: {e}"),
+						)
+					})
+			})
+		})
+		.mutation("connect", |t| {
+			t(|ctx, addr: String| async move {
+				let addr = addr.parse::<SocketAddr>().map_err(|e| {
+					rspc::Error::new(ErrorCode::BadRequest, format!("invalid address: {e}"))
+				})?;
+
+				ctx.p2p.connect(addr).await.map_err(|e| {
+					rspc::Error::new(
+						ErrorCode::InternalServerError,
+						format!("failed to save peer address: {e}"),
+					)
+				})
+			})
+		})
+		.mutation("setBandwidthLimits", |t| {
+			t(|ctx, limits: BandwidthLimits| async move {
+				ctx.p2p.set_bandwidth_limits(limits).await.map_err(|e| {
+					rspc::Error::new(
+						ErrorCode::InternalServerError,
+						format!("failed to save bandwidth limits: {e}"),
+					)
+				})
+			})
+		})
+		.mutation("pair", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct PairArgs {
+				peer_id: PeerId,
+				library_id: Uuid,
+				code: String,
+			}
+
+			t(|ctx, args: PairArgs| async move {
+				ctx.p2p
+					.pair(args.peer_id, args.library_id, &args.code)
+					.await
+					.map_err(|e| {
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							format!("failed to pair with peer: {e}"),
+						)
+					})
 			})
 		})
+		.mutation("acceptSpacedrop", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AcceptSpacedropArgs {
+				id: Uuid,
+				destination: String,
+			}
+
+			t(|ctx, args: AcceptSpacedropArgs| async move {
+				ctx.p2p
+					.accept_spacedrop(args.id, PathBuf::from(args.destination))
+					.await
+			})
+		})
+		.mutation("rejectSpacedrop", |t| {
+			t(|ctx, id: Uuid| async move { ctx.p2p.reject_spacedrop(id).await })
+		})
+		.mutation("cancelSpacedrop", |t| {
+			t(|ctx, id: Uuid| async move { ctx.p2p.cancel_spacedrop(id).await })
+		})
 }
@@ -24,4 +24,13 @@ pub fn mount() -> RouterBuilder {
 		.library_query("messages", |t| {
 			t(|_, _: (), library| async move { Ok(library.sync.get_ops().await?) })
 		})
+		.library_query("conflicts", |t| {
+			t(|_, _: (), library| async move { Ok(library.sync.get_conflicts().await?) })
+		})
+		.library_query("status", |t| {
+			t(|_, _: (), library| async move { Ok(library.sync.status().await?) })
+		})
+		.library_mutation("compact", |t| {
+			t(|_, _: (), library| async move { Ok(library.sync.compact().await?) })
+		})
 }
@@ -9,6 +9,7 @@ use crate::{
 	api::locations::{object_with_file_paths, ExplorerContext, ExplorerData, ExplorerItem},
 	invalidate_query,
 	library::Library,
+	object::preview::ThumbnailSize,
 	prisma::{object, tag, tag_on_object},
 	sync,
 };
@@ -59,13 +60,16 @@ pub(crate) fn mount() -> RouterBuilder {
 						.find_map(|c| c);
 
 					let has_thumbnail = if let Some(cas_id) = cas_id {
-						library.thumbnail_exists(cas_id).await.map_err(|e| {
-							rspc::Error::with_cause(
-								ErrorCode::InternalServerError,
-								"Failed to check that thumbnail exists".to_string(),
-								e,
-							)
-						})?
+						library
+							.thumbnail_exists(cas_id, ThumbnailSize::Small)
+							.await
+							.map_err(|e| {
+								rspc::Error::with_cause(
+									ErrorCode::InternalServerError,
+									"Failed to check that thumbnail exists".to_string(),
+									e,
+								)
+							})?
 					} else {
 						false
 					};
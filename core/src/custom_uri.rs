@@ -1,4 +1,17 @@
-use crate::{location::file_path_helper::MaterializedPath, prisma::file_path, Node};
+use crate::{
+	library::Library,
+	location::file_path_helper::MaterializedPath,
+	object::{
+		fs::at_rest::{AtRestReader, AT_REST_EXTENSION},
+		preview::{
+			generate_thumbnail_on_demand, mark_thumbnail_used, thumbstrip_path, video_preview_path,
+			ThumbnailSize, THUMBNAIL_CACHE_DIR_NAME,
+		},
+	},
+	p2p::RemoteError,
+	prisma::file_path,
+	Node,
+};
 
 use std::{
 	io,
@@ -11,14 +24,17 @@ use std::{
 #[cfg(not(target_os = "linux"))]
 use std::cmp::min;
 
+use chrono::{DateTime, Utc};
 use http_range::HttpRange;
 use httpz::{
-	http::{response::Builder, Method, Response, StatusCode},
+	http::{response::Builder, HeaderValue, Method, Response, StatusCode, Uri},
 	Endpoint, GenericEndpoint, HttpEndpoint, Request,
 };
 use mini_moka::sync::Cache;
 use once_cell::sync::Lazy;
 use prisma_client_rust::QueryError;
+use sd_crypto::types::Key;
+use sd_p2p::PeerId;
 use thiserror::Error;
 use tokio::{
 	fs::File,
@@ -30,13 +46,149 @@ use uuid::Uuid;
 // This LRU cache allows us to avoid doing a DB lookup on every request.
 // The main advantage of this LRU Cache is for video files. Video files are fetch in multiple chunks and the cache prevents a DB lookup on every chunk reducing the request time from 15-25ms to 1-10ms.
 type MetadataCacheKey = (Uuid, i32, i32);
-type NameAndExtension = (PathBuf, String);
-static FILE_METADATA_CACHE: Lazy<Cache<MetadataCacheKey, NameAndExtension>> =
+type NameExtensionMimeTypeAndCasId = (PathBuf, String, Option<String>, Option<String>);
+static FILE_METADATA_CACHE: Lazy<Cache<MetadataCacheKey, NameExtensionMimeTypeAndCasId>> =
 	Lazy::new(|| Cache::new(100));
 
+// The sizes of files on peers, so a range can be requested without asking for the size of the file first.
+type RemoteFileCacheKey = (PeerId, Uuid, i32, i32);
+static REMOTE_FILE_SIZE_CACHE: Lazy<Cache<RemoteFileCacheKey, u64>> = Lazy::new(|| Cache::new(100));
+
 // TODO: We should listen to events when deleting or moving a location and evict the cache accordingly.
 // TODO: Probs use this cache in rspc queries too!
 
+/// Thumbnails, film strips and video previews are named by the `cas_id` of their file, so what's served for a URL never changes.
+const IMMUTABLE_CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
+/// How long a signed URL can be requested for when no expiry is given, in seconds.
+pub const DEFAULT_SIGNED_URL_EXPIRY_SECS: u32 = 60 * 60;
+
+/// Authorizes requests to the custom URI server, which would otherwise serve every file in every library to anyone who can reach it.
+/// A server which exposes the rspc router over HTTP also authorizes requests to it with the session token.
+///
+/// Requests carry either the node's session token (as `?token=` or an `Authorization: Bearer` header), or a signature of their path
+/// which hasn't expired (`?expires=<unix timestamp>&signature=`) so that a single file can be shared. The key is generated when the
+/// node starts, so neither outlives it.
+pub struct CustomUriAuth {
+	key: Key,
+	session_token: blake3::Hash,
+}
+
+impl CustomUriAuth {
+	pub(crate) fn new() -> Self {
+		let key = Key::generate();
+		let session_token = blake3::keyed_hash(key.expose(), b"session");
+
+		Self { key, session_token }
+	}
+
+	pub fn session_token(&self) -> String {
+		self.session_token.to_hex().to_string()
+	}
+
+	/// Signs the path of a URL on the custom URI server (such as `/file/<library_id>/<location_id>/<file_path_id>`), returning it with
+	/// the query that authorizes requests for it until `expires`.
+	pub fn sign(&self, path: &str, expires: DateTime<Utc>) -> String {
+		let expires = expires.timestamp();
+		format!(
+			"{path}?expires={expires}&signature={}",
+			self.signature(path, expires).to_hex()
+		)
+	}
+
+	fn signature(&self, path: &str, expires: i64) -> blake3::Hash {
+		blake3::keyed_hash(self.key.expose(), format!("{expires}:{path}").as_bytes())
+	}
+
+	/// Whether `token` is the session token. The hashes are compared rather than the strings, as `blake3::Hash` compares in constant time.
+	pub fn is_session_token(&self, token: &str) -> bool {
+		matches!(blake3::Hash::from_hex(token), Ok(token) if token == self.session_token)
+	}
+
+	fn authorize(&self, req: &Request) -> Result<(), HandleCustomUriError> {
+		self.authorize_uri(req.uri(), bearer_token(req))
+	}
+
+	fn authorize_uri(&self, uri: &Uri, bearer: Option<&str>) -> Result<(), HandleCustomUriError> {
+		let token = uri_query_param(uri, "token").or(bearer);
+
+		if let Some(token) = token {
+			return if self.is_session_token(token) {
+				Ok(())
+			} else {
+				Err(HandleCustomUriError::Unauthorized("Invalid token!"))
+			};
+		}
+
+		let expires = uri_query_param(uri, "expires")
+			.and_then(|expires| expires.parse::<i64>().ok())
+			.ok_or(HandleCustomUriError::Unauthorized(
+				"Missing token or signature!",
+			))?;
+		if expires < Utc::now().timestamp() {
+			return Err(HandleCustomUriError::Unauthorized("The URL has expired!"));
+		}
+
+		match uri_query_param(uri, "signature").map(blake3::Hash::from_hex) {
+			Some(Ok(signature)) if signature == self.signature(uri.path(), expires) => Ok(()),
+			_ => Err(HandleCustomUriError::Unauthorized("Invalid signature!")),
+		}
+	}
+
+	/// The query which authorizes a request for `path` the same way the already authorized request for `uri` was, for URLs within a
+	/// response. A session token is passed along as it is, while a signed URL's expiry is kept and `path` is signed with it.
+	#[cfg(feature = "ffmpeg")]
+	fn query_for(&self, uri: &Uri, bearer: Option<&str>, path: &str) -> Option<String> {
+		if let Some(token) = uri_query_param(uri, "token").or(bearer) {
+			return Some(format!("token={token}"));
+		}
+
+		let expires = uri_query_param(uri, "expires")?.parse::<i64>().ok()?;
+		Some(format!(
+			"expires={expires}&signature={}",
+			self.signature(path, expires).to_hex()
+		))
+	}
+}
+
+fn bearer_token(req: &Request) -> Option<&str> {
+	req.headers()
+		.get("authorization")
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// A player resolves the segments of a playlist relative to it, which drops the playlist's query, so each segment is given the query
+/// that authorizes it.
+#[cfg(feature = "ffmpeg")]
+fn authorize_playlist(
+	auth: &CustomUriAuth,
+	uri: &Uri,
+	bearer: Option<&str>,
+	playlist: &str,
+) -> String {
+	let dir = uri.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+
+	playlist
+		.lines()
+		.map(|line| {
+			if line.is_empty() || line.starts_with('#') {
+				return line.to_string();
+			}
+
+			match auth.query_for(uri, bearer, &format!("{dir}/{line}")) {
+				Some(query) => format!("{line}?{query}"),
+				None => line.to_string(),
+			}
+		})
+		.fold(String::new(), |playlist, line| playlist + &line + "\n")
+}
+
+/// This evicts a file path from the metadata cache, for when it's been changed on disk (e.g. by being encrypted at rest).
+pub(crate) fn evict_file_metadata(library_id: Uuid, location_id: i32, file_path_id: i32) {
+	FILE_METADATA_CACHE.invalidate(&(library_id, location_id, file_path_id));
+}
+
 async fn handler(node: Arc<Node>, req: Request) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
 	let path = req
 		.uri()
@@ -46,13 +198,50 @@ async fn handler(node: Arc<Node>, req: Request) -> Result<Response<Vec<u8>>, Han
 		.split('/')
 		.collect::<Vec<_>>();
 
+	// preflight requests never carry credentials, and are answered without reading anything
+	if req.method() != Method::OPTIONS {
+		node.custom_uri_auth.authorize(&req)?;
+	}
+
 	match path.first() {
 		Some(&"thumbnail") => handle_thumbnail(&node, &path, &req).await,
+		Some(&"thumbstrip" | &"video-preview") => handle_video_preview(&node, &path, &req).await,
 		Some(&"file") => handle_file(&node, &path, &req).await,
+		#[cfg(feature = "ffmpeg")]
+		Some(&"transcode") => handle_transcode(&node, &path, &req).await,
+		Some(&"remote") => handle_remote(&node, &path, &req).await,
 		_ => Err(HandleCustomUriError::BadRequest("Invalid operation!")),
 	}
 }
 
+fn query_param<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+	uri_query_param(req.uri(), name)
+}
+
+fn uri_query_param<'a>(uri: &'a Uri, name: &str) -> Option<&'a str> {
+	uri.query().and_then(|query| {
+		query
+			.split('&')
+			.find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+	})
+}
+
+/// The `cas_id` that thumbnails and previews are requested by, which clients may send with a `.webp` extension.
+///
+/// It's used to build paths and look up files, so anything other than an alphanumeric `cas_id` is rejected.
+fn cas_id_param<'a>(path: &[&'a str]) -> Result<&'a str, HandleCustomUriError> {
+	let cas_id = path.get(1).ok_or(HandleCustomUriError::BadRequest(
+		"Invalid number of parameters!",
+	))?;
+	let cas_id = cas_id.strip_suffix(".webp").unwrap_or(cas_id);
+
+	if cas_id.is_empty() || !cas_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+		return Err(HandleCustomUriError::BadRequest("Invalid cas_id!"));
+	}
+
+	Ok(cas_id)
+}
+
 async fn read_file(mut file: File, length: u64, start: Option<u64>) -> io::Result<Vec<u8>> {
 	let mut buf = Vec::with_capacity(length as usize);
 	if let Some(start) = start {
@@ -65,6 +254,184 @@ async fn read_file(mut file: File, length: u64, start: Option<u64>) -> io::Resul
 	Ok(buf)
 }
 
+/// The contents of a file that's being served. For files that are encrypted at rest, only the blocks covering the range that's read are decrypted.
+pub(crate) enum FileSource {
+	File(File),
+	AtRest(AtRestReader),
+}
+
+impl FileSource {
+	pub(crate) async fn len(&self) -> io::Result<u64> {
+		match self {
+			Self::File(file) => Ok(file.metadata().await?.len()),
+			Self::AtRest(reader) => Ok(reader.size()),
+		}
+	}
+
+	pub(crate) async fn read(
+		self,
+		length: u64,
+		start: Option<u64>,
+	) -> Result<Vec<u8>, HandleCustomUriError> {
+		match self {
+			Self::File(file) => Ok(read_file(file, length, start).await?),
+			Self::AtRest(mut reader) => Ok(reader.read(start.unwrap_or(0), length).await?),
+		}
+	}
+}
+
+/// The version of a file path that's being served, which clients revalidate their cached copies against.
+pub(crate) struct FileVersion {
+	cas_id: Option<String>,
+	modified: Option<DateTime<Utc>>,
+}
+
+impl FileVersion {
+	/// The `cas_id` is only regenerated once the file has been identified again, but the modified date changes as soon as the
+	/// contents do, so together they're a strong validator.
+	fn etag(&self) -> Option<String> {
+		self.cas_id.as_ref().map(|cas_id| match self.modified {
+			Some(modified) => format!("\"{cas_id}-{:x}\"", modified.timestamp()),
+			None => format!("\"{cas_id}\""),
+		})
+	}
+}
+
+/// Opens a file path for serving, returning the extension it should be served as, the MIME type detected from its contents when it was identified,
+/// and its version.
+///
+/// This is also used to serve files to peers which are browsing the library remotely.
+pub(crate) async fn open_file_path(
+	library: &Library,
+	location_id: i32,
+	file_path_id: i32,
+) -> Result<(FileSource, String, Option<String>, FileVersion), HandleCustomUriError> {
+	let lru_cache_key = (library.id, location_id, file_path_id);
+
+	let (file_path_materialized_path, mut extension, mut mime_type, cas_id) =
+		if let Some(entry) = FILE_METADATA_CACHE.get(&lru_cache_key) {
+			entry
+		} else {
+			let file_path = library
+				.db
+				.file_path()
+				.find_unique(file_path::location_id_id(location_id, file_path_id))
+				.include(file_path::include!({ location }))
+				.exec()
+				.await?
+				.ok_or_else(|| HandleCustomUriError::NotFound("object"))?;
+
+			let lru_entry = (
+				Path::new(&file_path.location.path).join(&MaterializedPath::from((
+					location_id,
+					&file_path.materialized_path,
+				))),
+				file_path.extension,
+				file_path.mime_type,
+				file_path.cas_id,
+			);
+			FILE_METADATA_CACHE.insert(lru_cache_key, lru_entry.clone());
+
+			lru_entry
+		};
+
+	let file = File::open(&file_path_materialized_path)
+		.await
+		.map_err(|err| {
+			if err.kind() == io::ErrorKind::NotFound {
+				HandleCustomUriError::NotFound("file")
+			} else {
+				err.into()
+			}
+		})?;
+
+	let version = FileVersion {
+		cas_id,
+		modified: file
+			.metadata()
+			.await?
+			.modified()
+			.ok()
+			.map(DateTime::<Utc>::from),
+	};
+
+	// files that are encrypted at rest are named `name.ext.bytes`, so we serve them as `ext`
+	let file = if extension == AT_REST_EXTENSION {
+		extension = file_path_materialized_path
+			.file_stem()
+			.map(Path::new)
+			.and_then(Path::extension)
+			.and_then(|ext| ext.to_str())
+			.unwrap_or_default()
+			.to_lowercase();
+		// the contents were encrypted when they were identified, so the type comes from the extension
+		mime_type = None;
+
+		// this only reads the header, the contents are decrypted as they're read
+		FileSource::AtRest(AtRestReader::new(&library.key_manager, file).await?)
+	} else {
+		FileSource::File(file)
+	};
+
+	Ok((file, extension, mime_type, version))
+}
+
+fn parse_range(range: &HeaderValue, size: u64) -> Result<Option<HttpRange>, HandleCustomUriError> {
+	range
+		.to_str()
+		.ok()
+		.and_then(|range| HttpRange::parse(range, size).ok())
+		.ok_or_else(|| HandleCustomUriError::RangeNotSatisfiable("Error decoding range header!"))
+		.and_then(|range| {
+			// Let's support only 1 range for now
+			if range.len() > 1 {
+				Err(HandleCustomUriError::RangeNotSatisfiable(
+					"Multiple ranges are not supported!",
+				))
+			} else {
+				Ok(range.first().cloned())
+			}
+		})
+}
+
+/// Whether the copy the client has cached is still current, so it can be sent `304 Not Modified` instead of the body.
+///
+/// `If-None-Match` takes precedence over `If-Modified-Since` when both are sent, as the spec requires.
+/// https://httpwg.org/specs/rfc9110.html#field.if-none-match
+fn is_not_modified(req: &Request, etag: Option<&str>, modified: Option<DateTime<Utc>>) -> bool {
+	let method = req.method();
+	if method != Method::GET && method != Method::HEAD {
+		return false;
+	}
+
+	let header = |name: &str| {
+		req.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+	};
+
+	if let Some(if_none_match) = header("if-none-match") {
+		return etag.map_or(false, |etag| {
+			if_none_match
+				.split(',')
+				.map(str::trim)
+				.any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+		});
+	}
+
+	match (
+		header("if-modified-since").and_then(|date| DateTime::parse_from_rfc2822(date).ok()),
+		modified,
+	) {
+		(Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+		_ => false,
+	}
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+	date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
 fn cors(
 	method: &Method,
 	builder: &mut Builder,
@@ -95,24 +462,118 @@ async fn handle_thumbnail(
 		return Ok(response?);
 	}
 
-	let file_cas_id = path
-		.get(1)
-		.ok_or_else(|| HandleCustomUriError::BadRequest("Invalid number of parameters!"))?;
+	let file_cas_id = cas_id_param(path)?;
+
+	// The size is requested as the length of the longest edge (`?size=512`), and the small size is served otherwise
+	let size = match query_param(req, "size") {
+		Some(edge) => ThumbnailSize::for_edge(
+			edge.parse()
+				.map_err(|_| HandleCustomUriError::BadRequest("Invalid thumbnail size!"))?,
+		),
+		None => ThumbnailSize::Small,
+	};
+
+	let thumbnail_dir = node.config.data_directory().join(THUMBNAIL_CACHE_DIR_NAME);
+
+	// Larger sizes are only generated once they're requested, so we fall back to the nearest size we have until then
+	let mut sizes = ThumbnailSize::ALL;
+	sizes.sort_by_key(|other| other.max_edge().abs_diff(size.max_edge()));
+
+	let mut found = None;
+	for other in sizes {
+		let path = other.path(&thumbnail_dir, file_cas_id);
+		match File::open(&path).await {
+			Ok(file) => {
+				mark_thumbnail_used(&path);
+				found = Some((file, other));
+				break;
+			}
+			Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+			Err(err) => return Err(err.into()),
+		}
+	}
+
+	if !matches!(found, Some((_, found_size)) if found_size == size) && size != ThumbnailSize::Small
+	{
+		generate_thumbnail_on_demand(
+			node.library_manager.clone(),
+			thumbnail_dir,
+			file_cas_id.to_string(),
+			size,
+		);
+	}
+
+	let (file, found_size) = found.ok_or(HandleCustomUriError::NotFound("file"))?;
 
-	let filename = node
-		.config
-		.data_directory()
-		.join("thumbnails")
-		.join(file_cas_id)
-		.with_extension("webp");
+	// a smaller size that's served until the requested one is generated has to be revalidated, so it's replaced once it is
+	let etag = format!("\"{file_cas_id}-{}\"", found_size.max_edge());
+	builder = builder.header("ETag", &etag).header(
+		"Cache-Control",
+		if found_size == size {
+			IMMUTABLE_CACHE_CONTROL
+		} else {
+			"no-cache"
+		},
+	);
+
+	if is_not_modified(req, Some(&etag), None) {
+		return Ok(builder.status(StatusCode::NOT_MODIFIED).body(vec![])?);
+	}
 
-	let file = File::open(filename).await.map_err(|err| {
+	let content_lenght = file.metadata().await?.len();
+
+	Ok(builder
+		.header("Content-Type", "image/webp")
+		.header("X-Thumbnail-Size", found_size.max_edge())
+		.header("Content-Length", content_lenght)
+		.status(StatusCode::OK)
+		.body(if method == Method::HEAD {
+			vec![]
+		} else {
+			read_file(file, content_lenght, None).await?
+		})?)
+}
+
+/// Serves a video's film strip (`/thumbstrip/<cas_id>`) or animated preview (`/video-preview/<cas_id>`).
+///
+/// These are only generated by the thumbnailer job when it's asked for them, so they aren't generated on demand.
+async fn handle_video_preview(
+	node: &Node,
+	path: &[&str],
+	req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	let method = req.method();
+	let mut builder = Response::builder();
+	if let Some(response) = cors(method, &mut builder) {
+		return Ok(response?);
+	}
+
+	let file_cas_id = cas_id_param(path)?;
+
+	let etag = format!("\"{file_cas_id}\"");
+	builder = builder
+		.header("ETag", &etag)
+		.header("Cache-Control", IMMUTABLE_CACHE_CONTROL);
+
+	let thumbnail_dir = node.config.data_directory().join(THUMBNAIL_CACHE_DIR_NAME);
+	let preview_path = if path[0] == "thumbstrip" {
+		thumbstrip_path(thumbnail_dir, file_cas_id)
+	} else {
+		video_preview_path(thumbnail_dir, file_cas_id)
+	};
+
+	let file = File::open(&preview_path).await.map_err(|err| {
 		if err.kind() == io::ErrorKind::NotFound {
 			HandleCustomUriError::NotFound("file")
 		} else {
 			err.into()
 		}
 	})?;
+	mark_thumbnail_used(&preview_path);
+
+	if is_not_modified(req, Some(&etag), None) {
+		return Ok(builder.status(StatusCode::NOT_MODIFIED).body(vec![])?);
+	}
 
 	let content_lenght = file.metadata().await?.len();
 
@@ -159,51 +620,222 @@ async fn handle_file(
 			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing file_path_id!")
 		})?;
 
-	let lru_cache_key = (library_id, location_id, file_path_id);
+	let library = node
+		.library_manager
+		.get_ctx(library_id)
+		.await
+		.ok_or_else(|| HandleCustomUriError::NotFound("library"))?;
 
-	let (file_path_materialized_path, extension) =
-		if let Some(entry) = FILE_METADATA_CACHE.get(&lru_cache_key) {
-			entry
-		} else {
-			let library = node
-				.library_manager
-				.get_ctx(library_id)
-				.await
-				.ok_or_else(|| HandleCustomUriError::NotFound("library"))?;
-			let file_path = library
-				.db
-				.file_path()
-				.find_unique(file_path::location_id_id(location_id, file_path_id))
-				.include(file_path::include!({ location }))
-				.exec()
-				.await?
-				.ok_or_else(|| HandleCustomUriError::NotFound("object"))?;
+	let (file, extension, stored_mime_type, version) =
+		open_file_path(&library, location_id, file_path_id).await?;
 
-			let lru_entry = (
-				Path::new(&file_path.location.path).join(&MaterializedPath::from((
-					location_id,
-					&file_path.materialized_path,
-				))),
-				file_path.extension,
-			);
-			FILE_METADATA_CACHE.insert(lru_cache_key, lru_entry.clone());
+	let mime_type = served_mime_type(stored_mime_type.as_deref(), &extension)?;
 
-			lru_entry
-		};
+	// files can change at any time, so the cached copy is always revalidated
+	let etag = version.etag();
+	builder = builder.header("Cache-Control", "no-cache");
+	if let Some(etag) = &etag {
+		builder = builder.header("ETag", etag);
+	}
+	if let Some(modified) = version.modified {
+		builder = builder.header("Last-Modified", http_date(modified));
+	}
 
-	let file = File::open(file_path_materialized_path)
-		.await
-		.map_err(|err| {
-			if err.kind() == io::ErrorKind::NotFound {
-				HandleCustomUriError::NotFound("file")
-			} else {
-				err.into()
+	if is_not_modified(req, etag.as_deref(), version.modified) {
+		return Ok(builder.status(StatusCode::NOT_MODIFIED).body(vec![])?);
+	}
+
+	let mut content_lenght = file.len().await?;
+	// GET is the only method for which range handling is defined, according to the spec
+	// https://httpwg.org/specs/rfc9110.html#field.range
+	let range = match req.headers().get("range") {
+		Some(range) if method == Method::GET => parse_range(range, content_lenght)?,
+		_ => None,
+	};
+
+	let mut status_code = 200;
+	let buf = match range {
+		Some(range) => {
+			let file_size = content_lenght;
+			content_lenght = range.length;
+
+			// TODO: For some reason webkit2gtk doesn't like this at all.
+			// It causes it to only stream random pieces of any given audio file.
+			#[cfg(not(target_os = "linux"))]
+			// prevent max_length;
+			// specially on webview2
+			if range.length > file_size / 3 {
+				// max size sent (400kb / request)
+				// as it's local file system we can afford to read more often
+				content_lenght = min(file_size - range.start, 1024 * 400);
 			}
+
+			// last byte we are reading, the length of the range include the last byte
+			// who should be skipped on the header
+			let last_byte = range.start + content_lenght - 1;
+
+			// if the webview sent a range header, we need to send a 206 in return
+			status_code = 206;
+
+			// macOS and Windows supports audio and video, linux only supports audio
+			builder = builder
+				.header("Connection", "Keep-Alive")
+				.header("Accept-Ranges", "bytes")
+				.header(
+					"Content-Range",
+					format!("bytes {}-{}/{}", range.start, last_byte, file_size),
+				);
+
+			file.read(content_lenght, Some(range.start)).await?
+		}
+		_ if method == Method::HEAD => vec![],
+		_ => file.read(content_lenght, None).await?,
+	};
+
+	Ok(builder
+		.header("Accept-Ranges", "bytes")
+		.header("Content-type", mime_type)
+		// the type is what the file contains rather than what its extension claims, so the webview shouldn't second guess it
+		.header("X-Content-Type-Options", "nosniff")
+		// files (such as SVGs) which are opened directly can't run script as the app
+		.header("Content-Security-Policy", "sandbox")
+		.header("Content-Length", content_lenght)
+		.status(status_code)
+		.body(buf)?)
+}
+
+/// The MIME type detected from the file's magic bytes when it was identified, or the one for its extension if it hasn't been identified yet.
+///
+/// Files are served from the same origin as the app (and with the session token in their URL), so HTML and JavaScript are served
+/// as plain text, as otherwise any of them in a library could run script as the app.
+fn served_mime_type<'a>(
+	stored_mime_type: Option<&'a str>,
+	extension: &str,
+) -> Result<&'a str, HandleCustomUriError> {
+	let served = stored_mime_type.or_else(|| mime_type(extension)).ok_or(
+		HandleCustomUriError::BadRequest(
+			"TODO: This filetype is not supported because of the missing mime type!",
+		),
+	)?;
+
+	Ok(match served {
+		"text/html" | "application/xhtml+xml" | "text/javascript" | "application/javascript" => {
+			"text/plain"
+		}
+		served => served,
+	})
+}
+
+/// Serves videos and audio that browsers can't play as HLS, with the playlist at `/transcode/<library_id>/<location_id>/<file_path_id>/index.m3u8`
+/// and its segments at `<index>.ts` alongside it.
+#[cfg(feature = "ffmpeg")]
+async fn handle_transcode(
+	node: &Node,
+	path: &[&str],
+	req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	use crate::object::preview::{hls_playlist_for, hls_segment_for, TRANSCODE_CACHE_DIR_NAME};
+
+	let method = req.method();
+	let mut builder = Response::builder();
+	if let Some(response) = cors(method, &mut builder) {
+		return Ok(response?);
+	}
+
+	let library_id = path
+		.get(1)
+		.and_then(|id| Uuid::from_str(id).ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing library_id!")
 		})?;
 
-	// TODO: This should be determined from magic bytes when the file is indexed and stored it in the DB on the file path
-	// https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types/Common_types
-	let mime_type = match extension.as_str() {
+	let location_id = path
+		.get(2)
+		.and_then(|id| id.parse::<i32>().ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing location_id!")
+		})?;
+
+	let file_path_id = path
+		.get(3)
+		.and_then(|id| id.parse::<i32>().ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing file_path_id!")
+		})?;
+
+	let name = path.get(4).ok_or_else(|| {
+		HandleCustomUriError::BadRequest(
+			"Invalid number of parameters. Missing playlist or segment!",
+		)
+	})?;
+
+	let library = node
+		.library_manager
+		.get_ctx(library_id)
+		.await
+		.ok_or_else(|| HandleCustomUriError::NotFound("library"))?;
+
+	let file_path = library
+		.db
+		.file_path()
+		.find_unique(file_path::location_id_id(location_id, file_path_id))
+		.include(file_path::include!({ location }))
+		.exec()
+		.await?
+		.ok_or_else(|| HandleCustomUriError::NotFound("object"))?;
+
+	// FFmpeg reads the file itself, so it can't read one that's encrypted at rest
+	if file_path.extension == AT_REST_EXTENSION {
+		return Err(HandleCustomUriError::BadRequest(
+			"Files which are encrypted at rest can't be transcoded!",
+		));
+	}
+
+	let cas_id = file_path.cas_id.ok_or(HandleCustomUriError::BadRequest(
+		"The file hasn't been identified yet!",
+	))?;
+
+	let media_path = Path::new(&file_path.location.path).join(&MaterializedPath::from((
+		location_id,
+		&file_path.materialized_path,
+	)));
+	let transcode_dir = node.config.data_directory().join(TRANSCODE_CACHE_DIR_NAME);
+
+	let (content_type, body) = if *name == "index.m3u8" {
+		(
+			"application/vnd.apple.mpegurl",
+			authorize_playlist(
+				&node.custom_uri_auth,
+				req.uri(),
+				bearer_token(req),
+				&hls_playlist_for(&media_path, &transcode_dir, &cas_id).await?,
+			)
+			.into_bytes(),
+		)
+	} else {
+		let index = name
+			.strip_suffix(".ts")
+			.and_then(|index| index.parse::<u32>().ok())
+			.ok_or(HandleCustomUriError::BadRequest("Invalid segment!"))?;
+
+		let segment_path = hls_segment_for(&media_path, &transcode_dir, &cas_id, index)
+			.await?
+			.ok_or(HandleCustomUriError::NotFound("segment"))?;
+		mark_thumbnail_used(&segment_path);
+
+		("video/mp2t", tokio::fs::read(segment_path).await?)
+	};
+
+	Ok(builder
+		.header("Content-Type", content_type)
+		.header("Content-Length", body.len())
+		.status(StatusCode::OK)
+		.body(if method == Method::HEAD { vec![] } else { body })?)
+}
+
+// https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types/Common_types
+fn mime_type(extension: &str) -> Option<&'static str> {
+	Some(match extension {
 		// AAC audio
 		"aac" => "audio/aac",
 		// Musical Instrument Digital Interface (MIDI)
@@ -258,89 +890,150 @@ async fn handle_file(
 		"webp" => "image/webp",
 		// PDF document
 		"pdf" => "application/pdf",
-		_ => {
-			return Err(HandleCustomUriError::BadRequest(
-				"TODO: This filetype is not supported because of the missing mime type!",
-			));
+		_ => return None,
+	})
+}
+
+/// Serves thumbnails and files from a peer which is paired with their library, so they can be viewed before the library is synced.
+///
+/// The paths are `/remote/<peer_id>/thumbnail/<library_id>/<cas_id>` and `/remote/<peer_id>/file/<library_id>/<location_id>/<file_path_id>`.
+async fn handle_remote(
+	node: &Node,
+	path: &[&str],
+	req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	let method = req.method();
+	let mut builder = Response::builder();
+	if let Some(response) = cors(method, &mut builder) {
+		return Ok(response?);
+	}
+
+	let peer_id = path
+		.get(1)
+		.and_then(|id| PeerId::from_str(id).ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing peer_id!")
+		})?;
+
+	let library_id = path
+		.get(3)
+		.and_then(|id| Uuid::from_str(id).ok())
+		.ok_or_else(|| {
+			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing library_id!")
+		})?;
+
+	match path.get(2) {
+		Some(&"thumbnail") => {
+			let file_cas_id = path.get(4).ok_or_else(|| {
+				HandleCustomUriError::BadRequest("Invalid number of parameters. Missing cas_id!")
+			})?;
+
+			let thumbnail = node
+				.p2p
+				.request_thumbnail(peer_id, library_id, file_cas_id.to_string())
+				.await?;
+
+			Ok(builder
+				.header("Content-Type", "image/webp")
+				.header("Cache-Control", IMMUTABLE_CACHE_CONTROL)
+				.header("Content-Length", thumbnail.len())
+				.status(StatusCode::OK)
+				.body(if method == Method::HEAD {
+					vec![]
+				} else {
+					thumbnail
+				})?)
 		}
-	};
+		Some(&"file") => {
+			let location_id = path
+				.get(4)
+				.and_then(|id| id.parse::<i32>().ok())
+				.ok_or_else(|| {
+					HandleCustomUriError::BadRequest(
+						"Invalid number of parameters. Missing location_id!",
+					)
+				})?;
 
-	let mut content_lenght = file.metadata().await?.len();
-	// GET is the only method for which range handling is defined, according to the spec
-	// https://httpwg.org/specs/rfc9110.html#field.range
-	let range = if method == Method::GET {
-		if let Some(range) = req.headers().get("range") {
-			range
-				.to_str()
-				.ok()
-				.and_then(|range| HttpRange::parse(range, content_lenght).ok())
+			let file_path_id = path
+				.get(5)
+				.and_then(|id| id.parse::<i32>().ok())
 				.ok_or_else(|| {
-					HandleCustomUriError::RangeNotSatisfiable("Error decoding range header!")
-				})
-				.and_then(|range| {
-					// Let's support only 1 range for now
-					if range.len() > 1 {
-						Err(HandleCustomUriError::RangeNotSatisfiable(
-							"Multiple ranges are not supported!",
-						))
-					} else {
-						Ok(range.first().cloned())
-					}
-				})?
-		} else {
-			None
-		}
-	} else {
-		None
-	};
+					HandleCustomUriError::BadRequest(
+						"Invalid number of parameters. Missing file_path_id!",
+					)
+				})?;
 
-	let mut status_code = 200;
-	let buf = match range {
-		Some(range) => {
-			let file_size = content_lenght;
-			content_lenght = range.length;
+			let cache_key = (peer_id, library_id, location_id, file_path_id);
+			let request = |start, length| {
+				node.p2p.request_file(
+					peer_id,
+					library_id,
+					location_id,
+					file_path_id,
+					start,
+					length,
+				)
+			};
 
-			// TODO: For some reason webkit2gtk doesn't like this at all.
-			// It causes it to only stream random pieces of any given audio file.
-			#[cfg(not(target_os = "linux"))]
-			// prevent max_length;
-			// specially on webview2
-			if range.length > file_size / 3 {
-				// max size sent (400kb / request)
-				// as it's local file system we can afford to read more often
-				content_lenght = min(file_size - range.start, 1024 * 400);
-			}
+			let (file, start, buf) = match req.headers().get("range") {
+				Some(range) if method == Method::GET => {
+					// the size of the file is needed to parse the range, so it's requested first if we haven't seen the file recently
+					let size = match REMOTE_FILE_SIZE_CACHE.get(&cache_key) {
+						Some(size) => size,
+						None => request(0, Some(0)).await?.0.size,
+					};
 
-			// last byte we are reading, the length of the range include the last byte
-			// who should be skipped on the header
-			let last_byte = range.start + content_lenght - 1;
+					match parse_range(range, size)? {
+						Some(range) => {
+							let (file, buf) = request(range.start, Some(range.length)).await?;
+							(file, Some(range.start), buf)
+						}
+						None => {
+							let (file, buf) = request(0, None).await?;
+							(file, None, buf)
+						}
+					}
+				}
+				_ if method == Method::HEAD => (request(0, Some(0)).await?.0, None, vec![]),
+				_ => {
+					let (file, buf) = request(0, None).await?;
+					(file, None, buf)
+				}
+			};
+			REMOTE_FILE_SIZE_CACHE.insert(cache_key, file.size);
 
-			// if the webview sent a range header, we need to send a 206 in return
-			status_code = 206;
+			let mime_type = served_mime_type(file.mime_type.as_deref(), &file.extension)?;
 
-			// macOS and Windows supports audio and video, linux only supports audio
-			builder = builder
-				.header("Connection", "Keep-Alive")
-				.header("Accept-Ranges", "bytes")
-				.header(
+			// the peer sends at most `MAX_RANGE_LEN` bytes at once, so anything shorter than the file is a partial response
+			let start = start.unwrap_or_default();
+			let (status_code, content_lenght) = if method == Method::HEAD {
+				(200, file.size)
+			} else if start > 0 || (buf.len() as u64) < file.size {
+				builder = builder.header(
 					"Content-Range",
-					format!("bytes {}-{}/{}", range.start, last_byte, file_size),
+					format!(
+						"bytes {}-{}/{}",
+						start,
+						(start + buf.len() as u64).saturating_sub(1),
+						file.size
+					),
 				);
+				(206, buf.len() as u64)
+			} else {
+				(200, buf.len() as u64)
+			};
 
-			// FIXME: Add ETag support (caching on the webview)
-
-			read_file(file, content_lenght, Some(range.start)).await?
+			Ok(builder
+				.header("Accept-Ranges", "bytes")
+				.header("Content-type", mime_type)
+				.header("X-Content-Type-Options", "nosniff")
+				.header("Content-Security-Policy", "sandbox")
+				.header("Content-Length", content_lenght)
+				.status(status_code)
+				.body(buf)?)
 		}
-		_ if method == Method::HEAD => vec![],
-		_ => read_file(file, content_lenght, None).await?,
-	};
-
-	Ok(builder
-		.header("Accept-Ranges", "bytes")
-		.header("Content-type", mime_type)
-		.header("Content-Length", content_lenght)
-		.status(status_code)
-		.body(buf)?)
+		_ => Err(HandleCustomUriError::BadRequest("Invalid operation!")),
+	}
 }
 
 pub fn create_custom_uri_endpoint(node: Arc<Node>) -> Endpoint<impl HttpEndpoint> {
@@ -364,15 +1057,27 @@ pub enum HandleCustomUriError {
 	QueryError(#[from] QueryError),
 	#[error("{0}")]
 	BadRequest(&'static str),
+	#[error("{0}")]
+	Unauthorized(&'static str),
 	#[error("Range is not valid: {0}")]
 	RangeNotSatisfiable(&'static str),
 	#[error("resource '{0}' not found")]
 	NotFound(&'static str),
+	#[error("unable to decrypt file: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("error requesting from peer: {0}")]
+	Remote(#[from] RemoteError),
+	#[cfg(feature = "ffmpeg")]
+	#[error("error transcoding media: {0}")]
+	Transcode(#[from] sd_ffmpeg::ThumbnailerError),
 }
 
 impl From<HandleCustomUriError> for Response<Vec<u8>> {
 	fn from(value: HandleCustomUriError) -> Self {
-		let builder = Response::builder().header("Content-Type", "text/plain");
+		// without the CORS header, a browser reports an error response from another origin as a network error
+		let builder = Response::builder()
+			.header("Content-Type", "text/plain")
+			.header("Access-Control-Allow-Origin", "*");
 
 		(match value {
 			HandleCustomUriError::Http(err) => {
@@ -399,6 +1104,9 @@ impl From<HandleCustomUriError> for Response<Vec<u8>> {
 					.status(StatusCode::BAD_REQUEST)
 					.body(msg.as_bytes().to_vec())
 			}
+			HandleCustomUriError::Unauthorized(msg) => builder
+				.status(StatusCode::UNAUTHORIZED)
+				.body(msg.as_bytes().to_vec()),
 			HandleCustomUriError::RangeNotSatisfiable(msg) => {
 				error!("Invalid Range header in request: {}", msg);
 				builder
@@ -410,8 +1118,104 @@ impl From<HandleCustomUriError> for Response<Vec<u8>> {
 					.as_bytes()
 					.to_vec(),
 			),
+			HandleCustomUriError::Crypto(err) => {
+				error!("Unable to decrypt file: {}", err);
+				builder
+					.status(StatusCode::FORBIDDEN)
+					.body(b"Forbidden".to_vec())
+			}
+			HandleCustomUriError::Remote(RemoteError::NotFound) => builder
+				.status(StatusCode::NOT_FOUND)
+				.body(b"Resource not found on peer".to_vec()),
+			HandleCustomUriError::Remote(RemoteError::Forbidden) => builder
+				.status(StatusCode::FORBIDDEN)
+				.body(b"Forbidden".to_vec()),
+			HandleCustomUriError::Remote(err) => {
+				error!("Error requesting from peer: {}", err);
+				builder
+					.status(StatusCode::BAD_GATEWAY)
+					.body(b"Bad Gateway".to_vec())
+			}
+			#[cfg(feature = "ffmpeg")]
+			HandleCustomUriError::Transcode(err) => {
+				error!("Error transcoding media: {}", err);
+				builder
+					.status(StatusCode::INTERNAL_SERVER_ERROR)
+					.body(b"Internal Server Error".to_vec())
+			}
 		})
 		// SAFETY: This unwrap is ok as we have an hardcoded the response builders.
 		.expect("internal error building hardcoded HTTP error response")
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cas_id_param_strips_extension() {
+		assert_eq!(
+			cas_id_param(&["thumbnail", "0123abcd.webp"]).unwrap(),
+			"0123abcd"
+		);
+		assert_eq!(
+			cas_id_param(&["thumbnail", "0123abcd"]).unwrap(),
+			"0123abcd"
+		);
+
+		for invalid in [
+			".webp",
+			"..",
+			"0123abcd.png",
+			"../0123abcd",
+			"0123abcd-small",
+		] {
+			assert!(cas_id_param(&["thumbnail", invalid]).is_err());
+		}
+		assert!(cas_id_param(&["thumbnail"]).is_err());
+	}
+
+	#[cfg(feature = "ffmpeg")]
+	#[test]
+	fn playlist_segments_are_authorized() {
+		let auth = CustomUriAuth::new();
+		let dir = format!("/transcode/{}/1/2", Uuid::new_v4());
+		let playlist = sd_ffmpeg::hls_playlist(
+			std::time::Duration::from_secs(15),
+			sd_ffmpeg::HLS_SEGMENT_DURATION,
+			|index| format!("{index}.ts"),
+		);
+
+		let signed = auth.sign(
+			&format!("{dir}/index.m3u8"),
+			Utc::now() + chrono::Duration::hours(1),
+		);
+		let token = auth.session_token();
+
+		for (playlist_uri, bearer) in [
+			(format!("{dir}/index.m3u8?token={token}"), None),
+			(format!("{dir}/index.m3u8"), Some(token.as_str())),
+			(signed, None),
+		] {
+			let playlist_uri = playlist_uri.parse::<Uri>().unwrap();
+			auth.authorize_uri(&playlist_uri, bearer).unwrap();
+
+			let served = authorize_playlist(&auth, &playlist_uri, bearer, &playlist);
+			let segments = served
+				.lines()
+				.filter(|line| !line.starts_with('#'))
+				.collect::<Vec<_>>();
+			assert_eq!(segments.len(), 3);
+
+			// segments are fetched relative to the playlist, without its query or headers
+			for segment in segments {
+				let segment_uri = format!("{dir}/{segment}").parse::<Uri>().unwrap();
+				auth.authorize_uri(&segment_uri, None).unwrap();
+			}
+		}
+
+		let unauthorized = format!("{dir}/0.ts").parse::<Uri>().unwrap();
+		assert!(auth.authorize_uri(&unauthorized, None).is_err());
+	}
+}
@@ -9,10 +9,12 @@ use crate::{
 			shallow_file_identifier_job::ShallowFileIdentifierJob,
 		},
 		fs::{
-			copy::FileCopierJob, cut::FileCutterJob, delete::FileDeleterJob, erase::FileEraserJob,
+			at_rest::AtRestEncryptorJob, copy::FileCopierJob, cut::FileCutterJob,
+			delete::FileDeleterJob, erase::FileEraserJob,
 		},
 		preview::{
-			shallow_thumbnailer_job::ShallowThumbnailerJob, thumbnailer_job::ThumbnailerJob,
+			cleanup_job::ThumbnailCleanupJob, shallow_thumbnailer_job::ShallowThumbnailerJob,
+			thumbnailer_job::ThumbnailerJob,
 		},
 		validation::validator_job::ObjectValidatorJob,
 	},
@@ -278,6 +280,9 @@ impl JobManager {
 			<ShallowThumbnailerJob as StatefulJob>::NAME => {
 				Job::resume(job_report, ShallowThumbnailerJob {}, next_job)
 			}
+			<ThumbnailCleanupJob as StatefulJob>::NAME => {
+				Job::resume(job_report, ThumbnailCleanupJob {}, next_job)
+			}
 			<IndexerJob as StatefulJob>::NAME => Job::resume(job_report, IndexerJob {}, next_job),
 			<ShallowIndexerJob as StatefulJob>::NAME => {
 				Job::resume(job_report, ShallowIndexerJob {}, next_job)
@@ -303,6 +308,9 @@ impl JobManager {
 			<FileEraserJob as StatefulJob>::NAME => {
 				Job::resume(job_report, FileEraserJob {}, next_job)
 			}
+			<AtRestEncryptorJob as StatefulJob>::NAME => {
+				Job::resume(job_report, AtRestEncryptorJob {}, next_job)
+			}
 			_ => {
 				error!(
 					"Unknown job type: {}, id: {}",
@@ -1,16 +1,28 @@
 use crate::{
 	api::{CoreEvent, Router},
+	custom_uri::CustomUriAuth,
 	job::JobManager,
 	library::LibraryManager,
-	location::{LocationManager, LocationManagerError},
+	location::{self, LocationManager, LocationManagerError},
 	node::NodeConfigManager,
 	p2p::{P2PEvent, P2PManager},
 };
 use util::secure_temp_keystore::SecureTempKeystore;
 
-use std::{path::Path, sync::Arc};
+use once_cell::sync::OnceCell;
+use sd_crypto::keys::keyring::KeyringInterface;
+use std::{
+	path::Path,
+	sync::{Arc, Weak},
+};
 use thiserror::Error;
-use tokio::{fs, sync::broadcast};
+use tokio::{
+	fs,
+	sync::{
+		broadcast::{self, error::RecvError},
+		Mutex,
+	},
+};
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
@@ -36,6 +48,10 @@ pub struct NodeContext {
 	pub location_manager: Arc<LocationManager>,
 	pub event_bus_tx: broadcast::Sender<CoreEvent>,
 	pub p2p: Arc<P2PManager>,
+	/// keyring is shared by the key managers of all libraries. It's `None` if the configured backend isn't available.
+	pub keyring: Option<Arc<Mutex<KeyringInterface>>>,
+	/// The library manager is set once it's been created, as it's created with this context. It's used by jobs which span every library.
+	pub library_manager: Arc<OnceCell<Weak<LibraryManager>>>,
 }
 
 pub struct Node {
@@ -45,6 +61,7 @@ pub struct Node {
 	p2p: Arc<P2PManager>,
 	event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
 	secure_temp_keystore: Arc<SecureTempKeystore>,
+	custom_uri_auth: CustomUriAuth,
 	// peer_request: tokio::sync::Mutex<Option<PeerRequest>>,
 }
 
@@ -126,11 +143,18 @@ impl Node {
 
 		let event_bus = broadcast::channel(1024);
 		let config = NodeConfigManager::new(data_dir.to_path_buf()).await?;
+		let keyring = config
+			.get()
+			.await
+			.keyring
+			.build(data_dir)?
+			.map(|keyring| Arc::new(Mutex::new(keyring)));
 
 		let jobs = JobManager::new();
 		let location_manager = LocationManager::new();
 		let secure_temp_keystore = SecureTempKeystore::new();
 		let (p2p, mut p2p_rx) = P2PManager::new(config.clone()).await;
+		let library_manager_cell = Arc::new(OnceCell::new());
 
 		let library_manager = LibraryManager::new(
 			data_dir.join("libraries"),
@@ -140,10 +164,18 @@ impl Node {
 				location_manager: location_manager.clone(),
 				p2p: p2p.clone(),
 				event_bus_tx: event_bus.0.clone(),
+				keyring,
+				library_manager: library_manager_cell.clone(),
 			},
 		)
 		.await?;
 
+		library_manager_cell
+			.set(Arc::downgrade(&library_manager))
+			.map_err(|_| error!("The library manager has already been set!"))
+			.ok();
+		p2p.set_library_manager(&library_manager);
+
 		debug!("Watching locations");
 
 		tokio::spawn({
@@ -159,19 +191,52 @@ impl Node {
 						debug!("going to ingest {} operations", operations.len());
 
 						let Some(library) = library_manager.get_ctx(library_id).await else {
-						warn!("no library found!");
-						continue;
-					};
+							warn!("no library found!");
+							continue;
+						};
 
 						for op in operations {
-							println!("ingest lib id: {}", library.id);
-							library.sync.ingest_op(op).await.unwrap();
+							if let Err(e) = library.sync.ingest_op(op).await {
+								error!("Failed to ingest sync operation: {e:#?}");
+							}
 						}
 					}
 				}
 			}
 		});
 
+		// received files are indexed straight away if they were saved into a location
+		tokio::spawn({
+			let library_manager = library_manager.clone();
+			let mut p2p_rx = p2p.subscribe();
+
+			async move {
+				loop {
+					let event = match p2p_rx.recv().await {
+						Ok(event) => event,
+						// missing other events doesn't matter, so this only stops once the P2P manager is gone
+						Err(RecvError::Lagged(_)) => continue,
+						Err(RecvError::Closed) => break,
+					};
+
+					let P2PEvent::SpacedropCompleted { received, .. } = event else {
+						continue;
+					};
+
+					if received.is_empty() {
+						continue;
+					}
+
+					for library in library_manager.get_all_libraries().await {
+						location::scan_new_paths(&library, &received)
+							.await
+							.map_err(|e| error!("Failed to scan received Spacedrop: {e:#?}"))
+							.ok();
+					}
+				}
+			}
+		});
+
 		let router = api::mount();
 		let node = Node {
 			config,
@@ -180,6 +245,7 @@ impl Node {
 			p2p,
 			event_bus,
 			secure_temp_keystore,
+			custom_uri_auth: CustomUriAuth::new(),
 			// peer_request: tokio::sync::Mutex::new(None),
 		};
 
@@ -187,6 +253,11 @@ impl Node {
 		Ok((Arc::new(node), router))
 	}
 
+	/// Apps which embed the custom URI server give its session token to their frontend, so it can authorize its requests.
+	pub fn custom_uri_auth(&self) -> &CustomUriAuth {
+		&self.custom_uri_auth
+	}
+
 	pub async fn shutdown(&self) {
 		info!("Spacedrive shutting down...");
 		self.jobs.pause().await;
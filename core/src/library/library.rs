@@ -3,7 +3,7 @@ use crate::{
 	job::{IntoJob, JobInitData, JobManagerError, StatefulJob},
 	location::{file_path_helper::LastFilePathIdManager, LocationManager},
 	node::NodeConfigManager,
-	object::preview::THUMBNAIL_CACHE_DIR_NAME,
+	object::preview::{ThumbnailSize, THUMBNAIL_CACHE_DIR_NAME},
 	prisma::PrismaClient,
 	sync::SyncManager,
 	NodeContext,
@@ -85,13 +85,17 @@ impl Library {
 		&self.node_context.location_manager
 	}
 
-	pub async fn thumbnail_exists(&self, cas_id: &str) -> tokio::io::Result<bool> {
-		let thumb_path = self
-			.config()
-			.data_directory()
-			.join(THUMBNAIL_CACHE_DIR_NAME)
-			.join(cas_id)
-			.with_extension("webp");
+	pub async fn thumbnail_exists(
+		&self,
+		cas_id: &str,
+		size: ThumbnailSize,
+	) -> tokio::io::Result<bool> {
+		let thumb_path = size.path(
+			self.config()
+				.data_directory()
+				.join(THUMBNAIL_CACHE_DIR_NAME),
+			cas_id,
+		);
 
 		match tokio::fs::metadata(thumb_path).await {
 			Ok(_) => Ok(true),
@@ -2,6 +2,7 @@ use crate::{
 	invalidate_query,
 	location::file_path_helper::LastFilePathIdManager,
 	node::Platform,
+	p2p,
 	prisma::{node, PrismaClient},
 	sync::{SyncManager, SyncMessage},
 	util::{
@@ -202,7 +203,10 @@ impl LibraryManager {
 		indexer_rules_seeder(&library.db).await?;
 
 		// setup master password
-		let verification_key = KeyManager::onboarding(km_config, library.id).await?;
+		let verification_key = library
+			.key_manager
+			.onboarding(km_config, library.id)
+			.await?;
 
 		write_storedkey_to_db(&library.db, &verification_key).await?;
 
@@ -227,9 +231,9 @@ impl LibraryManager {
 			.collect()
 	}
 
-	// pub(crate) async fn get_all_libraries(&self) -> Vec<Library> {
-	// 	self.libraries.read().await.clone()
-	// }
+	pub(crate) async fn get_all_libraries(&self) -> Vec<Library> {
+		self.libraries.read().await.clone()
+	}
 
 	pub(crate) async fn edit(
 		&self,
@@ -359,7 +363,7 @@ impl LibraryManager {
 			.exec()
 			.await?;
 
-		let key_manager = Arc::new(KeyManager::new(vec![]).await?);
+		let key_manager = Arc::new(KeyManager::new(vec![], node_context.keyring.clone()).await?);
 		seed_keymanager(&db, &key_manager).await?;
 
 		let (sync_manager, mut sync_rx) = SyncManager::new(&db, id);
@@ -398,6 +402,10 @@ impl LibraryManager {
 			error!("Failed to resume jobs for library. {:#?}", e);
 		}
 
+		if let Err(e) = p2p::add_pending_pairings(&library).await {
+			error!("Failed to add the pending pairings to library. {:#?}", e);
+		}
+
 		Ok(library)
 	}
 }
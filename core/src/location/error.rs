@@ -5,6 +5,8 @@ use thiserror::Error;
 use tokio::io;
 use uuid::Uuid;
 
+use crate::job::JobManagerError;
+
 use super::{
 	file_path_helper::FilePathError, manager::LocationManagerError, metadata::LocationMetadataError,
 };
@@ -58,6 +60,8 @@ pub enum LocationError {
 	LocationManagerError(#[from] LocationManagerError),
 	#[error("File path related error (error: {0})")]
 	FilePathError(#[from] FilePathError),
+	#[error("Job manager error (error: {0:?})")]
+	JobManagerError(#[from] JobManagerError),
 }
 
 impl From<LocationError> for rspc::Error {
@@ -66,7 +70,8 @@ impl From<LocationError> for rspc::Error {
 			// Not found errors
 			LocationError::PathNotFound(_)
 			| LocationError::UuidNotFound(_)
-			| LocationError::IdNotFound(_) => {
+			| LocationError::IdNotFound(_)
+			| LocationError::DirectoryNotFound(_) => {
 				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
 			}
 
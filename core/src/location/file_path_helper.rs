@@ -38,9 +38,12 @@ file_path::select!(file_path_for_object_validator {
 		pub_id
 	}
 });
-file_path::select!(file_path_just_materialized_path_cas_id {
+file_path::select!(file_path_for_thumbnailer {
 	materialized_path
 	cas_id
+	extension
+	object_id
+	object: select { id pub_id perceptual_hash blurhash }
 });
 
 // File Path includes!
@@ -1,5 +1,6 @@
 use crate::{
 	invalidate_query,
+	job::Job,
 	library::Library,
 	location::{
 		delete_directory,
@@ -14,10 +15,9 @@ use crate::{
 	},
 	object::{
 		file_identifier::FileMetadata,
+		fs::at_rest::{AtRestEncryptorJobInit, AT_REST_EXTENSION},
 		object_just_id_has_thumbnail,
-		preview::{
-			can_generate_thumbnail_for_image, generate_image_thumbnail, THUMBNAIL_CACHE_DIR_NAME,
-		},
+		preview::{ThumbnailSize, Thumbnailer, THUMBNAIL_CACHE_DIR_NAME},
 		validation::hash::file_checksum,
 	},
 	prisma::{file_path, location, object},
@@ -34,11 +34,8 @@ use std::{
 	collections::HashSet,
 	fs::Metadata,
 	path::{Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR},
-	str::FromStr,
 };
 
-use sd_file_ext::extensions::ImageExtension;
-
 use chrono::{DateTime, Local};
 use int_enum::IntEnum;
 use notify::{Event, EventKind};
@@ -174,11 +171,14 @@ pub(super) async fn create_file(
     };
 
 	// generate provisional object
+	let file_metadata = FileMetadata::new(&location_path, &materialized_path).await?;
+	let detected_type_params = file_metadata.detected_type_params();
 	let FileMetadata {
 		cas_id,
 		kind,
 		fs_metadata,
-	} = FileMetadata::new(&location_path, &materialized_path).await?;
+		..
+	} = file_metadata;
 
 	let created_file = library
 		.last_file_path_id_manager
@@ -227,20 +227,76 @@ pub(super) async fn create_file(
 			.await?
 	};
 
-	db.file_path()
-		.update(
-			file_path::location_id_id(location_id, created_file.id),
-			vec![file_path::object_id::set(Some(object.id))],
-		)
+	let location_pub_id = find_location(library, location_id)
+		.select(location::select!({ pub_id }))
 		.exec()
-		.await?;
+		.await?
+		.ok_or(LocationManagerError::MissingLocation(location_id))?
+		.pub_id;
+
+	let sync = &library.sync;
+	let (sync_params, db_params): (Vec<_>, Vec<_>) = detected_type_params.into_iter().unzip();
+
+	sync.write_ops(
+		db,
+		(
+			sync_params
+				.into_iter()
+				.map(|(field, value)| {
+					sync.shared_update(
+						sync::file_path::SyncId {
+							location: sync::location::SyncId {
+								pub_id: location_pub_id.clone(),
+							},
+							id: created_file.id,
+						},
+						field,
+						value,
+					)
+				})
+				.collect(),
+			db.file_path().update(
+				file_path::location_id_id(location_id, created_file.id),
+				db_params
+					.into_iter()
+					.chain([file_path::object_id::set(Some(object.id))])
+					.collect(),
+			),
+		),
+	)
+	.await?;
 
-	if !object.has_thumbnail && !created_file.extension.is_empty() {
+	let encrypt_at_rest = find_location(library, location_id)
+		.select(location::select!({ encrypt_at_rest }))
+		.exec()
+		.await?
+		.map_or(false, |location| location.encrypt_at_rest)
+		&& created_file.extension != AT_REST_EXTENSION;
+
+	let needs_thumbnail = !object.has_thumbnail && !created_file.extension.is_empty();
+
+	if needs_thumbnail || encrypt_at_rest {
 		// Running in a detached task as thumbnail generation can take a while and we don't want to block the watcher
+		// The thumbnail is generated first, as the original file is gone once it's been encrypted
 		let path = path.to_path_buf();
 		let library = library.clone();
 		tokio::spawn(async move {
-			generate_thumbnail(&created_file.extension, &cas_id, path, &library).await;
+			if needs_thumbnail {
+				generate_thumbnail(&created_file.extension, &cas_id, path, &library).await;
+			}
+
+			if encrypt_at_rest {
+				if let Err(e) = library
+					.spawn_job(Job::new(AtRestEncryptorJobInit {
+						location_id,
+						sub_path: None,
+						file_path_id: Some(created_file.id),
+					}))
+					.await
+				{
+					error!("Failed to spawn encryption at rest job: {e:#?}");
+				}
+			}
 		});
 	}
 
@@ -351,15 +407,18 @@ async fn inner_update_file(
 		full_path.display()
 	);
 
-	let FileMetadata {
-		cas_id,
-		fs_metadata,
-		kind,
-	} = FileMetadata::new(
+	let file_metadata = FileMetadata::new(
 		&location_path,
 		&MaterializedPath::from((location_id, &file_path.materialized_path)),
 	)
 	.await?;
+	let detected_type_params = file_metadata.detected_type_params();
+	let FileMetadata {
+		cas_id,
+		fs_metadata,
+		kind,
+		..
+	} = file_metadata;
 
 	if let Some(old_cas_id) = &file_path.cas_id {
 		if old_cas_id != &cas_id {
@@ -396,6 +455,8 @@ async fn inner_update_file(
 				},
 			]
 			.into_iter()
+			// the new contents may be of a different type
+			.chain(detected_type_params)
 			.unzip();
 
 			// file content changed
@@ -669,13 +730,18 @@ async fn generate_thumbnail(
 	path: impl AsRef<Path>,
 	library: &Library,
 ) {
+	let Some(thumbnailer) = Thumbnailer::for_extension_str(extension) else {
+		return;
+	};
+
 	let path = path.as_ref();
-	let output_path = library
-		.config()
-		.data_directory()
-		.join(THUMBNAIL_CACHE_DIR_NAME)
-		.join(cas_id)
-		.with_extension("webp");
+	let output_path = ThumbnailSize::Small.path(
+		library
+			.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME),
+		cas_id,
+	);
 
 	if let Err(e) = fs::metadata(&output_path).await {
 		if e.kind() != ErrorKind::NotFound {
@@ -692,26 +758,11 @@ async fn generate_thumbnail(
 		return;
 	}
 
-	if let Ok(extension) = ImageExtension::from_str(extension) {
-		if can_generate_thumbnail_for_image(&extension) {
-			if let Err(e) = generate_image_thumbnail(path, &output_path).await {
-				error!("Failed to image thumbnail on location manager: {e:#?}");
-			}
-		}
-	}
-
-	#[cfg(feature = "ffmpeg")]
+	if let Err(e) = thumbnailer
+		.generate(path, &output_path, ThumbnailSize::Small)
+		.await
 	{
-		use crate::object::preview::{can_generate_thumbnail_for_video, generate_video_thumbnail};
-		use sd_file_ext::extensions::VideoExtension;
-
-		if let Ok(extension) = VideoExtension::from_str(extension) {
-			if can_generate_thumbnail_for_video(&extension) {
-				if let Err(e) = generate_video_thumbnail(path, &output_path).await {
-					error!("Failed to video thumbnail on location manager: {e:#?}");
-				}
-			}
-		}
+		error!("Failed to generate {thumbnailer:?} thumbnail on location manager: {e:#?}");
 	}
 }
 
@@ -7,8 +7,11 @@ use crate::{
 			file_identifier_job::FileIdentifierJobInit,
 			shallow_file_identifier_job::ShallowFileIdentifierJobInit,
 		},
+		fs::at_rest::AtRestEncryptorJobInit,
 		preview::{
-			shallow_thumbnailer_job::ShallowThumbnailerJobInit, thumbnailer_job::ThumbnailerJobInit,
+			cleanup_job::ThumbnailCleanupJobInit,
+			shallow_thumbnailer_job::ShallowThumbnailerJobInit,
+			thumbnailer_job::ThumbnailerJobInit,
 		},
 	},
 	prisma::{file_path, indexer_rules_in_location, location, node, object},
@@ -181,6 +184,7 @@ pub struct LocationUpdateArgs {
 	pub generate_preview_media: Option<bool>,
 	pub sync_preview_media: Option<bool>,
 	pub hidden: Option<bool>,
+	pub encrypt_at_rest: Option<bool>,
 	pub indexer_rules_ids: Vec<i32>,
 }
 
@@ -213,6 +217,12 @@ impl LocationUpdateArgs {
 			}),
 			self.hidden
 				.map(|v| (("hidden", json!(v)), location::hidden::set(v))),
+			self.encrypt_at_rest.map(|v| {
+				(
+					("encrypt_at_rest", json!(v)),
+					location::encrypt_at_rest::set(v),
+				)
+			}),
 		]
 		.into_iter()
 		.flatten()
@@ -241,10 +251,12 @@ impl LocationUpdateArgs {
 			.await?;
 
 			if location.node_id == library.node_local_id {
-				if let Some(mut metadata) =
-					SpacedriveLocationMetadataFile::try_load(&location.path).await?
-				{
-					metadata.update(library.id, self.name.unwrap()).await?;
+				if let Some(name) = self.name {
+					if let Some(mut metadata) =
+						SpacedriveLocationMetadataFile::try_load(&location.path).await?
+					{
+						metadata.update(library.id, name).await?;
+					}
 				}
 			}
 		}
@@ -324,27 +336,42 @@ pub async fn scan_location(
 	}
 
 	let location_base_data = location::Data::from(&location);
+	let location_id = location.id;
+	let encrypt_at_rest = location.encrypt_at_rest;
+
+	let job = Job::new(IndexerJobInit {
+		location,
+		sub_path: None,
+	})
+	.queue_next(FileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: None,
+	})
+	.queue_next(ThumbnailerJobInit {
+		location: location_base_data,
+		sub_path: None,
+		background: true,
+		video_previews: false,
+	});
+
+	let job = if encrypt_at_rest {
+		job.queue_next(AtRestEncryptorJobInit {
+			location_id,
+			sub_path: None,
+			file_path_id: None,
+		})
+	} else {
+		job
+	};
 
+	// the thumbnail cache is cleaned up after each full scan, so it's kept within its size limit without anyone running the cleanup.
+	// It's queued last, as it's skipped if another library's cleanup is already running, and nothing after it would run
 	library
-		.spawn_job(
-			Job::new(IndexerJobInit {
-				location,
-				sub_path: None,
-			})
-			.queue_next(FileIdentifierJobInit {
-				location: location_base_data.clone(),
-				sub_path: None,
-			})
-			.queue_next(ThumbnailerJobInit {
-				location: location_base_data,
-				sub_path: None,
-				background: true,
-			}),
-		)
+		.spawn_job(job.queue_next(ThumbnailCleanupJobInit {}))
 		.await
 }
 
-#[cfg(feature = "location-watcher")]
+/// This isn't gated behind the `location-watcher` feature, as received Spacedrops are scanned with it too (see [`scan_new_paths`]).
 pub async fn scan_location_sub_path(
 	library: &Library,
 	location: location_with_indexer_rules::Data,
@@ -356,23 +383,34 @@ pub async fn scan_location_sub_path(
 	}
 
 	let location_base_data = location::Data::from(&location);
+	let location_id = location.id;
+	let encrypt_at_rest = location.encrypt_at_rest;
+
+	let job = Job::new(IndexerJobInit {
+		location,
+		sub_path: Some(sub_path.clone()),
+	})
+	.queue_next(FileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: Some(sub_path.clone()),
+	})
+	.queue_next(ThumbnailerJobInit {
+		location: location_base_data,
+		sub_path: Some(sub_path.clone()),
+		background: true,
+		video_previews: false,
+	});
 
 	library
-		.spawn_job(
-			Job::new(IndexerJobInit {
-				location,
-				sub_path: Some(sub_path.clone()),
-			})
-			.queue_next(FileIdentifierJobInit {
-				location: location_base_data.clone(),
-				sub_path: Some(sub_path.clone()),
-			})
-			.queue_next(ThumbnailerJobInit {
-				location: location_base_data,
+		.spawn_job(if encrypt_at_rest {
+			job.queue_next(AtRestEncryptorJobInit {
+				location_id,
 				sub_path: Some(sub_path),
-				background: true,
-			}),
-		)
+				file_path_id: None,
+			})
+		} else {
+			job
+		})
 		.await
 }
 
@@ -387,25 +425,82 @@ pub async fn light_scan_location(
 	}
 
 	let location_base_data = location::Data::from(&location);
+	let location_id = location.id;
+	let encrypt_at_rest = location.encrypt_at_rest;
+
+	let job = Job::new(ShallowIndexerJobInit {
+		location,
+		sub_path: sub_path.clone(),
+	})
+	.queue_next(ShallowFileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: sub_path.clone(),
+	})
+	.queue_next(ShallowThumbnailerJobInit {
+		location: location_base_data,
+		sub_path: sub_path.clone(),
+	});
 
 	library
-		.spawn_job(
-			Job::new(ShallowIndexerJobInit {
-				location,
-				sub_path: sub_path.clone(),
-			})
-			.queue_next(ShallowFileIdentifierJobInit {
-				location: location_base_data.clone(),
-				sub_path: sub_path.clone(),
+		.spawn_job(if encrypt_at_rest {
+			job.queue_next(AtRestEncryptorJobInit {
+				location_id,
+				sub_path: Some(sub_path),
+				file_path_id: None,
 			})
-			.queue_next(ShallowThumbnailerJobInit {
-				location: location_base_data,
-				sub_path,
-			}),
-		)
+		} else {
+			job
+		})
 		.await
 }
 
+/// Indexes, identifies and generates thumbnails for files that were added outside of the location watcher (such as received Spacedrops),
+/// so they show up immediately even when the watcher isn't running.
+///
+/// Paths which aren't within one of this node's locations are ignored.
+pub async fn scan_new_paths(library: &Library, paths: &[PathBuf]) -> Result<(), LocationError> {
+	let locations = library
+		.db
+		.location()
+		.find_many(vec![location::node_id::equals(library.node_local_id)])
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await?;
+
+	let mut scanned_directories = HashSet::new();
+	for path in paths {
+		// the most specific location, in case locations are nested
+		let location = match locations
+			.iter()
+			.filter(|location| path.starts_with(&location.path))
+			.max_by_key(|location| location.path.len())
+		{
+			Some(location) => location,
+			None => continue,
+		};
+
+		let sub_path = path
+			.strip_prefix(&location.path)
+			.map_err(|_| LocationError::PathNotFound(path.clone()))?;
+
+		if fs::metadata(path)
+			.await
+			.map_err(|e| LocationError::LocationPathFilesystemMetadataAccess(e, path.clone()))?
+			.is_dir()
+		{
+			scan_location_sub_path(library, location.clone(), sub_path).await?;
+		} else {
+			// files are picked up by a light scan of their directory, which only needs to happen once
+			let parent = sub_path.parent().unwrap_or_else(|| Path::new(""));
+			if scanned_directories.insert((location.id, parent.to_path_buf())) {
+				light_scan_location(library, location.clone(), parent).await?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
 pub async fn relink_location(
 	library: &Library,
 	location_path: impl AsRef<Path>,
@@ -613,6 +708,7 @@ impl From<location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			encrypt_at_rest: data.encrypt_at_rest,
 			date_created: data.date_created,
 			node: None,
 			file_paths: None,
@@ -635,6 +731,7 @@ impl From<&location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			encrypt_at_rest: data.encrypt_at_rest,
 			date_created: data.date_created,
 			node: None,
 			file_paths: None,
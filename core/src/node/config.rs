@@ -1,9 +1,17 @@
 use rspc::Type;
-use sd_p2p::Keypair;
+use sd_crypto::{
+	keys::keyring::{FileKeyring, KeyringInterface, StaticKeyring},
+	types::SecretKeyString,
+	Protected,
+};
+use sd_p2p::{BandwidthLimits, Keypair, PeerId};
 use serde::{Deserialize, Serialize};
 use std::{
+	collections::HashMap,
+	env,
 	fs::File,
-	io::{self, BufReader, Seek, Write},
+	io::{self, BufRead, BufReader, Seek, Write},
+	net::SocketAddr,
 	path::{Path, PathBuf},
 	sync::Arc,
 };
@@ -14,6 +22,9 @@ use uuid::Uuid;
 /// NODE_STATE_CONFIG_NAME is the name of the file which stores the NodeState
 pub const NODE_STATE_CONFIG_NAME: &str = "node_state.sdconfig";
 
+/// KEYRING_FILE_NAME is the default name of the encrypted-file keyring, if that backend is in use.
+pub const KEYRING_FILE_NAME: &str = "keyring.sdkeyring";
+
 /// ConfigMetadata is a part of node configuration that is loaded before the main configuration and contains information about the schema of the config.
 /// This allows us to migrate breaking changes to the config format between Spacedrive releases.
 #[derive(Debug, Serialize, Deserialize, Clone, Type)]
@@ -48,6 +59,99 @@ pub struct NodeConfig {
 	// TODO: These will probs be replaced by your Spacedrive account in the near future.
 	pub p2p_email: Option<String>,
 	pub p2p_img_url: Option<String>,
+	/// Addresses of peers which can't be discovered on the local network (such as on another subnet or over a VPN). These are always connected to.
+	#[serde(default)]
+	pub p2p_manual_peers: Vec<SocketAddr>,
+	/// The last known addresses of the peers paired with this node's libraries, so they can be reconnected to when they aren't discoverable.
+	#[serde(default)]
+	#[specta(skip)]
+	pub p2p_known_peers: HashMap<PeerId, Vec<SocketAddr>>,
+	/// The hosts this node paired with for libraries it didn't have yet. They're added to each library once it's loaded.
+	#[serde(default)]
+	#[specta(skip)]
+	pub p2p_pending_pairings: HashMap<Uuid, Vec<PeerId>>,
+	/// How fast data is sent to and received from peers, so transfers don't saturate the network. This is unlimited by default.
+	#[serde(default)]
+	pub p2p_bandwidth_limits: BandwidthLimits,
+	/// The size in megabytes the thumbnail cache (including transcoded media) is kept within when it's cleaned up, with the least recently used thumbnails removed first. This is unlimited by default.
+	#[serde(default)]
+	pub thumbnail_cache_max_mb: Option<u32>,
+	/// The keyring backend used for storing the secret keys of this node's libraries.
+	#[serde(default)]
+	pub keyring: KeyringConfig,
+}
+
+/// KeyringConfig selects where the secret keys of a node's libraries are stored.
+///
+/// Headless nodes (e.g. `apps/server`) usually don't have an OS keyring available, so they should use one of the other backends.
+#[derive(Debug, Serialize, Deserialize, Clone, Type, Default)]
+#[serde(tag = "backend", rename_all = "camelCase")]
+pub enum KeyringConfig {
+	/// The Apple keychain or the Linux Secret Service. These are only available when the core is built with the `os-keyrings` feature.
+	#[default]
+	Os,
+	/// An encrypted file, unlocked with a password from the given source. The path defaults to `keyring.sdkeyring` within the data directory.
+	File {
+		path: Option<PathBuf>,
+		password: SecretSource,
+	},
+	/// A secret key from the given source that is used for every library. It is never persisted.
+	Static { secret_key: SecretSource },
+}
+
+/// SecretSource is where a secret is read from when the node starts.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+#[serde(tag = "source", rename_all = "camelCase")]
+pub enum SecretSource {
+	Environment { variable: String },
+	Stdin,
+}
+
+impl SecretSource {
+	/// Every backend fails to start if its secret isn't provided, rather than starting with a keyring that can't unlock anything.
+	fn read(&self) -> Result<Protected<String>, NodeConfigError> {
+		let secret = match self {
+			Self::Environment { variable } => env::var(variable).unwrap_or_default(),
+			Self::Stdin => {
+				let mut line = String::new();
+				io::stdin().lock().read_line(&mut line)?;
+				line.trim_end_matches(['\r', '\n']).to_string()
+			}
+		};
+
+		if secret.trim().is_empty() {
+			return Err(NodeConfigError::MissingSecret(match self {
+				Self::Environment { variable } => format!("the '{variable}' environment variable"),
+				Self::Stdin => "stdin".into(),
+			}));
+		}
+
+		Ok(Protected::new(secret))
+	}
+}
+
+impl KeyringConfig {
+	/// build will construct the configured keyring backend. `None` is returned if the OS keyring isn't available on this platform.
+	pub(crate) fn build(
+		&self,
+		data_directory: &Path,
+	) -> Result<Option<KeyringInterface>, NodeConfigError> {
+		match self {
+			Self::Os => Ok(KeyringInterface::new().ok()),
+			Self::File { path, password } => {
+				let path = path
+					.clone()
+					.unwrap_or_else(|| data_directory.join(KEYRING_FILE_NAME));
+
+				Ok(Some(KeyringInterface::with_backend(Box::new(
+					FileKeyring::open(path, password.read()?.into())?,
+				))))
+			}
+			Self::Static { secret_key } => Ok(Some(KeyringInterface::with_backend(Box::new(
+				StaticKeyring::new(Some(SecretKeyString(secret_key.read()?))),
+			)))),
+		}
+	}
 }
 
 // TODO: Probs remove this in future. It's just to prevent breaking changes.
@@ -63,6 +167,10 @@ pub enum NodeConfigError {
 	Json(#[from] serde_json::Error),
 	#[error("error migrating the config file")]
 	Migration(String),
+	#[error("the secret for the keyring wasn't provided by {0}")]
+	MissingSecret(String),
+	#[error("error initialising the keyring: {0}")]
+	Keyring(#[from] sd_crypto::Error),
 }
 
 impl NodeConfig {
@@ -84,6 +192,12 @@ impl NodeConfig {
 			keypair: Keypair::generate(),
 			p2p_email: None,
 			p2p_img_url: None,
+			p2p_manual_peers: Vec::new(),
+			p2p_known_peers: HashMap::new(),
+			p2p_pending_pairings: HashMap::new(),
+			p2p_bandwidth_limits: BandwidthLimits::default(),
+			thumbnail_cache_max_mb: None,
+			keyring: KeyringConfig::default(),
 		}
 	}
 }
@@ -110,7 +224,6 @@ impl NodeConfigManager {
 	}
 
 	/// write allows the user to update the configuration. This is done in a closure while a Mutex lock is held so that the user can't cause a race condition if the config were to be updated in multiple parts of the app at the same time.
-	#[allow(unused)]
 	pub(crate) async fn write<F: FnOnce(RwLockWriteGuard<NodeConfig>)>(
 		&self,
 		mutation_fn: F,
@@ -9,20 +9,20 @@ use crate::{
 	sync::SyncManager,
 };
 
-use sd_file_ext::{extensions::Extension, kind::ObjectKind};
+use sd_file_ext::{extensions::Extension, kind::ObjectKind, magic::DetectedType};
 use sd_sync::CRDTOperation;
 
 use futures::future::join_all;
 use int_enum::IntEnum;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::{
 	collections::{HashMap, HashSet},
 	path::{Path, PathBuf},
 };
 use thiserror::Error;
 use tokio::{fs, io};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 pub mod file_identifier_job;
@@ -42,6 +42,7 @@ pub struct FileMetadata {
 	pub cas_id: String,
 	pub kind: ObjectKind,
 	pub fs_metadata: std::fs::Metadata,
+	pub detected_type: DetectedType,
 }
 
 impl FileMetadata {
@@ -65,6 +66,14 @@ impl FileMetadata {
 			.map(Into::into)
 			.unwrap_or(ObjectKind::Unknown);
 
+		let detected_type = Extension::detect(&path).await?;
+		if detected_type.extension_mismatch {
+			warn!(
+				"The extension of {path:?} doesn't match its contents, which are {:?}",
+				detected_type.verified_extension
+			);
+		}
+
 		let cas_id = generate_cas_id(&path, fs_metadata.len()).await?;
 
 		info!("Analyzed file: {path:?} {cas_id:?} {kind:?}");
@@ -73,8 +82,36 @@ impl FileMetadata {
 			cas_id,
 			kind,
 			fs_metadata,
+			detected_type,
 		})
 	}
+
+	/// The file's type detected from its magic bytes, as the sync field and query param which set each part of it on the file path.
+	pub fn detected_type_params(&self) -> Vec<((&'static str, Value), file_path::SetParam)> {
+		let DetectedType {
+			verified_extension,
+			mime_type,
+			extension_mismatch,
+		} = self.detected_type;
+
+		let verified_extension = verified_extension.map(|ext| ext.to_string());
+		let mime_type = mime_type.map(ToString::to_string);
+
+		vec![
+			(
+				("mime_type", json!(mime_type)),
+				file_path::mime_type::set(mime_type),
+			),
+			(
+				("verified_extension", json!(verified_extension)),
+				file_path::verified_extension::set(verified_extension),
+			),
+			(
+				("extension_mismatch", json!(extension_mismatch)),
+				file_path::extension_mismatch::set(extension_mismatch),
+			),
+		]
+	}
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -123,32 +160,42 @@ async fn identifier_job_step(
 	})
 	.collect::<HashMap<i32, _>>();
 
-	// Assign cas_id to each file path
-	sync.write_ops(
-		db,
-		file_path_metas
-			.iter()
-			.map(|(id, (meta, _))| {
-				(
-					sync.shared_update(
-						sync::file_path::SyncId {
-							id: *id,
-							location: sync::location::SyncId {
-								pub_id: location.pub_id.clone(),
+	// Assign cas_id and the type detected from the magic bytes to each file path
+	let (sync_ops, db_ops): (Vec<_>, Vec<_>) = file_path_metas
+		.iter()
+		.map(|(id, (meta, _))| {
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [(
+				("cas_id", json!(&meta.cas_id)),
+				file_path::cas_id::set(Some(meta.cas_id.clone())),
+			)]
+			.into_iter()
+			.chain(meta.detected_type_params())
+			.unzip();
+
+			(
+				sync_params
+					.into_iter()
+					.map(|(field, value)| {
+						sync.shared_update(
+							sync::file_path::SyncId {
+								id: *id,
+								location: sync::location::SyncId {
+									pub_id: location.pub_id.clone(),
+								},
 							},
-						},
-						"cas_id",
-						json!(&meta.cas_id),
-					),
-					db.file_path().update(
-						file_path::location_id_id(location.id, *id),
-						vec![file_path::cas_id::set(Some(meta.cas_id.clone()))],
-					),
-				)
-			})
-			.unzip::<_, _, _, Vec<_>>(),
-	)
-	.await?;
+							field,
+							value,
+						)
+					})
+					.collect::<Vec<_>>(),
+				db.file_path()
+					.update(file_path::location_id_id(location.id, *id), db_params),
+			)
+		})
+		.unzip();
+
+	sync.write_ops(db, (sync_ops.into_iter().flatten().collect(), db_ops))
+		.await?;
 
 	let unique_cas_ids = file_path_metas
 		.values()
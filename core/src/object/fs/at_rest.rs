@@ -0,0 +1,513 @@
+use crate::{
+	custom_uri, invalidate_query,
+	job::{
+		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::{
+		ensure_sub_path_is_in_location, get_inode_and_device_from_path, MaterializedPath,
+	},
+	prisma::{file_path, key, location},
+	sync,
+};
+
+use std::{
+	ffi::OsString,
+	hash::Hash,
+	io::SeekFrom,
+	path::{Path, PathBuf},
+};
+
+use sd_crypto::{
+	crypto::{BlockDecryptor, Decryptor, Encryptor},
+	header::{file::FileHeader, keyslot::Keyslot},
+	keys::keymanager::KeyManager,
+	primitives::{AEAD_TAG_LEN, BLOCK_LEN, LATEST_FILE_HEADER, LATEST_KEYSLOT},
+	types::Key,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+	fs::File,
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::{error, trace, warn};
+use uuid::Uuid;
+
+use super::BYTES_EXT;
+
+/// The extension (as stored on `file_path`s) of files that have been encrypted at rest.
+pub const AT_REST_EXTENSION: &str = "bytes";
+
+pub struct AtRestEncryptorJob {}
+
+/// `AtRestEncryptorJobInit` encrypts every identified file within a location that has `encrypt_at_rest` enabled.
+///
+/// The scope may be narrowed to a `sub_path` (for location rescans), or to a single `file_path_id` (for the watcher).
+///
+/// Files are encrypted with the library's default key, and replaced with `.bytes` files.
+/// The existing `file_path` is updated in place, so the object (and everything attached to it, such as tags) is kept.
+#[derive(Serialize, Deserialize, Hash)]
+pub struct AtRestEncryptorJobInit {
+	pub location_id: i32,
+	pub sub_path: Option<PathBuf>,
+	pub file_path_id: Option<i32>,
+}
+
+impl JobInitData for AtRestEncryptorJobInit {
+	type Job = AtRestEncryptorJob;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AtRestEncryptorJobState {
+	location_path: PathBuf,
+	location_pub_id: Vec<u8>,
+	key_uuid: Uuid,
+	key_id: Option<i32>,
+	encrypted_count: usize,
+	encrypted_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AtRestEncryptorJobStep {
+	file_path_id: i32,
+	materialized_path: String,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for AtRestEncryptorJob {
+	type Init = AtRestEncryptorJobInit;
+	type Data = AtRestEncryptorJobState;
+	type Step = AtRestEncryptorJobStep;
+
+	const NAME: &'static str = "at_rest_encryptor";
+
+	fn new() -> Self {
+		Self {}
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let Library {
+			db, key_manager, ..
+		} = &ctx.library;
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(state.init.location_id))
+			.exec()
+			.await?
+			.ok_or(JobError::MissingData {
+				value: String::from("location which matches location_id"),
+			})?;
+
+		if !location.encrypt_at_rest {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Location doesn't have encryption at rest enabled".to_string(),
+			});
+		}
+
+		// files are left as they are until the key manager is unlocked with a default key, and the next scan will pick them up
+		let key_uuid = match key_manager.get_default().await {
+			Ok(key_uuid) => key_uuid,
+			Err(e) => {
+				return Err(JobError::EarlyFinish {
+					name: <Self as StatefulJob>::NAME.to_string(),
+					reason: format!("No default key is available: {e}"),
+				})
+			}
+		};
+
+		if key_manager.access_keymount(key_uuid).await.is_err() {
+			key_manager.mount(key_uuid).await?;
+		}
+
+		let key_id = db
+			.key()
+			.find_unique(key::uuid::equals(key_uuid.to_string()))
+			.select(key::select!({ id }))
+			.exec()
+			.await?
+			.map(|k| k.id);
+
+		let location_path = PathBuf::from(&location.path);
+
+		let mut params = vec![
+			file_path::location_id::equals(location.id),
+			file_path::is_dir::equals(false),
+			file_path::extension::not(AT_REST_EXTENSION.to_string()),
+			// only identified files, so the object is kept once the content changes
+			file_path::object_id::not(None),
+		];
+
+		if let Some(file_path_id) = state.init.file_path_id {
+			params.push(file_path::id::equals(file_path_id));
+		}
+
+		if let Some(ref sub_path) = state.init.sub_path {
+			let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+				.await
+				.map_err(|_| JobError::Path)?;
+
+			params.push(file_path::materialized_path::starts_with(
+				MaterializedPath::new(location.id, &location_path, full_path, true)
+					.map_err(|_| JobError::Path)?
+					.into(),
+			));
+		}
+
+		state.steps = db
+			.file_path()
+			.find_many(params)
+			.select(file_path::select!({ id materialized_path }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| AtRestEncryptorJobStep {
+				file_path_id: file_path.id,
+				materialized_path: file_path.materialized_path,
+			})
+			.collect();
+
+		state.data = Some(AtRestEncryptorJobState {
+			location_path,
+			location_pub_id: location.pub_id,
+			key_uuid,
+			key_id,
+			encrypted_count: 0,
+			encrypted_bytes: 0,
+		});
+
+		if state.steps.is_empty() {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Found no files to encrypt".to_string(),
+			});
+		}
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let location_id = state.init.location_id;
+		let Library {
+			db,
+			sync,
+			key_manager,
+			..
+		} = &ctx.library;
+
+		let data = state.data.as_mut().ok_or(JobError::MissingData {
+			value: String::from("job state"),
+		})?;
+
+		let full_path = data.location_path.join(&MaterializedPath::from((
+			location_id,
+			&step.materialized_path,
+		)));
+
+		let output_path = {
+			let mut path = OsString::from(full_path.as_os_str());
+			path.push(BYTES_EXT);
+			PathBuf::from(path)
+		};
+
+		if tokio::fs::metadata(&output_path).await.is_ok() {
+			warn!(
+				"skipping encryption at rest for {}, as {} already exists",
+				full_path.display(),
+				output_path.display()
+			);
+		} else {
+			// the watcher would otherwise see these as a brand new file, and a removed one
+			let mut ignore_guards = Vec::with_capacity(2);
+			for path in [&full_path, &output_path] {
+				ignore_guards.push(
+					ctx.library
+						.location_manager()
+						.temporary_ignore_events_for_path(location_id, ctx.library.clone(), path)
+						.await
+						.map_or_else(
+							|e| {
+								error!(
+									"Failed to make location manager ignore the path {}; Error: {e:#?}",
+									path.display()
+								);
+								None
+							},
+							Some,
+						),
+				);
+			}
+
+			if let Err(e) = encrypt_file(key_manager, data.key_uuid, &full_path, &output_path).await
+			{
+				tokio::fs::remove_file(&output_path).await.ok();
+				return Err(e);
+			}
+
+			let size = tokio::fs::metadata(&output_path).await?.len();
+			let (inode, device) = get_inode_and_device_from_path(&output_path)
+				.await
+				.map_err(|_| JobError::Path)?;
+
+			let name = full_path
+				.file_name()
+				.and_then(|name| name.to_str())
+				.ok_or(JobError::OsStr)?
+				.to_string();
+			let materialized_path = format!("{}{BYTES_EXT}", step.materialized_path);
+
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+				(
+					("materialized_path", json!(materialized_path)),
+					file_path::materialized_path::set(materialized_path.clone()),
+				),
+				(("name", json!(name)), file_path::name::set(name.clone())),
+				(
+					("extension", json!(AT_REST_EXTENSION)),
+					file_path::extension::set(AT_REST_EXTENSION.to_string()),
+				),
+				(
+					("size_in_bytes", json!(size.to_string())),
+					file_path::size_in_bytes::set(size.to_string()),
+				),
+				(
+					("inode", json!(inode.to_le_bytes())),
+					file_path::inode::set(inode.to_le_bytes().into()),
+				),
+				(
+					("device", json!(device.to_le_bytes())),
+					file_path::device::set(device.to_le_bytes().into()),
+				),
+			]
+			.into_iter()
+			.unzip();
+
+			sync.write_ops(
+				db,
+				(
+					sync_params
+						.into_iter()
+						.map(|(field, value)| {
+							sync.shared_update(
+								sync::file_path::SyncId {
+									location: sync::location::SyncId {
+										pub_id: data.location_pub_id.clone(),
+									},
+									id: step.file_path_id,
+								},
+								field,
+								value,
+							)
+						})
+						.collect(),
+					db.file_path().update(
+						file_path::location_id_id(location_id, step.file_path_id),
+						db_params,
+					),
+				),
+			)
+			.await?;
+
+			// the key is local to this library, so it isn't synced
+			db.file_path()
+				.update(
+					file_path::location_id_id(location_id, step.file_path_id),
+					vec![file_path::key_id::set(data.key_id)],
+				)
+				.exec()
+				.await?;
+
+			trace!("Encrypted at rest: {}", full_path.display());
+
+			tokio::fs::remove_file(&full_path).await?;
+
+			custom_uri::evict_file_metadata(ctx.library.id, location_id, step.file_path_id);
+
+			data.encrypted_count += 1;
+			data.encrypted_bytes += size;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		invalidate_query!(ctx.library, "locations.getExplorerData");
+
+		Ok(state.data.as_ref().map(|data| {
+			json!({
+				"location_id": state.init.location_id,
+				"encrypted_count": data.encrypted_count,
+				"encrypted_bytes": data.encrypted_bytes.to_string(),
+			})
+		}))
+	}
+}
+
+async fn encrypt_file(
+	key_manager: &KeyManager,
+	key_uuid: Uuid,
+	input_path: &Path,
+	output_path: &Path,
+) -> Result<(), JobError> {
+	let user_key = key_manager.access_keymount(key_uuid).await?.hashed_key;
+	let user_key_details = key_manager.access_keystore(key_uuid).await?;
+
+	let master_key = Key::generate();
+
+	let header = FileHeader::new(
+		LATEST_FILE_HEADER,
+		user_key_details.algorithm,
+		vec![
+			Keyslot::new(
+				LATEST_KEYSLOT,
+				user_key_details.algorithm,
+				user_key_details.hashing_algorithm,
+				user_key_details.content_salt,
+				user_key,
+				master_key.clone(),
+			)
+			.await?,
+		],
+	)?;
+
+	let mut reader = File::open(input_path).await?;
+	let mut writer = File::create(output_path).await?;
+
+	header.write(&mut writer).await?;
+
+	Encryptor::new(master_key, header.nonce, header.algorithm)?
+		.encrypt_streams(&mut reader, &mut writer, &header.generate_aad())
+		.await?;
+
+	writer.sync_all().await?;
+
+	Ok(())
+}
+
+/// This decrypts a file that was encrypted at rest, with whichever of the library's mounted keys is able to.
+///
+/// It's used for transparently serving (via the custom URI) and exporting these files.
+pub async fn decrypt_at_rest<W>(
+	key_manager: &KeyManager,
+	path: impl AsRef<Path>,
+	writer: &mut W,
+) -> Result<(), sd_crypto::Error>
+where
+	W: AsyncWriteExt + Unpin + Send,
+{
+	let mut reader = File::open(path).await?;
+
+	let (header, aad) = FileHeader::from_reader(&mut reader).await?;
+
+	let master_key = header
+		.decrypt_master_key_from_prehashed(key_manager.enumerate_hashed_keys())
+		.await?;
+
+	Decryptor::new(master_key, header.nonce, header.algorithm)?
+		.decrypt_streams(&mut reader, writer, &aad)
+		.await
+}
+
+/// Random access to the contents of a file that was encrypted at rest.
+///
+/// Only the blocks which cover a requested range are read and decrypted, so seeking within a large file (such as a video) stays cheap.
+pub struct AtRestReader {
+	file: File,
+	body_offset: u64,
+	size: u64,
+	aad: Vec<u8>,
+	decryptor: BlockDecryptor,
+}
+
+impl AtRestReader {
+	/// This reads the header of the file, and decrypts its master key with whichever of the library's mounted keys is able to.
+	pub async fn new(key_manager: &KeyManager, mut file: File) -> Result<Self, sd_crypto::Error> {
+		file.rewind().await?;
+
+		let (header, aad) = FileHeader::from_reader(&mut file).await?;
+		let body_offset = file.stream_position().await?;
+
+		let size =
+			BlockDecryptor::plaintext_len(file.metadata().await?.len().saturating_sub(body_offset))
+				.ok_or(sd_crypto::Error::Decrypt)?;
+
+		let master_key = header
+			.decrypt_master_key_from_prehashed(key_manager.enumerate_hashed_keys())
+			.await?;
+
+		Ok(Self {
+			file,
+			body_offset,
+			size,
+			aad,
+			decryptor: BlockDecryptor::new(master_key, header.nonce, header.algorithm)?,
+		})
+	}
+
+	/// The size of the decrypted contents.
+	pub const fn size(&self) -> u64 {
+		self.size
+	}
+
+	/// This reads up to `length` bytes of the decrypted contents, from `start`.
+	pub async fn read(&mut self, start: u64, length: u64) -> Result<Vec<u8>, sd_crypto::Error> {
+		let end = start.saturating_add(length).min(self.size);
+		let last_index = self.size / BLOCK_LEN as u64;
+
+		let mut buf = Vec::with_capacity(end.saturating_sub(start) as usize);
+		let mut block = vec![0u8; BLOCK_LEN + AEAD_TAG_LEN];
+
+		let mut position = start;
+		while position < end {
+			let index = position / BLOCK_LEN as u64;
+			let block_start = index * BLOCK_LEN as u64;
+			let last_block = index == last_index;
+
+			let block_len = if last_block {
+				(self.size - block_start) as usize + AEAD_TAG_LEN
+			} else {
+				BLOCK_LEN + AEAD_TAG_LEN
+			};
+
+			self.file
+				.seek(SeekFrom::Start(
+					self.body_offset + index * (BLOCK_LEN + AEAD_TAG_LEN) as u64,
+				))
+				.await?;
+			self.file.read_exact(&mut block[..block_len]).await?;
+
+			let plaintext = self.decryptor.decrypt_block(
+				index as u32,
+				last_block,
+				&block[..block_len],
+				&self.aad,
+			)?;
+
+			let from = (position - block_start) as usize;
+			let to = ((end - block_start) as usize).min(plaintext.len());
+			buf.extend_from_slice(&plaintext[from..to]);
+
+			position = block_start + to as u64;
+		}
+
+		Ok(buf)
+	}
+}
+
+/// This returns the path that an at-rest encrypted file should be exported to (i.e. without the `.bytes` extension).
+pub fn decrypted_path(path: &Path) -> Option<PathBuf> {
+	(path.extension()? == AT_REST_EXTENSION).then(|| path.with_extension(""))
+}
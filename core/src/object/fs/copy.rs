@@ -3,15 +3,20 @@ use crate::{
 	job::{
 		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
 	},
+	prisma::location,
 };
 
 use std::{hash::Hash, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tokio::{fs::File, io::AsyncWriteExt};
 use tracing::trace;
 
-use super::{context_menu_fs_info, get_path_from_location_id, osstr_to_string, FsInfo};
+use super::{
+	at_rest::{decrypt_at_rest, decrypted_path, AT_REST_EXTENSION},
+	context_menu_fs_info, get_path_from_location_id, osstr_to_string, FsInfo,
+};
 
 pub struct FileCopierJob {}
 
@@ -19,6 +24,9 @@ pub struct FileCopierJob {}
 pub struct FileCopierJobState {
 	pub target_path: PathBuf, // target dir prefix too
 	pub source_fs_info: FsInfo,
+	/// Files that are encrypted at rest are decrypted when they're copied to a location that isn't.
+	#[serde(default)]
+	pub decrypt_at_rest: bool,
 }
 
 #[derive(Serialize, Deserialize, Hash, Type)]
@@ -74,6 +82,27 @@ impl StatefulJob for FileCopierJob {
 		)
 		.await?;
 
+		let db = &ctx.library.db;
+		let encrypt_at_rest = |location_id| async move {
+			db.location()
+				.find_unique(location::id::equals(location_id))
+				.select(location::select!({ encrypt_at_rest }))
+				.exec()
+				.await
+				.map(|location| location.map_or(false, |l| l.encrypt_at_rest))
+		};
+
+		let decrypt_at_rest = encrypt_at_rest(state.init.source_location_id).await?
+			&& !encrypt_at_rest(state.init.target_location_id).await?;
+
+		// the copy of an encrypted file is named after the original file
+		let source_name_path = if decrypt_at_rest && !source_fs_info.path_data.is_dir {
+			decrypted_path(&source_fs_info.fs_path)
+				.unwrap_or_else(|| source_fs_info.fs_path.clone())
+		} else {
+			source_fs_info.fs_path.clone()
+		};
+
 		let mut full_target_path =
 			get_path_from_location_id(&ctx.library.db, state.init.target_location_id).await?;
 
@@ -84,7 +113,7 @@ impl StatefulJob for FileCopierJob {
 		// if no suffix has been selected, just use the file name
 		// if a suffix is provided and it's a directory, use the directory name + suffix
 		// if a suffix is provided and it's a file, use the (file name + suffix).extension
-		let file_name = osstr_to_string(source_fs_info.fs_path.file_name())?;
+		let file_name = osstr_to_string(source_name_path.file_name())?;
 
 		let target_file_name = state.init.target_file_name_suffix.as_ref().map_or_else(
 			|| Ok::<_, JobError>(file_name.clone()),
@@ -92,8 +121,8 @@ impl StatefulJob for FileCopierJob {
 				Ok(if source_fs_info.path_data.is_dir {
 					format!("{file_name}{suffix}")
 				} else {
-					osstr_to_string(source_fs_info.fs_path.file_stem())?
-						+ suffix + &source_fs_info.fs_path.extension().map_or_else(
+					osstr_to_string(source_name_path.file_stem())?
+						+ suffix + &source_name_path.extension().map_or_else(
 						|| Ok(String::new()),
 						|ext| ext.to_str().map(|e| format!(".{e}")).ok_or(JobError::OsStr),
 					)?
@@ -106,6 +135,7 @@ impl StatefulJob for FileCopierJob {
 		state.data = Some(FileCopierJobState {
 			target_path: full_target_path,
 			source_fs_info: source_fs_info.clone(),
+			decrypt_at_rest,
 		});
 
 		state.steps = [source_fs_info.into()].into_iter().collect();
@@ -138,9 +168,31 @@ impl StatefulJob for FileCopierJob {
 					);
 				}
 
-				trace!("Copying from {:?} to {:?}", path, target_path);
+				if job_state.decrypt_at_rest
+					&& path
+						.extension()
+						.map_or(false, |ext| ext == AT_REST_EXTENSION)
+				{
+					if job_state.source_fs_info.path_data.is_dir {
+						target_path = decrypted_path(&target_path).ok_or(JobError::Path)?;
+					}
+
+					trace!("Decrypting from {:?} to {:?}", path, target_path);
+
+					let mut writer = File::create(&target_path).await?;
+					if let Err(e) =
+						decrypt_at_rest(&ctx.library.key_manager, &path, &mut writer).await
+					{
+						drop(writer);
+						tokio::fs::remove_file(&target_path).await.ok();
+						return Err(e.into());
+					}
+					writer.flush().await?;
+				} else {
+					trace!("Copying from {:?} to {:?}", path, target_path);
 
-				tokio::fs::copy(&path, &target_path).await?;
+					tokio::fs::copy(&path, &target_path).await?;
+				}
 			}
 			FileCopierJobStep::Directory { path } => {
 				// if this is the very first path, create the target dir
@@ -3,17 +3,31 @@ use crate::{
 	job::{
 		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
 	},
+	location::file_path_helper::MaterializedPath,
+	object::preview::{
+		thumbstrip_path, video_preview_path, ThumbnailSize, THUMBNAIL_CACHE_DIR_NAME,
+	},
+	prisma::{file_path, object},
+	volume::{get_volumes, Volume},
 };
 
-use std::{hash::Hash, path::PathBuf};
+use std::{
+	collections::{hash_map::Entry, HashMap, HashSet},
+	hash::Hash,
+	path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tokio::fs::OpenOptions;
 use tracing::{trace, warn};
+use uuid::Uuid;
+
+use super::{context_menu_fs_info, get_path_from_location_id, FsInfo};
 
-use super::{context_menu_fs_info, FsInfo};
+/// Filesystems that never overwrite data in place, so extra passes only ever reach freshly allocated blocks.
+const COPY_ON_WRITE_FILESYSTEMS: [&str; 6] = ["apfs", "btrfs", "zfs", "bcachefs", "refs", "f2fs"];
 
 pub struct FileEraserJob {}
 
@@ -25,12 +39,31 @@ pub struct FileEraserJobInit {
 	#[specta(type = String)]
 	#[serde_as(as = "DisplayFromStr")]
 	pub passes: usize,
+	/// Also erase every other `file_path` of the same object(s), along with their cached thumbnails.
+	#[serde(default)]
+	pub include_copies: bool,
+	/// Read back the final pass of every file, and record whether it matched what was written.
+	#[serde(default)]
+	pub verify: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum FileEraserJobStep {
-	Directory { path: PathBuf },
-	File { path: PathBuf },
+	Directory {
+		path: PathBuf,
+	},
+	File {
+		path: PathBuf,
+	},
+	/// Another `file_path` of an erased object, whose row is removed once it's been erased.
+	Copy {
+		path: PathBuf,
+		location_id: i32,
+		path_id: i32,
+	},
+	Thumbnail {
+		path: PathBuf,
+	},
 }
 
 impl From<FsInfo> for FileEraserJobStep {
@@ -51,10 +84,133 @@ impl JobInitData for FileEraserJobInit {
 	type Job = FileEraserJob;
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileEraserJobState {
+	info: FsInfo,
+	volumes: Vec<Volume>,
+	report: EraseReport,
+}
+
+/// How a file was erased. This is decided per-file, based on the volume that it resides on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EraseStrategy {
+	/// The file was overwritten with the requested amount of passes.
+	Overwrite,
+	/// The file is on flash storage, where wear-levelling means extra passes only add wear.
+	SolidState,
+	/// The file is on a copy-on-write filesystem, where extra passes never reach the original blocks.
+	CopyOnWrite,
+}
+
+impl EraseStrategy {
+	fn reason(&self) -> Option<&'static str> {
+		match self {
+			Self::Overwrite => None,
+			Self::SolidState => Some("it's on a solid-state drive"),
+			Self::CopyOnWrite => Some("it's on a copy-on-write filesystem"),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErasedItemKind {
+	File,
+	Copy,
+	Thumbnail,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErasedItem {
+	pub path: PathBuf,
+	pub kind: ErasedItemKind,
+	pub size: u64,
+	pub strategy: EraseStrategy,
+	/// The amount of passes that were actually made, which may be fewer than requested (see [`EraseStrategy`]).
+	pub passes: usize,
+	/// Whether the final pass was read back and matched. This is `false` if verification wasn't requested.
+	pub verified: bool,
+}
+
+/// A record of everything that an erase job destroyed. This is returned as the job's metadata.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EraseReport {
+	pub requested_passes: usize,
+	/// The paths that were erased with fewer passes than requested.
+	pub reduced_passes: Vec<PathBuf>,
+	/// The paths whose final pass didn't read back as written.
+	pub failed_verification: Vec<PathBuf>,
+	pub items: Vec<ErasedItem>,
+	pub directories: Vec<PathBuf>,
+	pub total_bytes: u64,
+}
+
+fn strategy_for(path: &Path, volumes: &[Volume], passes: usize) -> (EraseStrategy, usize) {
+	// the most specific mount point wins, as volumes are often mounted within one another
+	let volume = volumes
+		.iter()
+		.filter(|volume| path.starts_with(&volume.mount_point))
+		.max_by_key(|volume| volume.mount_point.len());
+
+	let Some(volume) = volume else {
+		return (EraseStrategy::Overwrite, passes);
+	};
+
+	let is_copy_on_write = volume.file_system.as_ref().map_or(false, |fs| {
+		COPY_ON_WRITE_FILESYSTEMS.contains(&fs.to_lowercase().as_str())
+	});
+
+	if is_copy_on_write {
+		(EraseStrategy::CopyOnWrite, 1)
+	} else if volume.disk_type.as_deref() == Some("SSD") {
+		(EraseStrategy::SolidState, 1)
+	} else {
+		(EraseStrategy::Overwrite, passes)
+	}
+}
+
+/// This overwrites the file, truncates it, renames it to something random and then unlinks it.
+///
+/// The rename means the file's original name doesn't linger in the directory's metadata.
+///
+/// This returns the size of the file, and whether the final pass was verified.
+/// A file that fails verification is still truncated and unlinked, as it has been overwritten at least once.
+async fn erase_file(path: &Path, passes: usize, verify: bool) -> Result<(u64, bool), JobError> {
+	let mut file = OpenOptions::new().read(true).write(true).open(path).await?;
+	let file_len = file.metadata().await?.len();
+
+	let verified = if verify {
+		match sd_crypto::fs::erase::erase_and_verify(&mut file, file_len as usize, passes).await {
+			Ok(()) => true,
+			Err(sd_crypto::Error::EraseVerification) => {
+				warn!("Erase verification failed for file: {path:?}");
+				false
+			}
+			Err(e) => return Err(e.into()),
+		}
+	} else {
+		sd_crypto::fs::erase::erase(&mut file, file_len as usize, passes).await?;
+		false
+	};
+
+	file.sync_all().await?;
+	file.set_len(0).await?;
+	file.sync_all().await?;
+	drop(file);
+
+	let renamed = path.with_file_name(Uuid::new_v4().simple().to_string());
+	tokio::fs::rename(path, &renamed).await?;
+
+	trace!("Erasing file: {:?}", path);
+
+	tokio::fs::remove_file(&renamed).await?;
+
+	Ok((file_len, verified))
+}
+
 #[async_trait::async_trait]
 impl StatefulJob for FileEraserJob {
 	type Init = FileEraserJobInit;
-	type Data = FsInfo;
+	type Data = FileEraserJobState;
 	type Step = FileEraserJobStep;
 
 	const NAME: &'static str = "file_eraser";
@@ -64,13 +220,142 @@ impl StatefulJob for FileEraserJob {
 	}
 
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
-		let fs_info =
-			context_menu_fs_info(&ctx.library.db, state.init.location_id, state.init.path_id)
-				.await?;
+		// rejected before anything is done, rather than once the first file has been reached
+		if state.init.passes == 0 {
+			return Err(sd_crypto::Error::NoErasePasses.into());
+		}
+
+		let db = &ctx.library.db;
 
-		state.data = Some(fs_info.clone());
+		let fs_info = context_menu_fs_info(db, state.init.location_id, state.init.path_id).await?;
 
-		state.steps = [fs_info.into()].into_iter().collect();
+		state.steps = [fs_info.clone().into()].into_iter().collect();
+
+		if state.init.include_copies {
+			// every file_path within the selection, so we know which objects are being erased
+			let targets = if fs_info.path_data.is_dir {
+				db.file_path()
+					.find_many(vec![
+						file_path::location_id::equals(state.init.location_id),
+						file_path::materialized_path::starts_with(
+							fs_info.path_data.materialized_path.clone(),
+						),
+						file_path::is_dir::equals(false),
+					])
+					.select(file_path::select!({ id location_id object_id cas_id }))
+					.exec()
+					.await?
+					.into_iter()
+					.map(|fp| (fp.location_id, fp.id, fp.object_id, fp.cas_id))
+					.collect::<Vec<_>>()
+			} else {
+				vec![(
+					fs_info.path_data.location_id,
+					fs_info.path_data.id,
+					fs_info.path_data.object_id,
+					fs_info.path_data.cas_id.clone(),
+				)]
+			};
+
+			let target_ids = targets
+				.iter()
+				.map(|(location_id, id, _, _)| (*location_id, *id))
+				.collect::<HashSet<_>>();
+
+			let object_ids = targets
+				.iter()
+				.filter_map(|(_, _, object_id, _)| *object_id)
+				.collect::<HashSet<_>>()
+				.into_iter()
+				.collect::<Vec<_>>();
+
+			let mut cas_ids = targets
+				.into_iter()
+				.filter_map(|(_, _, _, cas_id)| cas_id)
+				.collect::<HashSet<_>>();
+
+			let copies = if object_ids.is_empty() {
+				vec![]
+			} else {
+				db.file_path()
+					.find_many(vec![
+						file_path::object_id::in_vec(object_ids),
+						file_path::is_dir::equals(false),
+					])
+					.select(file_path::select!({ id location_id materialized_path cas_id }))
+					.exec()
+					.await?
+			};
+
+			let mut location_paths = HashMap::new();
+
+			for copy in copies {
+				if target_ids.contains(&(copy.location_id, copy.id)) {
+					continue;
+				}
+
+				if let Entry::Vacant(entry) = location_paths.entry(copy.location_id) {
+					entry.insert(get_path_from_location_id(db, copy.location_id).await?);
+				}
+				let location_path = &location_paths[&copy.location_id];
+
+				state.steps.push_back(FileEraserJobStep::Copy {
+					path: location_path.join(&MaterializedPath::from((
+						copy.location_id,
+						&copy.materialized_path,
+					))),
+					location_id: copy.location_id,
+					path_id: copy.id,
+				});
+
+				if let Some(cas_id) = copy.cas_id {
+					cas_ids.insert(cas_id);
+				}
+			}
+
+			let thumbnail_dir = ctx
+				.library
+				.config()
+				.data_directory()
+				.join(THUMBNAIL_CACHE_DIR_NAME);
+
+			for cas_id in cas_ids {
+				for size in ThumbnailSize::ALL {
+					if ctx.library.thumbnail_exists(&cas_id, size).await? {
+						state.steps.push_back(FileEraserJobStep::Thumbnail {
+							path: size.path(&thumbnail_dir, &cas_id),
+						});
+					}
+				}
+
+				// videos may also have a film strip and an animated preview
+				for path in [
+					thumbstrip_path(&thumbnail_dir, &cas_id),
+					video_preview_path(&thumbnail_dir, &cas_id),
+				] {
+					if tokio::fs::metadata(&path).await.is_ok() {
+						state.steps.push_back(FileEraserJobStep::Thumbnail { path });
+					}
+				}
+			}
+		}
+
+		// `get_volumes()` scans every disk on the system, so keep it off of the async runtime
+		let volumes = tokio::task::spawn_blocking(get_volumes)
+			.await?
+			.unwrap_or_else(|e| {
+				warn!("unable to query volumes, falling back to the requested passes: {e:#?}");
+				vec![]
+			});
+
+		state.data = Some(FileEraserJobState {
+			info: fs_info,
+			volumes,
+			report: EraseReport {
+				requested_passes: state.init.passes,
+				..Default::default()
+			},
+		});
 
 		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 
@@ -82,29 +367,85 @@ impl StatefulJob for FileEraserJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		let step = &state.steps[0];
-
-		// need to handle stuff such as querying prisma for all paths of a file, and deleting all of those if requested (with a checkbox in the ui)
-		// maybe a files.countOccurances/and or files.getPath(location_id, path_id) to show how many of these files would be erased (and where?)
+		let step = state.steps[0].clone();
+		let data = state.data.as_mut().ok_or_else(|| JobError::MissingData {
+			value: String::from("job state"),
+		})?;
 
 		match step {
-			FileEraserJobStep::File { path } => {
-				let mut file = OpenOptions::new()
-					.read(true)
-					.write(true)
-					.open(&path)
-					.await?;
-				let file_len = file.metadata().await?.len();
-
-				sd_crypto::fs::erase::erase(&mut file, file_len as usize, state.init.passes)
-					.await?;
-				file.set_len(0).await?;
-				file.flush().await?;
-				drop(file);
-
-				trace!("Erasing file: {:?}", path);
-
-				tokio::fs::remove_file(&path).await?;
+			FileEraserJobStep::File { ref path }
+			| FileEraserJobStep::Copy { ref path, .. }
+			| FileEraserJobStep::Thumbnail { ref path } => {
+				let kind = match step {
+					FileEraserJobStep::Copy { .. } => ErasedItemKind::Copy,
+					FileEraserJobStep::Thumbnail { .. } => ErasedItemKind::Thumbnail,
+					_ => ErasedItemKind::File,
+				};
+
+				let (strategy, passes) = strategy_for(path, &data.volumes, state.init.passes);
+
+				if passes < state.init.passes {
+					if let Some(reason) = strategy.reason() {
+						ctx.progress(vec![JobReportUpdate::Message(format!(
+							"Erasing {} with {passes} pass(es) instead of {}, as {reason}",
+							path.display(),
+							state.init.passes
+						))]);
+					}
+					data.report.reduced_passes.push(path.clone());
+				}
+
+				let (size, verified) = erase_file(path, passes, state.init.verify).await?;
+
+				if state.init.verify && !verified {
+					data.report.failed_verification.push(path.clone());
+				}
+
+				if let FileEraserJobStep::Copy {
+					location_id,
+					path_id,
+					..
+				} = step
+				{
+					let db = &ctx.library.db;
+
+					let object_id = db
+						.file_path()
+						.find_unique(file_path::location_id_id(location_id, path_id))
+						.select(file_path::select!({ object_id }))
+						.exec()
+						.await?
+						.and_then(|file_path| file_path.object_id);
+
+					db.file_path()
+						.delete_many(vec![
+							file_path::location_id::equals(location_id),
+							file_path::id::equals(path_id),
+						])
+						.exec()
+						.await?;
+
+					if let Some(object_id) = object_id {
+						db.object()
+							.delete_many(vec![
+								object::id::equals(object_id),
+								// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
+								object::file_paths::none(vec![]),
+							])
+							.exec()
+							.await?;
+					}
+				}
+
+				data.report.total_bytes += size;
+				data.report.items.push(ErasedItem {
+					path: path.clone(),
+					kind,
+					size,
+					strategy,
+					passes,
+					verified,
+				});
 			}
 			FileEraserJobStep::Directory { path } => {
 				let mut dir = tokio::fs::read_dir(&path).await?;
@@ -118,6 +459,8 @@ impl StatefulJob for FileEraserJob {
 
 					ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 				}
+
+				data.report.directories.push(path);
 			}
 		};
 
@@ -128,16 +471,24 @@ impl StatefulJob for FileEraserJob {
 	}
 
 	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		if let Some(ref info) = state.data {
-			if info.path_data.is_dir {
-				tokio::fs::remove_dir_all(&info.fs_path).await?;
-			}
-		} else {
+		let Some(data) = &state.data else {
 			warn!("missing job state, unable to fully finalise erase job");
+			invalidate_query!(ctx.library, "locations.getExplorerData");
+			return Ok(Some(serde_json::to_value(&state.init)?));
+		};
+
+		if data.info.path_data.is_dir {
+			// every file within has been erased, so only the (empty) directory tree remains
+			let renamed = data
+				.info
+				.fs_path
+				.with_file_name(Uuid::new_v4().simple().to_string());
+			tokio::fs::rename(&data.info.fs_path, &renamed).await?;
+			tokio::fs::remove_dir_all(&renamed).await?;
 		}
 
 		invalidate_query!(ctx.library, "locations.getExplorerData");
 
-		Ok(Some(serde_json::to_value(&state.init)?))
+		Ok(Some(serde_json::to_value(&data.report)?))
 	}
 }
@@ -8,6 +8,7 @@ use std::{ffi::OsStr, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+pub mod at_rest;
 pub mod create;
 
 pub mod copy;
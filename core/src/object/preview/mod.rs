@@ -1,5 +1,12 @@
 mod media_data;
 mod thumbnail;
+#[cfg(feature = "ffmpeg")]
+mod transcode;
 
 pub use media_data::*;
 pub use thumbnail::*;
+#[cfg(feature = "ffmpeg")]
+pub use transcode::*;
+
+/// Where HLS playlists and segments are kept, within a directory for each file's `cas_id`.
+pub const TRANSCODE_CACHE_DIR_NAME: &str = "transcodes";
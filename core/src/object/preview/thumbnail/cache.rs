@@ -0,0 +1,331 @@
+//! The thumbnail directory is shared by every library on the node, so a thumbnail is only an orphan once no library has a file with its `cas_id`.
+//!
+//! When thumbnails are served their access time is updated, which is how the least recently used ones are found when the cache is over its size limit.
+//!
+//! The transcoded HLS playlists and segments are cached the same way, so they're cleaned up along with the thumbnails.
+
+use crate::{library::LibraryManager, prisma::file_path};
+
+use std::{
+	collections::HashSet,
+	fs::Metadata,
+	io,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use filetime::FileTime;
+use prisma_client_rust::QueryError;
+use tokio::fs;
+use tracing::trace;
+
+use super::{THUMBSTRIP_DIR_NAME, VIDEO_PREVIEW_DIR_NAME};
+
+/// A file in the thumbnail directory, which is a thumbnail of any size, a film strip or an animated preview, or a transcoded HLS file.
+#[derive(Debug)]
+pub(crate) struct CachedThumbnail {
+	pub path: PathBuf,
+	pub cas_id: String,
+	pub size: u64,
+	pub last_used: SystemTime,
+	/// Small thumbnails are generated by the thumbnailer job, while larger ones and transcodes are generated again when they're next requested.
+	pub is_small: bool,
+	/// Film strips and animated previews are only generated by the thumbnailer job, and their objects are flagged as having them,
+	/// so they're only removed once they're orphans.
+	pub evictable: bool,
+}
+
+/// Every `cas_id` which has a file in any of the node's libraries.
+pub(crate) async fn referenced_cas_ids(
+	library_manager: &LibraryManager,
+) -> Result<HashSet<String>, QueryError> {
+	let mut cas_ids = HashSet::new();
+
+	for library in library_manager.get_all_libraries().await {
+		cas_ids.extend(
+			library
+				.db
+				.file_path()
+				.find_many(vec![file_path::cas_id::not(None)])
+				.select(file_path::select!({ cas_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.filter_map(|file_path| file_path.cas_id),
+		);
+	}
+
+	Ok(cas_ids)
+}
+
+/// Every file in the thumbnail directory, including those of every size and kind within its subdirectories.
+pub(crate) async fn cached_thumbnails(thumbnail_dir: &Path) -> io::Result<Vec<CachedThumbnail>> {
+	let mut thumbnails = Vec::new();
+	let mut dirs = vec![(thumbnail_dir.to_path_buf(), true, true)];
+
+	while let Some((dir, is_root, evictable)) = dirs.pop() {
+		let mut entries = match fs::read_dir(&dir).await {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+
+		while let Some(entry) = entries.next_entry().await? {
+			let metadata = entry.metadata().await?;
+			let path = entry.path();
+
+			if metadata.is_dir() {
+				// the sizes and kinds only have a single level of directories
+				if is_root {
+					let evictable = !path.ends_with(THUMBSTRIP_DIR_NAME)
+						&& !path.ends_with(VIDEO_PREVIEW_DIR_NAME);
+					dirs.push((path, false, evictable));
+				}
+				continue;
+			}
+
+			let Some(cas_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+				continue;
+			};
+
+			thumbnails.push(CachedThumbnail {
+				cas_id: cas_id.to_string(),
+				size: metadata.len(),
+				last_used: last_used(&metadata),
+				is_small: is_root,
+				evictable,
+				path,
+			});
+		}
+	}
+
+	Ok(thumbnails)
+}
+
+/// Every HLS playlist and segment in the transcode directory, which are within a directory named after their file's `cas_id`.
+///
+/// Files which are still being written are skipped.
+pub(crate) async fn cached_transcodes(transcode_dir: &Path) -> io::Result<Vec<CachedThumbnail>> {
+	let mut transcodes = Vec::new();
+
+	let mut dirs = match fs::read_dir(transcode_dir).await {
+		Ok(dirs) => dirs,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(transcodes),
+		Err(e) => return Err(e),
+	};
+
+	while let Some(dir) = dirs.next_entry().await? {
+		let Ok(cas_id) = dir.file_name().into_string() else {
+			continue;
+		};
+		if !dir.file_type().await?.is_dir() {
+			continue;
+		}
+
+		let mut entries = fs::read_dir(dir.path()).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			if entry.file_name().to_string_lossy().starts_with('.') {
+				continue;
+			}
+
+			let metadata = entry.metadata().await?;
+			transcodes.push(CachedThumbnail {
+				path: entry.path(),
+				cas_id: cas_id.clone(),
+				size: metadata.len(),
+				last_used: last_used(&metadata),
+				is_small: false,
+				evictable: true,
+			});
+		}
+	}
+
+	Ok(transcodes)
+}
+
+/// Access times aren't updated by every file system, so when the file was written is used if it's later.
+fn last_used(metadata: &Metadata) -> SystemTime {
+	match (metadata.accessed(), metadata.modified()) {
+		(Ok(accessed), Ok(modified)) => accessed.max(modified),
+		(Ok(time), Err(_)) | (Err(_), Ok(time)) => time,
+		(Err(_), Err(_)) => SystemTime::UNIX_EPOCH,
+	}
+}
+
+/// The thumbnails to remove so the cache only holds referenced thumbnails, and fits within `max_bytes` if there's a limit.
+///
+/// Orphans are always removed, and then the least recently used thumbnails until the cache fits. Small thumbnails are removed last, as they
+/// aren't generated again until the thumbnailer job next runs. Film strips and previews are never evicted, so the cache can stay over
+/// its limit if they alone don't fit.
+pub(crate) fn thumbnails_to_remove(
+	thumbnails: Vec<CachedThumbnail>,
+	referenced_cas_ids: &HashSet<String>,
+	max_bytes: Option<u64>,
+	started_at: SystemTime,
+) -> Vec<CachedThumbnail> {
+	// thumbnails written since the referenced `cas_id`s were collected may be for files that have only just been identified
+	let (mut orphans, mut kept): (Vec<_>, Vec<_>) = thumbnails.into_iter().partition(|thumbnail| {
+		!referenced_cas_ids.contains(&thumbnail.cas_id) && thumbnail.last_used < started_at
+	});
+
+	let Some(max_bytes) = max_bytes else {
+		return orphans;
+	};
+
+	let mut total_bytes = kept.iter().map(|thumbnail| thumbnail.size).sum::<u64>();
+	if total_bytes <= max_bytes {
+		return orphans;
+	}
+
+	kept.retain(|thumbnail| thumbnail.evictable);
+	kept.sort_by_key(|thumbnail| (thumbnail.is_small, thumbnail.last_used));
+	for thumbnail in kept {
+		if total_bytes <= max_bytes {
+			break;
+		}
+
+		total_bytes -= thumbnail.size;
+		orphans.push(thumbnail);
+	}
+
+	orphans
+}
+
+/// Marks a thumbnail as just used, so it's among the last to be removed when the cache is over its size limit.
+pub(crate) fn mark_thumbnail_used(path: &Path) {
+	if let Err(e) = filetime::set_file_atime(path, FileTime::now()) {
+		trace!(
+			"Failed to update the access time of {}: {e}",
+			path.display()
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::object::preview::{thumbstrip_path, video_preview_path, ThumbnailSize};
+
+	use std::time::Duration;
+
+	fn thumbnail(cas_id: &str, size: u64, age_secs: u64, is_small: bool) -> CachedThumbnail {
+		CachedThumbnail {
+			path: PathBuf::from(cas_id).with_extension("webp"),
+			cas_id: cas_id.to_string(),
+			size,
+			last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000 - age_secs),
+			is_small,
+			evictable: true,
+		}
+	}
+
+	fn cas_ids(thumbnails: &[CachedThumbnail]) -> Vec<&str> {
+		thumbnails
+			.iter()
+			.map(|thumbnail| thumbnail.cas_id.as_str())
+			.collect()
+	}
+
+	#[test]
+	fn removes_orphans() {
+		let started_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+		let referenced = HashSet::from(["a".to_string()]);
+
+		let removed = thumbnails_to_remove(
+			vec![
+				thumbnail("a", 10, 100, true),
+				thumbnail("b", 10, 100, true),
+				// written after the job started
+				thumbnail("c", 10, 0, true),
+			],
+			&referenced,
+			None,
+			started_at,
+		);
+
+		assert_eq!(cas_ids(&removed), ["b"]);
+	}
+
+	#[test]
+	fn evicts_least_recently_used() {
+		let started_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+		let referenced = ["a", "b", "c", "d"]
+			.into_iter()
+			.map(ToString::to_string)
+			.collect();
+
+		let removed = thumbnails_to_remove(
+			vec![
+				thumbnail("a", 10, 400, true),
+				thumbnail("b", 10, 100, false),
+				thumbnail("c", 10, 300, false),
+				thumbnail("d", 10, 200, true),
+			],
+			&referenced,
+			Some(25),
+			started_at,
+		);
+
+		assert_eq!(cas_ids(&removed), ["c", "b"]);
+	}
+
+	#[test]
+	fn never_evicts_previews() {
+		let started_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+		let referenced = ["a", "b", "c"]
+			.into_iter()
+			.map(ToString::to_string)
+			.collect();
+
+		let mut preview = thumbnail("a", 10, 400, false);
+		preview.evictable = false;
+		let mut orphaned_preview = thumbnail("d", 10, 400, false);
+		orphaned_preview.evictable = false;
+
+		let removed = thumbnails_to_remove(
+			vec![
+				preview,
+				orphaned_preview,
+				thumbnail("b", 10, 300, false),
+				thumbnail("c", 10, 200, true),
+			],
+			&referenced,
+			Some(5),
+			started_at,
+		);
+
+		assert_eq!(cas_ids(&removed), ["d", "b", "c"]);
+	}
+
+	#[tokio::test]
+	async fn finds_previews_as_not_evictable() {
+		let dir = tempfile::tempdir().unwrap();
+		for path in [
+			ThumbnailSize::Small.path(dir.path(), "a"),
+			ThumbnailSize::Medium.path(dir.path(), "b"),
+			thumbstrip_path(dir.path(), "c"),
+			video_preview_path(dir.path(), "d"),
+		] {
+			fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+			fs::write(path, b"webp").await.unwrap();
+		}
+
+		let mut thumbnails = cached_thumbnails(dir.path())
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|thumbnail| (thumbnail.cas_id, thumbnail.is_small, thumbnail.evictable))
+			.collect::<Vec<_>>();
+		thumbnails.sort();
+
+		assert_eq!(
+			thumbnails,
+			[
+				("a".to_string(), true, true),
+				("b".to_string(), false, true),
+				("c".to_string(), false, false),
+				("d".to_string(), false, false),
+			]
+		);
+	}
+}
@@ -0,0 +1,173 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	object::preview::TRANSCODE_CACHE_DIR_NAME,
+};
+
+use std::{collections::VecDeque, hash::Hash, path::PathBuf, sync::Weak, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io};
+use tracing::info;
+
+use super::{
+	cache::{cached_thumbnails, cached_transcodes, referenced_cas_ids, thumbnails_to_remove},
+	THUMBNAIL_CACHE_DIR_NAME,
+};
+
+pub struct ThumbnailCleanupJob {}
+
+/// The thumbnail and transcode directories are shared by every library on the node, so the `cas_id`s referenced by each of them are kept.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ThumbnailCleanupJobInit {}
+
+impl Hash for ThumbnailCleanupJobInit {
+	// only one cleanup can run at a time, as they'd be removing the same thumbnails
+	fn hash<H: std::hash::Hasher>(&self, _: &mut H) {}
+}
+
+impl JobInitData for ThumbnailCleanupJobInit {
+	type Job = ThumbnailCleanupJob;
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ThumbnailCleanupReport {
+	orphans_removed: u32,
+	thumbnails_evicted: u32,
+	reclaimed_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailCleanupJobState {
+	report: ThumbnailCleanupReport,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailCleanupJobStep {
+	path: PathBuf,
+	/// Whether no library references the thumbnail, rather than it being evicted to keep the cache within its size limit.
+	orphan: bool,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ThumbnailCleanupJob {
+	type Init = ThumbnailCleanupJobInit;
+	type Data = ThumbnailCleanupJobState;
+	type Step = ThumbnailCleanupJobStep;
+
+	const NAME: &'static str = "thumbnail_cleanup";
+
+	fn new() -> Self {
+		Self {}
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		// taken before the referenced `cas_id`s are collected, so anything written after they are is kept
+		let started_at = SystemTime::now();
+
+		let library_manager = ctx
+			.library
+			.node_context
+			.library_manager
+			.get()
+			.and_then(Weak::upgrade)
+			.ok_or_else(|| JobError::MissingData {
+				value: String::from("library manager"),
+			})?;
+		let referenced_cas_ids = referenced_cas_ids(&library_manager).await?;
+
+		let node_config = ctx.library.config();
+		let data_dir = node_config.data_directory();
+		let max_bytes = node_config
+			.get()
+			.await
+			.thumbnail_cache_max_mb
+			.map(|mb| mb as u64 * 1024 * 1024);
+
+		let mut cached = cached_thumbnails(&data_dir.join(THUMBNAIL_CACHE_DIR_NAME)).await?;
+		cached.extend(cached_transcodes(&data_dir.join(TRANSCODE_CACHE_DIR_NAME)).await?);
+
+		state.steps = thumbnails_to_remove(cached, &referenced_cas_ids, max_bytes, started_at)
+			.into_iter()
+			.map(|thumbnail| ThumbnailCleanupJobStep {
+				orphan: !referenced_cas_ids.contains(&thumbnail.cas_id),
+				path: thumbnail.path,
+			})
+			.collect::<VecDeque<_>>();
+
+		state.data = Some(ThumbnailCleanupJobState {
+			report: ThumbnailCleanupReport::default(),
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state.data.as_mut().ok_or_else(|| JobError::MissingData {
+			value: String::from("job state"),
+		})?;
+
+		// the thumbnail may have already been removed, such as by erasing its file
+		match fs::metadata(&step.path).await {
+			Ok(metadata) => {
+				fs::remove_file(&step.path).await?;
+
+				// a file's transcode directory is removed along with its last playlist or segment
+				let transcode_dir = ctx
+					.library
+					.config()
+					.data_directory()
+					.join(TRANSCODE_CACHE_DIR_NAME);
+				if let Some(parent) = step
+					.path
+					.parent()
+					.filter(|parent| parent.parent() == Some(transcode_dir.as_path()))
+				{
+					fs::remove_dir(parent).await.ok();
+				}
+
+				data.report.reclaimed_bytes += metadata.len();
+				if step.orphan {
+					data.report.orphans_removed += 1;
+				} else {
+					data.report.thumbnails_evicted += 1;
+				}
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e.into()),
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Removed {} orphaned and {} evicted thumbnails, reclaiming {} bytes",
+			data.report.orphans_removed,
+			data.report.thumbnails_evicted,
+			data.report.reclaimed_bytes
+		);
+
+		invalidate_query!(ctx.library, "library.getStatistics");
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
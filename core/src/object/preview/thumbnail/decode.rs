@@ -0,0 +1,376 @@
+//! Decoding images for thumbnails, including the formats the `image` crate can't open by itself.
+//!
+//! The orientation from an image's EXIF metadata is applied, so thumbnails are shown the right way up.
+
+use super::ThumbnailSize;
+
+use std::{
+	error::Error,
+	fs::File,
+	io::{BufRead, BufReader, Cursor, Seek},
+	path::Path,
+};
+
+use image::{DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use sd_file_ext::extensions::ImageExtension;
+
+/// Whether the extension is for a camera's RAW image, which are thumbnailed from the JPEG preview embedded by the camera.
+pub const fn is_raw_image(extension: &ImageExtension) -> bool {
+	use ImageExtension::*;
+	matches!(
+		extension,
+		Raw | Dng | Cr2 | Cr3 | Dcr | Nef | Nwr | Arw | Raf | Orf | Rw2
+	)
+}
+
+/// Decodes an image so a thumbnail of the given size can be made from it.
+///
+/// Images are decoded by their contents if the extension is unknown.
+pub(super) fn decode_image(
+	path: &Path,
+	extension: Option<ImageExtension>,
+	size: ThumbnailSize,
+) -> Result<DynamicImage, Box<dyn Error>> {
+	match extension {
+		Some(ImageExtension::Svg) => render_svg(path, size),
+		#[cfg(feature = "heif")]
+		Some(ImageExtension::Heic | ImageExtension::Heif) => decode_heif(path),
+		Some(extension) if is_raw_image(&extension) => {
+			let data = std::fs::read(path)?;
+			let preview = find_raw_preview(&data, size).ok_or("no embedded preview was found")?;
+			let img = image::load_from_memory_with_format(preview, image::ImageFormat::Jpeg)?;
+
+			// The orientation is usually only within the RAW container, but some (such as Fujifilm's) only have it in the preview
+			let orientation = orientation(&mut Cursor::new(&data))
+				.or_else(|| orientation(&mut Cursor::new(preview)));
+
+			Ok(apply_orientation(img, orientation))
+		}
+		_ => {
+			let img = image::io::Reader::open(path)?
+				.with_guessed_format()?
+				.decode()?;
+
+			Ok(apply_orientation(
+				img,
+				orientation(&mut BufReader::new(File::open(path)?)),
+			))
+		}
+	}
+}
+
+/// Reads the EXIF orientation of an image, from 1 (upright) to 8.
+fn orientation(reader: &mut (impl BufRead + Seek)) -> Option<u32> {
+	exif::Reader::new()
+		.read_from_container(reader)
+		.ok()?
+		.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+		.value
+		.get_uint(0)
+}
+
+fn apply_orientation(img: DynamicImage, orientation: Option<u32>) -> DynamicImage {
+	match orientation {
+		Some(2) => img.fliph(),
+		Some(3) => img.rotate180(),
+		Some(4) => img.flipv(),
+		Some(5) => img.rotate90().fliph(),
+		Some(6) => img.rotate90(),
+		Some(7) => img.rotate270().fliph(),
+		Some(8) => img.rotate270(),
+		_ => img,
+	}
+}
+
+/// Finds the JPEG previews embedded in a RAW image, returning the smallest one which is at least as large as the thumbnail (or the largest one otherwise).
+///
+/// RAW formats store their previews differently, so rather than parsing each of them the file is searched for JPEGs.
+/// Lossless JPEGs are skipped, as that's how some formats (such as CR2) store the sensor data itself.
+fn find_raw_preview(data: &[u8], size: ThumbnailSize) -> Option<&[u8]> {
+	let mut previews = Vec::new();
+
+	let mut pos = 0;
+	while pos + 3 <= data.len() {
+		if data[pos..pos + 3] != [0xFF, 0xD8, 0xFF] {
+			pos += 1;
+			continue;
+		}
+
+		match parse_jpeg(data, pos) {
+			Some((end, edge)) => {
+				previews.push((&data[pos..end], edge));
+				pos = end;
+			}
+			None => pos += 1,
+		}
+	}
+
+	previews
+		.iter()
+		.filter(|(_, edge)| *edge >= size.max_edge())
+		.min_by_key(|(_, edge)| *edge)
+		.or_else(|| previews.iter().max_by_key(|(_, edge)| *edge))
+		.map(|(preview, _)| *preview)
+}
+
+/// Walks the segments of the JPEG starting at `start`, returning where it ends and the length of its longest edge.
+///
+/// `None` is returned if it isn't a baseline or progressive JPEG.
+fn parse_jpeg(data: &[u8], start: usize) -> Option<(usize, u32)> {
+	// skip the SOI marker
+	let mut pos = start + 2;
+	let mut edge = None;
+
+	loop {
+		if *data.get(pos)? != 0xFF {
+			return None;
+		}
+		// markers may be padded with any number of 0xFF bytes
+		while *data.get(pos)? == 0xFF {
+			pos += 1;
+		}
+
+		let marker = *data.get(pos)?;
+		pos += 1;
+
+		match marker {
+			// EOI
+			0xD9 => return Some((pos, edge?)),
+			// TEM and RSTn don't have a length
+			0x01 | 0xD0..=0xD7 => continue,
+			_ => {}
+		}
+
+		let len = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+		if len < 2 {
+			return None;
+		}
+
+		match marker {
+			// SOF0 to SOF2 are the baseline and progressive frames the decoder supports
+			0xC0..=0xC2 => {
+				let height = u16::from_be_bytes([*data.get(pos + 3)?, *data.get(pos + 4)?]);
+				let width = u16::from_be_bytes([*data.get(pos + 5)?, *data.get(pos + 6)?]);
+				edge = Some(width.max(height) as u32);
+			}
+			// the other SOFn (except DHT, JPG and DAC, which share their range)
+			0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+			_ => {}
+		}
+
+		pos += len;
+
+		// SOS is followed by the entropy-coded data, which ends at the first marker that isn't a stuffed byte or RSTn
+		if marker == 0xDA {
+			loop {
+				match (*data.get(pos)?, *data.get(pos + 1)?) {
+					(0xFF, 0x00 | 0xD0..=0xD7) => pos += 2,
+					(0xFF, _) => break,
+					_ => pos += 1,
+				}
+			}
+		}
+	}
+}
+
+/// Renders an SVG so its longest edge is the thumbnail's size, as vector images can be scaled up too.
+///
+/// Text isn't rendered, as that requires loading the system's fonts.
+fn render_svg(path: &Path, size: ThumbnailSize) -> Result<DynamicImage, Box<dyn Error>> {
+	let tree = usvg::Tree::from_data(&std::fs::read(path)?, &usvg::Options::default())?;
+
+	let (width, height) = (tree.size.width(), tree.size.height());
+	let scale = size.max_edge() as f64 / width.max(height);
+	let width = ((width * scale).round() as u32).max(1);
+	let height = ((height * scale).round() as u32).max(1);
+
+	let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or("invalid SVG size")?;
+	resvg::render(
+		&tree,
+		usvg::FitTo::Size(width, height),
+		tiny_skia::Transform::default(),
+		pixmap.as_mut(),
+	)
+	.ok_or("failed to render SVG")?;
+
+	// tiny-skia's pixels have their alpha premultiplied
+	let pixels = pixmap
+		.pixels()
+		.iter()
+		.flat_map(|pixel| {
+			let color = pixel.demultiply();
+			[color.red(), color.green(), color.blue(), color.alpha()]
+		})
+		.collect();
+
+	Ok(DynamicImage::ImageRgba8(
+		RgbaImage::from_raw(width, height, pixels).ok_or("invalid SVG size")?,
+	))
+}
+
+/// HEIF images are decoded with libheif, which also applies their rotation and mirroring.
+#[cfg(feature = "heif")]
+fn decode_heif(path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+	use libheif_rs::{ColorSpace, HeifContext, RgbChroma};
+
+	let ctx = HeifContext::read_from_file(path.to_str().ok_or("path isn't valid UTF-8")?)?;
+	let image = ctx
+		.primary_image_handle()?
+		.decode(ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+	let plane = image
+		.planes()
+		.interleaved
+		.ok_or("HEIF image wasn't decoded as RGBA")?;
+
+	// each row may be padded
+	let row_len = plane.width as usize * 4;
+	let pixels = plane
+		.data
+		.chunks(plane.stride)
+		.take(plane.height as usize)
+		.flat_map(|row| &row[..row_len])
+		.copied()
+		.collect();
+
+	Ok(DynamicImage::ImageRgba8(
+		RgbaImage::from_raw(plane.width, plane.height, pixels).ok_or("invalid HEIF image size")?,
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use image::{codecs::jpeg::JpegEncoder, ColorType, GenericImageView, GrayImage, RgbImage};
+
+	fn jpeg(width: u32, height: u32) -> Vec<u8> {
+		let img = RgbImage::from_pixel(width, height, image::Rgb([200, 100, 50]));
+		let mut buf = Vec::new();
+		JpegEncoder::new(&mut buf)
+			.encode(img.as_raw(), width, height, ColorType::Rgb8)
+			.unwrap();
+		buf
+	}
+
+	/// A JPEG with an EXIF segment containing only its orientation.
+	fn jpeg_with_orientation(orientation: u8) -> Vec<u8> {
+		let mut exif = b"Exif\0\0II\x2A\0\x08\0\0\0".to_vec();
+		// a single IFD entry for the orientation, which is a SHORT, followed by the offset of the next IFD
+		exif.extend([1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, orientation, 0, 0, 0]);
+		exif.extend([0, 0, 0, 0]);
+
+		let jpeg = jpeg(8, 8);
+		let mut buf = jpeg[..2].to_vec();
+		buf.extend([0xFF, 0xE1]);
+		buf.extend((exif.len() as u16 + 2).to_be_bytes());
+		buf.extend(exif);
+		buf.extend(&jpeg[2..]);
+		buf
+	}
+
+	/// A RAW file with a lossless JPEG (like CR2's sensor data) and two previews, surrounded by other data.
+	fn raw_file(small: &[u8], large: &[u8]) -> Vec<u8> {
+		let lossless = [
+			0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x0B, 0x08, 0x0F, 0xA0, 0x0F, 0xA0, 0x01, 0x01, 0x11,
+			0x00, 0xFF, 0xD9,
+		];
+
+		let mut data = vec![0x49, 0x49, 0x2A, 0x00];
+		for part in [&lossless[..], small, large] {
+			data.extend([0; 32]);
+			data.extend(part);
+		}
+		data.extend([0; 32]);
+		data
+	}
+
+	#[test]
+	fn finds_smallest_sufficient_preview() {
+		let (small, large) = (jpeg(160, 120), jpeg(640, 480));
+		let data = raw_file(&small, &large);
+
+		assert_eq!(
+			find_raw_preview(&data, ThumbnailSize::Small),
+			Some(&small[..])
+		);
+		assert_eq!(
+			find_raw_preview(&data, ThumbnailSize::Medium),
+			Some(&large[..])
+		);
+		// without a preview as large as the thumbnail, the largest is used
+		assert_eq!(
+			find_raw_preview(&data, ThumbnailSize::Large),
+			Some(&large[..])
+		);
+
+		let preview =
+			image::load_from_memory(find_raw_preview(&data, ThumbnailSize::Small).unwrap());
+		assert_eq!(preview.unwrap().dimensions(), (160, 120));
+	}
+
+	#[test]
+	fn no_preview_in_lossless_jpeg() {
+		assert_eq!(
+			find_raw_preview(&raw_file(&[], &[]), ThumbnailSize::Small),
+			None
+		);
+	}
+
+	#[test]
+	fn parses_jpeg_end_and_edge() {
+		let mut data = vec![0; 16];
+		let preview = jpeg_with_orientation(6);
+		data.extend(&preview);
+		data.extend([0xFF; 16]);
+
+		assert_eq!(parse_jpeg(&data, 16), Some((16 + preview.len(), 8)));
+		// truncated JPEGs aren't previews
+		assert_eq!(parse_jpeg(&preview[..preview.len() - 2], 0), None);
+	}
+
+	#[test]
+	fn reads_exif_orientation() {
+		for value in 1..=8 {
+			let jpeg = jpeg_with_orientation(value);
+			assert_eq!(orientation(&mut Cursor::new(&jpeg)), Some(value as u32));
+		}
+
+		assert_eq!(orientation(&mut Cursor::new(jpeg(8, 8))), None);
+	}
+
+	#[test]
+	fn applies_each_orientation() {
+		// 1 2
+		// 3 4
+		let img = DynamicImage::ImageLuma8(GrayImage::from_raw(2, 2, vec![1, 2, 3, 4]).unwrap());
+
+		for (orientation, expected) in [
+			(None, [1, 2, 3, 4]),
+			(Some(1), [1, 2, 3, 4]),
+			(Some(2), [2, 1, 4, 3]),
+			(Some(3), [4, 3, 2, 1]),
+			(Some(4), [3, 4, 1, 2]),
+			(Some(5), [1, 3, 2, 4]),
+			(Some(6), [3, 1, 4, 2]),
+			(Some(7), [4, 2, 3, 1]),
+			(Some(8), [2, 4, 1, 3]),
+		] {
+			let oriented = apply_orientation(img.clone(), orientation).into_luma8();
+			assert_eq!(oriented.as_raw(), &expected, "orientation {orientation:?}");
+		}
+	}
+
+	#[test]
+	fn rotations_swap_dimensions() {
+		let img = DynamicImage::ImageLuma8(GrayImage::new(4, 2));
+
+		for orientation in 1..=8 {
+			let expected = if orientation >= 5 { (2, 4) } else { (4, 2) };
+			assert_eq!(
+				apply_orientation(img.clone(), Some(orientation)).dimensions(),
+				expected
+			);
+		}
+	}
+}
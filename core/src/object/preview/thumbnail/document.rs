@@ -0,0 +1,130 @@
+//! Thumbnails of documents, which are the first page of PDFs and the first lines of text files.
+
+use super::{encode_thumbnail, write_thumbnail, ThumbnailSize};
+
+use std::{error::Error, path::Path};
+
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{DynamicImage, Rgba, RgbaImage};
+use tokio::{fs::File, io::AsyncReadExt, task::block_in_place};
+
+/// How much of a text file is read for its preview, which is more than enough for the lines that are shown.
+const TEXT_PREVIEW_MAX_BYTES: u64 = 16 * 1024;
+const TEXT_PREVIEW_COLUMNS: u32 = 64;
+const TEXT_PREVIEW_LINES: u32 = 48;
+const TAB_WIDTH: u32 = 4;
+
+/// The glyphs are 8x8 pixels, and are scaled up by whole pixels for larger thumbnails so they stay sharp.
+const GLYPH_SIZE: u32 = 8;
+const LINE_HEIGHT: u32 = 10;
+const MARGIN: u32 = 8;
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const FOREGROUND: Rgba<u8> = Rgba([40, 40, 40, 255]);
+
+pub async fn generate_text_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	size: ThumbnailSize,
+) -> Result<(), Box<dyn Error>> {
+	let mut text = Vec::new();
+	File::open(file_path)
+		.await?
+		.take(TEXT_PREVIEW_MAX_BYTES)
+		.read_to_end(&mut text)
+		.await?;
+
+	let webp = block_in_place(|| encode_thumbnail(render_text(&text, size)?, size))?;
+
+	write_thumbnail(output_path, webp).await
+}
+
+/// Renders the first lines of a text file onto a page, like how it looks in an editor.
+fn render_text(text: &[u8], size: ThumbnailSize) -> Result<DynamicImage, Box<dyn Error>> {
+	// a file with a text extension can still be binary
+	if text.contains(&0) {
+		return Err("file doesn't contain text".into());
+	}
+
+	let width = TEXT_PREVIEW_COLUMNS * GLYPH_SIZE + 2 * MARGIN;
+	let height = TEXT_PREVIEW_LINES * LINE_HEIGHT + 2 * MARGIN;
+	let scale = ((size.max_edge() + width - 1) / width).max(1);
+
+	let mut img = RgbaImage::from_pixel(width * scale, height * scale, BACKGROUND);
+
+	for (line_number, line) in String::from_utf8_lossy(text)
+		.lines()
+		.take(TEXT_PREVIEW_LINES as usize)
+		.enumerate()
+	{
+		let y = MARGIN + line_number as u32 * LINE_HEIGHT;
+
+		let mut column = 0;
+		for c in line.chars() {
+			if column >= TEXT_PREVIEW_COLUMNS {
+				break;
+			}
+
+			if c == '\t' {
+				column += TAB_WIDTH - column % TAB_WIDTH;
+				continue;
+			}
+
+			// characters without a glyph are left blank
+			if let Some(glyph) = BASIC_FONTS.get(c).or_else(|| LATIN_FONTS.get(c)) {
+				draw_glyph(&mut img, glyph, MARGIN + column * GLYPH_SIZE, y, scale);
+			}
+
+			column += 1;
+		}
+	}
+
+	Ok(DynamicImage::ImageRgba8(img))
+}
+
+/// Each byte of a glyph is a row of pixels, with the leftmost pixel in the lowest bit.
+fn draw_glyph(img: &mut RgbaImage, glyph: [u8; 8], x: u32, y: u32, scale: u32) {
+	for (row, bits) in (0..).zip(glyph) {
+		for column in (0..GLYPH_SIZE).filter(|column| bits & (1 << column) != 0) {
+			for dy in 0..scale {
+				for dx in 0..scale {
+					img.put_pixel(
+						(x + column) * scale + dx,
+						(y + row) * scale + dy,
+						FOREGROUND,
+					);
+				}
+			}
+		}
+	}
+}
+
+/// PDFs are rendered with pdfium, which is loaded from the system when a thumbnail is first generated.
+#[cfg(feature = "pdf")]
+pub async fn generate_pdf_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	size: ThumbnailSize,
+) -> Result<(), Box<dyn Error>> {
+	use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+
+	// pdfium has blocking code
+	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
+		let pdfium = Pdfium::new(Pdfium::bind_to_system_library()?);
+		let document = pdfium.load_pdf_from_file(file_path.as_ref(), None)?;
+
+		let img = document
+			.pages()
+			.get(0)?
+			.render_with_config(
+				&PdfRenderConfig::new()
+					.set_target_width(size.max_edge() as _)
+					.set_maximum_height(size.max_edge() as _),
+			)?
+			.as_image();
+
+		encode_thumbnail(img, size)
+	})?;
+
+	write_thumbnail(output_path, webp).await
+}
@@ -2,63 +2,140 @@ use crate::{
 	api::CoreEvent,
 	invalidate_query,
 	job::{JobError, JobReportUpdate, JobResult, WorkerContext},
+	library::LibraryManager,
 	location::{
-		file_path_helper::{
-			file_path_just_materialized_path_cas_id, FilePathError, MaterializedPath,
-		},
+		file_path_helper::{file_path_for_thumbnailer, FilePathError, MaterializedPath},
 		LocationId,
 	},
+	prisma::{file_path, object},
+	sync,
 };
 
 use std::{
+	collections::HashSet,
 	error::Error,
 	ops::Deref,
 	path::{Path, PathBuf},
+	str::FromStr,
+	sync::{Arc, Mutex},
 };
 
-use sd_file_ext::extensions::{Extension, ImageExtension};
+use sd_file_ext::extensions::ImageExtension;
 
 #[cfg(feature = "ffmpeg")]
 use sd_file_ext::extensions::VideoExtension;
 
-use image::{self, imageops, DynamicImage, GenericImageView};
+use image::{imageops, DynamicImage, GenericImageView};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
 use tokio::{fs, io, task::block_in_place};
-use tracing::{error, info, trace, warn};
-use webp::Encoder;
-
+use tracing::{debug, error, info, trace, warn};
+use webp::{Decoder, Encoder};
+
+mod cache;
+pub mod cleanup_job;
+mod decode;
+mod document;
+mod perceptual_hash;
+mod placeholder;
+mod registry;
 pub mod shallow_thumbnailer_job;
 pub mod thumbnailer_job;
+#[cfg(feature = "ffmpeg")]
+mod video_preview;
+
+pub(crate) use cache::mark_thumbnail_used;
+pub use decode::is_raw_image;
+pub use document::generate_text_thumbnail;
+pub use perceptual_hash::{
+	hamming_distance, perceptual_hash, similar_image_clusters, DEFAULT_SIMILARITY_DISTANCE,
+};
+pub use placeholder::Placeholder;
+pub use registry::Thumbnailer;
+
+#[cfg(feature = "pdf")]
+pub use document::generate_pdf_thumbnail;
+#[cfg(feature = "ffmpeg")]
+pub use video_preview::{generate_film_strip, generate_video_preview};
 
-static THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
 static THUMBNAIL_QUALITY: f32 = 30.0;
 pub static THUMBNAIL_CACHE_DIR_NAME: &str = "thumbnails";
+static THUMBSTRIP_DIR_NAME: &str = "strips";
+static VIDEO_PREVIEW_DIR_NAME: &str = "previews";
+
+/// The larger thumbnails which are being generated on demand, so a thumbnail that's requested many times is only generated once.
+static GENERATING_THUMBNAILS: Lazy<Mutex<HashSet<(String, ThumbnailSize)>>> =
+	Lazy::new(Default::default);
+
+/// The sizes thumbnails are generated at.
+///
+/// Small thumbnails are generated for every file that can have one, and larger sizes are generated when they're first requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ThumbnailSize {
+	Small,
+	Medium,
+	Large,
+}
 
-#[cfg(feature = "ffmpeg")]
-static FILTERED_VIDEO_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
-	sd_file_ext::extensions::ALL_VIDEO_EXTENSIONS
-		.iter()
-		.map(Clone::clone)
-		.filter(can_generate_thumbnail_for_video)
-		.map(Extension::Video)
-		.collect()
-});
-
-static FILTERED_IMAGE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
-	sd_file_ext::extensions::ALL_IMAGE_EXTENSIONS
-		.iter()
-		.map(Clone::clone)
-		.filter(can_generate_thumbnail_for_image)
-		.map(Extension::Image)
-		.collect()
-});
+impl ThumbnailSize {
+	/// Every size, from smallest to largest.
+	pub const ALL: [Self; 3] = [Self::Small, Self::Medium, Self::Large];
+
+	/// The length of the longest edge of a thumbnail of this size. Images which are already smaller than this aren't scaled up.
+	pub const fn max_edge(self) -> u32 {
+		match self {
+			Self::Small => 128,
+			Self::Medium => 512,
+			Self::Large => 1024,
+		}
+	}
+
+	/// The smallest size which is at least `edge` pixels along its longest edge, or the largest size if none are.
+	pub fn for_edge(edge: u32) -> Self {
+		Self::ALL
+			.into_iter()
+			.find(|size| size.max_edge() >= edge)
+			.unwrap_or(Self::Large)
+	}
+
+	/// Where a thumbnail of this size is stored within the thumbnail directory.
+	///
+	/// Small thumbnails are stored directly within it (where every thumbnail used to be), and larger ones within a directory named after their size.
+	pub fn path(self, thumbnail_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+		let dir = thumbnail_dir.as_ref();
+		match self {
+			Self::Small => dir.join(cas_id),
+			_ => dir.join(self.max_edge().to_string()).join(cas_id),
+		}
+		.with_extension("webp")
+	}
+}
+
+/// Where a video's film strip is stored within the thumbnail directory, which is its frames side by side.
+pub fn thumbstrip_path(thumbnail_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+	thumbnail_dir
+		.as_ref()
+		.join(THUMBSTRIP_DIR_NAME)
+		.join(cas_id)
+		.with_extension("webp")
+}
+
+/// Where a video's animated preview is stored within the thumbnail directory.
+pub fn video_preview_path(thumbnail_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+	thumbnail_dir
+		.as_ref()
+		.join(VIDEO_PREVIEW_DIR_NAME)
+		.join(cas_id)
+		.with_extension("webp")
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ThumbnailerJobState {
 	thumbnail_dir: PathBuf,
 	location_path: PathBuf,
+	video_previews: bool,
 	report: ThumbnailerJobReport,
 }
 
@@ -77,62 +154,167 @@ pub struct ThumbnailerJobReport {
 	thumbnails_created: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
-enum ThumbnailerJobStepKind {
-	Image,
-	#[cfg(feature = "ffmpeg")]
-	Video,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ThumbnailerJobStep {
-	file_path: file_path_just_materialized_path_cas_id::Data,
-	kind: ThumbnailerJobStepKind,
+	file_path: file_path_for_thumbnailer::Data,
+	thumbnailer: Thumbnailer,
 }
 
-pub async fn generate_image_thumbnail<P: AsRef<Path>>(
-	file_path: P,
-	output_path: P,
-) -> Result<(), Box<dyn Error>> {
-	// Webp creation has blocking code
-	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
-		// Using `image` crate, open the included .jpg file
-		let img = image::open(file_path)?;
-		let (w, h) = img.dimensions();
-		// Optionally, resize the existing photo and convert back into DynamicImage
-		let img = DynamicImage::ImageRgba8(imageops::resize(
+impl ThumbnailerJobStep {
+	/// `None` if there's no thumbnailer for the file's extension.
+	fn new(file_path: file_path_for_thumbnailer::Data) -> Option<Self> {
+		Thumbnailer::for_extension_str(&file_path.extension).map(|thumbnailer| Self {
+			file_path,
+			thumbnailer,
+		})
+	}
+}
+
+/// Scales an image down so its longest edge fits the thumbnail size, and encodes it as WebP.
+fn encode_thumbnail(img: DynamicImage, size: ThumbnailSize) -> Result<Vec<u8>, Box<dyn Error>> {
+	let (w, h) = img.dimensions();
+	// The image is converted to RGBA for the encoder either way
+	let scale = size.max_edge() as f32 / w.max(h) as f32;
+	let img = DynamicImage::ImageRgba8(if scale < 1.0 {
+		imageops::resize(
 			&img,
-			// FIXME : Think of a better heuristic to get the thumbnail size
-			(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+			((w as f32 * scale).round() as u32).max(1),
+			((h as f32 * scale).round() as u32).max(1),
 			imageops::FilterType::Triangle,
-		));
-		// Create the WebP encoder for the above image
-		let encoder = Encoder::from_image(&img)?;
+		)
+	} else {
+		img.to_rgba8()
+	});
+	// Create the WebP encoder for the above image
+	let encoder = Encoder::from_image(&img)?;
+
+	// Encode the image at a specified quality 0-100
+
+	// Type WebPMemory is !Send, which makes the Future in this function !Send,
+	// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+	// which implies on a unwanted clone...
+	Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+}
 
-		// Encode the image at a specified quality 0-100
+async fn write_thumbnail(
+	output_path: impl AsRef<Path>,
+	webp: Vec<u8>,
+) -> Result<(), Box<dyn Error>> {
+	if let Some(parent) = output_path.as_ref().parent() {
+		fs::create_dir_all(parent).await?;
+	}
 
-		// Type WebPMemory is !Send, which makes the Future in this function !Send,
-		// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
-		// which implies on a unwanted clone...
-		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+	fs::write(output_path, &webp).await.map_err(Into::into)
+}
+
+pub async fn generate_image_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	size: ThumbnailSize,
+) -> Result<(), Box<dyn Error>> {
+	let extension = file_path
+		.as_ref()
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.and_then(|extension| ImageExtension::from_str(&extension.to_lowercase()).ok());
+
+	// Decoding and WebP creation have blocking code
+	let webp = block_in_place(|| {
+		encode_thumbnail(
+			decode::decode_image(file_path.as_ref(), extension, size)?,
+			size,
+		)
 	})?;
 
-	fs::write(output_path, &webp).await.map_err(Into::into)
+	write_thumbnail(output_path, webp).await
 }
 
 #[cfg(feature = "ffmpeg")]
 pub async fn generate_video_thumbnail<P: AsRef<Path>>(
 	file_path: P,
 	output_path: P,
+	size: ThumbnailSize,
 ) -> Result<(), Box<dyn Error>> {
 	use sd_ffmpeg::to_thumbnail;
 
-	to_thumbnail(file_path, output_path, 256, THUMBNAIL_QUALITY).await?;
+	if let Some(parent) = output_path.as_ref().parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	to_thumbnail(file_path, output_path, size.max_edge(), THUMBNAIL_QUALITY).await?;
 
 	Ok(())
 }
 
+/// Generates a thumbnail in the background when it's first requested, from a file with the `cas_id` in any library.
+pub(crate) fn generate_thumbnail_on_demand(
+	library_manager: Arc<LibraryManager>,
+	thumbnail_dir: PathBuf,
+	cas_id: String,
+	size: ThumbnailSize,
+) {
+	let key = (cas_id, size);
+	if !GENERATING_THUMBNAILS.lock().unwrap().insert(key.clone()) {
+		return;
+	}
+
+	tokio::spawn(async move {
+		let (cas_id, size) = &key;
+		match generate_thumbnail_from_libraries(&library_manager, &thumbnail_dir, cas_id, *size)
+			.await
+		{
+			Ok(true) => debug!("Generated {size:?} thumbnail for '{cas_id}' on demand"),
+			Ok(false) => {
+				debug!("No file was found to generate a {size:?} thumbnail for '{cas_id}'")
+			}
+			Err(e) => error!("Failed to generate {size:?} thumbnail for '{cas_id}': {e:#?}"),
+		}
+
+		GENERATING_THUMBNAILS.lock().unwrap().remove(&key);
+	});
+}
+
+/// Returns whether a file was found to generate the thumbnail from.
+async fn generate_thumbnail_from_libraries(
+	library_manager: &LibraryManager,
+	thumbnail_dir: &Path,
+	cas_id: &str,
+	size: ThumbnailSize,
+) -> Result<bool, Box<dyn Error>> {
+	let output_path = size.path(thumbnail_dir, cas_id);
+
+	for library in library_manager.get_all_libraries().await {
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(vec![file_path::cas_id::equals(Some(cas_id.to_string()))])
+			.select(
+				file_path::select!({ materialized_path extension location: select { id path } }),
+			)
+			.exec()
+			.await?;
+
+		for file_path in file_paths {
+			let path = Path::new(&file_path.location.path).join(&MaterializedPath::from((
+				file_path.location.id,
+				&file_path.materialized_path,
+			)));
+
+			// the file may be in a location on another node
+			if fs::metadata(&path).await.is_err() {
+				continue;
+			}
+
+			if let Some(thumbnailer) = Thumbnailer::for_extension_str(&file_path.extension) {
+				thumbnailer.generate(&path, &output_path, size).await?;
+				return Ok(true);
+			}
+		}
+	}
+
+	Ok(false)
+}
+
 #[cfg(feature = "ffmpeg")]
 pub const fn can_generate_thumbnail_for_video(video_extension: &VideoExtension) -> bool {
 	use VideoExtension::*;
@@ -142,7 +324,12 @@ pub const fn can_generate_thumbnail_for_video(video_extension: &VideoExtension)
 
 pub const fn can_generate_thumbnail_for_image(image_extension: &ImageExtension) -> bool {
 	use ImageExtension::*;
-	matches!(image_extension, Jpg | Jpeg | Png | Webp | Gif)
+	matches!(
+		image_extension,
+		Jpg | Jpeg | Png | Webp | Gif | Bmp | Tiff | Svg
+	) || is_raw_image(image_extension)
+		// HEIF images can only be decoded with libheif
+		|| (cfg!(feature = "heif") && matches!(image_extension, Heic | Heif))
 }
 
 fn finalize_thumbnailer(data: &ThumbnailerJobState, ctx: WorkerContext) -> JobResult {
@@ -206,8 +393,8 @@ async fn inner_process_step(
 		return Ok(());
 	};
 
-	// Define and write the WebP-encoded file to a given path
-	let output_path = data.thumbnail_dir.join(format!("{cas_id}.webp"));
+	// Define and write the WebP-encoded file to a given path. Only the small size is generated up front
+	let output_path = ThumbnailSize::Small.path(&data.thumbnail_dir, cas_id);
 
 	match fs::metadata(&output_path).await {
 		Ok(_) => {
@@ -216,18 +403,15 @@ async fn inner_process_step(
 		Err(e) if e.kind() == io::ErrorKind::NotFound => {
 			info!("Writing {:?} to {:?}", path, output_path);
 
-			match step.kind {
-				ThumbnailerJobStepKind::Image => {
-					if let Err(e) = generate_image_thumbnail(&path, &output_path).await {
-						error!("Error generating thumb for image {:#?}", e);
-					}
-				}
-				#[cfg(feature = "ffmpeg")]
-				ThumbnailerJobStepKind::Video => {
-					if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
-						error!("Error generating thumb for video: {:?} {:#?}", &path, e);
-					}
-				}
+			if let Err(e) = step
+				.thumbnailer
+				.generate(&path, &output_path, ThumbnailSize::Small)
+				.await
+			{
+				error!(
+					"Error generating {:?} thumb for {:?}: {:#?}",
+					step.thumbnailer, &path, e
+				);
 			}
 
 			if !is_background {
@@ -243,5 +427,108 @@ async fn inner_process_step(
 		Err(e) => return Err(ThumbnailerError::from(e).into()),
 	}
 
+	if let Some(object) = &step.file_path.object {
+		process_thumbnail_metadata(step.thumbnailer, &output_path, object, ctx).await?;
+	}
+
+	#[cfg(feature = "ffmpeg")]
+	if let (true, Thumbnailer::Video, Some(object_id)) = (
+		data.video_previews,
+		step.thumbnailer,
+		step.file_path.object_id,
+	) {
+		video_preview::process_video_previews(
+			is_background,
+			&path,
+			&data.thumbnail_dir,
+			cas_id,
+			object_id,
+			ctx,
+		)
+		.await?;
+	}
+
 	Ok(())
 }
+
+/// Hashes images so near-duplicates can be found, and makes placeholders for images and videos, from their small thumbnail.
+///
+/// This is done for thumbnails that already exist too, and doesn't fail the job if the thumbnail couldn't be generated.
+async fn process_thumbnail_metadata(
+	thumbnailer: Thumbnailer,
+	thumbnail_path: &Path,
+	object: &file_path_for_thumbnailer::object::Data,
+	ctx: &WorkerContext,
+) -> Result<(), JobError> {
+	let needs_hash = thumbnailer == Thumbnailer::Image && object.perceptual_hash.is_none();
+	let needs_placeholder = thumbnailer.has_placeholder() && object.blurhash.is_none();
+	if !needs_hash && !needs_placeholder {
+		return Ok(());
+	}
+
+	let img = match decode_thumbnail(thumbnail_path).await {
+		Ok(img) => img,
+		Err(e) => {
+			error!("Error decoding thumbnail {:?}: {:#?}", thumbnail_path, e);
+			return Ok(());
+		}
+	};
+
+	let (db, sync) = (&ctx.library.db, &ctx.library.sync);
+
+	if needs_hash {
+		let hash = block_in_place(|| perceptual_hash(&img));
+
+		db.object()
+			.update(
+				object::id::equals(object.id),
+				vec![object::perceptual_hash::set(Some(hash))],
+			)
+			.exec()
+			.await?;
+	}
+
+	// Unlike the thumbnail itself, the placeholder is small enough to sync so other nodes can show it
+	if needs_placeholder {
+		let Placeholder {
+			blurhash,
+			dominant_color,
+		} = block_in_place(|| Placeholder::new(&img));
+
+		let sync_id = || sync::object::SyncId {
+			pub_id: object.pub_id.clone(),
+		};
+
+		sync.write_ops(
+			db,
+			(
+				vec![
+					sync.shared_update(sync_id(), "blurhash", json!(blurhash)),
+					sync.shared_update(sync_id(), "dominant_color", json!(dominant_color)),
+				],
+				vec![db.object().update(
+					object::id::equals(object.id),
+					vec![
+						object::blurhash::set(Some(blurhash)),
+						object::dominant_color::set(Some(dominant_color)),
+					],
+				)],
+			),
+		)
+		.await?;
+	}
+
+	Ok(())
+}
+
+/// The small thumbnail is used, as it's far quicker to decode than the original and is already scaled down.
+async fn decode_thumbnail(thumbnail_path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+	let webp = fs::read(thumbnail_path).await?;
+
+	block_in_place(|| {
+		Ok(Decoder::new(&webp)
+			.decode()
+			.ok_or("unable to decode the thumbnail")?
+			.to_image())
+	})
+}
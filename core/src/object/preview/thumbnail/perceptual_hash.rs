@@ -0,0 +1,199 @@
+//! Perceptual hashes of images, which are close together for copies of the same picture that have been resized or recompressed.
+//!
+//! This is a difference hash (dHash), so each bit is whether a part of the image is brighter than the part beside it. Those gradients
+//! survive scaling and compression, unlike the bytes that the `cas_id` is generated from.
+
+use std::{cmp::Reverse, collections::HashMap};
+
+use image::{imageops::FilterType, DynamicImage};
+
+/// The hash has a row of bits for every row, and one less column of bits than there are columns.
+const HASH_WIDTH: u32 = 9;
+const HASH_HEIGHT: u32 = 8;
+
+/// How many bits two hashes can differ by for their images to still be considered the same picture, out of the hash's 64.
+pub const DEFAULT_SIMILARITY_DISTANCE: u32 = 10;
+
+pub fn perceptual_hash(img: &DynamicImage) -> Vec<u8> {
+	let img = img
+		.resize_exact(HASH_WIDTH, HASH_HEIGHT, FilterType::Triangle)
+		.into_luma8();
+
+	(0..HASH_HEIGHT)
+		.map(|y| {
+			(0..HASH_WIDTH - 1).fold(0, |byte, x| {
+				(byte << 1) | u8::from(img.get_pixel(x, y)[0] > img.get_pixel(x + 1, y)[0])
+			})
+		})
+		.collect()
+}
+
+/// The number of bits that differ between two hashes.
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u32 {
+	a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+/// A BK-tree of hashes, which finds the ones within a distance of a hash without comparing it to every other.
+///
+/// Each child is keyed by its distance from its parent, so by the triangle inequality only children whose key is within `max_distance`
+/// of the query's distance to the parent can hold a match.
+struct BkTree<'a> {
+	hashes: &'a [(i32, Vec<u8>)],
+	/// The children of each hash, keyed by their distance from it.
+	children: Vec<HashMap<u32, usize>>,
+}
+
+impl<'a> BkTree<'a> {
+	fn new(hashes: &'a [(i32, Vec<u8>)]) -> Self {
+		let mut children = vec![HashMap::new(); hashes.len()];
+
+		for i in 1..hashes.len() {
+			let mut node = 0;
+			loop {
+				let distance = hamming_distance(&hashes[node].1, &hashes[i].1);
+				match children[node].get(&distance) {
+					Some(&child) => node = child,
+					None => {
+						children[node].insert(distance, i);
+						break;
+					}
+				}
+			}
+		}
+
+		Self { hashes, children }
+	}
+
+	/// The indexes of the hashes within `max_distance` of `hash`.
+	fn within(&self, hash: &[u8], max_distance: u32) -> Vec<usize> {
+		let mut found = vec![];
+		let mut nodes = if self.hashes.is_empty() {
+			vec![]
+		} else {
+			vec![0]
+		};
+
+		while let Some(node) = nodes.pop() {
+			let distance = hamming_distance(&self.hashes[node].1, hash);
+			if distance <= max_distance {
+				found.push(node);
+			}
+
+			nodes.extend(
+				self.children[node]
+					.iter()
+					.filter(|(child_distance, _)| child_distance.abs_diff(distance) <= max_distance)
+					.map(|(_, child)| *child),
+			);
+		}
+
+		found
+	}
+}
+
+/// Groups the ids of images whose hashes are within `max_distance` of the group's first image, leaving out images without similar ones.
+///
+/// Images are taken in order as the first of a new group unless they're already in one, so every image in a group is within
+/// `max_distance` of that first image, though two others in it can be up to twice that apart. The largest groups come first.
+///
+/// This is CPU bound on a whole library's hashes, so should be run on a blocking thread.
+pub fn similar_image_clusters(hashes: &[(i32, Vec<u8>)], max_distance: u32) -> Vec<Vec<i32>> {
+	let tree = BkTree::new(hashes);
+	let mut clustered = vec![false; hashes.len()];
+	let mut clusters = vec![];
+
+	for (i, (_, hash)) in hashes.iter().enumerate() {
+		if clustered[i] {
+			continue;
+		}
+
+		let mut cluster = tree
+			.within(hash, max_distance)
+			.into_iter()
+			.filter(|&j| !clustered[j])
+			.collect::<Vec<_>>();
+
+		if cluster.len() > 1 {
+			cluster.sort_unstable();
+			for &j in &cluster {
+				clustered[j] = true;
+			}
+			clusters.push(cluster.into_iter().map(|j| hashes[j].0).collect::<Vec<_>>());
+		}
+	}
+
+	clusters.sort_by_key(|cluster| (Reverse(cluster.len()), cluster[0]));
+
+	clusters
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use image::{ImageBuffer, Rgb};
+
+	/// Gets brighter to the right in the top half, and darker to the right in the bottom half.
+	fn gradients(width: u32, height: u32) -> DynamicImage {
+		DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+			let value = (x * 255 / width) as u8;
+			let value = if y < height / 2 { value } else { 255 - value };
+			Rgb([value, value, value])
+		}))
+	}
+
+	#[test]
+	fn resized_images_are_similar() {
+		let original = perceptual_hash(&gradients(640, 480));
+		let resized = perceptual_hash(&gradients(160, 120));
+		let flipped = perceptual_hash(&gradients(640, 480).flipv());
+
+		assert_eq!(original.len(), 8);
+		assert_eq!(hamming_distance(&original, &resized), 0);
+		assert_eq!(hamming_distance(&original, &flipped), 64);
+	}
+
+	#[test]
+	fn clusters_similar_hashes() {
+		let hashes = vec![
+			(1, vec![0b0000_0000; 8]),
+			(2, vec![0b1111_1111; 8]),
+			(3, vec![0b0000_0001; 8]),
+			(4, vec![0b0000_0011; 8]),
+			(5, vec![0b1111_1110; 8]),
+			(6, vec![0b0101_0101; 8]),
+		];
+
+		// 4 is within 8 bits of 3 but not of 1, which the group is formed around.
+		assert_eq!(
+			similar_image_clusters(&hashes, 8),
+			vec![vec![1, 3], vec![2, 5]]
+		);
+		assert_eq!(
+			similar_image_clusters(&hashes, 16),
+			vec![vec![1, 3, 4], vec![2, 5]]
+		);
+		assert!(similar_image_clusters(&hashes, 0).is_empty());
+	}
+
+	#[test]
+	fn bk_tree_finds_every_hash_within_distance() {
+		let hashes = (0..=255u8)
+			.map(|byte| (i32::from(byte), vec![byte, byte.rotate_left(3)]))
+			.collect::<Vec<_>>();
+		let tree = BkTree::new(&hashes);
+
+		for (_, hash) in &hashes {
+			for max_distance in [0, 2, 5] {
+				let mut found = tree.within(hash, max_distance);
+				found.sort_unstable();
+
+				let expected = (0..hashes.len())
+					.filter(|&j| hamming_distance(&hashes[j].1, hash) <= max_distance)
+					.collect::<Vec<_>>();
+
+				assert_eq!(found, expected);
+			}
+		}
+	}
+}
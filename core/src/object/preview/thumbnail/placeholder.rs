@@ -0,0 +1,177 @@
+//! Placeholders that are shown while a thumbnail loads, or instead of it on nodes that don't have it.
+//!
+//! A placeholder is a [blurhash](https://blurha.sh) of the thumbnail and its dominant color, which are small enough to be stored
+//! on the object and synced to other nodes.
+
+use std::{collections::HashMap, f32::consts::PI};
+
+use image::{DynamicImage, RgbImage};
+
+/// The number of horizontal and vertical cosine components in the blurhash, which is enough for the rough layout of a picture.
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+/// The thumbnail is shrunk before it's encoded, as a blurhash has far less detail than this anyway.
+const PLACEHOLDER_MAX_EDGE: u32 = 32;
+
+const BASE83_CHARACTERS: &[u8] =
+	b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+	pub blurhash: String,
+	/// The color as `#rrggbb`.
+	pub dominant_color: String,
+}
+
+impl Placeholder {
+	pub fn new(img: &DynamicImage) -> Self {
+		let img = img
+			.thumbnail(PLACEHOLDER_MAX_EDGE, PLACEHOLDER_MAX_EDGE)
+			.into_rgb8();
+
+		Self {
+			blurhash: blurhash(&img, BLURHASH_COMPONENTS),
+			dominant_color: dominant_color(&img),
+		}
+	}
+}
+
+fn blurhash(img: &RgbImage, (x_components, y_components): (u32, u32)) -> String {
+	let (width, height) = img.dimensions();
+	let pixels = img
+		.enumerate_pixels()
+		.map(|(x, y, pixel)| (x, y, pixel.0.map(srgb_to_linear)))
+		.collect::<Vec<_>>();
+
+	let factors = (0..y_components)
+		.flat_map(|j| (0..x_components).map(move |i| (i, j)))
+		.map(|(i, j)| {
+			let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+			let scale = normalisation / (width * height) as f32;
+
+			pixels.iter().fold([0.0; 3], |mut factor, (x, y, pixel)| {
+				let basis = (PI * i as f32 * *x as f32 / width as f32).cos()
+					* (PI * j as f32 * *y as f32 / height as f32).cos();
+				for (factor, channel) in factor.iter_mut().zip(pixel) {
+					*factor += basis * channel * scale;
+				}
+				factor
+			})
+		})
+		.collect::<Vec<_>>();
+
+	let (dc, ac) = factors
+		.split_first()
+		.expect("there's always a DC component");
+
+	let mut hash = String::new();
+	base83_encode((x_components - 1) + (y_components - 1) * 9, 1, &mut hash);
+
+	let max_value = match ac
+		.iter()
+		.flatten()
+		.map(|value| value.abs())
+		.reduce(f32::max)
+	{
+		Some(actual_max) => {
+			let quantised_max = ((actual_max * 166.0 - 0.5).floor() as i32).clamp(0, 82) as u32;
+			base83_encode(quantised_max, 1, &mut hash);
+			(quantised_max + 1) as f32 / 166.0
+		}
+		None => {
+			base83_encode(0, 1, &mut hash);
+			1.0
+		}
+	};
+
+	let [r, g, b] = dc.map(linear_to_srgb);
+	base83_encode((r << 16) + (g << 8) + b, 4, &mut hash);
+
+	for factor in ac {
+		let [r, g, b] = factor.map(|value| {
+			let value = (value / max_value).abs().sqrt().copysign(value);
+			(value * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+		});
+		base83_encode(r * 19 * 19 + g * 19 + b, 2, &mut hash);
+	}
+
+	hash
+}
+
+/// The average of the most common colors, once similar colors are grouped together.
+fn dominant_color(img: &RgbImage) -> String {
+	let mut buckets = HashMap::<_, (u32, [u32; 3])>::new();
+	for pixel in img.pixels() {
+		let (count, sum) = buckets
+			.entry(pixel.0.map(|channel| channel >> 4))
+			.or_default();
+		*count += 1;
+		for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+			*sum += u32::from(channel);
+		}
+	}
+
+	let [r, g, b] = buckets
+		.into_values()
+		.max_by_key(|(count, _)| *count)
+		.map(|(count, sum)| sum.map(|sum| sum / count))
+		.unwrap_or_default();
+
+	format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn base83_encode(value: u32, length: u32, hash: &mut String) {
+	for i in (0..length).rev() {
+		let digit = (value / 83u32.pow(i)) % 83;
+		hash.push(BASE83_CHARACTERS[digit as usize] as char);
+	}
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+	let value = f32::from(value) / 255.0;
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn linear_to_srgb(value: f32) -> u32 {
+	let value = value.clamp(0.0, 1.0);
+	if value <= 0.003_130_8 {
+		(value * 12.92 * 255.0 + 0.5) as u32
+	} else {
+		((1.055 * value.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use image::{ImageBuffer, Rgb};
+
+	#[test]
+	fn solid_color_placeholder() {
+		let img = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(64, 48, Rgb([255, 0, 0])));
+		let placeholder = Placeholder::new(&img);
+
+		// The size flag and maximum come first, then the red average, then 11 empty components
+		assert_eq!(placeholder.blurhash.len(), 28);
+		assert!(placeholder.blurhash.starts_with("L0TI:j"));
+		assert!(placeholder.blurhash.ends_with(&"fQ".repeat(11)));
+		assert_eq!(placeholder.dominant_color, "#ff0000");
+	}
+
+	#[test]
+	fn dominant_color_is_most_common() {
+		let img = DynamicImage::ImageRgb8(ImageBuffer::from_fn(32, 32, |x, _| {
+			if x < 24 {
+				Rgb([0, 0, 255])
+			} else {
+				Rgb([255, 255, 255])
+			}
+		}));
+
+		assert_eq!(Placeholder::new(&img).dominant_color, "#0000ff");
+	}
+}
@@ -0,0 +1,134 @@
+use super::{
+	can_generate_thumbnail_for_image, document::generate_text_thumbnail, generate_image_thumbnail,
+	ThumbnailSize,
+};
+
+use std::{collections::HashMap, error::Error, path::Path};
+
+use sd_file_ext::{
+	extensions::{
+		Extension, TextExtension, ALL_CODE_EXTENSIONS, ALL_IMAGE_EXTENSIONS, ALL_TEXT_EXTENSIONS,
+	},
+	magic::ExtensionPossibility,
+};
+
+#[cfg(feature = "ffmpeg")]
+use super::{can_generate_thumbnail_for_video, generate_video_thumbnail};
+#[cfg(feature = "ffmpeg")]
+use sd_file_ext::extensions::ALL_VIDEO_EXTENSIONS;
+
+#[cfg(feature = "pdf")]
+use super::document::generate_pdf_thumbnail;
+#[cfg(feature = "pdf")]
+use sd_file_ext::extensions::DocumentExtension;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// The thumbnailer for every extension that thumbnails can be generated for.
+static THUMBNAILERS: Lazy<HashMap<Extension, Thumbnailer>> = Lazy::new(|| {
+	let mut thumbnailers = HashMap::new();
+
+	thumbnailers.extend(
+		ALL_IMAGE_EXTENSIONS
+			.iter()
+			.filter(|extension| can_generate_thumbnail_for_image(extension))
+			.map(|extension| (Extension::Image(*extension), Thumbnailer::Image)),
+	);
+
+	#[cfg(feature = "ffmpeg")]
+	thumbnailers.extend(
+		ALL_VIDEO_EXTENSIONS
+			.iter()
+			.filter(|extension| can_generate_thumbnail_for_video(extension))
+			.map(|extension| (Extension::Video(*extension), Thumbnailer::Video)),
+	);
+
+	#[cfg(feature = "pdf")]
+	thumbnailers.insert(
+		Extension::Document(DocumentExtension::Pdf),
+		Thumbnailer::Pdf,
+	);
+
+	thumbnailers.extend(
+		ALL_TEXT_EXTENSIONS
+			.iter()
+			// RTF is mostly markup, so the first lines of it don't make a useful preview
+			.filter(|extension| !matches!(extension, TextExtension::Rtf))
+			.map(|extension| (Extension::Text(*extension), Thumbnailer::Text)),
+	);
+	thumbnailers.extend(
+		ALL_CODE_EXTENSIONS
+			.iter()
+			.map(|extension| (Extension::Code(*extension), Thumbnailer::Text)),
+	);
+
+	thumbnailers
+});
+
+/// How a thumbnail is generated for a file, which is decided by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Thumbnailer {
+	Image,
+	#[cfg(feature = "ffmpeg")]
+	Video,
+	/// The first page of the document is rendered.
+	#[cfg(feature = "pdf")]
+	Pdf,
+	/// The first lines of the file are rendered in a monospace font.
+	Text,
+}
+
+impl Thumbnailer {
+	pub fn for_extension(extension: &Extension) -> Option<Self> {
+		THUMBNAILERS.get(extension).copied()
+	}
+
+	/// Finds the thumbnailer for an extension as it's stored on a `file_path`.
+	///
+	/// When the extension is used by multiple kinds of file (such as `ts` for videos and TypeScript), the first kind with a thumbnailer is used.
+	pub fn for_extension_str(extension: &str) -> Option<Self> {
+		match Extension::from_str(extension)? {
+			ExtensionPossibility::Known(extension) => Self::for_extension(&extension),
+			ExtensionPossibility::Conflicts(extensions) => {
+				extensions.iter().find_map(Self::for_extension)
+			}
+		}
+	}
+
+	/// Every extension that thumbnails can be generated for, as they're stored on a `file_path`.
+	pub fn extensions() -> Vec<String> {
+		THUMBNAILERS.keys().map(ToString::to_string).collect()
+	}
+
+	/// Whether the thumbnail is a picture of the file, so a placeholder made from it looks like the file. Documents and text are
+	/// mostly blank page.
+	pub fn has_placeholder(self) -> bool {
+		match self {
+			Self::Image => true,
+			#[cfg(feature = "ffmpeg")]
+			Self::Video => true,
+			#[cfg(feature = "pdf")]
+			Self::Pdf => false,
+			Self::Text => false,
+		}
+	}
+
+	pub async fn generate(
+		self,
+		file_path: impl AsRef<Path>,
+		output_path: impl AsRef<Path>,
+		size: ThumbnailSize,
+	) -> Result<(), Box<dyn Error>> {
+		let (file_path, output_path) = (file_path.as_ref(), output_path.as_ref());
+
+		match self {
+			Self::Image => generate_image_thumbnail(file_path, output_path, size).await,
+			#[cfg(feature = "ffmpeg")]
+			Self::Video => generate_video_thumbnail(file_path, output_path, size).await,
+			#[cfg(feature = "pdf")]
+			Self::Pdf => generate_pdf_thumbnail(file_path, output_path, size).await,
+			Self::Text => generate_text_thumbnail(file_path, output_path, size).await,
+		}
+	}
+}
@@ -6,7 +6,7 @@ use crate::{
 	location::{
 		file_path_helper::{
 			ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
-			file_path_just_materialized_path_cas_id, get_existing_file_path_id, MaterializedPath,
+			file_path_for_thumbnailer, get_existing_file_path_id, MaterializedPath,
 		},
 		LocationId,
 	},
@@ -19,21 +19,15 @@ use std::{
 	path::{Path, PathBuf, MAIN_SEPARATOR_STR},
 };
 
-use sd_file_ext::extensions::Extension;
-
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tracing::info;
 
 use super::{
-	finalize_thumbnailer, process_step, ThumbnailerError, ThumbnailerJobReport,
-	ThumbnailerJobState, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_IMAGE_EXTENSIONS,
-	THUMBNAIL_CACHE_DIR_NAME,
+	finalize_thumbnailer, process_step, Thumbnailer, ThumbnailerError, ThumbnailerJobReport,
+	ThumbnailerJobState, ThumbnailerJobStep, THUMBNAIL_CACHE_DIR_NAME,
 };
 
-#[cfg(feature = "ffmpeg")]
-use super::FILTERED_VIDEO_EXTENSIONS;
-
 pub struct ShallowThumbnailerJob {}
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -104,42 +98,14 @@ impl StatefulJob for ShallowThumbnailerJob {
 			.expect("Location root path should already exist in the database")
 		};
 
-		info!("Searching for images in location {location_id} at parent directory with id {sub_path_id}");
+		info!("Searching for files in location {location_id} at parent directory with id {sub_path_id}");
 
 		// create all necessary directories if they don't exist
 		fs::create_dir_all(&thumbnail_dir).await?;
 
-		// query database for all image files in this location that need thumbnails
-		let image_files = get_files_by_extensions(
-			db,
-			location_id,
-			sub_path_id,
-			&FILTERED_IMAGE_EXTENSIONS,
-			ThumbnailerJobStepKind::Image,
-		)
-		.await?;
-		info!("Found {:?} image files", image_files.len());
-
-		#[cfg(feature = "ffmpeg")]
-		let all_files = {
-			// query database for all video files in this location that need thumbnails
-			let video_files = get_files_by_extensions(
-				db,
-				location_id,
-				sub_path_id,
-				&FILTERED_VIDEO_EXTENSIONS,
-				ThumbnailerJobStepKind::Video,
-			)
-			.await?;
-			info!("Found {:?} video files", video_files.len());
-
-			image_files
-				.into_iter()
-				.chain(video_files.into_iter())
-				.collect::<VecDeque<_>>()
-		};
-		#[cfg(not(feature = "ffmpeg"))]
-		let all_files = { image_files.into_iter().collect::<VecDeque<_>>() };
+		// query database for all files in this location that need thumbnails
+		let all_files = get_thumbnailable_files(db, location_id, sub_path_id).await?;
+		info!("Found {:?} files", all_files.len());
 
 		ctx.progress(vec![
 			JobReportUpdate::TaskCount(all_files.len()),
@@ -149,6 +115,7 @@ impl StatefulJob for ShallowThumbnailerJob {
 		state.data = Some(ThumbnailerJobState {
 			thumbnail_dir,
 			location_path,
+			video_previews: false,
 			report: ThumbnailerJobReport {
 				location_id,
 				materialized_path: if state.init.sub_path != Path::new("") {
@@ -194,24 +161,22 @@ impl StatefulJob for ShallowThumbnailerJob {
 	}
 }
 
-async fn get_files_by_extensions(
+async fn get_thumbnailable_files(
 	db: &PrismaClient,
 	location_id: LocationId,
 	parent_id: i32,
-	extensions: &[Extension],
-	kind: ThumbnailerJobStepKind,
-) -> Result<Vec<ThumbnailerJobStep>, JobError> {
+) -> Result<VecDeque<ThumbnailerJobStep>, JobError> {
 	Ok(db
 		.file_path()
 		.find_many(vec![
 			file_path::location_id::equals(location_id),
-			file_path::extension::in_vec(extensions.iter().map(ToString::to_string).collect()),
+			file_path::extension::in_vec(Thumbnailer::extensions()),
 			file_path::parent_id::equals(Some(parent_id)),
 		])
-		.select(file_path_just_materialized_path_cas_id::select())
+		.select(file_path_for_thumbnailer::select())
 		.exec()
 		.await?
 		.into_iter()
-		.map(|file_path| ThumbnailerJobStep { file_path, kind })
+		.filter_map(ThumbnailerJobStep::new)
 		.collect())
 }
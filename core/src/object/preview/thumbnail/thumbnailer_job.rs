@@ -4,29 +4,23 @@ use crate::{
 	},
 	library::Library,
 	location::file_path_helper::{
-		ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
-		file_path_just_materialized_path_cas_id, MaterializedPath,
+		ensure_sub_path_is_directory, ensure_sub_path_is_in_location, file_path_for_thumbnailer,
+		MaterializedPath,
 	},
 	prisma::{file_path, location, PrismaClient},
 };
 
 use std::{collections::VecDeque, hash::Hash, path::PathBuf};
 
-use sd_file_ext::extensions::Extension;
-
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tracing::info;
 
 use super::{
-	finalize_thumbnailer, process_step, ThumbnailerError, ThumbnailerJobReport,
-	ThumbnailerJobState, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_IMAGE_EXTENSIONS,
-	THUMBNAIL_CACHE_DIR_NAME,
+	finalize_thumbnailer, process_step, Thumbnailer, ThumbnailerError, ThumbnailerJobReport,
+	ThumbnailerJobState, ThumbnailerJobStep, THUMBNAIL_CACHE_DIR_NAME,
 };
 
-#[cfg(feature = "ffmpeg")]
-use super::FILTERED_VIDEO_EXTENSIONS;
-
 pub struct ThumbnailerJob {}
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -34,6 +28,9 @@ pub struct ThumbnailerJobInit {
 	pub location: location::Data,
 	pub sub_path: Option<PathBuf>,
 	pub background: bool,
+	/// Whether film strips and animated previews are generated for videos too, which takes much longer than their thumbnails.
+	#[serde(default)]
+	pub video_previews: bool,
 }
 
 impl Hash for ThumbnailerJobInit {
@@ -88,40 +85,14 @@ impl StatefulJob for ThumbnailerJob {
 				.map_err(ThumbnailerError::from)?
 		};
 
-		info!("Searching for images in location {location_id} at directory {materialized_path}");
+		info!("Searching for files in location {location_id} at directory {materialized_path}");
 
 		// create all necessary directories if they don't exist
 		fs::create_dir_all(&thumbnail_dir).await?;
 
-		// query database for all image files in this location that need thumbnails
-		let image_files = get_files_by_extensions(
-			db,
-			&materialized_path,
-			&FILTERED_IMAGE_EXTENSIONS,
-			ThumbnailerJobStepKind::Image,
-		)
-		.await?;
-		info!("Found {:?} image files", image_files.len());
-
-		#[cfg(feature = "ffmpeg")]
-		let all_files = {
-			// query database for all video files in this location that need thumbnails
-			let video_files = get_files_by_extensions(
-				db,
-				&materialized_path,
-				&FILTERED_VIDEO_EXTENSIONS,
-				ThumbnailerJobStepKind::Video,
-			)
-			.await?;
-			info!("Found {:?} video files", video_files.len());
-
-			image_files
-				.into_iter()
-				.chain(video_files.into_iter())
-				.collect::<VecDeque<_>>()
-		};
-		#[cfg(not(feature = "ffmpeg"))]
-		let all_files = { image_files.into_iter().collect::<VecDeque<_>>() };
+		// query database for all files in this location that need thumbnails
+		let all_files = get_thumbnailable_files(db, &materialized_path).await?;
+		info!("Found {:?} files", all_files.len());
 
 		ctx.progress(vec![
 			JobReportUpdate::TaskCount(all_files.len()),
@@ -131,6 +102,7 @@ impl StatefulJob for ThumbnailerJob {
 		state.data = Some(ThumbnailerJobState {
 			thumbnail_dir,
 			location_path,
+			video_previews: state.init.video_previews,
 			report: ThumbnailerJobReport {
 				location_id,
 				materialized_path: materialized_path.into(),
@@ -171,23 +143,21 @@ impl StatefulJob for ThumbnailerJob {
 	}
 }
 
-async fn get_files_by_extensions(
+async fn get_thumbnailable_files(
 	db: &PrismaClient,
 	materialized_path: &MaterializedPath<'_>,
-	extensions: &[Extension],
-	kind: ThumbnailerJobStepKind,
-) -> Result<Vec<ThumbnailerJobStep>, JobError> {
+) -> Result<VecDeque<ThumbnailerJobStep>, JobError> {
 	Ok(db
 		.file_path()
 		.find_many(vec![
 			file_path::location_id::equals(materialized_path.location_id()),
-			file_path::extension::in_vec(extensions.iter().map(ToString::to_string).collect()),
+			file_path::extension::in_vec(Thumbnailer::extensions()),
 			file_path::materialized_path::starts_with(materialized_path.into()),
 		])
-		.select(file_path_just_materialized_path_cas_id::select())
+		.select(file_path_for_thumbnailer::select())
 		.exec()
 		.await?
 		.into_iter()
-		.map(|file_path| ThumbnailerJobStep { file_path, kind })
+		.filter_map(ThumbnailerJobStep::new)
 		.collect())
 }
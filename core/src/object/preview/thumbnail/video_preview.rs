@@ -0,0 +1,134 @@
+//! Film strips and animated previews of videos, which are shown while hovering over them in the explorer.
+
+use super::{
+	thumbstrip_path, video_preview_path, ThumbnailSize, ThumbnailerError, THUMBNAIL_QUALITY,
+};
+
+use crate::{
+	invalidate_query,
+	job::{JobError, WorkerContext},
+	prisma::object,
+};
+
+use std::{error::Error, future::Future, path::Path, time::Duration};
+
+use sd_ffmpeg::{to_animated_preview, to_film_strip};
+use tokio::{fs, io};
+use tracing::{error, info};
+
+/// The film strip is scrubbed through while hovering, so it has more frames than the preview plays.
+const FILM_STRIP_FRAMES: u32 = 10;
+const VIDEO_PREVIEW_FRAMES: u32 = 12;
+const VIDEO_PREVIEW_FRAME_DURATION: Duration = Duration::from_millis(500);
+/// Previews are shown larger than thumbnails, so they're at a lower quality to keep them small.
+const VIDEO_PREVIEW_EDGE: u32 = 256;
+const VIDEO_PREVIEW_QUALITY: f32 = 20.0;
+
+pub async fn generate_film_strip(
+	file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+	if let Some(parent) = output_path.as_ref().parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	to_film_strip(
+		file_path,
+		output_path,
+		FILM_STRIP_FRAMES,
+		ThumbnailSize::Small.max_edge(),
+		THUMBNAIL_QUALITY,
+	)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn generate_video_preview(
+	file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+	if let Some(parent) = output_path.as_ref().parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	to_animated_preview(
+		file_path,
+		output_path,
+		VIDEO_PREVIEW_FRAMES,
+		VIDEO_PREVIEW_EDGE,
+		VIDEO_PREVIEW_FRAME_DURATION,
+		VIDEO_PREVIEW_QUALITY,
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// Generates whichever of the video's film strip and animated preview don't exist yet, and flags the object as having them.
+pub(super) async fn process_video_previews(
+	is_background: bool,
+	path: &Path,
+	thumbnail_dir: &Path,
+	cas_id: &str,
+	object_id: i32,
+	ctx: &WorkerContext,
+) -> Result<(), JobError> {
+	let mut params = Vec::with_capacity(2);
+
+	if generate_if_missing(
+		&thumbstrip_path(thumbnail_dir, cas_id),
+		generate_film_strip(path, thumbstrip_path(thumbnail_dir, cas_id)),
+	)
+	.await?
+	{
+		params.push(object::has_thumbstrip::set(true));
+	}
+
+	if generate_if_missing(
+		&video_preview_path(thumbnail_dir, cas_id),
+		generate_video_preview(path, video_preview_path(thumbnail_dir, cas_id)),
+	)
+	.await?
+	{
+		params.push(object::has_video_preview::set(true));
+	}
+
+	if params.is_empty() {
+		return Ok(());
+	}
+
+	ctx.library
+		.db
+		.object()
+		.update(object::id::equals(object_id), params)
+		.exec()
+		.await?;
+
+	if !is_background {
+		invalidate_query!(ctx.library, "locations.getExplorerData");
+	}
+
+	Ok(())
+}
+
+/// Returns whether the file exists once it's been generated, as failing to generate it doesn't fail the job.
+async fn generate_if_missing(
+	output_path: &Path,
+	generate: impl Future<Output = Result<(), Box<dyn Error>>>,
+) -> Result<bool, JobError> {
+	match fs::metadata(output_path).await {
+		Ok(_) => Ok(true),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			info!("Writing {:?}", output_path);
+
+			if let Err(e) = generate.await {
+				error!("Error generating {:?}: {:#?}", output_path, e);
+				return Ok(false);
+			}
+
+			Ok(true)
+		}
+		Err(e) => Err(ThumbnailerError::from(e).into()),
+	}
+}
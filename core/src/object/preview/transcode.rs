@@ -0,0 +1,159 @@
+//! HLS streams of videos and audio that browsers can't play, such as HEVC, MKV, AVI and FLAC.
+//!
+//! Segments are only transcoded when they're first requested, and are then kept in a directory for the file's `cas_id`.
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use sd_ffmpeg::{
+	hls_playlist, media_duration, to_hls_segment, ThumbnailerError, HLS_SEGMENT_DURATION,
+};
+use tokio::{fs, sync::Semaphore};
+use tracing::info;
+use uuid::Uuid;
+
+/// Transcoding takes most of a CPU core, so only this many segments are transcoded at once.
+const MAX_CONCURRENT_TRANSCODES: usize = 2;
+
+static TRANSCODE_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(MAX_CONCURRENT_TRANSCODES));
+
+/// The segments which are being transcoded, so concurrent requests for a segment wait for it to be transcoded once.
+static TRANSCODING_SEGMENTS: Lazy<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> =
+	Lazy::new(Default::default);
+
+pub fn hls_playlist_path(transcode_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+	transcode_dir.as_ref().join(cas_id).join("index.m3u8")
+}
+
+pub fn hls_segment_path(transcode_dir: impl AsRef<Path>, cas_id: &str, index: u32) -> PathBuf {
+	transcode_dir
+		.as_ref()
+		.join(cas_id)
+		.join(index.to_string())
+		.with_extension("ts")
+}
+
+/// The playlist refers to its segments relative to itself, so they're requested from alongside it.
+///
+/// It's kept with the segments, and as it has an entry for every segment it's also what the number of segments is read from,
+/// so the media is only probed for its duration once.
+pub(crate) async fn hls_playlist_for(
+	media_path: &Path,
+	transcode_dir: &Path,
+	cas_id: &str,
+) -> Result<String, ThumbnailerError> {
+	let playlist_path = hls_playlist_path(transcode_dir, cas_id);
+	if let Ok(playlist) = fs::read_to_string(&playlist_path).await {
+		return Ok(playlist);
+	}
+
+	let playlist = hls_playlist(
+		media_duration(media_path).await?,
+		HLS_SEGMENT_DURATION,
+		|index| format!("{index}.ts"),
+	);
+
+	write_atomically(&playlist_path, playlist.as_bytes()).await?;
+
+	Ok(playlist)
+}
+
+/// Returns the path of the segment once it's been transcoded, or `None` if the media isn't long enough to have it.
+pub(crate) async fn hls_segment_for(
+	media_path: &Path,
+	transcode_dir: &Path,
+	cas_id: &str,
+	index: u32,
+) -> Result<Option<PathBuf>, ThumbnailerError> {
+	let segment_path = hls_segment_path(transcode_dir, cas_id, index);
+	if fs::metadata(&segment_path).await.is_ok() {
+		return Ok(Some(segment_path));
+	}
+
+	let segment_count = hls_playlist_for(media_path, transcode_dir, cas_id)
+		.await?
+		.lines()
+		.filter(|line| line.starts_with("#EXTINF:"))
+		.count();
+	if index as usize >= segment_count {
+		return Ok(None);
+	}
+
+	let lock = TRANSCODING_SEGMENTS
+		.lock()
+		.unwrap()
+		.entry(segment_path.clone())
+		.or_default()
+		.clone();
+
+	let result = {
+		let _guard = lock.lock().await;
+		// another request may have transcoded it while this one was waiting
+		if fs::metadata(&segment_path).await.is_ok() {
+			Ok(Some(segment_path.clone()))
+		} else {
+			transcode_segment(media_path, &segment_path, index).await
+		}
+	};
+
+	// the last request for the segment to finish removes it, as the others still hold a reference
+	let mut transcoding = TRANSCODING_SEGMENTS.lock().unwrap();
+	if Arc::strong_count(&lock) == 2 {
+		transcoding.remove(&segment_path);
+	}
+
+	result
+}
+
+async fn transcode_segment(
+	media_path: &Path,
+	segment_path: &Path,
+	index: u32,
+) -> Result<Option<PathBuf>, ThumbnailerError> {
+	let _permit = TRANSCODE_PERMITS
+		.acquire()
+		.await
+		.expect("the transcode semaphore is never closed");
+
+	info!("Transcoding segment {index} of {:?}", media_path);
+
+	let partial_path = partial_path(segment_path);
+	if let Some(parent) = partial_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	if let Err(e) = to_hls_segment(
+		media_path,
+		&partial_path,
+		HLS_SEGMENT_DURATION * index,
+		HLS_SEGMENT_DURATION,
+	)
+	.await
+	{
+		fs::remove_file(&partial_path).await.ok();
+		return Err(e);
+	}
+
+	fs::rename(&partial_path, segment_path).await?;
+
+	Ok(Some(segment_path.to_path_buf()))
+}
+
+/// Files are written elsewhere and then moved into place, so one which another request is still writing is never served.
+async fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), ThumbnailerError> {
+	let partial_path = partial_path(path);
+	if let Some(parent) = partial_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	fs::write(&partial_path, contents).await?;
+	fs::rename(&partial_path, path).await.map_err(Into::into)
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+	path.with_file_name(format!(".{}.partial", Uuid::new_v4().simple()))
+}
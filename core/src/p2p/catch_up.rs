@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use sd_p2p::{
+	spacetime::{SpaceTimeStream, UnicastStream},
+	Manager, PeerId, Priority,
+};
+use sd_sync::CRDTOperation;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	library::Library,
+	sync::{GetOpsArgs, IngestError},
+};
+
+use super::{pairing, Header, PeerMetadata};
+
+/// How many operations are requested from a peer at once.
+const PAGE_SIZE: u32 = 1000;
+
+/// The largest message (a request or a page of operations) that will be accepted from a peer.
+pub(super) const MAX_MESSAGE_LEN: u32 = 64 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum CatchUpError {
+	#[error("failed to open a stream to the peer")]
+	Stream,
+	#[error("received a request over a broadcast stream")]
+	BroadcastStream,
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("message of {0} bytes is too large")]
+	MessageTooLarge(u32),
+	#[error("failed to encode message: {0}")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("failed to decode message: {0}")]
+	Decode(#[from] rmp_serde::decode::Error),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to ingest operation: {0}")]
+	Ingest(#[from] IngestError),
+}
+
+/// Brings a library up to date with a peer, by requesting every operation we haven't seen a page at a time.
+///
+/// The clocks sent with each request are advanced from the operations that were received, rather than read back from the database,
+/// so operations that can never be ingested don't stop us from making progress. Any other error stops catching up before the
+/// operation's clock is advanced, so it's requested again next time.
+pub(super) async fn catch_up(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	library: &Library,
+) -> Result<usize, CatchUpError> {
+	let mut clocks = library
+		.sync
+		.get_clocks()
+		.await?
+		.into_iter()
+		.collect::<HashMap<_, _>>();
+	let mut received = 0;
+
+	loop {
+		let ops = request_page(
+			manager,
+			peer_id,
+			library.id,
+			&GetOpsArgs {
+				node: library.sync.node(),
+				clocks: clocks.iter().map(|(node, clock)| (*node, *clock)).collect(),
+				count: PAGE_SIZE,
+			},
+		)
+		.await?;
+		let len = ops.len();
+
+		for op in ops {
+			let (node, timestamp) = (op.node, op.timestamp);
+
+			match library.sync.ingest_op(op).await {
+				Ok(()) => {}
+				Err(e) if e.is_permanent() => {
+					warn!("Skipping operation from peer '{peer_id}' which can't be ingested: {e}");
+				}
+				Err(e) => return Err(e.into()),
+			}
+
+			let clock = clocks.entry(node).or_insert(timestamp);
+			*clock = (*clock).max(timestamp);
+		}
+
+		received += len;
+		debug!(
+			"Received page of {len} operations for library '{}' from peer '{peer_id}'",
+			library.id
+		);
+
+		if len < PAGE_SIZE as usize {
+			break;
+		}
+	}
+
+	if received > 0 {
+		info!(
+			"Caught up library '{}' with {received} operations from peer '{peer_id}'",
+			library.id
+		);
+	}
+
+	Ok(received)
+}
+
+async fn request_page(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	library_id: Uuid,
+	args: &GetOpsArgs,
+) -> Result<Vec<CRDTOperation>, CatchUpError> {
+	let mut stream = manager
+		.stream(peer_id)
+		.await
+		.map_err(|_| CatchUpError::Stream)?;
+	stream.set_priority(Priority::High);
+
+	let args = rmp_serde::to_vec_named(args)?;
+	let mut buf = Header::GetOperations(library_id, args.len() as u32).to_bytes();
+	buf.extend_from_slice(&args);
+
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	let buf = read_message(&mut stream).await?;
+	Ok(rmp_serde::from_slice(&buf)?)
+}
+
+/// Responds to a peer's `Header::GetOperations` with a page of operations.
+///
+/// An empty page is sent if we don't have the library (or the peer hasn't been paired with it), so the peer isn't left waiting.
+///
+/// The request acknowledges the operations the peer already has, which is recorded against the node that's bound to the peer
+/// rather than the node in the request, so a peer can't acknowledge operations on behalf of another.
+pub(super) async fn respond(
+	stream: &mut SpaceTimeStream,
+	library: Option<Library>,
+	peer_id: PeerId,
+	len: u32,
+) -> Result<(), CatchUpError> {
+	let SpaceTimeStream::Unicast(stream) = stream else {
+		return Err(CatchUpError::BroadcastStream);
+	};
+	stream.set_priority(Priority::High);
+
+	if len > MAX_MESSAGE_LEN {
+		return Err(CatchUpError::MessageTooLarge(len));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+	let args: GetOpsArgs = rmp_serde::from_slice(&buf)?;
+
+	let ops = match library {
+		Some(library) => {
+			if let Some(node) = pairing::paired_node(&library, peer_id, args.node).await? {
+				library.sync.acknowledge(node, &args.clocks).await?;
+			}
+
+			library
+				.sync
+				.get_ops_after(GetOpsArgs {
+					count: args.count.min(PAGE_SIZE),
+					..args
+				})
+				.await?
+		}
+		None => Vec::new(),
+	};
+
+	let buf = rmp_serde::to_vec_named(&ops)?;
+	stream.write_all(&(buf.len() as u32).to_le_bytes()).await?;
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	Ok(())
+}
+
+async fn read_message(stream: &mut UnicastStream) -> Result<Vec<u8>, CatchUpError> {
+	let len = stream.read_u32_le().await?;
+	if len > MAX_MESSAGE_LEN {
+		return Err(CatchUpError::MessageTooLarge(len));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+	Ok(buf)
+}
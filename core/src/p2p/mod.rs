@@ -1,9 +1,17 @@
+mod catch_up;
 mod p2p_manager;
+mod pairing;
 mod peer_metadata;
 mod protocol;
+mod remote;
+mod spacedrop;
 
 pub use p2p_manager::*;
+pub(crate) use pairing::add_pending_pairings;
+pub use pairing::{PairingCode, PairingError};
 pub use peer_metadata::*;
 pub use protocol::*;
+pub use remote::RemoteError;
+pub use spacedrop::SPACEDROP_TIMEOUT;
 
 pub(super) const SPACEDRIVE_APP_ID: &str = "spacedrive";
@@ -1,28 +1,49 @@
-use std::{path::PathBuf, sync::Arc, time::Instant};
+use std::{
+	net::SocketAddr,
+	path::PathBuf,
+	sync::{Arc, Weak},
+	time::Duration,
+};
 
+use once_cell::sync::OnceCell;
 use rspc::Type;
 use sd_p2p::{
-	spaceblock::{BlockSize, TransferRequest},
-	Event, Manager, MetadataManager, PeerId,
+	spaceblock::SpaceblockError, spacetime::SpaceTimeStream, BandwidthLimits, Event, Manager,
+	MetadataManager, PeerId, Priority,
 };
 use sd_sync::CRDTOperation;
 use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
 use tokio::{
-	fs::File,
-	io::{AsyncReadExt, AsyncWriteExt, BufReader},
+	io::{AsyncReadExt, AsyncWriteExt},
 	sync::broadcast,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::{
-	node::{NodeConfig, NodeConfigManager},
+	api::locations::ExplorerData,
+	library::{Library, LibraryManager},
+	node::{NodeConfig, NodeConfigError, NodeConfigManager},
 	p2p::{OperatingSystem, SPACEDRIVE_APP_ID},
 };
 
-use super::{Header, PeerMetadata};
+use super::{
+	catch_up,
+	pairing::{self, PairingCode, PairingError, Pairings},
+	remote::{self, RemoteError, RemoteFile},
+	spacedrop::{build_manifest, Spacedrops},
+	Header, PeerMetadata,
+};
+
+/// How many of the last known addresses of each paired peer are remembered.
+const MAX_KNOWN_ADDRESSES: usize = 5;
+
+/// How long we wait to connect to a discovered peer before opening a stream to it anyway.
+const DIAL_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// TODO: P2P event for the frontend
+#[serde_as]
 #[derive(Debug, Clone, Type, Serialize)]
 #[serde(tag = "type")]
 pub enum P2PEvent {
@@ -35,21 +56,78 @@ pub enum P2PEvent {
 		library_id: Uuid,
 		operations: Vec<CRDTOperation>,
 	},
+	/// A peer would like to send us files. This must be accepted with `p2p.acceptSpacedrop` or rejected with `p2p.rejectSpacedrop`.
+	SpacedropRequest {
+		id: Uuid,
+		peer_id: PeerId,
+		files: Vec<String>,
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		total_size: u64,
+	},
+	/// Emitted for both sent and received Spacedrops.
+	SpacedropProgress {
+		id: Uuid,
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		transferred: u64,
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		total: u64,
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		bytes_per_second: u64,
+	},
+	SpacedropCompleted {
+		id: Uuid,
+		/// The files and directories that were received (not everything within them). This is empty for the sender.
+		received: Vec<PathBuf>,
+	},
+	SpacedropFailed {
+		id: Uuid,
+		error: String,
+	},
+	/// The traffic with a peer over the last second. This is emitted while we're sending or receiving data from the peer, and once more after we stop.
+	PeerThroughput {
+		peer_id: PeerId,
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		upload_bytes_per_second: u64,
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		download_bytes_per_second: u64,
+	},
 }
 
 pub struct P2PManager {
 	pub events: broadcast::Sender<P2PEvent>,
 	pub manager: Arc<Manager<PeerMetadata>>,
 	pub metadata_manager: Arc<MetadataManager<PeerMetadata>>,
+	spacedrops: Arc<Spacedrops>,
+	pairings: Arc<Pairings>,
+	node_config: Arc<NodeConfigManager>,
+	// This is set once the `LibraryManager` has been created, as it depends on the `P2PManager`
+	library_manager: Arc<OnceCell<Weak<LibraryManager>>>,
 }
 
 impl P2PManager {
 	pub async fn new(
 		node_config: Arc<NodeConfigManager>,
 	) -> (Arc<Self>, broadcast::Receiver<P2PEvent>) {
-		let (config, keypair) = {
+		let (config, keypair, known_addrs, bandwidth_limits) = {
 			let config = node_config.get().await;
-			(Self::config_to_metadata(&config), config.keypair)
+			let known_addrs = config
+				.p2p_manual_peers
+				.iter()
+				.chain(config.p2p_known_peers.values().flatten())
+				.copied()
+				.collect::<Vec<_>>();
+			(
+				Self::config_to_metadata(&config),
+				config.keypair,
+				known_addrs,
+				config.p2p_bandwidth_limits,
+			)
 		};
 
 		let metadata_manager = MetadataManager::new(config);
@@ -59,16 +137,31 @@ impl P2PManager {
 				.await
 				.unwrap();
 
+		manager.set_bandwidth_limits(bandwidth_limits);
+
 		info!(
 			"Node '{}' is now online listening at addresses: {:?}",
 			manager.peer_id(),
 			manager.listen_addrs().await
 		);
 
+		// these are the peers which mDNS won't find, if they're on another network
+		for addr in known_addrs {
+			manager.add_known_peer(addr).await;
+		}
+
 		let (tx, rx) = broadcast::channel(100);
+		let spacedrops = Arc::new(Spacedrops::default());
+		let pairings = Arc::new(Pairings::default());
+		let library_manager = Arc::new(OnceCell::<Weak<LibraryManager>>::new());
 
 		tokio::spawn({
 			let events = tx.clone();
+			let spacedrops = spacedrops.clone();
+			let pairings = pairings.clone();
+			let library_manager = library_manager.clone();
+			let manager = manager.clone();
+			let node_config = node_config.clone();
 
 			async move {
 				let mut shutdown = false;
@@ -88,11 +181,71 @@ impl P2PManager {
 								.map_err(|_| error!("Failed to send event to p2p event stream!"))
 								.ok();
 
-							// TODO: Don't just connect to everyone when we find them. We should only do it if we know them.
-							event.dial().await;
+							// we only connect to the peers we know, and anyone else is dialed when we need to send them something
+							let library_manager = library_manager.clone();
+							let node_config = node_config.clone();
+							tokio::spawn(async move {
+								if is_known_peer(&library_manager, &node_config, event.peer_id)
+									.await
+								{
+									event.dial().await;
+								}
+							});
+						}
+						Event::PeerConnected(peer) => {
+							// we pull anything we missed while we were offline for the libraries we share, and the peer does the same
+							let library_manager =
+								match library_manager.get().and_then(Weak::upgrade) {
+									Some(library_manager) => library_manager,
+									None => continue,
+								};
+							let manager = manager.clone();
+							let node_config = node_config.clone();
+
+							tokio::spawn(async move {
+								let mut paired = false;
+								for library in library_manager.get_all_libraries().await {
+									if !is_paired(&library, peer.peer_id).await {
+										continue;
+									}
+									paired = true;
+
+									catch_up::catch_up(&manager, peer.peer_id, &library)
+										.await
+										.map_err(|e| {
+											error!(
+												"Failed to catch up library '{}' with peer '{}': {e}",
+												library.id, peer.peer_id
+											)
+										})
+										.ok();
+								}
+
+								// we remember where we found paired peers, so we can reconnect to them when mDNS can't find them
+								if let Some(address) = peer.address.filter(|_| paired) {
+									remember_peer_address(&node_config, peer.peer_id, address)
+										.await;
+									manager.add_known_peer(address).await;
+								}
+							});
+						}
+						Event::Throughput { peers } => {
+							for peer in peers {
+								events
+									.send(P2PEvent::PeerThroughput {
+										peer_id: peer.peer_id,
+										upload_bytes_per_second: peer.upload,
+										download_bytes_per_second: peer.download,
+									})
+									.ok();
+							}
 						}
 						Event::PeerMessage(mut event) => {
 							let events = events.clone();
+							let spacedrops = spacedrops.clone();
+							let pairings = pairings.clone();
+							let library_manager = library_manager.clone();
+							let our_peer_id = manager.peer_id();
 
 							tokio::spawn(async move {
 								let header = Header::from_stream(&mut event.stream).await.unwrap();
@@ -101,30 +254,57 @@ impl P2PManager {
 									Header::Ping => {
 										debug!("Received ping from peer '{}'", event.peer_id);
 									}
-									Header::Spacedrop(req) => {
-										info!("Received Spacedrop from peer '{}' for file '{}' with file length '{}'", event.peer_id, req.name, req.size);
-
-										// TODO: Ask the user if they wanna reject/accept it
-
-										// TODO: Deal with binary data. Deal with blocking based on `req.block_size`, etc
-										let mut s = String::new();
-										event.stream.read_to_string(&mut s).await.unwrap();
-
-										println!(
-										"Recieved file '{}' with content '{}' through Spacedrop!",
-										req.name, s
-									);
-
-										// TODO: Save to the filesystem
+									Header::Spacedrop(manifest) => {
+										info!("Received Spacedrop from peer '{}' for {} files with a total length of '{}'", event.peer_id, manifest.files.len(), manifest.total_size());
+
+										spacedrops
+											.receive(
+												&events,
+												event.peer_id,
+												&mut event.stream,
+												manifest,
+											)
+											.await;
 									}
 									Header::Sync(library_id, len) => {
-										let mut buf = vec![0; len as usize]; // TODO: Designed for easily being able to be DOS the current Node
-										event.stream.read_exact(&mut buf).await.unwrap();
-
-										let mut buf: &[u8] = &buf;
-										let operations = rmp_serde::from_read(&mut buf).unwrap();
-
-										println!("Received sync events for library '{library_id}': {operations:?}");
+										if paired_library(
+											&library_manager,
+											library_id,
+											event.peer_id,
+										)
+										.await
+										.is_none()
+										{
+											warn!("Rejected sync events for library '{library_id}' from peer '{}' as it isn't paired with the library", event.peer_id);
+											return;
+										}
+
+										if let SpaceTimeStream::Unicast(stream) = &mut event.stream
+										{
+											stream.set_priority(Priority::High);
+										}
+
+										if len > catch_up::MAX_MESSAGE_LEN {
+											warn!("Rejected sync events of {len} bytes for library '{library_id}' from peer '{}' as they're too large", event.peer_id);
+											return;
+										}
+
+										let mut buf = vec![0; len as usize];
+										if let Err(e) = event.stream.read_exact(&mut buf).await {
+											error!("Failed to read sync events for library '{library_id}' from peer '{}': {e}", event.peer_id);
+											return;
+										}
+
+										let operations: Vec<CRDTOperation> =
+											match rmp_serde::from_slice(&buf) {
+												Ok(operations) => operations,
+												Err(e) => {
+													error!("Failed to decode sync events for library '{library_id}' from peer '{}': {e}", event.peer_id);
+													return;
+												}
+											};
+
+										debug!("Received sync events for library '{library_id}': {operations:?}");
 
 										events
 											.send(P2PEvent::SyncOperation {
@@ -133,6 +313,73 @@ impl P2PManager {
 											})
 											.ok();
 									}
+									Header::GetOperations(library_id, len) => {
+										let library = paired_library(
+											&library_manager,
+											library_id,
+											event.peer_id,
+										)
+										.await;
+
+										catch_up::respond(
+											&mut event.stream,
+											library,
+											event.peer_id,
+											len,
+										)
+										.await
+										.map_err(|e| {
+											error!(
+													"Failed to respond to sync request from peer '{}': {e}",
+													event.peer_id
+												)
+										})
+										.ok();
+									}
+									Header::Request(library_id, len) => {
+										let library = paired_library(
+											&library_manager,
+											library_id,
+											event.peer_id,
+										)
+										.await;
+
+										remote::respond(&mut event.stream, library, len)
+											.await
+											.map_err(|e| {
+												error!(
+													"Failed to respond to remote request from peer '{}': {e}",
+													event.peer_id
+												)
+											})
+											.ok();
+									}
+									Header::Pair(library_id, proof) => {
+										let library =
+											match library_manager.get().and_then(Weak::upgrade) {
+												Some(library_manager) => {
+													library_manager.get_ctx(library_id).await
+												}
+												None => None,
+											};
+
+										pairings
+											.respond(
+												&mut event.stream,
+												library,
+												our_peer_id,
+												event.peer_id,
+												proof,
+											)
+											.await
+											.map_err(|e| {
+												warn!(
+													"Failed to pair peer '{}' with library '{library_id}': {e}",
+													event.peer_id
+												)
+											})
+											.ok();
+									}
 								}
 							});
 						}
@@ -160,6 +407,10 @@ impl P2PManager {
 			events: tx,
 			manager,
 			metadata_manager,
+			spacedrops,
+			pairings,
+			node_config,
+			library_manager,
 		});
 
 		// TODO: Probs remove this once connection timeout/keepalive are working correctly
@@ -187,8 +438,12 @@ impl P2PManager {
 						.into_iter();
 					if let Some(peer_id) = connected.next() {
 						info!("Starting Spacedrop to peer '{}'", peer_id);
-						this.big_bad_spacedrop(peer_id, PathBuf::from("./demo.txt"))
-							.await;
+						if let Err(e) = this
+							.spacedrop(peer_id, vec![PathBuf::from("./demo.txt")])
+							.await
+						{
+							error!("Failed to start Spacedrop demo: {e}");
+						}
 					} else {
 						info!("No clients found so skipping Spacedrop demo!");
 					}
@@ -245,60 +500,365 @@ impl P2PManager {
 			.update(Self::config_to_metadata(&node_config_manager.get().await));
 	}
 
+	/// Allows the `P2PManager` to serve and catch up the node's libraries with its peers.
+	pub fn set_library_manager(&self, library_manager: &Arc<LibraryManager>) {
+		self.library_manager
+			.set(Arc::downgrade(library_manager))
+			.map_err(|_| error!("The library manager has already been set!"))
+			.ok();
+	}
+
 	pub fn subscribe(&self) -> broadcast::Receiver<P2PEvent> {
 		self.events.subscribe()
 	}
 
-	#[allow(unused)] // TODO: Remove `allow(unused)` once integrated
-	pub async fn broadcast_sync_events(&self, library_id: Uuid, event: Vec<CRDTOperation>) {
+	/// Sends sync events to every connected peer which has been paired with the library.
+	pub async fn broadcast_sync_events(&self, library: &Library, event: Vec<CRDTOperation>) {
 		let mut buf = rmp_serde::to_vec_named(&event).unwrap(); // TODO: Error handling
-		let mut head_buf = Header::Sync(library_id, buf.len() as u32).to_bytes(); // Max Sync payload is like 4GB
+		if buf.len() > catch_up::MAX_MESSAGE_LEN as usize {
+			// peers would reject it, and the operations can still be caught up on instead
+			warn!(
+				"Not broadcasting {} bytes of sync events, as they're too large",
+				buf.len()
+			);
+			return;
+		}
+		let mut head_buf = Header::Sync(library.id, buf.len() as u32).to_bytes();
 		head_buf.append(&mut buf);
 
-		debug!("broadcasting sync events. payload_len={}", buf.len());
+		debug!("broadcasting sync events. payload_len={}", head_buf.len());
+
+		for peer_id in self.manager.get_connected_peers().await.unwrap_or_default() {
+			if !is_paired(library, peer_id).await {
+				continue;
+			}
 
-		self.manager.broadcast(head_buf).await;
+			match self.manager.stream(peer_id).await {
+				Ok(mut stream) => {
+					stream.set_priority(Priority::High);
+					if let Err(e) = stream.write_all(&head_buf).await {
+						error!("Failed to send sync events to peer '{peer_id}': {e}");
+					}
+				}
+				Err(_) => error!("Failed to open a stream to peer '{peer_id}'"),
+			}
+		}
 	}
 
 	pub async fn ping(&self) {
 		self.manager.broadcast(Header::Ping.to_bytes()).await;
 	}
 
-	pub async fn big_bad_spacedrop(&self, peer_id: PeerId, path: PathBuf) {
-		let mut stream = self.manager.stream(peer_id).await.unwrap(); // TODO: handle providing incorrect peer id
-
-		let file = File::open(&path).await.unwrap();
-		let metadata = file.metadata().await.unwrap();
-		let mut reader = BufReader::new(file);
+	/// Sends files and directories to a peer. The transfer happens in the background once the peer accepts it.
+	///
+	/// The returned id is used for its `P2PEvent`s, and for cancelling it.
+	pub async fn spacedrop(
+		self: &Arc<Self>,
+		peer_id: PeerId,
+		paths: Vec<PathBuf>,
+	) -> Result<Uuid, SpaceblockError> {
+		let (manifest, sources) = build_manifest(&paths).await?;
+
+		dial_discovered(&self.manager, peer_id).await;
+		let mut stream = self
+			.manager
+			.stream(peer_id)
+			.await
+			.map_err(|_| SpaceblockError::Stream)?;
 
 		stream
-			.write_all(
-				&Header::Spacedrop(TransferRequest {
-					name: path.file_name().unwrap().to_str().unwrap().to_string(), // TODO: Encode this as bytes instead
-					size: metadata.len(),
-					block_size: BlockSize::from_size(metadata.len()),
-				})
-				.to_bytes(),
-			)
+			.write_all(&Header::Spacedrop(manifest.clone()).to_bytes())
+			.await?;
+
+		let id = Uuid::new_v4();
+
+		tokio::spawn({
+			let this = self.clone();
+			async move {
+				this.spacedrops
+					.send(&this.events, id, peer_id, stream, manifest, sources)
+					.await;
+			}
+		});
+
+		Ok(id)
+	}
+
+	/// Connects to a peer at an address, such as one which can't be discovered because it's on another network.
+	/// The address is saved to the node's config, so it's reconnected to whenever the node starts.
+	pub async fn connect(&self, addr: SocketAddr) -> Result<(), NodeConfigError> {
+		self.node_config
+			.write(|mut config| {
+				if !config.p2p_manual_peers.contains(&addr) {
+					config.p2p_manual_peers.push(addr);
+				}
+			})
+			.await?;
+
+		self.manager.add_known_peer(addr).await;
+
+		Ok(())
+	}
+
+	/// Forgets the addresses of the peers which are no longer paired with any of our libraries, so they aren't redialed.
+	///
+	/// Addresses which were added manually with `connect` are still redialed.
+	pub async fn prune_known_peers(&self) {
+		let Some(library_manager) = self.library_manager.get().and_then(Weak::upgrade) else {
+			return;
+		};
+		let libraries = library_manager.get_all_libraries().await;
+		let config = self.node_config.get().await;
+
+		let mut unpaired = Vec::new();
+		for peer_id in config.p2p_known_peers.keys() {
+			let mut paired = false;
+			for library in &libraries {
+				if is_paired(library, *peer_id).await {
+					paired = true;
+					break;
+				}
+			}
+
+			if !paired {
+				unpaired.push(*peer_id);
+			}
+		}
+
+		if unpaired.is_empty() {
+			return;
+		}
+
+		for peer_id in &unpaired {
+			for addr in &config.p2p_known_peers[peer_id] {
+				if !config.p2p_manual_peers.contains(addr) {
+					self.manager.remove_known_peer(*addr).await;
+				}
+			}
+		}
+
+		self.node_config
+			.write(|mut config| {
+				for peer_id in &unpaired {
+					config.p2p_known_peers.remove(peer_id);
+				}
+			})
 			.await
-			.unwrap();
+			.map_err(|e| error!("Failed to remove the addresses of unpaired peers: {e}"))
+			.ok();
+	}
 
-		debug!("Starting Spacedrop to peer '{peer_id}'");
-		let i = Instant::now();
+	/// Limits how fast data is sent to and received from peers. The limits are saved to the node's config.
+	pub async fn set_bandwidth_limits(
+		&self,
+		limits: BandwidthLimits,
+	) -> Result<(), NodeConfigError> {
+		self.node_config
+			.write(|mut config| config.p2p_bandwidth_limits = limits)
+			.await?;
 
-		// TODO: Replace this with the Spaceblock `Block` system
-		let mut buffer = Vec::new();
-		reader.read_to_end(&mut buffer).await.unwrap();
-		println!("READ {:?}", buffer);
-		stream.write_all(&buffer).await.unwrap();
+		self.manager.set_bandwidth_limits(limits);
 
-		debug!(
-			"Finished Spacedrop to peer '{peer_id}' after '{:?}",
-			i.elapsed()
-		);
+		Ok(())
+	}
+
+	/// Creates a code which allows a peer to pair with a library. See `pairing` for how it's used.
+	pub async fn create_pairing_code(&self, library_id: Uuid) -> PairingCode {
+		self.pairings
+			.create(self.manager.peer_id(), library_id)
+			.await
+	}
+
+	/// Pairs with a library on a peer, using the code the peer showed to the user.
+	pub async fn pair(
+		&self,
+		peer_id: PeerId,
+		library_id: Uuid,
+		code: &str,
+	) -> Result<(), PairingError> {
+		let library = match self.library_manager.get().and_then(Weak::upgrade) {
+			Some(library_manager) => library_manager.get_ctx(library_id).await,
+			None => None,
+		};
+
+		dial_discovered(&self.manager, peer_id).await;
+
+		pairing::pair(
+			&self.manager,
+			&self.node_config,
+			peer_id,
+			library_id,
+			code,
+			library,
+		)
+		.await
+	}
+
+	/// Lists a directory of a library on a peer, without it being synced.
+	pub async fn request_explorer_data(
+		&self,
+		peer_id: PeerId,
+		library_id: Uuid,
+		location_id: i32,
+		path: String,
+	) -> Result<ExplorerData, RemoteError> {
+		remote::request_explorer_data(&self.manager, peer_id, library_id, location_id, path).await
+	}
+
+	pub async fn request_thumbnail(
+		&self,
+		peer_id: PeerId,
+		library_id: Uuid,
+		cas_id: String,
+	) -> Result<Vec<u8>, RemoteError> {
+		remote::request_thumbnail(&self.manager, peer_id, library_id, cas_id).await
+	}
+
+	/// Requests a range of a file from a peer. At most `MAX_RANGE_LEN` bytes are returned, even if more were requested.
+	pub async fn request_file(
+		&self,
+		peer_id: PeerId,
+		library_id: Uuid,
+		location_id: i32,
+		file_path_id: i32,
+		start: u64,
+		length: Option<u64>,
+	) -> Result<(RemoteFile, Vec<u8>), RemoteError> {
+		remote::request_file(
+			&self.manager,
+			peer_id,
+			library_id,
+			location_id,
+			file_path_id,
+			start,
+			length,
+		)
+		.await
+	}
+
+	pub async fn accept_spacedrop(&self, id: Uuid, destination: PathBuf) -> bool {
+		self.spacedrops.accept(id, destination).await
+	}
+
+	pub async fn reject_spacedrop(&self, id: Uuid) -> bool {
+		self.spacedrops.reject(id).await
+	}
+
+	pub async fn cancel_spacedrop(&self, id: Uuid) -> bool {
+		self.spacedrops.cancel(id).await
 	}
 
 	pub async fn shutdown(&self) {
 		self.manager.shutdown().await;
 	}
 }
+
+/// Whether a peer is allowed to sync a library. Errors are treated as the peer not being paired.
+async fn is_paired(library: &Library, peer_id: PeerId) -> bool {
+	pairing::is_paired(library, peer_id)
+		.await
+		.map_err(|e| {
+			error!(
+				"Failed to check if peer '{peer_id}' is paired with library '{}': {e}",
+				library.id
+			)
+		})
+		.unwrap_or(false)
+}
+
+/// Whether a discovered peer should be connected to, as it's paired with one of our libraries or we've paired with it before.
+async fn is_known_peer(
+	library_manager: &OnceCell<Weak<LibraryManager>>,
+	node_config: &NodeConfigManager,
+	peer_id: PeerId,
+) -> bool {
+	let config = node_config.get().await;
+	if config.p2p_known_peers.contains_key(&peer_id)
+		|| config
+			.p2p_pending_pairings
+			.values()
+			.any(|peers| peers.contains(&peer_id))
+	{
+		return true;
+	}
+
+	let Some(library_manager) = library_manager.get().and_then(Weak::upgrade) else {
+		return false;
+	};
+
+	for library in library_manager.get_all_libraries().await {
+		if is_paired(&library, peer_id).await {
+			return true;
+		}
+	}
+
+	false
+}
+
+/// Connects to a discovered peer we aren't connected to, as only known peers are connected to when they're discovered.
+async fn dial_discovered(manager: &Manager<PeerMetadata>, peer_id: PeerId) {
+	let peer = manager
+		.get_discovered_peers()
+		.await
+		.into_iter()
+		.find(|peer| peer.peer_id == peer_id);
+	let Some(peer) = peer else {
+		return;
+	};
+
+	if is_connected(manager, peer_id).await {
+		return;
+	}
+
+	peer.dial().await;
+
+	tokio::time::timeout(DIAL_TIMEOUT, async {
+		while !is_connected(manager, peer_id).await {
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+	})
+	.await
+	.map_err(|_| warn!("Timed out connecting to peer '{peer_id}'"))
+	.ok();
+}
+
+async fn is_connected(manager: &Manager<PeerMetadata>, peer_id: PeerId) -> bool {
+	manager
+		.get_connected_peers()
+		.await
+		.map(|peers| peers.contains(&peer_id))
+		.unwrap_or(false)
+}
+
+/// The library with the given id, if we have it and the peer has been paired with it.
+async fn paired_library(
+	library_manager: &OnceCell<Weak<LibraryManager>>,
+	library_id: Uuid,
+	peer_id: PeerId,
+) -> Option<Library> {
+	let library = library_manager
+		.get()
+		.and_then(Weak::upgrade)?
+		.get_ctx(library_id)
+		.await?;
+
+	is_paired(&library, peer_id).await.then_some(library)
+}
+
+/// Saves the address a paired peer was found at, keeping the most recent few.
+async fn remember_peer_address(
+	node_config: &NodeConfigManager,
+	peer_id: PeerId,
+	address: SocketAddr,
+) {
+	node_config
+		.write(|mut config| {
+			let addresses = config.p2p_known_peers.entry(peer_id).or_default();
+			addresses.retain(|known| *known != address);
+			addresses.insert(0, address);
+			addresses.truncate(MAX_KNOWN_ADDRESSES);
+		})
+		.await
+		.map_err(|e| error!("Failed to save the address of peer '{peer_id}': {e}"))
+		.ok();
+}
@@ -0,0 +1,383 @@
+//! Pairing allows a peer to join a library, after which it's allowed to sync it with us.
+//!
+//! A pairing looks like this:
+//!  - The node with the library creates a [`PairingCode`], which is shown to the user (as the code itself, or as a QR code of the whole thing).
+//!  - The joining node sends `Header::Pair` to the peer which created it, with a proof that it knows the code.
+//!  - The host checks the proof and responds with `PAIRING_ACCEPTED` and a proof of its own, so the joining node knows it's talking to the peer which showed the code.
+//!
+//! The `PeerId`s within each proof are authenticated by the transport, as a `PeerId` is derived from the peer's `Keypair`
+//! and the peer has to prove it owns that `Keypair` when the connection is established. This means a proof can't be replayed by any other peer.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rspc::Type;
+use sd_p2p::{spacetime::SpaceTimeStream, Manager, PeerId};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	sync::Mutex,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	library::Library,
+	node::{NodeConfigError, NodeConfigManager},
+	prisma::paired_peer,
+};
+
+use super::{Header, PeerMetadata};
+
+/// How long a pairing code can be used for after it's created.
+const PAIRING_CODE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How many incorrect proofs are accepted for a pairing code before it's discarded, as a code is short enough to be guessed.
+const MAX_PAIRING_ATTEMPTS: u8 = 3;
+
+/// The number of possible pairing codes, as they're six digits.
+const PAIRING_CODE_RANGE: u32 = 1_000_000;
+
+/// Sent by the host in response to `Header::Pair`.
+const PAIRING_REJECTED: u8 = 0;
+const PAIRING_ACCEPTED: u8 = 1;
+
+const PROOF_CONTEXT: &str = "spacedrive 2023-03-08 library pairing proof";
+
+#[derive(Debug, Error)]
+pub enum PairingError {
+	#[error("failed to open a stream to the peer")]
+	Stream,
+	#[error("received a pairing request over a broadcast stream")]
+	BroadcastStream,
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("the pairing code is incorrect or has expired")]
+	Rejected,
+	#[error("the peer could not prove that it knows the pairing code")]
+	InvalidProof,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to save the pairing to the node's config: {0}")]
+	Config(#[from] NodeConfigError),
+}
+
+/// Everything a peer needs to join a library. This is what should be encoded into a QR code.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct PairingCode {
+	pub peer_id: PeerId,
+	pub library_id: Uuid,
+	pub code: String,
+	pub expires_at: DateTime<Utc>,
+}
+
+struct PendingPairing {
+	code: String,
+	expires: Instant,
+	attempts: u8,
+}
+
+/// The pairing codes which have been created for each library. Each library only has a single code at a time.
+#[derive(Default)]
+pub(super) struct Pairings {
+	pending: Mutex<HashMap<Uuid, PendingPairing>>,
+}
+
+impl Pairings {
+	/// Creates a new pairing code for a library, replacing any previous one.
+	pub async fn create(&self, peer_id: PeerId, library_id: Uuid) -> PairingCode {
+		let code = format!("{:06}", random_code());
+
+		self.pending.lock().await.insert(
+			library_id,
+			PendingPairing {
+				code: code.clone(),
+				expires: Instant::now() + PAIRING_CODE_TIMEOUT,
+				attempts: 0,
+			},
+		);
+
+		PairingCode {
+			peer_id,
+			library_id,
+			code,
+			expires_at: Utc::now()
+				+ chrono::Duration::from_std(PAIRING_CODE_TIMEOUT)
+					.expect("the timeout is a valid duration"),
+		}
+	}
+
+	/// Responds to a peer's `Header::Pair`, adding it to the library if its proof is correct.
+	///
+	/// A code can only be used once, so it's discarded once a peer has been paired with it.
+	pub async fn respond(
+		&self,
+		stream: &mut SpaceTimeStream,
+		library: Option<Library>,
+		our_peer_id: PeerId,
+		their_peer_id: PeerId,
+		proof: [u8; 32],
+	) -> Result<(), PairingError> {
+		let SpaceTimeStream::Unicast(stream) = stream else {
+			return Err(PairingError::BroadcastStream);
+		};
+
+		let verified = match &library {
+			Some(library) => {
+				self.verify(library.id, our_peer_id, their_peer_id, proof)
+					.await
+			}
+			None => None,
+		};
+
+		let (library, code) = match (library, verified) {
+			(Some(library), Some(code)) => (library, code),
+			_ => {
+				stream.write_u8(PAIRING_REJECTED).await?;
+				stream.flush().await?;
+				return Err(PairingError::Rejected);
+			}
+		};
+
+		add_paired_peer(&library, their_peer_id).await?;
+
+		stream.write_u8(PAIRING_ACCEPTED).await?;
+		stream
+			.write_all(create_proof(&code, our_peer_id, their_peer_id, library.id).as_bytes())
+			.await?;
+		stream.flush().await?;
+
+		info!(
+			"Paired peer '{their_peer_id}' with library '{}'",
+			library.id
+		);
+
+		Ok(())
+	}
+
+	/// Checks a peer's proof against the library's pairing code, returning the code if it's correct.
+	async fn verify(
+		&self,
+		library_id: Uuid,
+		our_peer_id: PeerId,
+		their_peer_id: PeerId,
+		proof: [u8; 32],
+	) -> Option<String> {
+		let mut pending = self.pending.lock().await;
+		let pairing = pending.get_mut(&library_id)?;
+
+		if pairing.expires < Instant::now() {
+			pending.remove(&library_id);
+			return None;
+		}
+
+		if create_proof(&pairing.code, their_peer_id, our_peer_id, library_id)
+			== blake3::Hash::from(proof)
+		{
+			return pending.remove(&library_id).map(|pairing| pairing.code);
+		}
+
+		pairing.attempts += 1;
+		if pairing.attempts >= MAX_PAIRING_ATTEMPTS {
+			warn!("Discarding the pairing code for library '{library_id}' after too many incorrect attempts");
+			pending.remove(&library_id);
+		}
+
+		None
+	}
+}
+
+/// Joins a library on a peer using the code it showed to the user.
+///
+/// The peer is added to the library so that it's allowed to sync with us too.
+/// If we don't have the library yet, the peer is saved to the node's config and added once the library is loaded.
+pub(super) async fn pair(
+	manager: &Manager<PeerMetadata>,
+	node_config: &NodeConfigManager,
+	peer_id: PeerId,
+	library_id: Uuid,
+	code: &str,
+	library: Option<Library>,
+) -> Result<(), PairingError> {
+	let mut stream = manager
+		.stream(peer_id)
+		.await
+		.map_err(|_| PairingError::Stream)?;
+	let our_peer_id = manager.peer_id();
+
+	let proof = create_proof(code, our_peer_id, peer_id, library_id);
+	stream
+		.write_all(&Header::Pair(library_id, *proof.as_bytes()).to_bytes())
+		.await?;
+	stream.flush().await?;
+
+	if stream.read_u8().await? != PAIRING_ACCEPTED {
+		return Err(PairingError::Rejected);
+	}
+
+	let mut proof = [0u8; 32];
+	stream.read_exact(&mut proof).await?;
+	if create_proof(code, peer_id, our_peer_id, library_id) != blake3::Hash::from(proof) {
+		return Err(PairingError::InvalidProof);
+	}
+
+	match library {
+		Some(library) => add_paired_peer(&library, peer_id).await?,
+		None => {
+			node_config
+				.write(|mut config| {
+					let peers = config.p2p_pending_pairings.entry(library_id).or_default();
+					if !peers.contains(&peer_id) {
+						peers.push(peer_id);
+					}
+				})
+				.await?;
+		}
+	}
+
+	info!("Paired with peer '{peer_id}' for library '{library_id}'");
+
+	Ok(())
+}
+
+/// Adds the hosts we paired with before we had the library to it, once it's been loaded.
+pub(crate) async fn add_pending_pairings(library: &Library) -> Result<(), PairingError> {
+	let config = library.node_context.config.get().await;
+	let Some(peers) = config.p2p_pending_pairings.get(&library.id) else {
+		return Ok(());
+	};
+
+	for peer_id in peers {
+		add_paired_peer(library, *peer_id).await?;
+		info!("Paired with peer '{peer_id}' for library '{}'", library.id);
+	}
+
+	library
+		.node_context
+		.config
+		.write(|mut config| {
+			config.p2p_pending_pairings.remove(&library.id);
+		})
+		.await?;
+
+	Ok(())
+}
+
+/// Whether a peer has been paired with a library, and so is allowed to sync it.
+pub(super) async fn is_paired(
+	library: &Library,
+	peer_id: PeerId,
+) -> prisma_client_rust::Result<bool> {
+	Ok(library
+		.db
+		.paired_peer()
+		.find_unique(paired_peer::peer_id::equals(peer_id.to_string()))
+		.exec()
+		.await?
+		.is_some())
+}
+
+/// The sync node that a paired peer acknowledges operations as, or `None` if the peer isn't paired or can't be trusted as `claimed`.
+///
+/// The node a peer first claims to be is bound to it, as its `PeerId` is authenticated by the transport.
+/// From then on, the peer is always treated as that node. A node which is bound to another peer (or is us) can't be claimed.
+pub(super) async fn paired_node(
+	library: &Library,
+	peer_id: PeerId,
+	claimed: Uuid,
+) -> prisma_client_rust::Result<Option<Uuid>> {
+	let peer = library
+		.db
+		.paired_peer()
+		.find_unique(paired_peer::peer_id::equals(peer_id.to_string()))
+		.exec()
+		.await?;
+	let Some(peer) = peer else {
+		return Ok(None);
+	};
+
+	if let Some(node) = peer.node_pub_id {
+		return Ok(Uuid::from_slice(&node).ok());
+	}
+
+	let claimed_by_another_peer = library
+		.db
+		.paired_peer()
+		.count(vec![paired_peer::node_pub_id::equals(Some(
+			claimed.as_bytes().to_vec(),
+		))])
+		.exec()
+		.await?
+		> 0;
+
+	if claimed == library.sync.node() || claimed_by_another_peer {
+		warn!("Peer '{peer_id}' claimed to be node '{claimed}', which belongs to another peer");
+		return Ok(None);
+	}
+
+	library
+		.db
+		.paired_peer()
+		.update(
+			paired_peer::id::equals(peer.id),
+			vec![paired_peer::node_pub_id::set(Some(
+				claimed.as_bytes().to_vec(),
+			))],
+		)
+		.exec()
+		.await?;
+
+	Ok(Some(claimed))
+}
+
+async fn add_paired_peer(library: &Library, peer_id: PeerId) -> prisma_client_rust::Result<()> {
+	library
+		.db
+		.paired_peer()
+		.upsert(
+			paired_peer::peer_id::equals(peer_id.to_string()),
+			paired_peer::create(peer_id.to_string(), vec![]),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	invalidate_query!(library, "library.pairedPeers");
+
+	Ok(())
+}
+
+/// A random pairing code, without the bias of taking a random number modulo the range.
+///
+/// Values past the largest multiple of the range that fits in a `u32` are drawn again, so every code is equally likely.
+fn random_code() -> u32 {
+	const LIMIT: u32 = u32::MAX - u32::MAX % PAIRING_CODE_RANGE;
+
+	loop {
+		let random = *Uuid::new_v4().as_bytes();
+		let value = u32::from_le_bytes([random[0], random[1], random[2], random[3]]);
+		if value < LIMIT {
+			return value % PAIRING_CODE_RANGE;
+		}
+	}
+}
+
+/// A proof that the peer `from` knows the code for pairing with `to`.
+///
+/// The order of the `PeerId`s means each side's proof is different, so the host can't just send back the proof it received.
+/// Comparing `blake3::Hash`es is done in constant time.
+fn create_proof(code: &str, from: PeerId, to: PeerId, library_id: Uuid) -> blake3::Hash {
+	let mut hasher = blake3::Hasher::new_keyed(&blake3::derive_key(PROOF_CONTEXT, code.as_bytes()));
+	for peer_id in [from, to] {
+		let peer_id = peer_id.to_string();
+		hasher.update(&(peer_id.len() as u16).to_le_bytes());
+		hasher.update(peer_id.as_bytes());
+	}
+	hasher.update(library_id.as_bytes());
+	hasher.finalize()
+}
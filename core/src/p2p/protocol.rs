@@ -1,14 +1,20 @@
 use tokio::io::AsyncReadExt;
 use uuid::Uuid;
 
-use sd_p2p::{spaceblock::TransferRequest, spacetime::SpaceTimeStream};
+use sd_p2p::{spaceblock::TransferManifest, spacetime::SpaceTimeStream};
 
 /// TODO
 #[derive(Debug, PartialEq, Eq)]
 pub enum Header {
 	Ping,
-	Spacedrop(TransferRequest),
+	Spacedrop(TransferManifest),
 	Sync(Uuid, u32),
+	/// A request for the operations of a library that we haven't seen, followed by a `GetOpsArgs` of the given length.
+	GetOperations(Uuid, u32),
+	/// A request to join a library, with a proof that the peer knows its pairing code.
+	Pair(Uuid, [u8; 32]),
+	/// A request to browse a library, followed by a `RemoteRequest` of the given length.
+	Request(Uuid, u32),
 }
 
 impl Header {
@@ -20,7 +26,11 @@ impl Header {
 		match discriminator {
 			0 => match stream {
 				SpaceTimeStream::Unicast(stream) => {
-					Ok(Self::Spacedrop(TransferRequest::from_stream(stream).await?))
+					Ok(Self::Spacedrop(
+						TransferManifest::from_stream(stream)
+							.await
+							.map_err(|_| ())?, // TODO: Error handling
+					))
 				}
 				_ => todo!(),
 			},
@@ -35,15 +45,40 @@ impl Header {
 
 				Ok(Self::Sync(Uuid::from_slice(&uuid).unwrap(), len)) // TODO: Error handling
 			}
+			3 => {
+				let mut uuid = [0u8; 16];
+				stream.read_exact(&mut uuid).await.map_err(|_| ())?; // TODO: Error handling
+
+				let len = stream.read_u32_le().await.map_err(|_| ())?; // TODO: Error handling
+
+				Ok(Self::GetOperations(Uuid::from_bytes(uuid), len))
+			}
+			4 => {
+				let mut uuid = [0u8; 16];
+				stream.read_exact(&mut uuid).await.map_err(|_| ())?; // TODO: Error handling
+
+				let mut proof = [0u8; 32];
+				stream.read_exact(&mut proof).await.map_err(|_| ())?; // TODO: Error handling
+
+				Ok(Self::Pair(Uuid::from_bytes(uuid), proof))
+			}
+			5 => {
+				let mut uuid = [0u8; 16];
+				stream.read_exact(&mut uuid).await.map_err(|_| ())?; // TODO: Error handling
+
+				let len = stream.read_u32_le().await.map_err(|_| ())?; // TODO: Error handling
+
+				Ok(Self::Request(Uuid::from_bytes(uuid), len))
+			}
 			_ => Err(()),
 		}
 	}
 
 	pub fn to_bytes(&self) -> Vec<u8> {
 		match self {
-			Self::Spacedrop(transfer_request) => {
+			Self::Spacedrop(manifest) => {
 				let mut bytes = vec![0];
-				bytes.extend_from_slice(&transfer_request.to_bytes());
+				bytes.extend_from_slice(&manifest.to_bytes());
 				bytes
 			}
 			Self::Ping => vec![1],
@@ -57,6 +92,24 @@ impl Header {
 
 				bytes
 			}
+			Self::GetOperations(uuid, len) => {
+				let mut bytes = vec![3];
+				bytes.extend_from_slice(uuid.as_bytes());
+				bytes.extend_from_slice(&len.to_le_bytes());
+				bytes
+			}
+			Self::Pair(uuid, proof) => {
+				let mut bytes = vec![4];
+				bytes.extend_from_slice(uuid.as_bytes());
+				bytes.extend_from_slice(proof);
+				bytes
+			}
+			Self::Request(uuid, len) => {
+				let mut bytes = vec![5];
+				bytes.extend_from_slice(uuid.as_bytes());
+				bytes.extend_from_slice(&len.to_le_bytes());
+				bytes
+			}
 		}
 	}
 }
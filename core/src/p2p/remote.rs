@@ -0,0 +1,369 @@
+//! Lets paired peers browse a library's files on this node, without waiting for them to be synced.
+//!
+//! A request is sent as `Header::Request`, followed by a [`RemoteRequest`].
+//! It's answered with a status byte and then (if the status is [`RESPONSE_OK`]) the length-prefixed frames documented on each request.
+
+use rspc::ErrorCode;
+use sd_crypto::primitives::BLOCK_LEN;
+use sd_p2p::{
+	spacetime::{SpaceTimeStream, UnicastStream},
+	Manager, PeerId, Priority,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+	api::locations::{get_explorer_data, ExplorerData},
+	custom_uri::{open_file_path, HandleCustomUriError},
+	library::Library,
+	location::LocationError,
+	object::preview::{ThumbnailSize, THUMBNAIL_CACHE_DIR_NAME},
+	prisma::file_path,
+};
+
+use super::{Header, PeerMetadata};
+
+/// The most bytes of a file that are sent for a single request. Larger ranges are truncated, so the requester should ask for the rest.
+///
+/// This is a multiple of the block length of files that are encrypted at rest, and truncated ranges end on a block boundary,
+/// so a transfer of one of these files decrypts each of its blocks once rather than the whole file for every request.
+const MAX_RANGE_LEN: u64 = 8 * BLOCK_LEN as u64;
+
+/// The largest message (a request or a frame of a response) that will be accepted from a peer.
+const MAX_MESSAGE_LEN: u32 = 64 * 1024 * 1024;
+
+const RESPONSE_OK: u8 = 0;
+const RESPONSE_NOT_FOUND: u8 = 1;
+const RESPONSE_FORBIDDEN: u8 = 2;
+const RESPONSE_INVALID: u8 = 3;
+const RESPONSE_ERROR: u8 = 4;
+
+#[derive(Debug, Error)]
+pub enum RemoteError {
+	#[error("failed to open a stream to the peer")]
+	Stream,
+	#[error("received a request over a broadcast stream")]
+	BroadcastStream,
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("message of {0} bytes is too large")]
+	MessageTooLarge(u32),
+	#[error("failed to encode message: {0}")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("failed to decode message: {0}")]
+	Decode(#[from] rmp_serde::decode::Error),
+	#[error("the requested resource wasn't found")]
+	NotFound,
+	#[error("this node isn't paired with the library")]
+	Forbidden,
+	#[error("the request was invalid")]
+	InvalidRequest,
+	#[error("the peer failed to handle the request")]
+	Peer,
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+}
+
+impl From<RemoteError> for rspc::Error {
+	fn from(err: RemoteError) -> Self {
+		let code = match err {
+			RemoteError::NotFound => ErrorCode::NotFound,
+			RemoteError::Forbidden => ErrorCode::Forbidden,
+			RemoteError::InvalidRequest => ErrorCode::BadRequest,
+			_ => ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, err.to_string(), err)
+	}
+}
+
+impl From<LocationError> for RemoteError {
+	fn from(err: LocationError) -> Self {
+		match err {
+			LocationError::IdNotFound(_) | LocationError::DirectoryNotFound(_) => Self::NotFound,
+			LocationError::DatabaseError(err) => Self::Database(err),
+			LocationError::IOError(err) => Self::Io(err),
+			err => {
+				error!("Failed to list directory for a remote request: {err}");
+				Self::Peer
+			}
+		}
+	}
+}
+
+impl From<HandleCustomUriError> for RemoteError {
+	fn from(err: HandleCustomUriError) -> Self {
+		match err {
+			HandleCustomUriError::NotFound(_) => Self::NotFound,
+			HandleCustomUriError::QueryError(err) => Self::Database(err),
+			HandleCustomUriError::Io(err) => Self::Io(err),
+			err => {
+				error!("Failed to open file for a remote request: {err}");
+				Self::Peer
+			}
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) enum RemoteRequest {
+	/// The contents of a directory, as returned by `locations.getExplorerData`. Answered with an `ExplorerData`.
+	ExplorerData { location_id: i32, path: String },
+	/// The thumbnail of an object in the library. Answered with the WebP image.
+	Thumbnail { cas_id: String },
+	/// A range of a file's bytes. Answered with a [`RemoteFile`], followed by the bytes.
+	File {
+		location_id: i32,
+		file_path_id: i32,
+		start: u64,
+		/// Everything from `start` is sent if this is `None`, up to [`MAX_RANGE_LEN`].
+		length: Option<u64>,
+	},
+}
+
+impl RemoteRequest {
+	/// Files are sent after every other kind of message, so browsing a peer doesn't hold up sync.
+	fn priority(&self) -> Priority {
+		match self {
+			Self::File { .. } => Priority::Bulk,
+			_ => Priority::Normal,
+		}
+	}
+}
+
+/// The metadata of a file on a peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteFile {
+	/// The extension the file should be served as, which isn't the extension on disk for files that are encrypted at rest.
+	pub extension: String,
+	/// The MIME type detected from the file's contents, if the peer has identified it.
+	#[serde(default)]
+	pub mime_type: Option<String>,
+	/// The size of the whole file, regardless of the range that was requested.
+	pub size: u64,
+}
+
+pub(super) async fn request_explorer_data(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	library_id: Uuid,
+	location_id: i32,
+	path: String,
+) -> Result<ExplorerData, RemoteError> {
+	let mut stream = request(
+		manager,
+		peer_id,
+		library_id,
+		&RemoteRequest::ExplorerData { location_id, path },
+	)
+	.await?;
+
+	Ok(rmp_serde::from_slice(&read_frame(&mut stream).await?)?)
+}
+
+pub(super) async fn request_thumbnail(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	library_id: Uuid,
+	cas_id: String,
+) -> Result<Vec<u8>, RemoteError> {
+	let mut stream = request(
+		manager,
+		peer_id,
+		library_id,
+		&RemoteRequest::Thumbnail { cas_id },
+	)
+	.await?;
+
+	read_frame(&mut stream).await
+}
+
+/// Requests a range of a file, returning its metadata and the bytes that were sent (which may be fewer than were requested).
+pub(super) async fn request_file(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	library_id: Uuid,
+	location_id: i32,
+	file_path_id: i32,
+	start: u64,
+	length: Option<u64>,
+) -> Result<(RemoteFile, Vec<u8>), RemoteError> {
+	let mut stream = request(
+		manager,
+		peer_id,
+		library_id,
+		&RemoteRequest::File {
+			location_id,
+			file_path_id,
+			start,
+			length,
+		},
+	)
+	.await?;
+
+	let file = rmp_serde::from_slice(&read_frame(&mut stream).await?)?;
+	Ok((file, read_frame(&mut stream).await?))
+}
+
+async fn request(
+	manager: &Manager<PeerMetadata>,
+	peer_id: PeerId,
+	library_id: Uuid,
+	req: &RemoteRequest,
+) -> Result<UnicastStream, RemoteError> {
+	let mut stream = manager
+		.stream(peer_id)
+		.await
+		.map_err(|_| RemoteError::Stream)?;
+	stream.set_priority(req.priority());
+
+	let req = rmp_serde::to_vec_named(req)?;
+	let mut buf = Header::Request(library_id, req.len() as u32).to_bytes();
+	buf.extend_from_slice(&req);
+
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	match stream.read_u8().await? {
+		RESPONSE_OK => Ok(stream),
+		RESPONSE_NOT_FOUND => Err(RemoteError::NotFound),
+		RESPONSE_FORBIDDEN => Err(RemoteError::Forbidden),
+		RESPONSE_INVALID => Err(RemoteError::InvalidRequest),
+		_ => Err(RemoteError::Peer),
+	}
+}
+
+/// Responds to a peer's `Header::Request`.
+///
+/// `library` should be `None` if we don't have the library or the peer hasn't been paired with it, and the request is then refused.
+pub(super) async fn respond(
+	stream: &mut SpaceTimeStream,
+	library: Option<Library>,
+	len: u32,
+) -> Result<(), RemoteError> {
+	let SpaceTimeStream::Unicast(stream) = stream else {
+		return Err(RemoteError::BroadcastStream);
+	};
+
+	if len > MAX_MESSAGE_LEN {
+		return Err(RemoteError::MessageTooLarge(len));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+	let req: RemoteRequest = rmp_serde::from_slice(&buf)?;
+	stream.set_priority(req.priority());
+
+	let result = match library {
+		Some(library) => handle(&library, req).await,
+		None => Err(RemoteError::Forbidden),
+	};
+
+	match result {
+		Ok(frames) => {
+			stream.write_u8(RESPONSE_OK).await?;
+			for frame in frames {
+				stream.write_u32_le(frame.len() as u32).await?;
+				stream.write_all(&frame).await?;
+			}
+		}
+		Err(err) => {
+			stream
+				.write_u8(match err {
+					RemoteError::NotFound => RESPONSE_NOT_FOUND,
+					RemoteError::Forbidden => RESPONSE_FORBIDDEN,
+					RemoteError::InvalidRequest => RESPONSE_INVALID,
+					_ => {
+						error!("Failed to handle remote request: {err}");
+						RESPONSE_ERROR
+					}
+				})
+				.await?;
+		}
+	}
+
+	stream.flush().await?;
+
+	Ok(())
+}
+
+async fn handle(library: &Library, req: RemoteRequest) -> Result<Vec<Vec<u8>>, RemoteError> {
+	match req {
+		RemoteRequest::ExplorerData { location_id, path } => {
+			let data = get_explorer_data(library, location_id, path).await?;
+			Ok(vec![rmp_serde::to_vec_named(&data)?])
+		}
+		RemoteRequest::Thumbnail { cas_id } => {
+			// thumbnails are shared by every library on the node, so we check the object is in this one
+			if cas_id.is_empty() || !cas_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+				return Err(RemoteError::InvalidRequest);
+			}
+
+			library
+				.db
+				.file_path()
+				.find_first(vec![file_path::cas_id::equals(Some(cas_id.clone()))])
+				.exec()
+				.await?
+				.ok_or(RemoteError::NotFound)?;
+
+			let path = ThumbnailSize::Small.path(
+				library
+					.config()
+					.data_directory()
+					.join(THUMBNAIL_CACHE_DIR_NAME),
+				&cas_id,
+			);
+
+			match tokio::fs::read(path).await {
+				Ok(thumbnail) => Ok(vec![thumbnail]),
+				Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+					Err(RemoteError::NotFound)
+				}
+				Err(err) => Err(err.into()),
+			}
+		}
+		RemoteRequest::File {
+			location_id,
+			file_path_id,
+			start,
+			length,
+		} => {
+			let (file, extension, mime_type, _) =
+				open_file_path(library, location_id, file_path_id).await?;
+
+			let size = file.len().await?;
+			if start > size {
+				return Err(RemoteError::InvalidRequest);
+			}
+
+			let length = length
+				.unwrap_or(size - start)
+				.min(size - start)
+				.min(MAX_RANGE_LEN - start % BLOCK_LEN as u64);
+
+			Ok(vec![
+				rmp_serde::to_vec_named(&RemoteFile {
+					extension,
+					mime_type,
+					size,
+				})?,
+				file.read(length, Some(start)).await?,
+			])
+		}
+	}
+}
+
+async fn read_frame(stream: &mut UnicastStream) -> Result<Vec<u8>, RemoteError> {
+	let len = stream.read_u32_le().await?;
+	if len > MAX_MESSAGE_LEN {
+		return Err(RemoteError::MessageTooLarge(len));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+	Ok(buf)
+}
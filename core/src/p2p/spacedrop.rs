@@ -0,0 +1,504 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex as StdMutex,
+	},
+	time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use filetime::FileTime;
+use sd_p2p::{
+	spaceblock::{relative_path, SpaceblockError, Transfer, TransferManifest, TransferRequest},
+	spacetime::{SpaceTimeStream, UnicastStream},
+	PeerId, Priority,
+};
+use tokio::{
+	fs::{self, File, OpenOptions},
+	io::{AsyncReadExt, AsyncWriteExt, BufReader},
+	sync::{broadcast, oneshot, Mutex, RwLock},
+	time::timeout,
+};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use super::P2PEvent;
+
+/// How long the receiver has to accept or reject a Spacedrop before it's rejected automatically.
+pub const SPACEDROP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often progress events are emitted for each transfer.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sent by the receiver once the user has decided what to do with a Spacedrop.
+const SPACEDROP_REJECTED: u8 = 0;
+const SPACEDROP_ACCEPTED: u8 = 1;
+
+/// The state of every Spacedrop that's waiting for the user, or is in progress.
+#[derive(Default)]
+pub(super) struct Spacedrops {
+	pending: Mutex<HashMap<Uuid, oneshot::Sender<Option<PathBuf>>>>,
+	cancellations: RwLock<HashMap<Uuid, Arc<AtomicBool>>>,
+}
+
+impl Spacedrops {
+	/// Accepts an incoming Spacedrop, saving it within `destination` (which should be a directory).
+	pub async fn accept(&self, id: Uuid, destination: PathBuf) -> bool {
+		self.pending
+			.lock()
+			.await
+			.remove(&id)
+			.map_or(false, |tx| tx.send(Some(destination)).is_ok())
+	}
+
+	pub async fn reject(&self, id: Uuid) -> bool {
+		self.pending
+			.lock()
+			.await
+			.remove(&id)
+			.map_or(false, |tx| tx.send(None).is_ok())
+	}
+
+	/// Cancels an in progress Spacedrop (either sent or received). A received file can be resumed if it's sent again.
+	pub async fn cancel(&self, id: Uuid) -> bool {
+		self.cancellations
+			.read()
+			.await
+			.get(&id)
+			.map(|cancelled| cancelled.store(true, Ordering::Relaxed))
+			.is_some()
+	}
+
+	/// Asks the user to accept an incoming Spacedrop, and receives it if they do.
+	pub async fn receive(
+		&self,
+		events: &broadcast::Sender<P2PEvent>,
+		peer_id: PeerId,
+		stream: &mut SpaceTimeStream,
+		manifest: TransferManifest,
+	) {
+		let SpaceTimeStream::Unicast(stream) = stream else {
+			error!("Received Spacedrop from peer '{peer_id}' over a broadcast stream!");
+			return;
+		};
+
+		let id = Uuid::new_v4();
+		let (tx, rx) = oneshot::channel();
+		self.pending.lock().await.insert(id, tx);
+
+		events
+			.send(P2PEvent::SpacedropRequest {
+				id,
+				peer_id,
+				files: manifest.roots().into_iter().map(Into::into).collect(),
+				total_size: manifest.total_size(),
+			})
+			.ok();
+
+		let destination = match timeout(SPACEDROP_TIMEOUT, rx).await {
+			Ok(Ok(Some(destination))) => destination,
+			Ok(Ok(None)) => {
+				info!("Rejected Spacedrop '{id}' from peer '{peer_id}'");
+				stream.write_u8(SPACEDROP_REJECTED).await.ok();
+				return;
+			}
+			Ok(Err(_)) | Err(_) => {
+				self.pending.lock().await.remove(&id);
+				stream.write_u8(SPACEDROP_REJECTED).await.ok();
+				events
+					.send(P2PEvent::SpacedropFailed {
+						id,
+						error: "The Spacedrop was not accepted in time".into(),
+					})
+					.ok();
+				return;
+			}
+		};
+
+		let cancelled = Arc::new(AtomicBool::new(false));
+		self.cancellations
+			.write()
+			.await
+			.insert(id, cancelled.clone());
+
+		// the files are sent after every other kind of message, so a Spacedrop doesn't hold up sync
+		stream.set_priority(Priority::Bulk);
+
+		let result = async {
+			stream.write_u8(SPACEDROP_ACCEPTED).await?;
+			stream.flush().await?;
+
+			receive_manifest(
+				stream,
+				&manifest,
+				&destination,
+				&cancelled,
+				progress_reporter(events.clone(), id, manifest.total_size()),
+			)
+			.await
+		}
+		.await;
+
+		self.cancellations.write().await.remove(&id);
+
+		match result {
+			Ok(received) => {
+				info!(
+					"Received {} files from peer '{peer_id}' into '{}' through Spacedrop!",
+					manifest.files.len(),
+					destination.display()
+				);
+				events
+					.send(P2PEvent::SpacedropCompleted { id, received })
+					.ok();
+			}
+			Err(e) => {
+				error!("Failed to receive Spacedrop from peer '{peer_id}': {e}");
+				events
+					.send(P2PEvent::SpacedropFailed {
+						id,
+						error: e.to_string(),
+					})
+					.ok();
+			}
+		}
+	}
+
+	/// Sends files to a peer once they've accepted them.
+	/// `stream` should have already had the manifest written to it, and `sources` holds the path of each file within it.
+	pub async fn send(
+		&self,
+		events: &broadcast::Sender<P2PEvent>,
+		id: Uuid,
+		peer_id: PeerId,
+		mut stream: UnicastStream,
+		manifest: TransferManifest,
+		sources: Vec<PathBuf>,
+	) {
+		let cancelled = Arc::new(AtomicBool::new(false));
+		self.cancellations
+			.write()
+			.await
+			.insert(id, cancelled.clone());
+
+		stream.set_priority(Priority::Bulk);
+
+		let result = async {
+			// the receiver times out on its own, so this only needs to account for latency
+			let response = timeout(SPACEDROP_TIMEOUT * 2, stream.read_u8())
+				.await
+				.map_err(|_| "timed out waiting for the peer to respond".to_string())?
+				.map_err(|e| e.to_string())?;
+
+			if response != SPACEDROP_ACCEPTED {
+				return Err("the Spacedrop was rejected by the peer".to_string());
+			}
+
+			debug!("Starting Spacedrop to peer '{peer_id}'");
+			let i = Instant::now();
+
+			let on_progress = progress_reporter(events.clone(), id, manifest.total_size());
+			let mut completed = 0;
+			for (req, source) in manifest.files.iter().zip(&sources) {
+				let file = File::open(source).await.map_err(|e| e.to_string())?;
+
+				Transfer::new(req, |offset| on_progress(completed + offset), &cancelled)
+					.send(&mut stream, BufReader::new(file))
+					.await
+					.map_err(|e| e.to_string())?;
+
+				completed += req.size;
+			}
+
+			debug!(
+				"Finished Spacedrop to peer '{peer_id}' after '{:?}",
+				i.elapsed()
+			);
+
+			Ok(())
+		}
+		.await;
+
+		self.cancellations.write().await.remove(&id);
+
+		match result {
+			Ok(()) => {
+				events
+					.send(P2PEvent::SpacedropCompleted {
+						id,
+						received: Vec::new(),
+					})
+					.ok();
+			}
+			Err(error) => {
+				error!("Failed Spacedrop to peer '{peer_id}': {error}");
+				events.send(P2PEvent::SpacedropFailed { id, error }).ok();
+			}
+		}
+	}
+}
+
+/// This returns a progress callback for a `Transfer`, which emits (throttled) progress events.
+///
+/// The speed is measured from the first callback, so a resumed transfer doesn't count the bytes it already had.
+fn progress_reporter(
+	events: broadcast::Sender<P2PEvent>,
+	id: Uuid,
+	total: u64,
+) -> impl Fn(u64) + Send {
+	let state = StdMutex::new(None::<(Instant, u64, Option<Instant>)>);
+
+	move |transferred| {
+		let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+		let now = Instant::now();
+
+		let (started, started_at, last_emitted) = state.get_or_insert((now, transferred, None));
+
+		if transferred < total
+			&& last_emitted.map_or(false, |last| now.duration_since(last) < PROGRESS_INTERVAL)
+		{
+			return;
+		}
+		*last_emitted = Some(now);
+
+		let elapsed = now.duration_since(*started).as_secs_f64();
+		let bytes_per_second = if elapsed > 0.0 {
+			((transferred - *started_at) as f64 / elapsed) as u64
+		} else {
+			0
+		};
+
+		events
+			.send(P2PEvent::SpacedropProgress {
+				id,
+				transferred,
+				total,
+				bytes_per_second,
+			})
+			.ok();
+	}
+}
+
+/// Builds the manifest for sending `paths` (which may be files or directories), along with the path of each file within it.
+///
+/// Each path is sent by its name, so only the contents of directories keep their structure.
+pub(super) async fn build_manifest(
+	paths: &[PathBuf],
+) -> Result<(TransferManifest, Vec<PathBuf>), SpaceblockError> {
+	let mut manifest = TransferManifest::default();
+	let mut sources = Vec::new();
+
+	for root in paths {
+		let base = root.parent().unwrap_or(root);
+
+		let mut to_walk = vec![root.clone()];
+		while let Some(path) = to_walk.pop() {
+			let metadata = fs::metadata(&path).await?;
+			let name = path
+				.strip_prefix(base)
+				.ok()
+				.and_then(|name| name.to_str())
+				.filter(|name| !name.is_empty())
+				.ok_or(SpaceblockError::InvalidName)?
+				.replace(std::path::MAIN_SEPARATOR, "/");
+
+			if metadata.is_dir() {
+				let mut read_dir = fs::read_dir(&path).await?;
+				while let Some(entry) = read_dir.next_entry().await? {
+					// symlinks could point outside of the directory (or back into it)
+					if entry.file_type().await?.is_symlink() {
+						warn!("Skipping symlink '{}' in Spacedrop", entry.path().display());
+						continue;
+					}
+
+					to_walk.push(entry.path());
+				}
+
+				manifest.directories.push(name);
+			} else {
+				let mut req = TransferRequest::new(name, metadata.len())?;
+				req.modified = metadata
+					.modified()
+					.ok()
+					.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+					.map(|modified| modified.as_secs());
+				#[cfg(unix)]
+				{
+					use std::os::unix::fs::PermissionsExt;
+					req.permissions = Some(metadata.permissions().mode());
+				}
+
+				manifest.files.push(req);
+				sources.push(path);
+			}
+		}
+	}
+
+	Ok((manifest, sources))
+}
+
+/// Receives every file in the manifest into `destination`, returning the paths of the files and directories that were sent (not everything within them).
+///
+/// Directories are merged with any that already exist, but files are never overwritten. A file is saved as `name (1).ext` (and so on) instead,
+/// which also keeps files of the same name from different roots of the Spacedrop apart.
+async fn receive_manifest(
+	stream: &mut UnicastStream,
+	manifest: &TransferManifest,
+	destination: &Path,
+	cancelled: &AtomicBool,
+	on_progress: impl Fn(u64),
+) -> Result<Vec<PathBuf>, SpaceblockError> {
+	// everything is validated up front, so a bad name doesn't leave a transfer half received
+	let directories = manifest
+		.directories
+		.iter()
+		.map(|name| relative_path(name))
+		.collect::<Result<Vec<_>, _>>()?;
+	let files = manifest
+		.files
+		.iter()
+		.map(|req| relative_path(&req.name))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let mut received = Vec::new();
+	for (name, directory) in manifest.directories.iter().zip(directories) {
+		let path = destination.join(directory);
+		fs::create_dir_all(&path).await?;
+
+		if !name.contains('/') {
+			received.push(path);
+		}
+	}
+
+	let mut completed = 0;
+	for (req, path) in manifest.files.iter().zip(files) {
+		let path = unused_path(destination.join(path)).await;
+
+		receive_file(stream, req, &path, cancelled, |offset| {
+			on_progress(completed + offset)
+		})
+		.await?;
+
+		completed += req.size;
+
+		if !req.name.contains('/') {
+			received.push(path);
+		}
+	}
+
+	Ok(received)
+}
+
+/// Returns `path`, or the first of `name (1).ext`, `name (2).ext`, etc. which doesn't exist yet.
+///
+/// Partially received files only exist as `.part` files, so an interrupted transfer picks the same path when it's resumed.
+async fn unused_path(path: PathBuf) -> PathBuf {
+	let mut candidate = path.clone();
+	let mut n = 1;
+	while fs::symlink_metadata(&candidate).await.is_ok() {
+		candidate = numbered_path(&path, n);
+		n += 1;
+	}
+
+	candidate
+}
+
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+	path.with_file_name(match path.extension() {
+		Some(extension) => format!("{stem} ({n}).{}", extension.to_string_lossy()),
+		None => format!("{stem} ({n})"),
+	})
+}
+
+/// Receives a file into `path`, resuming a previous attempt if one exists.
+///
+/// The file is received into a `.part` file (named after the file's size too, so a different file of the same name isn't resumed),
+/// which is renamed once the transfer is complete.
+async fn receive_file(
+	stream: &mut UnicastStream,
+	req: &TransferRequest,
+	path: &Path,
+	cancelled: &AtomicBool,
+	on_progress: impl Fn(u64),
+) -> Result<(), SpaceblockError> {
+	let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+		return Err(SpaceblockError::InvalidName);
+	};
+
+	fs::create_dir_all(parent).await?;
+
+	let mut part_name = name.to_os_string();
+	part_name.push(format!(".{}.part", req.size));
+	let part_path = parent.join(part_name);
+
+	let mut file = OpenOptions::new()
+		.create(true)
+		.write(true)
+		.open(&part_path)
+		.await?;
+	let received = file.metadata().await?.len();
+
+	if received > 0 {
+		info!("Resuming Spacedrop of '{}' from {received} bytes", req.name);
+	}
+
+	Transfer::new(req, on_progress, cancelled)
+		.receive(stream, &mut file, received)
+		.await?;
+
+	file.set_len(req.size).await?;
+	file.sync_all().await?;
+	drop(file);
+
+	fs::rename(&part_path, path).await?;
+
+	// the file is already received, so failing to restore its metadata isn't fatal
+	if let Some(modified) = req.modified {
+		let modified = FileTime::from_unix_time(modified as i64, 0);
+		if let Err(e) = filetime::set_file_mtime(path, modified) {
+			warn!(
+				"Failed to set modification time of '{}': {e}",
+				path.display()
+			);
+		}
+	}
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+
+		if let Some(permissions) = req.permissions {
+			// only the permission bits are kept, so a peer can't send us a setuid file
+			let permissions = std::fs::Permissions::from_mode(permissions & 0o777);
+			if let Err(e) = fs::set_permissions(path, permissions).await {
+				warn!("Failed to set permissions of '{}': {e}", path.display());
+			}
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn numbered_paths() {
+		assert_eq!(
+			numbered_path(Path::new("/drops/photo.jpg"), 1),
+			Path::new("/drops/photo (1).jpg")
+		);
+		assert_eq!(
+			numbered_path(Path::new("/drops/archive.tar.gz"), 2),
+			Path::new("/drops/archive.tar (2).gz")
+		);
+		assert_eq!(
+			numbered_path(Path::new("/drops/README"), 3),
+			Path::new("/drops/README (3)")
+		);
+	}
+}
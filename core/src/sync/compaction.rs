@@ -0,0 +1,386 @@
+use crate::prisma::{node, owned_operation, shared_operation, PrismaClient};
+
+use std::collections::{HashMap, HashSet};
+
+use prisma_client_rust::Direction;
+use rspc::Type;
+use sd_sync::SharedOperationData;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use tracing::debug;
+use uuid::Uuid;
+
+/// How many operations are deleted per query, to stay within SQLite's limit on query parameters.
+const DELETE_CHUNK_SIZE: usize = 500;
+
+/// How many operations are read per query.
+const PAGE_SIZE: i64 = 1000;
+
+#[serde_as]
+#[derive(Serialize, Type, Debug, Default)]
+pub struct CompactionStats {
+	/// Updates to a field which were superseded by a newer update to the same field.
+	pub collapsed: u32,
+	/// Operations on records which have since been deleted, leaving only the delete as a tombstone.
+	pub tombstoned: u32,
+	/// Operations which every known node has already received.
+	pub pruned: u32,
+	/// The size of the operations that were removed.
+	/// The database file only shrinks once SQLite vacuums the freed pages, so this is the space available for reuse.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub reclaimed_bytes: u64,
+}
+
+shared_operation::select!(shared_operation_compaction {
+	id timestamp model record_id kind data node_id
+});
+owned_operation::select!(owned_operation_compaction {
+	id timestamp model data node_id
+});
+
+/// Compacts the operation log of a library.
+///
+/// The newest operation from each node is always kept, as it's what our clock for that node is derived from when catching up.
+/// Removing it would make peers send us older operations again, which would then be applied on top of newer ones.
+///
+/// Operations are read a page at a time, newest first, so only the ids of the operations being removed are held in memory.
+pub(super) async fn compact(
+	db: &PrismaClient,
+	local_node: Uuid,
+) -> prisma_client_rust::Result<CompactionStats> {
+	let mut stats = CompactionStats::default();
+	let watermark = prune_watermark(db, local_node).await?;
+
+	let mut compactor = SharedCompactor::new(watermark);
+	let mut to_delete = Vec::new();
+	let mut cursor = None;
+
+	loop {
+		let mut query = db
+			.shared_operation()
+			.find_many(vec![])
+			.order_by(shared_operation::timestamp::order(Direction::Desc))
+			.order_by(shared_operation::id::order(Direction::Desc))
+			.take(PAGE_SIZE);
+		if let Some(id) = cursor.take() {
+			query = query.cursor(shared_operation::id::equals(id)).skip(1);
+		}
+
+		let page = query
+			.select(shared_operation_compaction::select())
+			.exec()
+			.await?;
+		let len = page.len();
+
+		for op in page {
+			if let Some(removal) = compactor.removal(&op) {
+				match removal {
+					Removal::Collapsed => stats.collapsed += 1,
+					Removal::Tombstoned => stats.tombstoned += 1,
+					Removal::Pruned => stats.pruned += 1,
+				}
+
+				stats.reclaimed_bytes +=
+					(op.id.len() + op.model.len() + op.record_id.len() + op.data.len()) as u64;
+				to_delete.push(op.id.clone());
+			}
+
+			cursor = Some(op.id);
+		}
+
+		if len < PAGE_SIZE as usize {
+			break;
+		}
+	}
+
+	for chunk in to_delete.chunks(DELETE_CHUNK_SIZE) {
+		db.shared_operation()
+			.delete_many(vec![shared_operation::id::in_vec(chunk.to_vec())])
+			.exec()
+			.await?;
+	}
+
+	// owned operations are only ever written by the node that owns the data, so they can only be pruned
+	if let Some(watermark) = watermark {
+		let mut newest_owned = HashSet::new();
+		let mut to_delete = Vec::new();
+		let mut cursor = None;
+
+		loop {
+			let mut query = db
+				.owned_operation()
+				.find_many(vec![])
+				.order_by(owned_operation::timestamp::order(Direction::Desc))
+				.order_by(owned_operation::id::order(Direction::Desc))
+				.take(PAGE_SIZE);
+			if let Some(id) = cursor.take() {
+				query = query.cursor(owned_operation::id::equals(id)).skip(1);
+			}
+
+			let page = query
+				.select(owned_operation_compaction::select())
+				.exec()
+				.await?;
+			let len = page.len();
+
+			for op in page {
+				if !newest_owned.insert(op.node_id) && op.timestamp < watermark {
+					stats.pruned += 1;
+					stats.reclaimed_bytes += (op.id.len() + op.model.len() + op.data.len()) as u64;
+					to_delete.push(op.id.clone());
+				}
+
+				cursor = Some(op.id);
+			}
+
+			if len < PAGE_SIZE as usize {
+				break;
+			}
+		}
+
+		for chunk in to_delete.chunks(DELETE_CHUNK_SIZE) {
+			db.owned_operation()
+				.delete_many(vec![owned_operation::id::in_vec(chunk.to_vec())])
+				.exec()
+				.await?;
+		}
+	}
+
+	debug!("Compacted operation log: {stats:?}");
+
+	Ok(stats)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Removal {
+	Collapsed,
+	Tombstoned,
+	Pruned,
+}
+
+/// Decides which shared operations can be removed. Operations must be passed to it newest first,
+/// so the first one seen for each node, record or field is the newest.
+struct SharedCompactor {
+	watermark: Option<i64>,
+	newest: HashSet<i32>,
+	deleted_records: HashSet<(String, Vec<u8>)>,
+	updated_fields: HashSet<((String, Vec<u8>), String)>,
+}
+
+impl SharedCompactor {
+	fn new(watermark: Option<i64>) -> Self {
+		Self {
+			watermark,
+			newest: HashSet::new(),
+			deleted_records: HashSet::new(),
+			updated_fields: HashSet::new(),
+		}
+	}
+
+	fn removal(&mut self, op: &shared_operation_compaction::Data) -> Option<Removal> {
+		let is_newest = self.newest.insert(op.node_id);
+		let record = (op.model.clone(), op.record_id.clone());
+
+		let superseded = if self.deleted_records.contains(&record) {
+			Some(Removal::Tombstoned)
+		} else if op.kind == "d" {
+			self.deleted_records.insert(record);
+			None
+		} else if op.kind == "u" {
+			match serde_json::from_slice::<SharedOperationData>(&op.data) {
+				Ok(SharedOperationData::Update { field, .. }) => {
+					let is_latest = self.updated_fields.insert((record, field));
+					(!is_latest).then_some(Removal::Collapsed)
+				}
+				_ => None,
+			}
+		} else {
+			None
+		};
+
+		if is_newest {
+			return None;
+		}
+
+		superseded.or_else(|| {
+			self.watermark
+				.filter(|watermark| op.timestamp < *watermark)
+				.map(|_| Removal::Pruned)
+		})
+	}
+}
+
+/// Operations older than this have been received by every node we know of, so they can be pruned.
+async fn prune_watermark(
+	db: &PrismaClient,
+	local_node: Uuid,
+) -> prisma_client_rust::Result<Option<i64>> {
+	Ok(watermark(
+		db.node()
+			.find_many(vec![node::pub_id::not(local_node.as_bytes().to_vec())])
+			.select(node::select!({ acknowledged_timestamp }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|node| node.acknowledged_timestamp),
+	))
+}
+
+/// The oldest timestamp that has been acknowledged by the other nodes.
+///
+/// Nodes that have never acknowledged anything are ignored. These are nodes we've only received operations from
+/// (possibly relayed by another peer), which have never requested operations from us and so aren't relying on our history.
+/// This is `None` until some node has acknowledged something, as there's nothing to prune before anyone has synced with us.
+fn watermark(acknowledged: impl IntoIterator<Item = Option<i64>>) -> Option<i64> {
+	acknowledged.into_iter().flatten().min()
+}
+
+/// The timestamp up to which a node has received every operation we have, given the clocks it sent us.
+///
+/// This is the oldest of its clocks for the nodes we have operations from, as it may be missing anything newer.
+pub(super) fn acknowledged_timestamp(
+	our_clocks: &[(Uuid, uhlc::NTP64)],
+	their_clocks: &[(Uuid, uhlc::NTP64)],
+) -> i64 {
+	let their_clocks = their_clocks.iter().copied().collect::<HashMap<_, _>>();
+
+	our_clocks
+		.iter()
+		.map(|(node, _)| their_clocks.get(node).map_or(0, |clock| clock.0 as i64))
+		.min()
+		.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use serde_json::json;
+	use uhlc::NTP64;
+
+	fn op(
+		id: u8,
+		node_id: i32,
+		timestamp: i64,
+		record: u8,
+		data: SharedOperationData,
+	) -> shared_operation_compaction::Data {
+		let kind = match &data {
+			SharedOperationData::Create(_) => "c",
+			SharedOperationData::Update { .. } => "u",
+			SharedOperationData::Delete => "d",
+		};
+
+		shared_operation_compaction::Data {
+			id: vec![id],
+			timestamp,
+			model: "Tag".to_string(),
+			record_id: vec![record],
+			kind: kind.to_string(),
+			data: serde_json::to_vec(&data).unwrap(),
+			node_id,
+		}
+	}
+
+	fn update(field: &str) -> SharedOperationData {
+		SharedOperationData::Update {
+			field: field.to_string(),
+			value: json!("value"),
+		}
+	}
+
+	#[test]
+	fn acknowledged_timestamp_is_oldest_clock() {
+		let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+		let ours = [(a, NTP64(30)), (b, NTP64(40))];
+
+		assert_eq!(
+			acknowledged_timestamp(&ours, &[(a, NTP64(20)), (b, NTP64(10))]),
+			10
+		);
+		// a node it has nothing from could be missing everything
+		assert_eq!(acknowledged_timestamp(&ours, &[(a, NTP64(20))]), 0);
+		// clocks for nodes we have nothing from don't matter
+		assert_eq!(
+			acknowledged_timestamp(&ours, &[(a, NTP64(20)), (b, NTP64(25)), (c, NTP64(1))]),
+			20
+		);
+		assert_eq!(acknowledged_timestamp(&[], &[(a, NTP64(20))]), 0);
+	}
+
+	#[test]
+	fn watermark_ignores_nodes_which_never_acknowledged() {
+		assert_eq!(watermark([Some(20), None, Some(10)]), Some(10));
+		assert_eq!(watermark([None, None]), None);
+		assert_eq!(watermark(std::iter::empty()), None);
+	}
+
+	#[test]
+	fn collapses_superseded_updates() {
+		let mut compactor = SharedCompactor::new(None);
+
+		assert_eq!(compactor.removal(&op(1, 1, 40, 1, update("name"))), None);
+		assert_eq!(compactor.removal(&op(2, 1, 30, 1, update("color"))), None);
+		assert_eq!(
+			compactor.removal(&op(3, 1, 20, 1, update("name"))),
+			Some(Removal::Collapsed)
+		);
+		// the same field of another record
+		assert_eq!(compactor.removal(&op(4, 1, 10, 2, update("name"))), None);
+	}
+
+	#[test]
+	fn tombstones_deleted_records() {
+		let mut compactor = SharedCompactor::new(None);
+
+		assert_eq!(
+			compactor.removal(&op(1, 1, 30, 1, SharedOperationData::Delete)),
+			None
+		);
+		assert_eq!(
+			compactor.removal(&op(2, 1, 20, 1, update("name"))),
+			Some(Removal::Tombstoned)
+		);
+		assert_eq!(
+			compactor.removal(&op(
+				3,
+				1,
+				10,
+				1,
+				SharedOperationData::Create(sd_sync::SharedOperationCreateData::Atomic)
+			)),
+			Some(Removal::Tombstoned)
+		);
+	}
+
+	#[test]
+	fn prunes_acknowledged_operations() {
+		let mut compactor = SharedCompactor::new(Some(25));
+
+		assert_eq!(compactor.removal(&op(1, 1, 40, 1, update("a"))), None);
+		assert_eq!(compactor.removal(&op(2, 1, 30, 2, update("a"))), None);
+		assert_eq!(
+			compactor.removal(&op(3, 1, 20, 3, update("a"))),
+			Some(Removal::Pruned)
+		);
+		// the newest operation from each node is kept, however old it is
+		assert_eq!(compactor.removal(&op(4, 2, 10, 4, update("a"))), None);
+		assert_eq!(
+			compactor.removal(&op(5, 2, 5, 5, update("a"))),
+			Some(Removal::Pruned)
+		);
+	}
+
+	#[test]
+	fn keeps_newest_operation_from_each_node() {
+		let mut compactor = SharedCompactor::new(None);
+
+		assert_eq!(
+			compactor.removal(&op(1, 1, 30, 1, SharedOperationData::Delete)),
+			None
+		);
+		// this would be tombstoned, but it's the newest operation from node 2
+		assert_eq!(compactor.removal(&op(2, 2, 20, 1, update("name"))), None);
+	}
+}
@@ -0,0 +1,271 @@
+use crate::prisma::{node, shared_operation, sync_conflict, PrismaClient};
+
+use super::IngestError;
+
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::Direction;
+use rspc::Type;
+use sd_sync::{CRDTOperation, SharedOperation, SharedOperationData};
+use serde::Serialize;
+use serde_json::Value;
+use serde_with::{serde_as, DisplayFromStr};
+use tracing::debug;
+use uuid::Uuid;
+
+/// A conflict between updates to the same field, as recorded by [`resolve_update`].
+#[derive(Serialize, Type, Debug)]
+pub struct SyncConflict {
+	pub id: i32,
+	pub model: String,
+	pub record_id: Value,
+	pub field: String,
+	/// The update which was applied.
+	pub winner: ConflictingUpdate,
+	/// The update which was discarded.
+	pub loser: ConflictingUpdate,
+	pub date_resolved: DateTime<FixedOffset>,
+}
+
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct ConflictingUpdate {
+	pub node: Uuid,
+	pub value: Value,
+	/// The HLC timestamp of the update.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub timestamp: u64,
+}
+
+shared_operation::select!(shared_operation_update {
+	timestamp data node: select { pub_id }
+});
+
+/// An update to a field, as compared when resolving conflicts.
+struct Update<'a> {
+	node: Uuid,
+	timestamp: u64,
+	value: &'a Value,
+}
+
+/// Decides whether an update received from a peer should be applied, which is only the case if it's newer than every other update to the field.
+///
+/// If the newest update to the field came from another node and neither node had seen the other's update when making their own,
+/// the updates conflicted and the one which was discarded is recorded in the `sync_conflict` table.
+pub(super) async fn resolve_update(
+	db: &PrismaClient,
+	op: &CRDTOperation,
+	shared_op: &SharedOperation,
+) -> Result<bool, IngestError> {
+	let SharedOperationData::Update { field, value } = &shared_op.data else {
+		return Ok(true);
+	};
+
+	let record_id = serde_json::to_vec(&shared_op.record_id)?;
+
+	let newest = db
+		.shared_operation()
+		.find_first(vec![
+			shared_operation::model::equals(shared_op.model.clone()),
+			shared_operation::record_id::equals(record_id.clone()),
+			shared_operation::field::equals(Some(field.clone())),
+		])
+		.order_by(shared_operation::timestamp::order(Direction::Desc))
+		.select(shared_operation_update::select())
+		.exec()
+		.await?;
+	let Some(newest) = newest else {
+		return Ok(true);
+	};
+
+	let newest_node = match Uuid::from_slice(&newest.node.pub_id) {
+		Ok(node) => node,
+		Err(_) => return Ok(true),
+	};
+	let newest_value = match serde_json::from_slice(&newest.data) {
+		Ok(SharedOperationData::Update { value, .. }) => value,
+		_ => return Ok(true),
+	};
+
+	let incoming = Update {
+		node: op.node,
+		timestamp: op.timestamp.0,
+		value,
+	};
+	let existing = Update {
+		node: newest_node,
+		timestamp: newest.timestamp as u64,
+		value: &newest_value,
+	};
+
+	let apply = wins(&incoming, &existing);
+
+	// we only need to know what the node had seen of ours if its update is going to replace ours
+	let acknowledged = if apply && incoming.node != existing.node {
+		db.node()
+			.find_unique(node::pub_id::equals(op.node.as_bytes().to_vec()))
+			.select(node::select!({ acknowledged_timestamp }))
+			.exec()
+			.await?
+			.and_then(|node| node.acknowledged_timestamp)
+	} else {
+		None
+	};
+
+	if !conflicted(&incoming, &existing, apply, acknowledged) {
+		return Ok(apply);
+	}
+
+	let (winner, loser) = if apply {
+		(incoming, existing)
+	} else {
+		(existing, incoming)
+	};
+
+	debug!(
+		"Resolved conflicting updates to field '{field}' of {} {}, keeping the update from node '{}'",
+		shared_op.model, shared_op.record_id, winner.node
+	);
+
+	db.sync_conflict()
+		.create(
+			shared_op.model.clone(),
+			record_id,
+			field.clone(),
+			serde_json::to_vec(winner.value)?,
+			winner.timestamp as i64,
+			winner.node.as_bytes().to_vec(),
+			serde_json::to_vec(loser.value)?,
+			loser.timestamp as i64,
+			loser.node.as_bytes().to_vec(),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	Ok(apply)
+}
+
+/// Whether an incoming update replaces the newest update to the field, which is the case if it's newer.
+///
+/// Timestamps are only equal if they're from different nodes, so the node id breaks the tie like it does for HLC timestamps.
+fn wins(incoming: &Update, newest: &Update) -> bool {
+	(incoming.timestamp, incoming.node) > (newest.timestamp, newest.node)
+}
+
+/// Whether two updates to a field conflicted, as neither node had seen the other's update when making their own.
+///
+/// An update which is older than ours can't have been made after seeing ours, as the node's clock would've been moved past it.
+/// For an update which is newer, we only know the node had seen ours if it had `acknowledged` it.
+fn conflicted(
+	incoming: &Update,
+	newest: &Update,
+	applied: bool,
+	acknowledged: Option<i64>,
+) -> bool {
+	if incoming.node == newest.node || incoming.value == newest.value {
+		return false;
+	}
+
+	!applied
+		|| acknowledged.map_or(true, |acknowledged| {
+			(acknowledged as u64) < newest.timestamp
+		})
+}
+
+/// The conflicts which have been resolved, newest first.
+pub(super) async fn get_conflicts(
+	db: &PrismaClient,
+) -> prisma_client_rust::Result<Vec<SyncConflict>> {
+	Ok(db
+		.sync_conflict()
+		.find_many(vec![])
+		.order_by(sync_conflict::id::order(Direction::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.flat_map(conflict_from_db)
+		.collect())
+}
+
+fn conflict_from_db(conflict: sync_conflict::Data) -> Option<SyncConflict> {
+	Some(SyncConflict {
+		id: conflict.id,
+		model: conflict.model,
+		record_id: serde_json::from_slice(&conflict.record_id).ok()?,
+		field: conflict.field,
+		winner: ConflictingUpdate {
+			node: Uuid::from_slice(&conflict.winner_node).ok()?,
+			value: serde_json::from_slice(&conflict.winner_value).ok()?,
+			timestamp: conflict.winner_timestamp as u64,
+		},
+		loser: ConflictingUpdate {
+			node: Uuid::from_slice(&conflict.loser_node).ok()?,
+			value: serde_json::from_slice(&conflict.loser_value).ok()?,
+			timestamp: conflict.loser_timestamp as u64,
+		},
+		date_resolved: conflict.date_resolved,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use serde_json::json;
+
+	const A: Uuid = Uuid::from_u128(1);
+	const B: Uuid = Uuid::from_u128(2);
+
+	fn update(node: Uuid, timestamp: u64, value: &Value) -> Update {
+		Update {
+			node,
+			timestamp,
+			value,
+		}
+	}
+
+	#[test]
+	fn newest_update_wins() {
+		let (old, new) = (json!("old"), json!("new"));
+
+		assert!(wins(&update(A, 2, &new), &update(B, 1, &old)));
+		assert!(!wins(&update(A, 1, &old), &update(B, 2, &new)));
+	}
+
+	#[test]
+	fn tie_is_broken_by_node() {
+		let (a, b) = (json!("a"), json!("b"));
+
+		assert!(wins(&update(B, 1, &b), &update(A, 1, &a)));
+		assert!(!wins(&update(A, 1, &a), &update(B, 1, &b)));
+	}
+
+	#[test]
+	fn concurrent_updates_conflict() {
+		let (a, b) = (json!("a"), json!("b"));
+		let ours = update(A, 2, &a);
+
+		// the incoming update replaces ours, but the node hadn't seen ours
+		let newer = update(B, 3, &b);
+		assert!(conflicted(&newer, &ours, true, None));
+		assert!(conflicted(&newer, &ours, true, Some(1)));
+
+		// the incoming update is older than ours, so it was made without seeing ours
+		let older = update(B, 1, &b);
+		assert!(conflicted(&older, &ours, false, None));
+	}
+
+	#[test]
+	fn sequential_updates_dont_conflict() {
+		let (a, b) = (json!("a"), json!("b"));
+		let ours = update(A, 2, &a);
+
+		// the node had seen our update before replacing it
+		assert!(!conflicted(&update(B, 3, &b), &ours, true, Some(2)));
+		// updates from the same node are always sequential
+		assert!(!conflicted(&update(A, 3, &b), &ours, true, None));
+		// updates to the same value don't lose anything
+		assert!(!conflicted(&update(B, 3, &a), &ours, true, None));
+	}
+}
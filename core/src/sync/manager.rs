@@ -2,14 +2,55 @@ use crate::prisma::*;
 
 use std::{collections::HashMap, sync::Arc};
 
+use chrono::Utc;
+
 use sd_sync::*;
 
-use serde_json::{from_value, json, to_vec, Value};
+use prisma_client_rust::Direction;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{from_value, json, to_vec, Map, Value};
+use thiserror::Error;
 use tokio::sync::broadcast::{self, Receiver, Sender};
-use uhlc::{HLCBuilder, HLC, NTP64};
+use tracing::warn;
+use uhlc::{HLCBuilder, Timestamp, HLC, NTP64};
 use uuid::Uuid;
 
-use super::ModelSyncData;
+use super::{
+	compaction, conflicts, status, CompactionStats, ModelSyncData, SyncConflict, SyncStatus,
+};
+
+#[derive(Debug, Error)]
+pub enum IngestError {
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to serialize operation: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error("operation is missing the '{0}' field")]
+	MissingField(&'static str),
+	#[error("operation has an invalid value for the '{0}' field: {1}")]
+	InvalidField(&'static str, serde_json::Error),
+	#[error("operation refers to an unknown field '{0}'")]
+	UnknownField(String),
+	#[error("operation refers to a {0} which doesn't exist")]
+	MissingRecord(&'static str),
+	#[error("unsupported operation: {0:?}")]
+	Unsupported(CRDTOperation),
+}
+
+impl IngestError {
+	/// Whether the operation itself is invalid, so ingesting it again will never succeed.
+	///
+	/// Other errors (such as a busy database) may succeed if the operation is ingested again later.
+	pub fn is_permanent(&self) -> bool {
+		matches!(
+			self,
+			Self::Unsupported(_)
+				| Self::UnknownField(_)
+				| Self::InvalidField(..)
+				| Self::MissingField(_)
+		)
+	}
+}
 
 #[derive(Clone)]
 pub enum SyncMessage {
@@ -17,6 +58,16 @@ pub enum SyncMessage {
 	Created(CRDTOperation),
 }
 
+/// A request for the operations that a node hasn't seen yet.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetOpsArgs {
+	/// The node making the request, which acknowledges that it has everything up to its clocks.
+	pub node: Uuid,
+	/// The latest timestamp the requesting node has for each node. Operations from nodes that aren't in here are all returned.
+	pub clocks: Vec<(Uuid, NTP64)>,
+	pub count: u32,
+}
+
 pub struct SyncManager {
 	db: Arc<PrismaClient>,
 	node: Uuid,
@@ -81,7 +132,7 @@ impl SyncManager {
 							kind.to_string(),
 							to_vec(&shared_op.data).unwrap(),
 							node::pub_id::equals(op.node.as_bytes().to_vec()),
-							vec![],
+							vec![shared_operation::field::set(updated_field(&shared_op.data))],
 						))
 					}
 					_ => None,
@@ -140,7 +191,7 @@ impl SyncManager {
 						kind.to_string(),
 						to_vec(&shared_op.data).unwrap(),
 						node::pub_id::equals(op.node.as_bytes().to_vec()),
-						vec![],
+						vec![shared_operation::field::set(updated_field(&shared_op.data))],
 					),
 					query,
 				))
@@ -160,45 +211,222 @@ impl SyncManager {
 			.db
 			.shared_operation()
 			.find_many(vec![])
-			.order_by(shared_operation::timestamp::order(
-				prisma_client_rust::Direction::Asc,
-			))
-			.include(shared_operation::include!({ node: select {
-                pub_id
-            } }))
+			.order_by(shared_operation::timestamp::order(Direction::Asc))
+			.include(shared_operation_with_node::include())
 			.exec()
 			.await?
 			.into_iter()
-			.flat_map(|op| {
-				Some(CRDTOperation {
-					id: Uuid::from_slice(&op.id).ok()?,
-					node: Uuid::from_slice(&op.node.pub_id).ok()?,
-					timestamp: NTP64(op.timestamp as u64),
-					typ: CRDTOperationType::Shared(SharedOperation {
-						record_id: serde_json::from_slice(&op.record_id).ok()?,
-						model: op.model,
-						data: serde_json::from_slice(&op.data).ok()?,
-					}),
-				})
-			})
+			.flat_map(shared_op_from_db)
 			.collect())
 	}
 
-	pub async fn ingest_op(&self, op: CRDTOperation) -> prisma_client_rust::Result<()> {
+	/// The latest timestamp of the operations we have from each node.
+	///
+	/// These are sent to peers when catching up, so they only send the operations we're missing.
+	pub async fn get_clocks(&self) -> prisma_client_rust::Result<Vec<(Uuid, NTP64)>> {
+		let db = &self.db;
+		let mut clocks = Vec::new();
+
+		for node in db
+			.node()
+			.find_many(vec![])
+			.select(node::select!({ id pub_id }))
+			.exec()
+			.await?
+		{
+			let Ok(pub_id) = Uuid::from_slice(&node.pub_id) else {
+				continue;
+			};
+
+			let (shared, owned) = db
+				._batch((
+					db.shared_operation()
+						.find_first(vec![shared_operation::node_id::equals(node.id)])
+						.order_by(shared_operation::timestamp::order(Direction::Desc))
+						.select(shared_operation::select!({ timestamp })),
+					db.owned_operation()
+						.find_first(vec![owned_operation::node_id::equals(node.id)])
+						.order_by(owned_operation::timestamp::order(Direction::Desc))
+						.select(owned_operation::select!({ timestamp })),
+				))
+				.await?;
+
+			if let Some(timestamp) = shared
+				.map(|op| op.timestamp)
+				.into_iter()
+				.chain(owned.map(|op| op.timestamp))
+				.max()
+			{
+				clocks.push((pub_id, NTP64(timestamp as u64)));
+			}
+		}
+
+		Ok(clocks)
+	}
+
+	/// The oldest `args.count` operations which are newer than the requesting node's clocks, ordered by timestamp.
+	///
+	/// As timestamps are unique per node, the requester can page through everything by advancing its clocks to the newest operation of each node it receives.
+	pub async fn get_ops_after(
+		&self,
+		args: GetOpsArgs,
+	) -> prisma_client_rust::Result<Vec<CRDTOperation>> {
+		let db = &self.db;
+		let clocks = args.clocks.into_iter().collect::<HashMap<_, _>>();
+		let mut ops = Vec::new();
+
+		for node in db
+			.node()
+			.find_many(vec![])
+			.select(node::select!({ id pub_id }))
+			.exec()
+			.await?
+		{
+			let after = Uuid::from_slice(&node.pub_id)
+				.ok()
+				.and_then(|pub_id| clocks.get(&pub_id))
+				.map_or(-1, |clock| clock.0 as i64);
+
+			let (shared, owned) = db
+				._batch((
+					db.shared_operation()
+						.find_many(vec![
+							shared_operation::node_id::equals(node.id),
+							shared_operation::timestamp::gt(after),
+						])
+						.order_by(shared_operation::timestamp::order(Direction::Asc))
+						.take(args.count as i64)
+						.include(shared_operation_with_node::include()),
+					db.owned_operation()
+						.find_many(vec![
+							owned_operation::node_id::equals(node.id),
+							owned_operation::timestamp::gt(after),
+						])
+						.order_by(owned_operation::timestamp::order(Direction::Asc))
+						.take(args.count as i64)
+						.include(owned_operation_with_node::include()),
+				))
+				.await?;
+
+			ops.extend(shared.into_iter().flat_map(shared_op_from_db));
+			ops.extend(owned.into_iter().flat_map(owned_op_from_db));
+		}
+
+		ops.sort_by_key(|op| op.timestamp);
+		ops.truncate(args.count as usize);
+
+		Ok(ops)
+	}
+
+	/// Records how far a node has caught up with us, from the clocks it sent when requesting operations.
+	pub async fn acknowledge(
+		&self,
+		node: Uuid,
+		clocks: &[(Uuid, NTP64)],
+	) -> prisma_client_rust::Result<()> {
+		let acknowledged = compaction::acknowledged_timestamp(&self.get_clocks().await?, clocks);
+
+		let params = || {
+			vec![
+				node::acknowledged_timestamp::set(Some(acknowledged)),
+				node::last_synced::set(Some(Utc::now().into())),
+			]
+		};
+
+		self.db
+			.node()
+			.upsert(
+				node::pub_id::equals(node.as_bytes().to_vec()),
+				node::create_unchecked(node.as_bytes().to_vec(), "TEMP".to_string(), params()),
+				params(),
+			)
+			.exec()
+			.await?;
+
+		Ok(())
+	}
+
+	/// Removes operations that are no longer needed by any node, to stop the operation log growing forever.
+	///
+	/// This collapses superseded updates, reduces deleted records to a tombstone and prunes everything
+	/// that all other nodes have acknowledged.
+	pub async fn compact(&self) -> prisma_client_rust::Result<CompactionStats> {
+		compaction::compact(&self.db, self.node).await
+	}
+
+	/// The conflicts between concurrent updates to the same field which were resolved when ingesting operations, newest first.
+	pub async fn get_conflicts(&self) -> prisma_client_rust::Result<Vec<SyncConflict>> {
+		conflicts::get_conflicts(&self.db).await
+	}
+
+	/// How far each other node has synced the library with us.
+	pub async fn status(&self) -> prisma_client_rust::Result<SyncStatus> {
+		status::status(&self.db, self.node, &self.get_clocks().await?).await
+	}
+
+	/// The id this node's operations are created with.
+	pub fn node(&self) -> Uuid {
+		self.node
+	}
+
+	/// Applies an operation from another node, and stores it so it can be passed on to other nodes.
+	///
+	/// Operations come from peers, so an operation which can't be applied returns an error rather than panicking.
+	pub async fn ingest_op(&self, op: CRDTOperation) -> Result<(), IngestError> {
 		let db = &self.db;
 
+		// operations may be received more than once (from the live broadcast and when catching up)
+		let (shared, owned) = db
+			._batch((
+				db.shared_operation()
+					.count(vec![shared_operation::id::equals(
+						op.id.as_bytes().to_vec(),
+					)]),
+				db.owned_operation()
+					.count(vec![owned_operation::id::equals(op.id.as_bytes().to_vec())]),
+			))
+			.await?;
+		if shared + owned > 0 {
+			return Ok(());
+		}
+
+		self.clock
+			.update_with_timestamp(&Timestamp::new(op.timestamp, op.node.into()))
+			.map_err(|e| warn!("Failed to update clock with ingested operation: {e}"))
+			.ok();
+
+		let last_synced = || vec![node::last_synced::set(Some(Utc::now().into()))];
 		db.node()
 			.upsert(
 				node::pub_id::equals(op.node.as_bytes().to_vec()),
-				node::create_unchecked(op.node.as_bytes().to_vec(), "TEMP".to_string(), vec![]),
-				vec![],
+				node::create_unchecked(
+					op.node.as_bytes().to_vec(),
+					"TEMP".to_string(),
+					last_synced(),
+				),
+				last_synced(),
 			)
 			.exec()
 			.await?;
 
 		let msg = SyncMessage::Ingested(op.clone());
 
-		match ModelSyncData::from_op(op.typ.clone()).unwrap() {
+		// an update which is older than the field's newest update is stored (so it isn't requested again) but not applied
+		if let CRDTOperationType::Shared(shared_op) = &op.typ {
+			if !conflicts::resolve_update(db, &op, shared_op).await? {
+				insert_shared_op(db, &op, shared_op).await?;
+				self.tx.send(msg).ok();
+				return Ok(());
+			}
+		}
+
+		let Some(data) = ModelSyncData::from_op(op.typ.clone()) else {
+			warn!("Ignoring operation for unsupported model: {op:?}");
+			insert_op(db, &op).await?;
+			return Ok(());
+		};
+
+		match data {
 			ModelSyncData::FilePath(id, shared_op) => {
 				let location = db
 					.location()
@@ -206,7 +434,7 @@ impl SyncManager {
 					.select(location::select!({ id }))
 					.exec()
 					.await?
-					.unwrap();
+					.ok_or(IngestError::MissingRecord("location"))?;
 
 				match shared_op {
 					SharedOperationData::Create(SharedOperationCreateData::Unique(mut data)) => {
@@ -214,17 +442,15 @@ impl SyncManager {
 							.create(
 								id.id,
 								location::id::equals(location.id),
-								serde_json::from_value(data.remove("materialized_path").unwrap())
-									.unwrap(),
-								serde_json::from_value(data.remove("name").unwrap()).unwrap(),
-								serde_json::from_value(
-									data.remove("extension").unwrap_or_else(|| {
-										serde_json::Value::String("".to_string())
-									}),
-								)
-								.unwrap(),
-								serde_json::from_value(data.remove("inode").unwrap()).unwrap(),
-								serde_json::from_value(data.remove("device").unwrap()).unwrap(),
+								take_field(&mut data, "materialized_path")?,
+								take_field(&mut data, "name")?,
+								data.remove("extension")
+									.map(from_value)
+									.transpose()
+									.map_err(|e| IngestError::InvalidField("extension", e))?
+									.unwrap_or_default(),
+								take_field(&mut data, "inode")?,
+								take_field(&mut data, "device")?,
 								data.into_iter()
 									.flat_map(|(k, v)| file_path::SetParam::deserialize(&k, v))
 									.collect(),
@@ -237,28 +463,31 @@ impl SyncManager {
 							.file_path()
 							.update(
 								file_path::location_id_id(location.id, id.id),
-								vec![file_path::SetParam::deserialize(&field, value).unwrap()],
+								vec![file_path::SetParam::deserialize(&field, value)
+									.ok_or(IngestError::UnknownField(field))?],
 							)
 							.exec()
 							.await?;
 					}
-					_ => todo!(),
+					_ => return Err(IngestError::Unsupported(op)),
 				}
 			}
 			ModelSyncData::Location(id, shared_op) => match shared_op {
 				SharedOperationData::Create(SharedOperationCreateData::Unique(mut data)) => {
+					let node = take_field::<HashMap<String, Value>>(&mut data, "node")?
+						.into_iter()
+						.next()
+						.and_then(|(field, value)| {
+							node::UniqueWhereParam::deserialize(&field, value)
+						})
+						.ok_or(IngestError::UnknownField("node".to_string()))?;
+
 					db.location()
 						.create(
 							id.pub_id,
-							serde_json::from_value(data.remove("name").unwrap()).unwrap(),
-							serde_json::from_value(data.remove("path").unwrap()).unwrap(),
-							{
-								let val: std::collections::HashMap<String, Value> =
-									from_value(data.remove("node").unwrap()).unwrap();
-								let val = val.into_iter().next().unwrap();
-
-								node::UniqueWhereParam::deserialize(&val.0, val.1).unwrap()
-							},
+							take_field(&mut data, "name")?,
+							take_field(&mut data, "path")?,
+							node,
 							data.into_iter()
 								.flat_map(|(k, v)| location::SetParam::deserialize(&k, v))
 								.collect(),
@@ -266,7 +495,7 @@ impl SyncManager {
 						.exec()
 						.await?;
 				}
-				_ => todo!(),
+				_ => return Err(IngestError::Unsupported(op)),
 			},
 			ModelSyncData::Object(id, shared_op) => match shared_op {
 				SharedOperationData::Create(_) => {
@@ -284,71 +513,52 @@ impl SyncManager {
 					db.object()
 						.update(
 							object::pub_id::equals(id.pub_id),
-							vec![object::SetParam::deserialize(&field, value).unwrap()],
+							vec![object::SetParam::deserialize(&field, value)
+								.ok_or(IngestError::UnknownField(field))?],
 						)
 						.exec()
 						.await?;
 				}
-				_ => todo!(),
+				_ => return Err(IngestError::Unsupported(op)),
 			},
 			ModelSyncData::Tag(id, shared_op) => match shared_op {
-				SharedOperationData::Create(create_data) => match create_data {
-					SharedOperationCreateData::Unique(create_data) => {
-						db.tag()
-							.create(
-								id.pub_id,
-								create_data
-									.into_iter()
-									.flat_map(|(field, value)| {
-										tag::SetParam::deserialize(&field, value)
-									})
-									.collect(),
-							)
-							.exec()
-							.await?;
-					}
-					_ => unreachable!(),
-				},
+				SharedOperationData::Create(SharedOperationCreateData::Unique(create_data)) => {
+					db.tag()
+						.create(
+							id.pub_id,
+							create_data
+								.into_iter()
+								.flat_map(|(field, value)| {
+									tag::SetParam::deserialize(&field, value)
+								})
+								.collect(),
+						)
+						.exec()
+						.await?;
+				}
 				SharedOperationData::Update { field, value } => {
 					db.tag()
 						.update(
 							tag::pub_id::equals(id.pub_id),
-							vec![tag::SetParam::deserialize(&field, value).unwrap()],
+							vec![tag::SetParam::deserialize(&field, value)
+								.ok_or(IngestError::UnknownField(field))?],
 						)
 						.exec()
 						.await?;
 				}
 				SharedOperationData::Delete => {
+					// the tag may have never been received, if its other operations were compacted away
 					db.tag()
-						.delete(tag::pub_id::equals(id.pub_id))
+						.delete_many(vec![tag::pub_id::equals(id.pub_id)])
 						.exec()
 						.await?;
 				}
+				_ => return Err(IngestError::Unsupported(op)),
 			},
-			_ => todo!(),
+			_ => return Err(IngestError::Unsupported(op)),
 		}
 
-		if let CRDTOperationType::Shared(shared_op) = op.typ {
-			let kind = match &shared_op.data {
-				SharedOperationData::Create(_) => "c",
-				SharedOperationData::Update { .. } => "u",
-				SharedOperationData::Delete => "d",
-			};
-
-			db.shared_operation()
-				.create(
-					op.id.as_bytes().to_vec(),
-					op.timestamp.0 as i64,
-					shared_op.model.to_string(),
-					to_vec(&shared_op.record_id).unwrap(),
-					kind.to_string(),
-					to_vec(&shared_op.data).unwrap(),
-					node::pub_id::equals(op.node.as_bytes().to_vec()),
-					vec![],
-				)
-				.exec()
-				.await?;
-		}
+		insert_op(db, &op).await?;
 
 		self.tx.send(msg).ok();
 
@@ -489,3 +699,104 @@ impl SyncManager {
 		}))
 	}
 }
+
+shared_operation::include!(shared_operation_with_node {
+	node: select { pub_id }
+});
+owned_operation::include!(owned_operation_with_node {
+	node: select { pub_id }
+});
+
+/// Stores an ingested operation, which is how operations that have already been ingested are recognised.
+async fn insert_op(db: &PrismaClient, op: &CRDTOperation) -> Result<(), IngestError> {
+	match &op.typ {
+		CRDTOperationType::Shared(shared_op) => insert_shared_op(db, op, shared_op).await,
+		CRDTOperationType::Owned(owned_op) => {
+			db.owned_operation()
+				.create(
+					op.id.as_bytes().to_vec(),
+					op.timestamp.0 as i64,
+					to_vec(&owned_op.items)?,
+					owned_op.model.clone(),
+					node::pub_id::equals(op.node.as_bytes().to_vec()),
+					vec![],
+				)
+				.exec()
+				.await?;
+
+			Ok(())
+		}
+		// relation operations aren't stored by this node either
+		CRDTOperationType::Relation(_) => Ok(()),
+	}
+}
+
+async fn insert_shared_op(
+	db: &PrismaClient,
+	op: &CRDTOperation,
+	shared_op: &SharedOperation,
+) -> Result<(), IngestError> {
+	let kind = match &shared_op.data {
+		SharedOperationData::Create(_) => "c",
+		SharedOperationData::Update { .. } => "u",
+		SharedOperationData::Delete => "d",
+	};
+
+	db.shared_operation()
+		.create(
+			op.id.as_bytes().to_vec(),
+			op.timestamp.0 as i64,
+			shared_op.model.to_string(),
+			to_vec(&shared_op.record_id)?,
+			kind.to_string(),
+			to_vec(&shared_op.data)?,
+			node::pub_id::equals(op.node.as_bytes().to_vec()),
+			vec![shared_operation::field::set(updated_field(&shared_op.data))],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// The field changed by an update operation, which is stored alongside it so a field's updates can be queried.
+fn updated_field(data: &SharedOperationData) -> Option<String> {
+	match data {
+		SharedOperationData::Update { field, .. } => Some(field.clone()),
+		_ => None,
+	}
+}
+
+/// Removes a required field from the data of a create operation.
+fn take_field<T: DeserializeOwned>(
+	data: &mut Map<String, Value>,
+	field: &'static str,
+) -> Result<T, IngestError> {
+	from_value(data.remove(field).ok_or(IngestError::MissingField(field))?)
+		.map_err(|e| IngestError::InvalidField(field, e))
+}
+
+fn shared_op_from_db(op: shared_operation_with_node::Data) -> Option<CRDTOperation> {
+	Some(CRDTOperation {
+		id: Uuid::from_slice(&op.id).ok()?,
+		node: Uuid::from_slice(&op.node.pub_id).ok()?,
+		timestamp: NTP64(op.timestamp as u64),
+		typ: CRDTOperationType::Shared(SharedOperation {
+			record_id: serde_json::from_slice(&op.record_id).ok()?,
+			model: op.model,
+			data: serde_json::from_slice(&op.data).ok()?,
+		}),
+	})
+}
+
+fn owned_op_from_db(op: owned_operation_with_node::Data) -> Option<CRDTOperation> {
+	Some(CRDTOperation {
+		id: Uuid::from_slice(&op.id).ok()?,
+		node: Uuid::from_slice(&op.node.pub_id).ok()?,
+		timestamp: NTP64(op.timestamp as u64),
+		typ: CRDTOperationType::Owned(OwnedOperation {
+			model: op.model,
+			items: serde_json::from_slice(&op.data).ok()?,
+		}),
+	})
+}
@@ -1,4 +1,10 @@
+mod compaction;
+mod conflicts;
 mod manager;
+mod status;
 
 pub use crate::prisma_sync::*;
+pub use compaction::CompactionStats;
+pub use conflicts::{ConflictingUpdate, SyncConflict};
 pub use manager::*;
+pub use status::{NodeSyncStatus, SyncStatus};
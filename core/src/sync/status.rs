@@ -0,0 +1,88 @@
+use crate::prisma::{node, owned_operation, shared_operation, PrismaClient};
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use rspc::Type;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use uhlc::NTP64;
+use uuid::Uuid;
+
+#[derive(Serialize, Type, Debug)]
+pub struct SyncStatus {
+	/// The id this node's operations are created with.
+	pub node: Uuid,
+	/// Every other node which has synced the library with us.
+	pub nodes: Vec<NodeSyncStatus>,
+}
+
+/// How far a node has synced a library with us.
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct NodeSyncStatus {
+	pub node: Uuid,
+	pub name: String,
+	/// The HLC timestamp of the newest operation we have from the node.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub clock: Option<u64>,
+	/// The HLC timestamp up to which the node has received every operation from us. This is `None` until it first requests operations from us.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub acknowledged_timestamp: Option<u64>,
+	/// How many of the operations we have (excluding its own) the node hasn't acknowledged yet.
+	pub pending_operations: u32,
+	pub last_synced: Option<DateTime<FixedOffset>>,
+}
+
+pub(super) async fn status(
+	db: &PrismaClient,
+	local_node: Uuid,
+	clocks: &[(Uuid, NTP64)],
+) -> prisma_client_rust::Result<SyncStatus> {
+	let clocks = clocks.iter().copied().collect::<HashMap<_, _>>();
+	let mut nodes = Vec::new();
+
+	for node in db
+		.node()
+		.find_many(vec![node::pub_id::not(local_node.as_bytes().to_vec())])
+		.select(node::select!({ id pub_id name acknowledged_timestamp last_synced }))
+		.exec()
+		.await?
+	{
+		let Ok(pub_id) = Uuid::from_slice(&node.pub_id) else {
+			continue;
+		};
+
+		let after = node.acknowledged_timestamp.unwrap_or(-1);
+		let (shared, owned) = db
+			._batch((
+				db.shared_operation().count(vec![
+					shared_operation::node_id::not(node.id),
+					shared_operation::timestamp::gt(after),
+				]),
+				db.owned_operation().count(vec![
+					owned_operation::node_id::not(node.id),
+					owned_operation::timestamp::gt(after),
+				]),
+			))
+			.await?;
+
+		nodes.push(NodeSyncStatus {
+			node: pub_id,
+			name: node.name,
+			clock: clocks.get(&pub_id).map(|clock| clock.0),
+			acknowledged_timestamp: node
+				.acknowledged_timestamp
+				.map(|timestamp| timestamp as u64),
+			pending_operations: (shared + owned) as u32,
+			last_synced: node.last_synced,
+		});
+	}
+
+	Ok(SyncStatus {
+		node: local_node,
+		nodes,
+	})
+}
@@ -5,7 +5,7 @@ use tokio::io::AsyncReadExt;
 
 mod stream;
 
-pub use self::stream::{Decryptor, Encryptor};
+pub use self::stream::{BlockDecryptor, Decryptor, Encryptor};
 
 /// This is used to exhaustively read from an asynchronous reader into a buffer.
 ///
@@ -40,7 +40,7 @@ mod tests {
 	use rand_chacha::ChaCha20Rng;
 
 	use crate::{
-		primitives::BLOCK_LEN,
+		primitives::{AEAD_TAG_LEN, BLOCK_LEN},
 		types::{Algorithm, Key, Nonce},
 	};
 
@@ -351,6 +351,57 @@ mod tests {
 		assert_eq!(buf, output);
 	}
 
+	#[tokio::test]
+	async fn xchacha_decrypt_individual_blocks() {
+		let mut buf = vec![0u8; BLOCK_LEN * 2 + 17];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+		let mut writer = Cursor::new(Vec::new());
+
+		let encryptor = Encryptor::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305).unwrap();
+
+		encryptor
+			.encrypt_streams(buf.as_slice(), &mut writer, &AAD)
+			.await
+			.unwrap();
+
+		let ciphertext = writer.into_inner();
+		let blocks = ciphertext
+			.chunks(BLOCK_LEN + AEAD_TAG_LEN)
+			.collect::<Vec<_>>();
+
+		assert_eq!(
+			BlockDecryptor::plaintext_len(ciphertext.len() as u64),
+			Some(buf.len() as u64)
+		);
+
+		let decryptor =
+			BlockDecryptor::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305).unwrap();
+
+		// the blocks are decrypted out of order, as they would be when seeking
+		assert_eq!(
+			decryptor.decrypt_block(2, true, blocks[2], &AAD).unwrap(),
+			buf[BLOCK_LEN * 2..]
+		);
+		assert_eq!(
+			decryptor.decrypt_block(1, false, blocks[1], &AAD).unwrap(),
+			buf[BLOCK_LEN..BLOCK_LEN * 2]
+		);
+
+		// a block can't be passed off as another
+		assert!(decryptor.decrypt_block(0, false, blocks[1], &AAD).is_err());
+		assert!(decryptor.decrypt_block(1, true, blocks[1], &AAD).is_err());
+	}
+
+	#[test]
+	fn block_plaintext_len() {
+		assert_eq!(BlockDecryptor::plaintext_len(AEAD_TAG_LEN as u64), Some(0));
+		assert_eq!(
+			BlockDecryptor::plaintext_len((BLOCK_LEN + AEAD_TAG_LEN * 2) as u64),
+			Some(BLOCK_LEN as u64)
+		);
+		assert_eq!(BlockDecryptor::plaintext_len(3), None);
+	}
+
 	#[tokio::test]
 	#[should_panic(expected = "NonceLengthMismatch")]
 	async fn encrypt_with_invalid_nonce() {
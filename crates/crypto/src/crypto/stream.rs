@@ -6,7 +6,7 @@ use crate::{
 	Error, Protected, Result,
 };
 use aead::{
-	stream::{DecryptorLE31, EncryptorLE31},
+	stream::{DecryptorLE31, EncryptorLE31, NewStream, StreamLE31, StreamPrimitive},
 	Payload,
 };
 use aes_gcm::Aes256Gcm;
@@ -167,3 +167,68 @@ impl_stream!(
 	XChaCha20Poly1305,
 	Aes256Gcm
 );
+
+/// This decrypts individual blocks of a stream that was encrypted with `Encryptor::encrypt_streams()`.
+///
+/// Unlike `Decryptor`, the blocks may be decrypted in any order, so only the ones which cover a range of the plaintext need to be read.
+///
+/// Every block is `BLOCK_LEN + AEAD_TAG_LEN` bytes long, apart from the last which is always shorter (and may be empty, aside from its tag).
+pub enum BlockDecryptor {
+	XChaCha20Poly1305(Box<StreamLE31<XChaCha20Poly1305>>),
+	Aes256Gcm(Box<StreamLE31<Aes256Gcm>>),
+}
+
+impl BlockDecryptor {
+	/// The desired master key, nonce and algorithm should be provided.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn new(key: Key, nonce: Nonce, algorithm: Algorithm) -> Result<Self> {
+		if nonce.len() != algorithm.nonce_len() {
+			return Err(Error::NonceLengthMismatch);
+		}
+
+		let s = match algorithm {
+			Algorithm::XChaCha20Poly1305 => {
+				Self::XChaCha20Poly1305(Box::new(StreamLE31::new(&key.into(), &nonce.into())))
+			}
+			Algorithm::Aes256Gcm => {
+				Self::Aes256Gcm(Box::new(StreamLE31::new(&key.into(), &nonce.into())))
+			}
+		};
+
+		Ok(s)
+	}
+
+	/// This decrypts the block at `index`. The AAD must be the same as the AAD that the stream was encrypted with.
+	///
+	/// The last block of a stream must be decrypted with `last_block` set, or it won't authenticate (and vice versa).
+	pub fn decrypt_block(
+		&self,
+		index: u32,
+		last_block: bool,
+		block: &[u8],
+		aad: &[u8],
+	) -> Result<Vec<u8>> {
+		let payload = Payload { aad, msg: block };
+
+		match self {
+			Self::XChaCha20Poly1305(s) => s.decrypt(index, last_block, payload),
+			Self::Aes256Gcm(s) => s.decrypt(index, last_block, payload),
+		}
+		.map_err(|_| Error::Decrypt)
+	}
+
+	/// This returns the length of the plaintext within an encrypted stream of `ciphertext_len` bytes (excluding any header).
+	///
+	/// `None` is returned if the length isn't possible for an encrypted stream.
+	#[must_use]
+	pub const fn plaintext_len(ciphertext_len: u64) -> Option<u64> {
+		let full_blocks = ciphertext_len / (BLOCK_LEN + AEAD_TAG_LEN) as u64;
+		let remainder = ciphertext_len % (BLOCK_LEN + AEAD_TAG_LEN) as u64;
+
+		if remainder < AEAD_TAG_LEN as u64 {
+			return None;
+		}
+
+		Some(full_blocks * BLOCK_LEN as u64 + remainder - AEAD_TAG_LEN as u64)
+	}
+}
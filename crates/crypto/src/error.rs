@@ -4,6 +4,8 @@ use std::string::FromUtf8Error;
 
 use thiserror::Error;
 
+use crate::header::file::HeaderSection;
+
 #[cfg(feature = "rspc")]
 impl From<Error> for rspc::Error {
 	fn from(err: Error) -> Self {
@@ -37,6 +39,20 @@ pub enum Error {
 	NoMetadata,
 	#[error("tried adding too many keyslots to a header")]
 	TooManyKeyslots,
+	#[error("the last remaining keyslot can't be removed from a header")]
+	LastKeyslot,
+	#[error("the erased data could not be verified")]
+	EraseVerification,
+	#[error("at least one pass is required to erase data")]
+	NoErasePasses,
+	#[error("the provided recovery shares are invalid")]
+	InvalidShares,
+	#[error("unable to parse the header's {section} (at byte offset {offset}): {source}")]
+	HeaderParse {
+		section: HeaderSection,
+		offset: u64,
+		source: Box<Error>,
+	},
 
 	// key manager
 	#[error("requested key wasn't found in the key manager")]
@@ -77,10 +93,13 @@ pub enum Error {
 	#[cfg(all(any(target_os = "macos", target_os = "ios"), feature = "os-keyrings"))]
 	#[error("error with the apple keyring: {0}")]
 	AppleKeyringError(#[from] security_framework::base::Error),
-	#[cfg(feature = "os-keyrings")]
+	#[cfg(feature = "keyring")]
 	#[error("generic keyring error")]
 	KeyringError,
-	#[cfg(feature = "os-keyrings")]
+	#[cfg(feature = "keyring")]
+	#[error("the keyring is read-only")]
+	KeyringReadOnly,
+	#[cfg(feature = "keyring")]
 	#[error("keyring not available on this platform")]
 	KeyringNotSupported,
 }
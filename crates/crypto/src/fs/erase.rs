@@ -1,6 +1,7 @@
-use crate::{primitives::BLOCK_LEN, Result};
+use crate::{primitives::BLOCK_LEN, Error, Result};
 
 use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 /// This is used for erasing a file.
@@ -14,29 +15,135 @@ use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 /// can guarantee a perfect erasure on solid-state drives.
 ///
 /// This also does not factor in temporary files, caching, thumbnails, etc.
+///
+/// `Error::NoErasePasses` is returned if `passes` is zero, as nothing would be overwritten.
 pub async fn erase<RW>(stream: &mut RW, size: usize, passes: usize) -> Result<()>
 where
 	RW: AsyncReadExt + AsyncWriteExt + AsyncSeekExt + Unpin + Send,
 {
-	let block_count = size / BLOCK_LEN;
-	let additional = size % BLOCK_LEN;
-
-	let mut buf = vec![0u8; BLOCK_LEN].into_boxed_slice();
-	let mut end_buf = vec![0u8; additional].into_boxed_slice();
+	if passes == 0 {
+		return Err(Error::NoErasePasses);
+	}
 
 	for _ in 0..passes {
-		stream.rewind().await?;
-		for _ in 0..block_count {
-			rand_chacha::ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
-			stream.write_all(&buf).await?;
+		overwrite(stream, size, &mut ChaCha20Rng::from_entropy()).await?;
+	}
+
+	stream.rewind().await?;
+
+	Ok(())
+}
+
+/// This is the same as `erase()`, but the final pass is read back and compared against what was written.
+///
+/// The final pass is generated from a seeded CSPRNG, so it can be regenerated for comparison without holding it in memory.
+///
+/// `Error::EraseVerification` is returned if the stream's contents don't match. This should be treated as a failed erasure.
+///
+/// Note: the read-back may be served from the OS's page cache, so this verifies what the OS accepted, not necessarily what reached the disk.
+/// It will catch short writes, files that were truncated mid-erase and streams that silently discard writes.
+pub async fn erase_and_verify<RW>(stream: &mut RW, size: usize, passes: usize) -> Result<()>
+where
+	RW: AsyncReadExt + AsyncWriteExt + AsyncSeekExt + Unpin + Send,
+{
+	if passes == 0 {
+		return Err(Error::NoErasePasses);
+	}
+
+	for _ in 1..passes {
+		overwrite(stream, size, &mut ChaCha20Rng::from_entropy()).await?;
+	}
+
+	let mut seed = [0u8; 32];
+	ChaCha20Rng::from_entropy().fill_bytes(&mut seed);
+
+	overwrite(stream, size, &mut ChaCha20Rng::from_seed(seed)).await?;
+
+	stream.rewind().await?;
+
+	let mut expected_rng = ChaCha20Rng::from_seed(seed);
+	let mut expected = vec![0u8; BLOCK_LEN].into_boxed_slice();
+	let mut actual = vec![0u8; BLOCK_LEN].into_boxed_slice();
+
+	let mut remaining = size;
+	while remaining > 0 {
+		let len = remaining.min(BLOCK_LEN);
+
+		expected_rng.fill_bytes(&mut expected[..len]);
+		stream
+			.read_exact(&mut actual[..len])
+			.await
+			.map_err(|_| Error::EraseVerification)?;
+
+		if expected[..len] != actual[..len] {
+			return Err(Error::EraseVerification);
 		}
 
-		rand_chacha::ChaCha20Rng::from_entropy().fill_bytes(&mut end_buf);
-		stream.write_all(&end_buf).await?;
-		stream.flush().await?;
+		remaining -= len;
 	}
 
 	stream.rewind().await?;
 
 	Ok(())
 }
+
+/// This overwrites the entire stream (up to `size`) once, with data from the provided RNG.
+async fn overwrite<RW>(stream: &mut RW, size: usize, rng: &mut ChaCha20Rng) -> Result<()>
+where
+	RW: AsyncWriteExt + AsyncSeekExt + Unpin + Send,
+{
+	let mut buf = vec![0u8; BLOCK_LEN].into_boxed_slice();
+
+	stream.rewind().await?;
+
+	let mut remaining = size;
+	while remaining > 0 {
+		let len = remaining.min(BLOCK_LEN);
+
+		rng.fill_bytes(&mut buf[..len]);
+		stream.write_all(&buf[..len]).await?;
+
+		remaining -= len;
+	}
+
+	stream.flush().await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn erase_and_verify_overwrites_stream() {
+		let original = vec![0xAAu8; BLOCK_LEN * 2 + 17];
+		let mut stream = Cursor::new(original.clone());
+
+		erase_and_verify(&mut stream, original.len(), 2)
+			.await
+			.unwrap();
+
+		let erased = stream.into_inner();
+		assert_eq!(erased.len(), original.len());
+		assert_ne!(erased, original);
+	}
+
+	#[tokio::test]
+	async fn zero_passes_are_rejected() {
+		let original = vec![0xAAu8; BLOCK_LEN];
+		let mut stream = Cursor::new(original.clone());
+
+		assert!(matches!(
+			erase(&mut stream, original.len(), 0).await,
+			Err(Error::NoErasePasses)
+		));
+		assert!(matches!(
+			erase_and_verify(&mut stream, original.len(), 0).await,
+			Err(Error::NoErasePasses)
+		));
+		assert_eq!(stream.into_inner(), original);
+	}
+}
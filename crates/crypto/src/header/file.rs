@@ -29,7 +29,10 @@
 //! // Write the header to the file
 //! header.write(&mut writer).unwrap();
 //! ```
-use std::io::{Cursor, SeekFrom};
+use std::{
+	fmt::Display,
+	io::{Cursor, SeekFrom},
+};
 
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
@@ -40,8 +43,8 @@ use crate::{
 
 use super::{
 	keyslot::{Keyslot, KEYSLOT_SIZE},
-	metadata::Metadata,
-	preview_media::PreviewMedia,
+	metadata::{Metadata, MetadataVersion},
+	preview_media::{PreviewMedia, PreviewMediaVersion},
 };
 
 /// These are used to quickly and easily identify Spacedrive-encrypted files
@@ -246,92 +249,141 @@ impl FileHeader {
 		}
 	}
 
+	/// This is used for adding a keyslot to an existing header (e.g. to allow a second password to decrypt the file).
+	///
+	/// As the serialized header always reserves space for two keyslots, the header may be re-written in-place afterwards.
+	pub fn add_keyslot(&mut self, keyslot: Keyslot) -> Result<()> {
+		if self.keyslots.len() >= 2 {
+			return Err(Error::TooManyKeyslots);
+		}
+
+		self.keyslots.push(keyslot);
+		Ok(())
+	}
+
+	/// This is used for removing a keyslot from an existing header.
+	///
+	/// The last remaining keyslot can't be removed, as the file would then be impossible to decrypt.
+	pub fn remove_keyslot(&mut self, index: usize) -> Result<Keyslot> {
+		if index >= self.keyslots.len() {
+			return Err(Error::NoKeyslots);
+		} else if self.keyslots.len() == 1 {
+			return Err(Error::LastKeyslot);
+		}
+
+		Ok(self.keyslots.remove(index))
+	}
+
 	/// This deserializes a header directly from a reader, and leaves the reader at the start of the encrypted data.
 	///
 	/// On error, the cursor will not be rewound.
 	///
+	/// If any part of the header is damaged, `Error::HeaderParse` will be returned with the section (and offset) that failed to parse.
+	///
 	/// It returns both the header, and the AAD that should be used for decryption.
 	pub async fn from_reader<R>(reader: &mut R) -> Result<(Self, Vec<u8>)>
 	where
 		R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
 	{
 		let mut magic_bytes = [0u8; MAGIC_BYTES.len()];
-		reader.read_exact(&mut magic_bytes).await?;
+		reader
+			.read_exact(&mut magic_bytes)
+			.await
+			.map_err(|e| section_error(HeaderSection::MagicBytes, 0)(e.into()))?;
 
 		if magic_bytes != MAGIC_BYTES {
-			return Err(Error::Serialization);
+			return Err(section_error(HeaderSection::MagicBytes, 0)(
+				Error::Serialization,
+			));
 		}
 
-		let mut version = [0u8; 2];
-
-		reader.read_exact(&mut version).await?;
-		let version = FileHeaderVersion::from_bytes(version)?;
-
-		// Rewind so we can get the AAD
-		reader.rewind().await?;
-
-		// read the aad according to the size
-		let mut aad = vec![0u8; Self::size(version)];
-		reader.read_exact(&mut aad).await?;
-
-		// seek back to the start (plus magic bytes and the two version bytes)
-		reader
-			.seek(SeekFrom::Start(MAGIC_BYTES.len() as u64 + 2))
-			.await?;
+		let version = async {
+			let mut version = [0u8; 2];
+			reader.read_exact(&mut version).await?;
+			FileHeaderVersion::from_bytes(version)
+		}
+		.await
+		.map_err(section_error(
+			HeaderSection::Version,
+			MAGIC_BYTES.len() as u64,
+		))?;
 
 		// read the header
 		let header = match version {
 			FileHeaderVersion::V1 => {
-				let mut algorithm = [0u8; 2];
-				reader.read_exact(&mut algorithm).await?;
-				let algorithm = Algorithm::from_bytes(algorithm)?;
+				let offset = reader.stream_position().await?;
+				let algorithm = async {
+					let mut algorithm = [0u8; 2];
+					reader.read_exact(&mut algorithm).await?;
+					Algorithm::from_bytes(algorithm)
+				}
+				.await
+				.map_err(section_error(HeaderSection::Algorithm, offset))?;
 
-				let mut nonce = vec![0u8; algorithm.nonce_len()];
-				reader.read_exact(&mut nonce).await?;
-				let nonce = Nonce::try_from(nonce)?;
+				let offset = reader.stream_position().await?;
+				let nonce = async {
+					let mut nonce = vec![0u8; algorithm.nonce_len()];
+					reader.read_exact(&mut nonce).await?;
+					let nonce = Nonce::try_from(nonce)?;
 
-				// read and discard the padding
-				reader.read_exact(&mut vec![0u8; 25 - nonce.len()]).await?;
+					// read and discard the padding
+					reader.read_exact(&mut vec![0u8; 25 - nonce.len()]).await?;
 
+					Ok::<_, Error>(nonce)
+				}
+				.await
+				.map_err(section_error(HeaderSection::Nonce, offset))?;
+
+				let offset = reader.stream_position().await?;
 				let mut keyslot_bytes = vec![0u8; KEYSLOT_SIZE * 2]; // length of 2x keyslots
 				let mut keyslots: Vec<Keyslot> = Vec::new();
 
-				reader.read_exact(&mut keyslot_bytes).await?;
-				let mut keyslot_reader = Cursor::new(keyslot_bytes);
-
-				for _ in 0..2 {
-					Keyslot::from_reader(&mut keyslot_reader)
-						.map(|k| keyslots.push(k))
-						.ok();
+				reader
+					.read_exact(&mut keyslot_bytes)
+					.await
+					.map_err(|e| section_error(HeaderSection::Keyslot(0), offset)(e.into()))?;
+
+				for (i, bytes) in keyslot_bytes.chunks_exact(KEYSLOT_SIZE).enumerate() {
+					// unused keyslots are zeroed
+					if bytes.iter().all(|b| *b == 0) {
+						continue;
+					}
+
+					keyslots.push(Keyslot::from_reader(&mut Cursor::new(bytes)).map_err(
+						section_error(
+							HeaderSection::Keyslot(i),
+							offset + (i * KEYSLOT_SIZE) as u64,
+						),
+					)?);
 				}
 
-				let metadata = if let Ok(metadata) = Metadata::from_reader(reader).await {
-					Ok::<Option<Metadata>, Error>(Some(metadata))
+				let offset = reader.stream_position().await?;
+				let metadata = if Self::peek_marker(reader)
+					.await?
+					.map_or(false, |m| MetadataVersion::from_bytes(m).is_ok())
+				{
+					Some(
+						Metadata::from_reader(reader)
+							.await
+							.map_err(section_error(HeaderSection::Metadata, offset))?,
+					)
+				} else {
+					None
+				};
+
+				let offset = reader.stream_position().await?;
+				let preview_media = if Self::peek_marker(reader)
+					.await?
+					.map_or(false, |m| PreviewMediaVersion::from_bytes(m).is_ok())
+				{
+					Some(
+						PreviewMedia::from_reader(reader)
+							.await
+							.map_err(section_error(HeaderSection::PreviewMedia, offset))?,
+					)
 				} else {
-					reader
-						.seek(SeekFrom::Start(
-							Self::size(version) as u64 + (KEYSLOT_SIZE * 2) as u64,
-						))
-						.await?;
-					Ok(None)
-				}?;
-
-				let preview_media =
-					if let Ok(preview_media) = PreviewMedia::from_reader(reader).await {
-						Ok::<Option<PreviewMedia>, Error>(Some(preview_media))
-					} else {
-						let seek_len = metadata.as_ref().map_or_else(
-							|| Self::size(version) as u64 + (KEYSLOT_SIZE * 2) as u64,
-							|metadata| {
-								Self::size(version) as u64
-									+ (KEYSLOT_SIZE * 2) as u64 + metadata.size() as u64
-							},
-						);
-
-						reader.seek(SeekFrom::Start(seek_len)).await?;
-
-						Ok(None)
-					}?;
+					None
+				};
 
 				Self {
 					version,
@@ -344,8 +396,68 @@ impl FileHeader {
 			}
 		};
 
+		// the AAD is made up of sections which have been read by now, so a truncated header has already been reported above
+		let end = reader.stream_position().await?;
+		reader.rewind().await?;
+
+		let mut aad = vec![0u8; Self::size(version)];
+		reader.read_exact(&mut aad).await?;
+
+		reader.seek(SeekFrom::Start(end)).await?;
+
 		Ok((header, aad))
 	}
+
+	/// This reads the next two bytes (which will be a version marker if a header item is present), and then seeks back.
+	async fn peek_marker<R>(reader: &mut R) -> Result<Option<[u8; 2]>>
+	where
+		R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	{
+		let offset = reader.stream_position().await?;
+
+		let mut marker = [0u8; 2];
+		let marker = reader.read_exact(&mut marker).await.ok().map(|_| marker);
+
+		reader.seek(SeekFrom::Start(offset)).await?;
+
+		Ok(marker)
+	}
+}
+
+/// This identifies a section of a serialized `FileHeader`.
+///
+/// It's used to report exactly which part of a damaged header failed to parse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderSection {
+	MagicBytes,
+	Version,
+	Algorithm,
+	Nonce,
+	Keyslot(usize),
+	Metadata,
+	PreviewMedia,
+}
+
+impl Display for HeaderSection {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match *self {
+			Self::MagicBytes => write!(f, "magic bytes"),
+			Self::Version => write!(f, "version"),
+			Self::Algorithm => write!(f, "algorithm"),
+			Self::Nonce => write!(f, "nonce"),
+			Self::Keyslot(i) => write!(f, "keyslot {}", i + 1),
+			Self::Metadata => write!(f, "metadata"),
+			Self::PreviewMedia => write!(f, "preview media"),
+		}
+	}
+}
+
+fn section_error(section: HeaderSection, offset: u64) -> impl FnOnce(Error) -> Error {
+	move |source| Error::HeaderParse {
+		section,
+		offset,
+		source: Box::new(source),
+	}
 }
 
 #[cfg(test)]
@@ -638,6 +750,79 @@ mod tests {
 		assert!(header.keyslots.len() == 2);
 	}
 
+	#[tokio::test]
+	async fn deserialize_header_with_damaged_keyslot() {
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		let mut bytes = header.to_bytes().unwrap();
+
+		// corrupt the first keyslot's version
+		bytes[36] = 0xFF;
+
+		let result = FileHeader::from_reader(&mut Cursor::new(bytes)).await;
+
+		assert!(matches!(
+			result,
+			Err(Error::HeaderParse {
+				section: HeaderSection::Keyslot(0),
+				offset: 36,
+				..
+			})
+		));
+	}
+
+	#[tokio::test]
+	async fn deserialize_truncated_header() {
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		let bytes = header.to_bytes().unwrap();
+
+		// cut off within the algorithm, and then within the nonce's padding
+		for (len, expected_section, expected_offset) in [
+			(10, HeaderSection::Algorithm, 9),
+			(30, HeaderSection::Nonce, 11),
+		] {
+			let result = FileHeader::from_reader(&mut Cursor::new(bytes[..len].to_vec())).await;
+
+			assert!(matches!(
+				result,
+				Err(Error::HeaderParse {
+					section,
+					offset,
+					..
+				}) if section == expected_section && offset == expected_offset
+			));
+		}
+	}
+
 	#[tokio::test]
 	async fn aad_validity() {
 		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
@@ -57,6 +57,12 @@ use uuid::Uuid;
 
 use super::keyring::{Identifier, KeyringInterface};
 
+#[cfg(feature = "serde")]
+use super::{
+	recovery::{RecoveryKit, SharingParams},
+	shamir::{self, Share},
+};
+
 /// This is a stored key, and can be freely written to the database.
 ///
 /// It contains no sensitive information that is not encrypted.
@@ -96,6 +102,34 @@ pub enum StoredKeyVersion {
 	V1,
 }
 
+/// This is a keystore backup, ready to be imported with `KeyManager::import_keystore_backup()`.
+pub enum KeystoreBackup {
+	/// A plain keystore dump. It requires the master password and secret key at the time of the backup.
+	Keystore {
+		master_password: Protected<String>,
+		secret_key: SecretKeyString,
+		stored_keys: Vec<StoredKey>,
+	},
+	/// A recovery kit, along with the secret to open it with.
+	#[cfg(feature = "serde")]
+	RecoveryKit {
+		kit: RecoveryKit,
+		secret: RecoverySecret,
+	},
+}
+
+/// This is used for opening a recovery kit.
+#[cfg(feature = "serde")]
+pub enum RecoverySecret {
+	Password(Protected<Vec<u8>>),
+	Shares(Vec<Share>),
+	/// The master password and secret key of the library, at the time the kit was exported.
+	MasterPassword {
+		master_password: Protected<String>,
+		secret_key: SecretKeyString,
+	},
+}
+
 /// This is a mounted key, and needs to be kept somewhat hidden.
 ///
 /// This contains the plaintext key, and the same key hashed with the content salt.
@@ -121,10 +155,19 @@ pub struct KeyManager {
 }
 impl KeyManager {
 	/// Initialize the Key Manager with `StoredKeys` retrieved from the database.
-	pub async fn new(stored_keys: Vec<StoredKey>) -> Result<Self> {
-		let keyring = KeyringInterface::new()
-			.map(|k| Arc::new(Mutex::new(k)))
-			.ok();
+	///
+	/// If no keyring is provided, the OS keyring will be used (if it's available).
+	///
+	/// The keyring may be shared between multiple key managers, as all items are identified by their library's UUID.
+	pub async fn new(
+		stored_keys: Vec<StoredKey>,
+		keyring: Option<Arc<Mutex<KeyringInterface>>>,
+	) -> Result<Self> {
+		let keyring = keyring.or_else(|| {
+			KeyringInterface::new()
+				.map(|k| Arc::new(Mutex::new(k)))
+				.ok()
+		});
 
 		let keymanager = Self {
 			root_key: Mutex::new(None),
@@ -245,7 +288,11 @@ impl KeyManager {
 	///
 	/// It will also generate a verification key, which should be written to the database.
 	#[allow(clippy::needless_pass_by_value)]
-	pub async fn onboarding(config: OnboardingConfig, library_uuid: Uuid) -> Result<StoredKey> {
+	pub async fn onboarding(
+		&self,
+		config: OnboardingConfig,
+		library_uuid: Uuid,
+	) -> Result<StoredKey> {
 		let content_salt = Salt::generate();
 		let secret_key = SecretKey::generate();
 
@@ -291,17 +338,15 @@ impl KeyManager {
 		)
 		.await?;
 
-		// attempt to insert into the OS keyring
-		// can ignore false here as we want to silently error
-		if let Ok(keyring) = KeyringInterface::new() {
-			let identifier = Identifier {
-				application: APP_IDENTIFIER,
-				library_uuid: &library_uuid.to_string(),
-				usage: SECRET_KEY_IDENTIFIER,
-			};
-
-			keyring.insert(identifier, secret_key.into()).ok();
-		}
+		// attempt to insert into the keyring
+		// can ignore the error here as we want to silently error
+		self.keyring_insert(
+			library_uuid,
+			SECRET_KEY_IDENTIFIER.to_string(),
+			secret_key.into(),
+		)
+		.await
+		.ok();
 
 		let verification_key = StoredKey {
 			uuid: Uuid::new_v4(),
@@ -450,71 +495,156 @@ impl KeyManager {
 		Ok(verification_key)
 	}
 
-	/// This re-encrypts master keys so they can be imported from a key backup into the current key manager.
+	/// This exports a recovery kit, which is protected by the provided password.
 	///
-	/// It returns a `Vec<StoredKey>` so they can be written to the database.
-	#[allow(clippy::needless_pass_by_value)]
-	pub async fn import_keystore_backup(
+	/// The kit may be imported into any library with `KeyManager::import_keystore_backup()`.
+	#[cfg(feature = "serde")]
+	pub async fn export_recovery_kit(
 		&self,
-		master_password: Protected<String>, // at the time of the backup
-		secret_key: SecretKeyString,        // at the time of the backup
-		stored_keys: &[StoredKey],          // from the backup
-	) -> Result<Vec<StoredKey>> {
+		password: Protected<Vec<u8>>,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+	) -> Result<RecoveryKit> {
 		self.ensure_unlocked().await?;
 
-		// this backup should contain a verification key, which will tell us the algorithm+hashing algorithm
-		let secret_key = secret_key.into();
+		RecoveryKit::new(
+			self.get_root_key().await?,
+			self.get_verification_key().await?,
+			&self.dump_keystore(),
+			password,
+			None,
+			algorithm,
+			hashing_algorithm,
+		)
+		.await
+	}
+
+	/// This exports a recovery kit, with a random recovery secret that's split into `shares` shares (any `threshold` of which can open the kit).
+	///
+	/// The shares should be distributed between trustees - they are not stored anywhere else.
+	#[cfg(feature = "serde")]
+	pub async fn export_shared_recovery_kit(
+		&self,
+		threshold: u8,
+		shares: u8,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+	) -> Result<(RecoveryKit, Vec<Share>)> {
+		self.ensure_unlocked().await?;
 
-		let mut old_verification_key = None;
+		let recovery_secret = Key::generate();
+		let split_shares = shamir::split(recovery_secret.expose(), threshold, shares)?;
 
-		let keys: Vec<StoredKey> = stored_keys
-			.iter()
-			.filter_map(|key| {
-				if key.key_type == StoredKeyType::Root {
-					old_verification_key = Some(key.clone());
-					None
-				} else {
-					Some(key.clone())
-				}
-			})
-			.collect();
+		let kit = RecoveryKit::new(
+			self.get_root_key().await?,
+			self.get_verification_key().await?,
+			&self.dump_keystore(),
+			Protected::new(recovery_secret.expose().to_vec()),
+			Some(SharingParams { threshold, shares }),
+			algorithm,
+			hashing_algorithm,
+		)
+		.await?;
 
-		let old_verification_key = old_verification_key.ok_or(Error::NoVerificationKey)?;
+		Ok((kit, split_shares))
+	}
 
-		let old_root_key = match old_verification_key.version {
+	/// This decrypts a library's root key, with the master password and secret key that its verification key was created with.
+	async fn decrypt_root_key(
+		verification_key: &StoredKey,
+		master_password: Protected<String>,
+		secret_key: SecretKeyString,
+	) -> Result<Key> {
+		match verification_key.version {
 			StoredKeyVersion::V1 => {
-				let hashed_password = old_verification_key.hashing_algorithm.hash(
+				let hashed_password = verification_key.hashing_algorithm.hash(
 					master_password.into(),
-					old_verification_key.content_salt,
-					Some(secret_key),
+					verification_key.content_salt,
+					Some(secret_key.into()),
 				)?;
 
 				// decrypt the root key's KEK
 				let master_key = Decryptor::decrypt_bytes(
 					Key::derive(
 						hashed_password,
-						old_verification_key.salt,
+						verification_key.salt,
 						MASTER_PASSWORD_CONTEXT,
 					),
-					old_verification_key.master_key_nonce,
-					old_verification_key.algorithm,
-					&old_verification_key.master_key,
+					verification_key.master_key_nonce,
+					verification_key.algorithm,
+					&verification_key.master_key,
 					&[],
 				)
 				.await?;
 
 				// get the root key from the backup
-				let old_root_key = Decryptor::decrypt_bytes(
+				let root_key = Decryptor::decrypt_bytes(
 					Key::try_from(master_key)?,
-					old_verification_key.key_nonce,
-					old_verification_key.algorithm,
-					&old_verification_key.key,
+					verification_key.key_nonce,
+					verification_key.algorithm,
+					&verification_key.key,
 					&[],
 				)
 				.await?;
 
-				Key::try_from(old_root_key)?
+				Key::try_from(root_key)
+			}
+		}
+	}
+
+	/// This re-encrypts master keys so they can be imported from a key backup into the current key manager.
+	///
+	/// The backup may either be a plain keystore dump (from `keys.backupKeystore`), or a recovery kit.
+	///
+	/// It returns a `Vec<StoredKey>` so they can be written to the database.
+	pub async fn import_keystore_backup(&self, backup: KeystoreBackup) -> Result<Vec<StoredKey>> {
+		self.ensure_unlocked().await?;
+
+		let (old_root_key, keys) = match backup {
+			KeystoreBackup::Keystore {
+				master_password,
+				secret_key,
+				stored_keys,
+			} => {
+				// this backup should contain a verification key, which will tell us the algorithm+hashing algorithm
+				let (old_verification_key, keys): (Vec<_>, Vec<_>) = stored_keys
+					.into_iter()
+					.partition(|key| key.key_type == StoredKeyType::Root);
+
+				let old_verification_key = old_verification_key
+					.into_iter()
+					.next()
+					.ok_or(Error::NoVerificationKey)?;
+
+				let old_root_key =
+					Self::decrypt_root_key(&old_verification_key, master_password, secret_key)
+						.await?;
+
+				(old_root_key, keys)
 			}
+			#[cfg(feature = "serde")]
+			KeystoreBackup::RecoveryKit { kit, secret } => match secret {
+				RecoverySecret::Password(password) => kit.open(password).await?,
+				RecoverySecret::Shares(shares) => {
+					if let Some(sharing) = kit.sharing {
+						if shares.len() < usize::from(sharing.threshold) {
+							return Err(Error::InvalidShares);
+						}
+					}
+
+					kit.open(shamir::combine(&shares)?).await?
+				}
+				RecoverySecret::MasterPassword {
+					master_password,
+					secret_key,
+				} => {
+					kit.open_with_root_key(
+						Self::decrypt_root_key(&kit.verification_key, master_password, secret_key)
+							.await?,
+					)
+					.await?
+				}
+			},
 		};
 
 		let mut reencrypted_keys = Vec::new();
@@ -0,0 +1,250 @@
+//! This is Spacedrive's encrypted-file keyring. It has no platform dependencies, so it's suitable for headless nodes.
+//!
+//! All items are held in a single file, which is encrypted with XChaCha20-Poly1305. The key is derived from a user-provided password with Argon2id.
+//!
+//! The file layout is `MAGIC || version (1 byte) || salt || nonce || ciphertext`, and the plaintext is a simple length-prefixed list of label/value pairs.
+//! The file is re-written (via a temporary file + rename) every time an item is inserted or deleted.
+
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use chacha20poly1305::{
+	aead::{Aead, KeyInit, Payload},
+	XChaCha20Poly1305, XNonce,
+};
+use rand::{RngCore, SeedableRng};
+
+use crate::{
+	keys::keyring::{Identifier, Keyring},
+	primitives::SALT_LEN,
+	types::{HashingAlgorithm, Key, Params, Salt, SecretKeyString},
+	Error, Protected, Result,
+};
+
+const MAGIC: [u8; 8] = *b"sdkeyrng";
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+pub struct FileKeyring {
+	path: PathBuf,
+	key: Key,
+	salt: Salt,
+	items: Mutex<BTreeMap<String, Protected<Vec<u8>>>>,
+}
+
+impl FileKeyring {
+	/// This opens the keyring file at the provided path, or creates a new (empty) keyring if the file doesn't exist yet.
+	///
+	/// An incorrect password will return `Error::IncorrectPassword`.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn open(path: impl AsRef<Path>, password: Protected<Vec<u8>>) -> Result<Self> {
+		let path = path.as_ref().to_path_buf();
+
+		if !path.try_exists()? {
+			let salt = Salt::generate();
+			let keyring = Self {
+				key: Self::derive_key(password, salt)?,
+				path,
+				salt,
+				items: Mutex::new(BTreeMap::new()),
+			};
+
+			keyring.write(&keyring.items.lock().unwrap_or_else(|e| e.into_inner()))?;
+
+			return Ok(keyring);
+		}
+
+		let bytes = fs::read(&path)?;
+
+		if bytes.len() < HEADER_LEN
+			|| bytes[..MAGIC.len()] != MAGIC
+			|| bytes[MAGIC.len()] != VERSION
+		{
+			return Err(Error::Serialization);
+		}
+
+		let salt_start = MAGIC.len() + 1;
+		let salt = Salt::try_from(bytes[salt_start..salt_start + SALT_LEN].to_vec())?;
+		let nonce = XNonce::clone_from_slice(&bytes[salt_start + SALT_LEN..HEADER_LEN]);
+
+		let key = Self::derive_key(password, salt)?;
+
+		let plaintext = Protected::new(
+			XChaCha20Poly1305::new(&key.clone().into())
+				.decrypt(
+					&nonce,
+					Payload {
+						msg: &bytes[HEADER_LEN..],
+						aad: &bytes[..HEADER_LEN],
+					},
+				)
+				.map_err(|_| Error::IncorrectPassword)?,
+		);
+
+		Ok(Self {
+			path,
+			key,
+			salt,
+			items: Mutex::new(Self::deserialize_items(plaintext.expose())?),
+		})
+	}
+
+	fn derive_key(password: Protected<Vec<u8>>, salt: Salt) -> Result<Key> {
+		HashingAlgorithm::Argon2id(Params::Standard).hash(password, salt, None)
+	}
+
+	fn serialize_items(items: &BTreeMap<String, Protected<Vec<u8>>>) -> Result<Protected<Vec<u8>>> {
+		fn len_bytes(len: usize) -> Result<[u8; 4]> {
+			u32::try_from(len)
+				.map(u32::to_le_bytes)
+				.map_err(|_| Error::Serialization)
+		}
+
+		let mut buffer = Vec::new();
+		buffer.extend_from_slice(&len_bytes(items.len())?);
+
+		for (label, value) in items {
+			buffer.extend_from_slice(&len_bytes(label.len())?);
+			buffer.extend_from_slice(label.as_bytes());
+			buffer.extend_from_slice(&len_bytes(value.expose().len())?);
+			buffer.extend_from_slice(value.expose());
+		}
+
+		Ok(Protected::new(buffer))
+	}
+
+	fn deserialize_items(bytes: &[u8]) -> Result<BTreeMap<String, Protected<Vec<u8>>>> {
+		fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+			if bytes.len() < len {
+				return Err(Error::Serialization);
+			}
+
+			let (taken, rest) = bytes.split_at(len);
+			*bytes = rest;
+			Ok(taken)
+		}
+
+		fn take_len(bytes: &mut &[u8]) -> Result<usize> {
+			let mut len = [0u8; 4];
+			len.copy_from_slice(take(bytes, 4)?);
+			Ok(u32::from_le_bytes(len) as usize)
+		}
+
+		let mut bytes = bytes;
+		let mut items = BTreeMap::new();
+
+		for _ in 0..take_len(&mut bytes)? {
+			let label_len = take_len(&mut bytes)?;
+			let label = String::from_utf8(take(&mut bytes, label_len)?.to_vec())?;
+			let value_len = take_len(&mut bytes)?;
+			let value = Protected::new(take(&mut bytes, value_len)?.to_vec());
+
+			items.insert(label, value);
+		}
+
+		Ok(items)
+	}
+
+	fn write(&self, items: &BTreeMap<String, Protected<Vec<u8>>>) -> Result<()> {
+		let mut nonce = [0u8; NONCE_LEN];
+		rand_chacha::ChaCha20Rng::from_entropy().fill_bytes(&mut nonce);
+
+		let mut header = Vec::with_capacity(HEADER_LEN);
+		header.extend_from_slice(&MAGIC);
+		header.push(VERSION);
+		header.extend_from_slice(&self.salt);
+		header.extend_from_slice(&nonce);
+
+		let plaintext = Self::serialize_items(items)?;
+
+		let ciphertext = XChaCha20Poly1305::new(&self.key.clone().into())
+			.encrypt(
+				XNonce::from_slice(&nonce),
+				Payload {
+					msg: plaintext.expose(),
+					aad: &header,
+				},
+			)
+			.map_err(|_| Error::Encrypt)?;
+
+		let mut bytes = header;
+		bytes.extend_from_slice(&ciphertext);
+
+		// write to a temporary file first, so a crash mid-write can't corrupt the keyring
+		let tmp_path = self.path.with_extension("tmp");
+		fs::write(&tmp_path, bytes)?;
+		fs::rename(tmp_path, &self.path)?;
+
+		Ok(())
+	}
+}
+
+impl Keyring for FileKeyring {
+	fn insert(&self, identifier: Identifier, value: SecretKeyString) -> Result<()> {
+		let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+		items.insert(
+			identifier.to_label(),
+			Protected::new(value.expose().as_bytes().to_vec()),
+		);
+
+		self.write(&items)
+	}
+
+	fn retrieve(&self, identifier: Identifier) -> Result<Protected<Vec<u8>>> {
+		self.items
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.get(&identifier.to_label())
+			.cloned()
+			.ok_or(Error::KeyringError)
+	}
+
+	fn delete(&self, identifier: Identifier) -> Result<()> {
+		let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+		items
+			.remove(&identifier.to_label())
+			.ok_or(Error::KeyringError)?;
+
+		self.write(&items)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const IDENTIFIER: Identifier = Identifier {
+		application: "Spacedrive",
+		library_uuid: "00000000-0000-0000-0000-000000000000",
+		usage: "Secret key",
+	};
+
+	#[test]
+	fn file_keyring_roundtrip() {
+		let path = std::env::temp_dir().join(format!("{}.sdkeyring", uuid::Uuid::new_v4()));
+
+		let keyring = FileKeyring::open(&path, Protected::new(b"password".to_vec())).unwrap();
+		keyring
+			.insert(IDENTIFIER, SecretKeyString::new("secret".to_string()))
+			.unwrap();
+		drop(keyring);
+
+		let keyring = FileKeyring::open(&path, Protected::new(b"password".to_vec())).unwrap();
+		assert_eq!(
+			keyring.retrieve(IDENTIFIER).unwrap().expose(),
+			&b"secret".to_vec()
+		);
+
+		assert!(matches!(
+			FileKeyring::open(&path, Protected::new(b"wrong".to_vec())),
+			Err(Error::IncorrectPassword)
+		));
+
+		fs::remove_file(path).unwrap();
+	}
+}
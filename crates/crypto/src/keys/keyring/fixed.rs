@@ -0,0 +1,64 @@
+//! This is Spacedrive's static keyring. It holds a secret key that was provided when the node started (such as via an environment variable or stdin).
+//!
+//! Nothing is ever persisted. Items inserted at runtime (e.g. after the user provides the secret key manually) are only held in memory.
+//!
+//! The provided secret key is returned for every library, so this is best suited to nodes that only host a single library.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+	keys::keyring::{Identifier, Keyring},
+	primitives::SECRET_KEY_IDENTIFIER,
+	types::SecretKeyString,
+	Error, Protected, Result,
+};
+
+pub struct StaticKeyring {
+	secret_key: Option<Protected<Vec<u8>>>,
+	items: Mutex<HashMap<String, Protected<Vec<u8>>>>,
+}
+
+impl StaticKeyring {
+	#[must_use]
+	pub fn new(secret_key: Option<SecretKeyString>) -> Self {
+		Self {
+			secret_key: secret_key.map(|k| Protected::new(k.expose().as_bytes().to_vec())),
+			items: Mutex::new(HashMap::new()),
+		}
+	}
+}
+
+impl Keyring for StaticKeyring {
+	fn insert(&self, identifier: Identifier, value: SecretKeyString) -> Result<()> {
+		self.items.lock().unwrap_or_else(|e| e.into_inner()).insert(
+			identifier.to_label(),
+			Protected::new(value.expose().as_bytes().to_vec()),
+		);
+
+		Ok(())
+	}
+
+	fn retrieve(&self, identifier: Identifier) -> Result<Protected<Vec<u8>>> {
+		if let Some(value) = self
+			.items
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.get(&identifier.to_label())
+		{
+			return Ok(value.clone());
+		}
+
+		match &self.secret_key {
+			Some(secret_key) if identifier.usage == SECRET_KEY_IDENTIFIER => Ok(secret_key.clone()),
+			_ => Err(Error::KeyringError),
+		}
+	}
+
+	fn delete(&self, identifier: Identifier) -> Result<()> {
+		self.items
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.remove(&identifier.to_label())
+			.map_or(Err(Error::KeyringReadOnly), |_| Ok(()))
+	}
+}
@@ -1,11 +1,23 @@
+//! This module contains Spacedrive's keyring backends.
+//!
+//! Every backend implements the `Keyring` trait, and `KeyringInterface` is a thin wrapper around whichever one is in use.
+//!
+//! The OS keyrings are preferred, but headless nodes (e.g. a NAS running `apps/server`) often have neither a keychain nor a Secret Service provider.
+//! For those, the encrypted-file keyring or a static keyring (with the secret key provided via the environment or stdin) may be used instead.
+
 use crate::{types::SecretKeyString, Protected, Result};
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "os-keyrings"))]
 pub mod linux;
 
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(all(any(target_os = "macos", target_os = "ios"), feature = "os-keyrings"))]
 pub mod apple;
 
+pub mod file;
+pub mod fixed;
+
+pub use self::{file::FileKeyring, fixed::StaticKeyring};
+
 /// This identifier is platform-agnostic and is used for identifying keys within OS keyrings
 #[derive(Clone, Copy)]
 pub struct Identifier<'a> {
@@ -38,34 +50,56 @@ impl<'a> Identifier<'a> {
 	pub fn to_apple_account(self) -> String {
 		format!("{} - {}", self.library_uuid, self.usage)
 	}
+
+	/// This is used as the lookup key for keyrings that aren't provided by the OS.
+	#[must_use]
+	pub fn to_label(self) -> String {
+		format!("{}/{}/{}", self.application, self.library_uuid, self.usage)
+	}
 }
 
+/// This is the trait that all keyring backends must implement.
+///
+/// Implement it (and pass it to `KeyringInterface::with_backend()`) to provide your own backend.
 pub trait Keyring {
 	fn insert(&self, identifier: Identifier, value: SecretKeyString) -> Result<()>;
 	fn retrieve(&self, identifier: Identifier) -> Result<Protected<Vec<u8>>>;
 	fn delete(&self, identifier: Identifier) -> Result<()>;
 }
 
-/// This should be used to interact with all OS keyrings.
+/// This should be used to interact with all keyrings.
 pub struct KeyringInterface {
 	keyring: Box<dyn Keyring + Send>,
 }
 
 impl KeyringInterface {
+	/// This initialises the interface with the OS keyring for the current platform.
 	pub fn new() -> Result<Self> {
-		#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios")))]
+		#[cfg(not(all(
+			feature = "os-keyrings",
+			any(target_os = "linux", target_os = "macos", target_os = "ios")
+		)))]
 		return Err(crate::Error::KeyringNotSupported);
 
-		#[cfg(target_os = "linux")]
+		#[cfg(all(feature = "os-keyrings", target_os = "linux"))]
 		let keyring = Box::new(self::linux::LinuxKeyring::new()?);
 
-		#[cfg(any(target_os = "macos", target_os = "ios"))]
+		#[cfg(all(feature = "os-keyrings", any(target_os = "macos", target_os = "ios")))]
 		let keyring = Box::new(self::apple::AppleKeyring {});
 
-		#[cfg(any(target_os = "linux", target_os = "macos", target_os = "ios"))]
+		#[cfg(all(
+			feature = "os-keyrings",
+			any(target_os = "linux", target_os = "macos", target_os = "ios")
+		))]
 		Ok(Self { keyring })
 	}
 
+	/// This initialises the interface with a custom backend, such as the `FileKeyring` or the `StaticKeyring`.
+	#[must_use]
+	pub fn with_backend(keyring: Box<dyn Keyring + Send>) -> Self {
+		Self { keyring }
+	}
+
 	pub fn insert(&self, identifier: Identifier, value: SecretKeyString) -> Result<()> {
 		self.keyring.insert(identifier, value)
 	}
@@ -2,8 +2,13 @@
 
 pub mod hashing;
 
-#[cfg(all(feature = "keymanager", feature = "os-keyrings"))]
+#[cfg(feature = "keymanager")]
 pub mod keymanager;
 
-#[cfg(feature = "os-keyrings")]
+#[cfg(feature = "keyring")]
 pub mod keyring;
+
+#[cfg(all(feature = "keymanager", feature = "serde"))]
+pub mod recovery;
+
+pub mod shamir;
@@ -0,0 +1,276 @@
+//! This module contains Spacedrive's recovery kits.
+//!
+//! A recovery kit is a portable, versioned backup of a library's keystore. It's self-contained, so it may be imported into any library, on any node.
+//!
+//! The keystore (along with the root key that its keys are encrypted with) is encrypted with a random kit key. That kit key is then wrapped twice:
+//! once with a key derived from the recovery secret, and once with the library's root key.
+//! The library's verification key is included too, so the master password and secret key (at the time of export) can also open the kit.
+//!
+//! The recovery secret is either a password, or a random secret that's split between trustees with Shamir's secret sharing (see `keys::shamir`).
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+	crypto::{Decryptor, Encryptor},
+	keys::keymanager::{StoredKey, StoredKeyType},
+	primitives::{KEY_LEN, RECOVERY_KIT_CONTEXT},
+	types::{Algorithm, EncryptedKey, HashingAlgorithm, Key, Nonce, Salt},
+	Error, Protected, Result,
+};
+
+/// This denotes the `RecoveryKit` version.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecoveryKitVersion {
+	V1,
+}
+
+/// These are the Shamir's secret sharing parameters that a kit's recovery secret was split with.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+pub struct SharingParams {
+	pub threshold: u8,
+	pub shares: u8,
+}
+
+/// This is a recovery kit. It can be freely written to a file, as everything sensitive within it is encrypted.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecoveryKit {
+	pub version: RecoveryKitVersion,
+	pub id: Uuid,
+	pub algorithm: Algorithm,
+	pub hashing_algorithm: HashingAlgorithm, // used for hashing the recovery secret
+	pub content_salt: Salt,                  // salt for hashing the recovery secret
+	pub sharing: Option<SharingParams>,
+	pub recovery_salt: Salt,
+	pub recovery_nonce: Nonce,
+	pub recovery_kit_key: EncryptedKey, // the kit key, encrypted with the hashed recovery secret
+	pub root_salt: Salt,
+	pub root_nonce: Nonce,
+	pub root_kit_key: EncryptedKey, // the kit key, encrypted with the library's root key
+	pub verification_key: StoredKey,
+	pub keystore_nonce: Nonce,
+	pub keystore: Vec<u8>, // encrypted with the kit key. this is `root key || JSON(stored keys)`
+}
+
+impl RecoveryKit {
+	/// This creates a new recovery kit, containing the provided stored keys.
+	///
+	/// Memory-only keys are excluded, as are any root keys.
+	#[allow(clippy::needless_pass_by_value)]
+	pub async fn new(
+		root_key: Key,
+		verification_key: StoredKey,
+		stored_keys: &[StoredKey],
+		recovery_secret: Protected<Vec<u8>>,
+		sharing: Option<SharingParams>,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+	) -> Result<Self> {
+		let id = Uuid::new_v4();
+		let kit_key = Key::generate();
+
+		let content_salt = Salt::generate();
+		let hashed_secret = hashing_algorithm.hash(recovery_secret, content_salt, None)?;
+
+		let recovery_salt = Salt::generate();
+		let recovery_nonce = Nonce::generate(algorithm)?;
+		let recovery_kit_key = EncryptedKey::try_from(
+			Encryptor::encrypt_bytes(
+				Key::derive(hashed_secret, recovery_salt, RECOVERY_KIT_CONTEXT),
+				recovery_nonce,
+				algorithm,
+				kit_key.expose(),
+				id.as_bytes(),
+			)
+			.await?,
+		)?;
+
+		let root_salt = Salt::generate();
+		let root_nonce = Nonce::generate(algorithm)?;
+		let root_kit_key = EncryptedKey::try_from(
+			Encryptor::encrypt_bytes(
+				Key::derive(root_key.clone(), root_salt, RECOVERY_KIT_CONTEXT),
+				root_nonce,
+				algorithm,
+				kit_key.expose(),
+				id.as_bytes(),
+			)
+			.await?,
+		)?;
+
+		let stored_keys = stored_keys
+			.iter()
+			.filter(|k| !k.memory_only && k.key_type == StoredKeyType::User)
+			.cloned()
+			.collect::<Vec<_>>();
+
+		let mut payload = root_key.expose().to_vec();
+		payload.extend_from_slice(
+			&serde_json::to_vec(&stored_keys).map_err(|_| Error::Serialization)?,
+		);
+		let payload = Protected::new(payload);
+
+		let keystore_nonce = Nonce::generate(algorithm)?;
+		let keystore = Encryptor::encrypt_bytes(
+			kit_key,
+			keystore_nonce,
+			algorithm,
+			payload.expose(),
+			id.as_bytes(),
+		)
+		.await?;
+
+		Ok(Self {
+			version: RecoveryKitVersion::V1,
+			id,
+			algorithm,
+			hashing_algorithm,
+			content_salt,
+			sharing,
+			recovery_salt,
+			recovery_nonce,
+			recovery_kit_key,
+			root_salt,
+			root_nonce,
+			root_kit_key,
+			verification_key,
+			keystore_nonce,
+			keystore,
+		})
+	}
+
+	/// This opens the kit with its recovery secret (either the password, or the secret that was reconstructed from the shares).
+	///
+	/// It returns the root key that the keys were encrypted with, along with the keys themselves.
+	pub async fn open(&self, recovery_secret: Protected<Vec<u8>>) -> Result<(Key, Vec<StoredKey>)> {
+		match self.version {
+			RecoveryKitVersion::V1 => {
+				let hashed_secret =
+					self.hashing_algorithm
+						.hash(recovery_secret, self.content_salt, None)?;
+
+				let kit_key = Decryptor::decrypt_bytes(
+					Key::derive(hashed_secret, self.recovery_salt, RECOVERY_KIT_CONTEXT),
+					self.recovery_nonce,
+					self.algorithm,
+					&self.recovery_kit_key,
+					self.id.as_bytes(),
+				)
+				.await
+				.map_or(Err(Error::IncorrectPassword), Key::try_from)?;
+
+				self.open_keystore(kit_key).await
+			}
+		}
+	}
+
+	/// This opens the kit with the root key of the library that it was exported from.
+	///
+	/// The root key can be obtained from the kit's verification key, with the master password and secret key at the time of export.
+	pub async fn open_with_root_key(&self, root_key: Key) -> Result<(Key, Vec<StoredKey>)> {
+		match self.version {
+			RecoveryKitVersion::V1 => {
+				let kit_key = Decryptor::decrypt_bytes(
+					Key::derive(root_key, self.root_salt, RECOVERY_KIT_CONTEXT),
+					self.root_nonce,
+					self.algorithm,
+					&self.root_kit_key,
+					self.id.as_bytes(),
+				)
+				.await
+				.map_or(Err(Error::IncorrectPassword), Key::try_from)?;
+
+				self.open_keystore(kit_key).await
+			}
+		}
+	}
+
+	async fn open_keystore(&self, kit_key: Key) -> Result<(Key, Vec<StoredKey>)> {
+		let payload = Decryptor::decrypt_bytes(
+			kit_key,
+			self.keystore_nonce,
+			self.algorithm,
+			&self.keystore,
+			self.id.as_bytes(),
+		)
+		.await?;
+
+		if payload.expose().len() < KEY_LEN {
+			return Err(Error::Serialization);
+		}
+
+		let (root_key, stored_keys) = payload.expose().split_at(KEY_LEN);
+
+		Ok((
+			Key::try_from(Protected::new(root_key.to_vec()))?,
+			serde_json::from_slice(stored_keys).map_err(|_| Error::Serialization)?,
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		keys::keymanager::StoredKeyVersion, keys::shamir, primitives::ENCRYPTED_KEY_LEN,
+		types::Params,
+	};
+
+	fn stored_key(key_type: StoredKeyType) -> StoredKey {
+		StoredKey {
+			uuid: Uuid::new_v4(),
+			version: StoredKeyVersion::V1,
+			key_type,
+			algorithm: Algorithm::XChaCha20Poly1305,
+			hashing_algorithm: HashingAlgorithm::Argon2id(Params::Standard),
+			content_salt: Salt::generate(),
+			master_key: EncryptedKey([0u8; ENCRYPTED_KEY_LEN]),
+			master_key_nonce: Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap(),
+			key_nonce: Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap(),
+			key: vec![1, 2, 3],
+			salt: Salt::generate(),
+			memory_only: false,
+			automount: false,
+		}
+	}
+
+	#[tokio::test]
+	async fn recovery_kit_with_shares() {
+		let root_key = Key::generate();
+		let user_key = stored_key(StoredKeyType::User);
+
+		let secret = Key::generate().expose().to_vec();
+		let shares = shamir::split(&secret, 2, 3).unwrap();
+
+		let kit = RecoveryKit::new(
+			root_key.clone(),
+			stored_key(StoredKeyType::Root),
+			&[user_key.clone()],
+			Protected::new(secret),
+			Some(SharingParams {
+				threshold: 2,
+				shares: 3,
+			}),
+			Algorithm::XChaCha20Poly1305,
+			HashingAlgorithm::Argon2id(Params::Standard),
+		)
+		.await
+		.unwrap();
+
+		let kit: RecoveryKit = serde_json::from_slice(&serde_json::to_vec(&kit).unwrap()).unwrap();
+
+		let (recovered_root_key, keys) = kit
+			.open(shamir::combine(&shares[1..]).unwrap())
+			.await
+			.unwrap();
+		assert_eq!(recovered_root_key.expose(), root_key.expose());
+		assert!(keys == vec![user_key]);
+
+		assert!(kit.open_with_root_key(root_key).await.is_ok());
+		assert!(matches!(
+			kit.open(Protected::new(b"wrong".to_vec())).await,
+			Err(Error::IncorrectPassword)
+		));
+	}
+}
@@ -0,0 +1,204 @@
+//! This module contains a small implementation of Shamir's secret sharing, over GF(2^8).
+//!
+//! It is used for splitting recovery kit secrets between multiple trustees. Any `threshold` of the shares can reconstruct the secret, and fewer reveal nothing about it.
+//!
+//! Every byte of the secret is shared independently, so each share is exactly as long as the secret (plus its index).
+
+use std::{fmt::Display, str::FromStr};
+
+use rand::{RngCore, SeedableRng};
+use zeroize::Zeroize;
+
+use crate::{Error, Protected, Result};
+
+/// This is a single share of a secret.
+///
+/// The index is the share's x-coordinate, and is never zero (as that's where the secret lives).
+///
+/// Shares may be converted to/from a string (e.g. for printing or showing to a trustee) in the form `index-data`, both hex-encoded.
+#[derive(Clone)]
+pub struct Share {
+	pub index: u8,
+	pub data: Protected<Vec<u8>>,
+}
+
+impl Display for Share {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:02x}-{}", self.index, hex::encode(self.data.expose()))
+	}
+}
+
+impl FromStr for Share {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		let (index, data) = s.trim().split_once('-').ok_or(Error::InvalidShares)?;
+
+		let index = u8::from_str_radix(index, 16).map_err(|_| Error::InvalidShares)?;
+		let data = hex::decode(data.replace(['-', ' '], "")).map_err(|_| Error::InvalidShares)?;
+
+		if index == 0 || data.is_empty() {
+			return Err(Error::InvalidShares);
+		}
+
+		Ok(Self {
+			index,
+			data: Protected::new(data),
+		})
+	}
+}
+
+/// This splits a secret into `count` shares, any `threshold` of which can be used to reconstruct it.
+pub fn split(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>> {
+	if secret.is_empty() || threshold == 0 || count < threshold {
+		return Err(Error::InvalidShares);
+	}
+
+	let mut rng = rand_chacha::ChaCha20Rng::from_entropy();
+	let mut coefficients = vec![0u8; usize::from(threshold - 1)];
+
+	let mut shares: Vec<(u8, Vec<u8>)> = (1..=count)
+		.map(|index| (index, Vec::with_capacity(secret.len())))
+		.collect();
+
+	for &byte in secret {
+		// a new random polynomial for every byte, with the byte itself as the constant term
+		rng.fill_bytes(&mut coefficients);
+
+		for (index, data) in &mut shares {
+			data.push(evaluate(byte, &coefficients, *index));
+		}
+	}
+
+	coefficients.zeroize();
+
+	Ok(shares
+		.into_iter()
+		.map(|(index, data)| Share {
+			index,
+			data: Protected::new(data),
+		})
+		.collect())
+}
+
+/// This reconstructs a secret from the provided shares.
+///
+/// There's no way to tell if too few shares were provided - the result will just be incorrect.
+/// The caller should verify the secret (e.g. by decrypting something with it).
+pub fn combine(shares: &[Share]) -> Result<Protected<Vec<u8>>> {
+	let len = shares
+		.first()
+		.ok_or(Error::InvalidShares)?
+		.data
+		.expose()
+		.len();
+
+	for (i, share) in shares.iter().enumerate() {
+		if share.index == 0
+			|| share.data.expose().len() != len
+			|| shares[..i].iter().any(|s| s.index == share.index)
+		{
+			return Err(Error::InvalidShares);
+		}
+	}
+
+	let secret = (0..len)
+		.map(|position| {
+			// lagrange interpolation at x = 0 (subtraction is xor in GF(2^8))
+			shares.iter().fold(0u8, |secret, share| {
+				let basis = shares
+					.iter()
+					.filter(|other| other.index != share.index)
+					.fold(1u8, |basis, other| {
+						mul(basis, mul(other.index, inverse(other.index ^ share.index)))
+					});
+
+				secret ^ mul(share.data.expose()[position], basis)
+			})
+		})
+		.collect();
+
+	Ok(Protected::new(secret))
+}
+
+/// This evaluates the polynomial (with `constant` as the constant term) at `x`, with Horner's method.
+fn evaluate(constant: u8, coefficients: &[u8], x: u8) -> u8 {
+	let result = coefficients
+		.iter()
+		.rev()
+		.fold(0u8, |result, &coefficient| mul(result, x) ^ coefficient);
+
+	mul(result, x) ^ constant
+}
+
+/// This multiplies two elements of GF(2^8), with the AES polynomial. It doesn't branch on either input.
+const fn mul(mut a: u8, mut b: u8) -> u8 {
+	let mut product = 0u8;
+	let mut i = 0;
+
+	while i < 8 {
+		product ^= a & 0u8.wrapping_sub(b & 1);
+		let carry = 0u8.wrapping_sub(a >> 7);
+		a = (a << 1) ^ (0x1b & carry);
+		b >>= 1;
+		i += 1;
+	}
+
+	product
+}
+
+/// This finds the multiplicative inverse of an element of GF(2^8), as `a^254`.
+const fn inverse(a: u8) -> u8 {
+	let mut result = a;
+	let mut i = 0;
+
+	while i < 6 {
+		result = mul(mul(result, result), a);
+		i += 1;
+	}
+
+	mul(result, result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SECRET: &[u8] = b"a very secret recovery secret";
+
+	#[test]
+	fn split_and_combine() {
+		let shares = split(SECRET, 3, 5).unwrap();
+
+		let subset = [shares[0].clone(), shares[2].clone(), shares[4].clone()];
+		assert_eq!(combine(&subset).unwrap().expose(), SECRET);
+
+		assert_eq!(combine(&shares).unwrap().expose(), SECRET);
+	}
+
+	#[test]
+	fn combine_below_threshold() {
+		let shares = split(SECRET, 3, 5).unwrap();
+
+		assert_ne!(combine(&shares[..2]).unwrap().expose(), SECRET);
+	}
+
+	#[test]
+	fn share_string_roundtrip() {
+		let shares = split(SECRET, 2, 2).unwrap();
+
+		let parsed: Vec<Share> = shares
+			.iter()
+			.map(|s| s.to_string().parse().unwrap())
+			.collect();
+
+		assert_eq!(combine(&parsed).unwrap().expose(), SECRET);
+	}
+
+	#[test]
+	fn inverse_is_correct() {
+		for a in 1..=255u8 {
+			assert_eq!(mul(a, inverse(a)), 1);
+		}
+	}
+}
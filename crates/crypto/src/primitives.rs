@@ -64,6 +64,9 @@ pub const ROOT_KEY_CONTEXT: &str = "spacedrive 2022-12-14 12:53:54 root key deri
 pub const MASTER_PASSWORD_CONTEXT: &str =
 	"spacedrive 2022-12-14 15:35:41 master password hash derivation";
 
+/// Defines the context string for BLAKE3-KDF in regards to recovery kit key derivation
+pub const RECOVERY_KIT_CONTEXT: &str = "spacedrive 2023-03-02 16:41:27 recovery kit key derivation";
+
 /// Defines the context string for BLAKE3-KDF in regards to file key derivation (for file encryption)
 pub const FILE_KEY_CONTEXT: &str = "spacedrive 2022-12-14 12:54:12 file key derivation";
 
@@ -107,6 +107,12 @@ pub enum FfmpegError {
 	FilterGraphAllocation,
 	#[error("Codec Open Error")]
 	CodecOpen,
+	#[error("Codec allocation error")]
+	CodecAllocation,
+	#[error("Packet allocation error")]
+	PacketAllocation,
+	#[error("Output stream allocation error")]
+	StreamAllocation,
 }
 
 impl From<c_int> for FfmpegError {
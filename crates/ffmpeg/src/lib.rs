@@ -4,17 +4,21 @@ use crate::{
 	video_frame::VideoFrame,
 };
 
-use std::path::Path;
+use std::{path::Path, time::Duration};
+use tokio::{fs, task::spawn_blocking};
 
 mod error;
 mod film_strip;
 mod movie_decoder;
+mod preview;
 mod thumbnailer;
+mod transcode;
 mod utils;
 mod video_frame;
 
 pub use error::ThumbnailerError;
 pub use thumbnailer::{Thumbnailer, ThumbnailerBuilder};
+pub use transcode::{hls_playlist, hls_segment_count, HLS_SEGMENT_DURATION};
 
 /// Helper function to generate a thumbnail file from a video file with reasonable defaults
 pub async fn to_thumbnail(
@@ -46,6 +50,78 @@ pub async fn to_webp_bytes(
 		.await
 }
 
+/// Helper function to generate a film strip sprite from a video file, with `frame_count` frames placed side by side
+///
+/// The frames are spread evenly over the video, and their longest edge is `frame_size`
+pub async fn to_film_strip(
+	video_file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+	frame_count: u32,
+	frame_size: u32,
+	quality: f32,
+) -> Result<(), ThumbnailerError> {
+	let frames = preview::decode_frames(
+		video_file_path.as_ref().to_path_buf(),
+		frame_count,
+		frame_size,
+	)
+	.await?;
+
+	let webp = spawn_blocking(move || preview::encode_film_strip(&frames, quality)).await?;
+
+	fs::write(output_path, webp).await.map_err(Into::into)
+}
+
+/// Helper function to generate a looping animated webp from a video file, with `frame_count` frames
+/// spread evenly over the video which are each shown for `frame_duration`
+pub async fn to_animated_preview(
+	video_file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+	frame_count: u32,
+	frame_size: u32,
+	frame_duration: Duration,
+	quality: f32,
+) -> Result<(), ThumbnailerError> {
+	let frames = preview::decode_frames(
+		video_file_path.as_ref().to_path_buf(),
+		frame_count,
+		frame_size,
+	)
+	.await?;
+
+	let webp =
+		spawn_blocking(move || preview::encode_animated_webp(&frames, frame_duration, quality))
+			.await?;
+
+	fs::write(output_path, webp).await.map_err(Into::into)
+}
+
+/// Helper function to get the duration of a video or audio file
+pub async fn media_duration(
+	media_file_path: impl AsRef<Path>,
+) -> Result<Duration, ThumbnailerError> {
+	let media_file_path = media_file_path.as_ref().to_path_buf();
+
+	spawn_blocking(move || transcode::media_duration(&media_file_path)).await?
+}
+
+/// Helper function to write the part of a video or audio file from `start` which lasts `duration` as an HLS segment,
+/// which browsers can play as it's H.264 video and AAC audio in MPEG-TS
+pub async fn to_hls_segment(
+	media_file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+	start: Duration,
+	duration: Duration,
+) -> Result<(), ThumbnailerError> {
+	let media_file_path = media_file_path.as_ref().to_path_buf();
+	let output_path = output_path.as_ref().to_path_buf();
+
+	spawn_blocking(move || {
+		transcode::transcode_segment(&media_file_path, &output_path, start, duration)
+	})
+	.await?
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
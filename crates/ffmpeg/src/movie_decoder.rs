@@ -730,7 +730,7 @@ impl Drop for MovieDecoder {
 	}
 }
 
-fn check_error(return_code: i32, error_message: &str) -> Result<(), ThumbnailerError> {
+pub(crate) fn check_error(return_code: i32, error_message: &str) -> Result<(), ThumbnailerError> {
 	if return_code < 0 {
 		Err(ThumbnailerError::FfmpegWithReason(
 			FfmpegError::from(return_code),
@@ -741,7 +741,7 @@ fn check_error(return_code: i32, error_message: &str) -> Result<(), ThumbnailerE
 	}
 }
 
-fn setup_filter(
+pub(crate) fn setup_filter(
 	filter_ctx: *mut *mut AVFilterContext,
 	filter_name: &str,
 	filter_setup_name: &str,
@@ -768,7 +768,7 @@ fn setup_filter(
 	)
 }
 
-fn setup_filter_without_args(
+pub(crate) fn setup_filter_without_args(
 	filter_ctx: *mut *mut AVFilterContext,
 	filter_name: &str,
 	filter_setup_name: &str,
@@ -0,0 +1,247 @@
+//! Previews made of many frames of a video, which are a film strip sprite and an animated WebP.
+
+use crate::{MovieDecoder, ThumbnailSize, ThumbnailerError, VideoFrame};
+
+use std::{ops::Deref, path::PathBuf, time::Duration};
+
+use tokio::task::spawn_blocking;
+use webp::Encoder;
+
+/// Decodes `count` frames spread evenly over the video, each scaled so its longest edge is `size`.
+///
+/// The frames are packed RGB, without the padding FFmpeg may add to each row.
+/// Frames which fail to decode are skipped, so fewer frames may be returned.
+pub(crate) async fn decode_frames(
+	video_file_path: PathBuf,
+	count: u32,
+	size: u32,
+) -> Result<Vec<VideoFrame>, ThumbnailerError> {
+	spawn_blocking(move || {
+		// the frames have to come from the video itself, not from its cover art
+		let mut decoder = MovieDecoder::new(video_file_path, false)?;
+		// We actually have to decode a frame to get some metadata before we can start decoding for real
+		decoder.decode_video_frame()?;
+
+		let duration = decoder.get_video_duration().as_secs_f32();
+		let mut frames = Vec::with_capacity(count as usize);
+
+		for i in 0..count {
+			// the middle of each of `count` equal parts of the video, which skips black frames at the very start and end
+			let seconds = duration * (i as f32 + 0.5) / count as f32;
+			if decoder.seek(seconds.round() as i64).is_err() {
+				continue;
+			}
+
+			let mut frame = VideoFrame::default();
+			if decoder
+				.get_scaled_video_frame(Some(ThumbnailSize::Size(size)), true, &mut frame)
+				.is_err()
+			{
+				continue;
+			}
+
+			// the frames are only comparable if they're the same size, which they should be as they're from the same stream
+			if frames.first().map_or(false, |first: &VideoFrame| {
+				(first.width, first.height) != (frame.width, frame.height)
+			}) {
+				continue;
+			}
+
+			pack_rows(&mut frame);
+			frames.push(frame);
+		}
+
+		if frames.is_empty() {
+			return Err(ThumbnailerError::FrameDecodeError);
+		}
+
+		Ok(frames)
+	})
+	.await?
+}
+
+fn pack_rows(frame: &mut VideoFrame) {
+	let row_len = frame.width as usize * 3;
+	if frame.line_size as usize == row_len {
+		return;
+	}
+
+	frame.data = frame
+		.data
+		.chunks(frame.line_size as usize)
+		.flat_map(|row| &row[..row_len])
+		.copied()
+		.collect();
+	frame.line_size = row_len as u32;
+}
+
+/// Places the frames side by side, and encodes them as a single WebP image.
+pub(crate) fn encode_film_strip(frames: &[VideoFrame], quality: f32) -> Vec<u8> {
+	let (width, height) = (frames[0].width, frames[0].height);
+	let row_len = width as usize * 3;
+	let strip_width = width * frames.len() as u32;
+
+	let mut strip = Vec::with_capacity(row_len * frames.len() * height as usize);
+	for row in 0..height as usize {
+		for frame in frames {
+			strip.extend_from_slice(&frame.data[row * row_len..(row + 1) * row_len]);
+		}
+	}
+
+	Encoder::from_rgb(&strip, strip_width, height)
+		.encode(quality)
+		.deref()
+		.to_vec()
+}
+
+/// Encodes each frame as a WebP image, and combines them into an animated WebP which loops forever.
+///
+/// The `webp` crate can only encode still images, so the animation is assembled from them as described in
+/// https://developers.google.com/speed/webp/docs/riff_container#animation
+pub(crate) fn encode_animated_webp(
+	frames: &[VideoFrame],
+	frame_duration: Duration,
+	quality: f32,
+) -> Vec<u8> {
+	let (width, height) = (frames[0].width, frames[0].height);
+	let frame_duration = (frame_duration.as_millis() as u32).min(0xFF_FFFF);
+
+	let mut chunks = Vec::new();
+
+	// only the animation flag is set, and then the size of the canvas
+	let mut vp8x = vec![0x02, 0, 0, 0];
+	vp8x.extend_from_slice(&u24(width - 1));
+	vp8x.extend_from_slice(&u24(height - 1));
+	push_chunk(&mut chunks, b"VP8X", &vp8x);
+
+	// a transparent background, and a loop count of 0 (which is forever)
+	push_chunk(&mut chunks, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+
+	for frame in frames {
+		let webp = Encoder::from_rgb(&frame.data, width, height)
+			.encode(quality)
+			.deref()
+			.to_vec();
+
+		// every frame covers the whole canvas, so its offset is 0
+		let mut anmf = Vec::new();
+		anmf.extend_from_slice(&u24(0));
+		anmf.extend_from_slice(&u24(0));
+		anmf.extend_from_slice(&u24(width - 1));
+		anmf.extend_from_slice(&u24(height - 1));
+		anmf.extend_from_slice(&u24(frame_duration));
+		// the frame isn't blended with the previous one, which it fully replaces anyway
+		anmf.push(0b10);
+		anmf.extend(image_chunks(&webp));
+		push_chunk(&mut chunks, b"ANMF", &anmf);
+	}
+
+	let mut webp = Vec::with_capacity(chunks.len() + 12);
+	webp.extend_from_slice(b"RIFF");
+	webp.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+	webp.extend_from_slice(b"WEBP");
+	webp.extend(chunks);
+	webp
+}
+
+fn u24(value: u32) -> [u8; 3] {
+	let [a, b, c, _] = value.to_le_bytes();
+	[a, b, c]
+}
+
+fn push_chunk(buf: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+	buf.extend_from_slice(fourcc);
+	buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+	buf.extend_from_slice(payload);
+	// chunks are padded to an even size
+	if payload.len() % 2 == 1 {
+		buf.push(0);
+	}
+}
+
+/// The chunks of a still WebP image which make up a frame of an animation, which are the image data and its alpha.
+fn image_chunks(webp: &[u8]) -> Vec<u8> {
+	let mut chunks = Vec::new();
+
+	// skip the RIFF header
+	let mut pos = 12;
+	while pos + 8 <= webp.len() {
+		let fourcc = &webp[pos..pos + 4];
+		let len = u32::from_le_bytes([webp[pos + 4], webp[pos + 5], webp[pos + 6], webp[pos + 7]])
+			as usize;
+		let end = (pos + 8 + len + len % 2).min(webp.len());
+
+		if matches!(fourcc, b"ALPH" | b"VP8 " | b"VP8L") {
+			chunks.extend_from_slice(&webp[pos..end]);
+		}
+
+		pos = end;
+	}
+
+	chunks
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn frame(width: u32, height: u32, value: u8) -> VideoFrame {
+		VideoFrame {
+			width,
+			height,
+			line_size: width * 3,
+			data: vec![value; (width * height * 3) as usize],
+			source: None,
+		}
+	}
+
+	#[test]
+	fn test_pack_rows() {
+		let mut frame = VideoFrame {
+			width: 2,
+			height: 2,
+			line_size: 8,
+			data: vec![1, 1, 1, 2, 2, 2, 0, 0, 3, 3, 3, 4, 4, 4, 0, 0],
+			source: None,
+		};
+
+		pack_rows(&mut frame);
+
+		assert_eq!(frame.line_size, 6);
+		assert_eq!(frame.data, vec![1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4]);
+	}
+
+	#[test]
+	fn test_encode_animated_webp() {
+		let frames = [frame(16, 8, 0), frame(16, 8, 128), frame(16, 8, 255)];
+		let webp = encode_animated_webp(&frames, Duration::from_millis(500), 50.0);
+
+		assert_eq!(&webp[0..4], b"RIFF");
+		assert_eq!(
+			u32::from_le_bytes(webp[4..8].try_into().unwrap()) as usize,
+			webp.len() - 8
+		);
+		assert_eq!(&webp[8..12], b"WEBP");
+
+		assert_eq!(&webp[12..16], b"VP8X");
+		assert_eq!(webp[20], 0x02);
+		assert_eq!(&webp[24..27], &[15, 0, 0]);
+		assert_eq!(&webp[27..30], &[7, 0, 0]);
+		assert_eq!(&webp[30..34], b"ANIM");
+
+		// every frame should be an ANMF chunk containing a VP8 chunk
+		let mut pos = 44;
+		let mut count = 0;
+		while pos < webp.len() {
+			assert_eq!(&webp[pos..pos + 4], b"ANMF");
+			assert_eq!(&webp[pos + 20..pos + 23], &[0xF4, 0x01, 0]);
+			assert_eq!(&webp[pos + 24..pos + 27], b"VP8");
+
+			let len = u32::from_le_bytes(webp[pos + 4..pos + 8].try_into().unwrap()) as usize;
+			pos += 8 + len + len % 2;
+			count += 1;
+		}
+		assert_eq!(pos, webp.len());
+		assert_eq!(count, frames.len());
+	}
+}
@@ -0,0 +1,929 @@
+//! Transcoding of video and audio which browsers can't play into HLS segments, which are MPEG-TS files of H.264 video and AAC audio.
+//!
+//! Every segment is made on its own so any of them can be requested first when seeking, and they keep the timestamps of the original
+//! file so they line up when played one after another. Audio which is already AAC or MP3 is remuxed rather than transcoded, but video
+//! is always encoded so every segment starts with a key frame and lasts exactly as long as the playlist says. A copied stream can only
+//! be cut at its own key frames, which can be further apart than a segment.
+
+use crate::{
+	error::{FfmpegError, ThumbnailerError},
+	movie_decoder::{check_error, setup_filter, setup_filter_without_args},
+	utils::from_path,
+};
+
+use ffmpeg_sys_next::{
+	av_buffersink_get_frame, av_buffersink_get_h, av_buffersink_get_sample_aspect_ratio,
+	av_buffersink_get_time_base, av_buffersink_get_w, av_buffersink_set_frame_size,
+	av_buffersrc_add_frame, av_buffersrc_write_frame, av_channel_layout_default,
+	av_channel_layout_describe, av_find_best_stream, av_frame_alloc, av_frame_free, av_frame_unref,
+	av_guess_frame_rate, av_interleaved_write_frame, av_opt_set, av_packet_alloc, av_packet_free,
+	av_packet_rescale_ts, av_packet_unref, av_read_frame, av_rescale_q, av_seek_frame,
+	av_write_trailer, avcodec_alloc_context3, avcodec_find_decoder, avcodec_find_encoder,
+	avcodec_free_context, avcodec_open2, avcodec_parameters_copy, avcodec_parameters_from_context,
+	avcodec_parameters_to_context, avcodec_receive_frame, avcodec_receive_packet,
+	avcodec_send_frame, avcodec_send_packet, avfilter_graph_alloc, avfilter_graph_config,
+	avfilter_graph_free, avfilter_link, avformat_alloc_output_context2, avformat_close_input,
+	avformat_find_stream_info, avformat_free_context, avformat_new_stream, avformat_open_input,
+	avformat_write_header, avio_closep, avio_open, AVCodecContext, AVCodecID, AVFilterContext,
+	AVFilterGraph, AVFormatContext, AVFrame, AVMediaType, AVPacket, AVPixelFormat, AVRational,
+	AVSampleFormat, AVStream, AVERROR, AVERROR_EOF, AVFMT_GLOBALHEADER, AVFMT_NOFILE,
+	AVIO_FLAG_WRITE, AVSEEK_FLAG_BACKWARD, AV_CODEC_CAP_VARIABLE_FRAME_SIZE,
+	AV_CODEC_FLAG_GLOBAL_HEADER, AV_DISPOSITION_ATTACHED_PIC, AV_NOPTS_VALUE, AV_TIME_BASE, EAGAIN,
+};
+use std::{
+	ffi::{c_int, CStr, CString},
+	path::Path,
+	time::Duration,
+};
+
+const AVERROR_EAGAIN: c_int = AVERROR(EAGAIN);
+const TIME_BASE: AVRational = AVRational {
+	num: 1,
+	den: AV_TIME_BASE as c_int,
+};
+
+/// How long each segment is, apart from the last one.
+pub const HLS_SEGMENT_DURATION: Duration = Duration::from_secs(6);
+
+/// Videos are scaled down to 1080p, as larger ones take too long to transcode in real time.
+const VIDEO_MAX_HEIGHT: u32 = 1080;
+const AUDIO_SAMPLE_RATE: c_int = 48_000;
+const AUDIO_BIT_RATE: i64 = 160_000;
+
+/// The number of segments that media of `duration` is split into.
+pub fn hls_segment_count(duration: Duration, segment_duration: Duration) -> u32 {
+	(duration.as_secs_f64() / segment_duration.as_secs_f64()).ceil() as u32
+}
+
+/// A playlist of every segment of media which lasts `duration`, where `segment_uri` is the URI of the segment at an index.
+pub fn hls_playlist(
+	duration: Duration,
+	segment_duration: Duration,
+	segment_uri: impl Fn(u32) -> String,
+) -> String {
+	let mut playlist = format!(
+		"#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n",
+		segment_duration.as_secs_f64().ceil() as u64
+	);
+
+	for index in 0..hls_segment_count(duration, segment_duration) {
+		let length = segment_duration.min(duration - segment_duration * index);
+		playlist.push_str(&format!(
+			"#EXTINF:{:.3},\n{}\n",
+			length.as_secs_f64(),
+			segment_uri(index)
+		));
+	}
+
+	playlist.push_str("#EXT-X-ENDLIST\n");
+	playlist
+}
+
+/// The duration of the longest stream in the file.
+pub(crate) fn media_duration(path: &Path) -> Result<Duration, ThumbnailerError> {
+	let path_cstring = from_path(path)?;
+	let mut format_context = std::ptr::null_mut();
+
+	check_error(
+		unsafe {
+			avformat_open_input(
+				&mut format_context,
+				path_cstring.as_ptr(),
+				std::ptr::null_mut(),
+				std::ptr::null_mut(),
+			)
+		},
+		"Failed to open input",
+	)?;
+
+	let duration = check_error(
+		unsafe { avformat_find_stream_info(format_context, std::ptr::null_mut()) },
+		"Failed to get stream info",
+	)
+	.map(|_| unsafe { (*format_context).duration });
+
+	unsafe { avformat_close_input(&mut format_context) };
+
+	match duration? {
+		duration if duration == AV_NOPTS_VALUE || duration <= 0 => {
+			Err(ThumbnailerError::FfmpegWithReason(
+				FfmpegError::InvalidData,
+				"Media has no duration".to_string(),
+			))
+		}
+		duration => Ok(Duration::from_micros(duration as u64)),
+	}
+}
+
+/// Writes the part of the media from `start` which lasts `duration` to `output` as an MPEG-TS segment.
+pub(crate) fn transcode_segment(
+	input: &Path,
+	output: &Path,
+	start: Duration,
+	duration: Duration,
+) -> Result<(), ThumbnailerError> {
+	SegmentTranscoder::new(input, output)?.transcode(
+		start.as_micros() as i64,
+		(start + duration).as_micros() as i64,
+	)
+}
+
+struct SegmentTranscoder {
+	input: *mut AVFormatContext,
+	output: *mut AVFormatContext,
+	streams: Vec<OutputStream>,
+	packet: *mut AVPacket,
+	frame: *mut AVFrame,
+}
+
+impl SegmentTranscoder {
+	fn new(input: &Path, output: &Path) -> Result<Self, ThumbnailerError> {
+		let mut transcoder = Self {
+			input: std::ptr::null_mut(),
+			output: std::ptr::null_mut(),
+			streams: vec![],
+			packet: unsafe { av_packet_alloc() },
+			frame: unsafe { av_frame_alloc() },
+		};
+		if transcoder.packet.is_null() {
+			return Err(FfmpegError::PacketAllocation.into());
+		}
+		if transcoder.frame.is_null() {
+			return Err(FfmpegError::FrameAllocation.into());
+		}
+
+		let input_cstring = from_path(input)?;
+		let output_cstring = from_path(output)?;
+		let format_name = CString::new("mpegts").unwrap();
+
+		check_error(
+			unsafe {
+				avformat_open_input(
+					&mut transcoder.input,
+					input_cstring.as_ptr(),
+					std::ptr::null_mut(),
+					std::ptr::null_mut(),
+				)
+			},
+			"Failed to open input",
+		)?;
+		check_error(
+			unsafe { avformat_find_stream_info(transcoder.input, std::ptr::null_mut()) },
+			"Failed to get stream info",
+		)?;
+		check_error(
+			unsafe {
+				avformat_alloc_output_context2(
+					&mut transcoder.output,
+					std::ptr::null_mut(),
+					format_name.as_ptr(),
+					output_cstring.as_ptr(),
+				)
+			},
+			"Failed to create output",
+		)?;
+
+		for media_type in [
+			AVMediaType::AVMEDIA_TYPE_VIDEO,
+			AVMediaType::AVMEDIA_TYPE_AUDIO,
+		] {
+			let stream_index = unsafe {
+				av_find_best_stream(
+					transcoder.input,
+					media_type,
+					-1,
+					-1,
+					std::ptr::null_mut(),
+					0,
+				)
+			};
+			if stream_index < 0 {
+				continue;
+			}
+
+			let stream = unsafe { *(*transcoder.input).streams.offset(stream_index as isize) };
+			// cover art is stored as a video stream with a single frame
+			if unsafe { (*stream).disposition } & AV_DISPOSITION_ATTACHED_PIC as c_int != 0 {
+				continue;
+			}
+
+			let output_stream = OutputStream::new(transcoder.input, transcoder.output, stream)?;
+			transcoder.streams.push(output_stream);
+		}
+
+		if transcoder.streams.is_empty() {
+			return Err(FfmpegError::StreamNotFound.into());
+		}
+
+		unsafe {
+			if (*(*transcoder.output).oformat).flags & AVFMT_NOFILE as c_int == 0 {
+				check_error(
+					avio_open(
+						&mut (*transcoder.output).pb,
+						output_cstring.as_ptr(),
+						AVIO_FLAG_WRITE as c_int,
+					),
+					"Failed to open output",
+				)?;
+			}
+		}
+
+		check_error(
+			unsafe { avformat_write_header(transcoder.output, std::ptr::null_mut()) },
+			"Failed to write header",
+		)?;
+
+		Ok(transcoder)
+	}
+
+	/// `start` and `end` are in microseconds from the start of the media.
+	fn transcode(&mut self, start: i64, end: i64) -> Result<(), ThumbnailerError> {
+		if start > 0 {
+			let start_time = match unsafe { (*self.input).start_time } {
+				AV_NOPTS_VALUE => 0,
+				start_time => start_time,
+			};
+
+			// the segment may start after a key frame, so the frames before it are decoded and then dropped
+			check_error(
+				unsafe {
+					av_seek_frame(
+						self.input,
+						-1,
+						start_time + start,
+						AVSEEK_FLAG_BACKWARD as c_int,
+					)
+				},
+				"Seeking media failed",
+			)?;
+		}
+
+		while !self.streams.iter().all(|stream| stream.finished) {
+			match unsafe { av_read_frame(self.input, self.packet) } {
+				AVERROR_EOF => break,
+				ret => check_error(ret, "Failed to read packet")?,
+			}
+
+			let stream_index = unsafe { (*self.packet).stream_index };
+			let result = match self
+				.streams
+				.iter_mut()
+				.find(|stream| stream.input_index == stream_index)
+			{
+				Some(stream) => {
+					stream.process_packet(self.output, self.packet, self.frame, start, end)
+				}
+				None => Ok(()),
+			};
+
+			unsafe { av_packet_unref(self.packet) };
+			result?;
+		}
+
+		for stream in &mut self.streams {
+			stream.flush(self.output, self.packet, self.frame, start, end)?;
+		}
+
+		check_error(
+			unsafe { av_write_trailer(self.output) },
+			"Failed to write trailer",
+		)
+	}
+}
+
+impl Drop for SegmentTranscoder {
+	fn drop(&mut self) {
+		// the encoders have to be freed before the contexts their streams belong to
+		self.streams.clear();
+
+		unsafe {
+			av_packet_free(&mut self.packet);
+			av_frame_free(&mut self.frame);
+
+			if !self.output.is_null() {
+				if (*(*self.output).oformat).flags & AVFMT_NOFILE as c_int == 0 {
+					avio_closep(&mut (*self.output).pb);
+				}
+				avformat_free_context(self.output);
+				self.output = std::ptr::null_mut();
+			}
+
+			if !self.input.is_null() {
+				avformat_close_input(&mut self.input);
+			}
+		}
+	}
+}
+
+struct OutputStream {
+	input_index: c_int,
+	input_time_base: AVRational,
+	/// Where the stream's timestamps start from, which is subtracted from them so the first segment starts at zero.
+	start_time: i64,
+	output: *mut AVStream,
+	/// `None` when the stream is remuxed as it is.
+	encoder: Option<StreamEncoder>,
+	finished: bool,
+}
+
+impl OutputStream {
+	fn new(
+		input_context: *mut AVFormatContext,
+		output_context: *mut AVFormatContext,
+		input: *mut AVStream,
+	) -> Result<Self, ThumbnailerError> {
+		let codec_params = unsafe { (*input).codecpar };
+		let is_video = unsafe { (*codec_params).codec_type } == AVMediaType::AVMEDIA_TYPE_VIDEO;
+
+		let output = unsafe { avformat_new_stream(output_context, std::ptr::null()) };
+		if output.is_null() {
+			return Err(FfmpegError::StreamAllocation.into());
+		}
+
+		let can_remux = !is_video
+			&& matches!(
+				unsafe { (*codec_params).codec_id },
+				AVCodecID::AV_CODEC_ID_AAC | AVCodecID::AV_CODEC_ID_MP3
+			);
+
+		let encoder = if can_remux {
+			check_error(
+				unsafe { avcodec_parameters_copy((*output).codecpar, codec_params) },
+				"Failed to copy codec parameters",
+			)?;
+			unsafe {
+				(*(*output).codecpar).codec_tag = 0;
+				(*output).time_base = (*input).time_base;
+			}
+
+			None
+		} else {
+			let encoder = StreamEncoder::new(input_context, output_context, input, is_video)?;
+			check_error(
+				unsafe { avcodec_parameters_from_context((*output).codecpar, encoder.encoder) },
+				"Failed to get parameters from encoder",
+			)?;
+			unsafe { (*output).time_base = (*encoder.encoder).time_base };
+
+			Some(encoder)
+		};
+
+		Ok(Self {
+			input_index: unsafe { (*input).index },
+			input_time_base: unsafe { (*input).time_base },
+			start_time: match unsafe { (*input).start_time } {
+				AV_NOPTS_VALUE => 0,
+				start_time => start_time,
+			},
+			output,
+			encoder,
+			finished: false,
+		})
+	}
+
+	/// Microseconds from the start of the stream.
+	fn time(&self, timestamp: i64) -> i64 {
+		unsafe { av_rescale_q(timestamp - self.start_time, self.input_time_base, TIME_BASE) }
+	}
+
+	fn process_packet(
+		&mut self,
+		output_context: *mut AVFormatContext,
+		packet: *mut AVPacket,
+		frame: *mut AVFrame,
+		start: i64,
+		end: i64,
+	) -> Result<(), ThumbnailerError> {
+		if self.finished {
+			return Ok(());
+		}
+
+		if self.encoder.is_some() {
+			return self.decode(output_context, packet, frame, start, end);
+		}
+
+		let timestamp = match unsafe { ((*packet).pts, (*packet).dts) } {
+			(AV_NOPTS_VALUE, AV_NOPTS_VALUE) => return Ok(()),
+			(AV_NOPTS_VALUE, dts) => dts,
+			(pts, _) => pts,
+		};
+		let time = self.time(timestamp);
+
+		if time >= end {
+			self.finished = true;
+			return Ok(());
+		}
+		if time < start {
+			return Ok(());
+		}
+
+		unsafe {
+			if (*packet).pts != AV_NOPTS_VALUE {
+				(*packet).pts -= self.start_time;
+			}
+			if (*packet).dts != AV_NOPTS_VALUE {
+				(*packet).dts -= self.start_time;
+			}
+		}
+
+		write_packet(output_context, self.output, packet, self.input_time_base)
+	}
+
+	/// Decodes the packet, and encodes the frames within the segment. A null packet drains the decoder.
+	fn decode(
+		&mut self,
+		output_context: *mut AVFormatContext,
+		packet: *mut AVPacket,
+		frame: *mut AVFrame,
+		start: i64,
+		end: i64,
+	) -> Result<(), ThumbnailerError> {
+		let Some(encoder) = &mut self.encoder else {
+			return Ok(());
+		};
+
+		match unsafe { avcodec_send_packet(encoder.decoder, packet) } {
+			AVERROR_EAGAIN | AVERROR_EOF => {}
+			ret => check_error(ret, "Failed to send packet to decoder")?,
+		}
+
+		loop {
+			match unsafe { avcodec_receive_frame(encoder.decoder, frame) } {
+				0 => {}
+				AVERROR_EAGAIN | AVERROR_EOF => return Ok(()),
+				ret => {
+					return Err(ThumbnailerError::FfmpegWithReason(
+						FfmpegError::from(ret),
+						"Failed to receive frame from decoder".to_string(),
+					))
+				}
+			}
+
+			let timestamp = unsafe { (*frame).best_effort_timestamp };
+			if timestamp == AV_NOPTS_VALUE {
+				unsafe { av_frame_unref(frame) };
+				continue;
+			}
+
+			let time = unsafe {
+				av_rescale_q(timestamp - self.start_time, self.input_time_base, TIME_BASE)
+			};
+			if time >= end {
+				self.finished = true;
+				unsafe { av_frame_unref(frame) };
+				return Ok(());
+			}
+			if time < start {
+				unsafe { av_frame_unref(frame) };
+				continue;
+			}
+
+			unsafe { (*frame).pts = timestamp - self.start_time };
+			let result = encoder.encode(output_context, self.output, packet, frame);
+			unsafe { av_frame_unref(frame) };
+			result?;
+		}
+	}
+
+	fn flush(
+		&mut self,
+		output_context: *mut AVFormatContext,
+		packet: *mut AVPacket,
+		frame: *mut AVFrame,
+		start: i64,
+		end: i64,
+	) -> Result<(), ThumbnailerError> {
+		if self.encoder.is_none() {
+			return Ok(());
+		}
+
+		if !self.finished {
+			self.decode(output_context, std::ptr::null_mut(), frame, start, end)?;
+		}
+
+		match &mut self.encoder {
+			Some(encoder) => {
+				encoder.encode(output_context, self.output, packet, std::ptr::null_mut())
+			}
+			None => Ok(()),
+		}
+	}
+}
+
+/// Decodes a stream, and encodes it as H.264 or AAC after converting it to a format the encoder accepts.
+struct StreamEncoder {
+	decoder: *mut AVCodecContext,
+	encoder: *mut AVCodecContext,
+	filter_graph: *mut AVFilterGraph,
+	filter_source: *mut AVFilterContext,
+	filter_sink: *mut AVFilterContext,
+	filtered_frame: *mut AVFrame,
+}
+
+impl StreamEncoder {
+	fn new(
+		input_context: *mut AVFormatContext,
+		output_context: *mut AVFormatContext,
+		input: *mut AVStream,
+		is_video: bool,
+	) -> Result<Self, ThumbnailerError> {
+		let mut encoder = Self {
+			decoder: std::ptr::null_mut(),
+			encoder: std::ptr::null_mut(),
+			filter_graph: std::ptr::null_mut(),
+			filter_source: std::ptr::null_mut(),
+			filter_sink: std::ptr::null_mut(),
+			filtered_frame: unsafe { av_frame_alloc() },
+		};
+		if encoder.filtered_frame.is_null() {
+			return Err(FfmpegError::FrameAllocation.into());
+		}
+
+		let decoder_codec = unsafe { avcodec_find_decoder((*(*input).codecpar).codec_id) };
+		if decoder_codec.is_null() {
+			return Err(FfmpegError::DecoderNotFound.into());
+		}
+
+		encoder.decoder = unsafe { avcodec_alloc_context3(decoder_codec) };
+		if encoder.decoder.is_null() {
+			return Err(FfmpegError::CodecAllocation.into());
+		}
+
+		check_error(
+			unsafe { avcodec_parameters_to_context(encoder.decoder, (*input).codecpar) },
+			"Failed to get parameters from context",
+		)?;
+		unsafe { (*encoder.decoder).pkt_timebase = (*input).time_base };
+		check_error(
+			unsafe { avcodec_open2(encoder.decoder, decoder_codec, std::ptr::null_mut()) },
+			"Failed to open decoder",
+		)?;
+
+		let time_base = unsafe { (*input).time_base };
+		if is_video {
+			encoder.initialize_video_filter_graph(time_base)?;
+		} else {
+			encoder.initialize_audio_filter_graph(time_base)?;
+		}
+
+		let encoder_codec = unsafe {
+			avcodec_find_encoder(if is_video {
+				AVCodecID::AV_CODEC_ID_H264
+			} else {
+				AVCodecID::AV_CODEC_ID_AAC
+			})
+		};
+		if encoder_codec.is_null() {
+			return Err(FfmpegError::EncoderNotFound.into());
+		}
+
+		encoder.encoder = unsafe { avcodec_alloc_context3(encoder_codec) };
+		if encoder.encoder.is_null() {
+			return Err(FfmpegError::CodecAllocation.into());
+		}
+
+		unsafe {
+			let context = encoder.encoder;
+			if is_video {
+				let frame_rate = av_guess_frame_rate(input_context, input, std::ptr::null_mut());
+
+				(*context).width = av_buffersink_get_w(encoder.filter_sink);
+				(*context).height = av_buffersink_get_h(encoder.filter_sink);
+				(*context).sample_aspect_ratio =
+					av_buffersink_get_sample_aspect_ratio(encoder.filter_sink);
+				(*context).pix_fmt = AVPixelFormat::AV_PIX_FMT_YUV420P;
+				(*context).time_base = av_buffersink_get_time_base(encoder.filter_sink);
+				(*context).framerate = frame_rate;
+				// a key frame every couple of seconds, and no B-frames so the segment's first timestamps aren't negative
+				(*context).gop_size = if frame_rate.num > 0 && frame_rate.den > 0 {
+					2 * frame_rate.num / frame_rate.den
+				} else {
+					60
+				};
+				(*context).max_b_frames = 0;
+
+				// this is only an option of libx264, so other H.264 encoders ignore it
+				let preset_key = CString::new("preset").unwrap();
+				let preset_value = CString::new("veryfast").unwrap();
+				av_opt_set(
+					(*context).priv_data,
+					preset_key.as_ptr(),
+					preset_value.as_ptr(),
+					0,
+				);
+			} else {
+				(*context).sample_fmt = AVSampleFormat::AV_SAMPLE_FMT_FLTP;
+				(*context).sample_rate = AUDIO_SAMPLE_RATE;
+				av_channel_layout_default(&mut (*context).ch_layout, 2);
+				(*context).bit_rate = AUDIO_BIT_RATE;
+				(*context).time_base = AVRational {
+					num: 1,
+					den: AUDIO_SAMPLE_RATE,
+				};
+			}
+
+			if (*(*output_context).oformat).flags & AVFMT_GLOBALHEADER as c_int != 0 {
+				(*context).flags |= AV_CODEC_FLAG_GLOBAL_HEADER as c_int;
+			}
+		}
+
+		check_error(
+			unsafe { avcodec_open2(encoder.encoder, encoder_codec, std::ptr::null_mut()) },
+			"Failed to open encoder",
+		)?;
+
+		unsafe {
+			// the AAC encoder needs frames of exactly its frame size
+			if !is_video
+				&& (*encoder.encoder).frame_size > 0
+				&& (*encoder_codec).capabilities & AV_CODEC_CAP_VARIABLE_FRAME_SIZE as c_int == 0
+			{
+				av_buffersink_set_frame_size(
+					encoder.filter_sink,
+					(*encoder.encoder).frame_size as u32,
+				);
+			}
+		}
+
+		Ok(encoder)
+	}
+
+	fn initialize_video_filter_graph(
+		&mut self,
+		time_base: AVRational,
+	) -> Result<(), ThumbnailerError> {
+		self.filter_graph = unsafe { avfilter_graph_alloc() };
+		if self.filter_graph.is_null() {
+			return Err(FfmpegError::FilterGraphAllocation.into());
+		}
+
+		let args = unsafe {
+			format!(
+				"video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+				(*self.decoder).width,
+				(*self.decoder).height,
+				(*self.decoder).pix_fmt as i32,
+				time_base.num,
+				time_base.den,
+				(*self.decoder).sample_aspect_ratio.num,
+				i32::max((*self.decoder).sample_aspect_ratio.den, 1)
+			)
+		};
+
+		setup_filter(
+			&mut self.filter_source,
+			"buffer",
+			"transcode_buffer",
+			&args,
+			self.filter_graph,
+			"Failed to create filter source",
+		)?;
+
+		setup_filter_without_args(
+			&mut self.filter_sink,
+			"buffersink",
+			"transcode_buffersink",
+			self.filter_graph,
+			"Failed to create filter sink",
+		)?;
+
+		// H.264 in 4:2:0 needs even dimensions
+		let mut scale_filter = std::ptr::null_mut();
+		setup_filter(
+			&mut scale_filter,
+			"scale",
+			"transcode_scale",
+			&format!("w=-2:h=min(trunc(ih/2)*2,{VIDEO_MAX_HEIGHT})"),
+			self.filter_graph,
+			"Failed to create scale filter",
+		)?;
+
+		let mut format_filter = std::ptr::null_mut();
+		setup_filter(
+			&mut format_filter,
+			"format",
+			"transcode_format",
+			"pix_fmts=yuv420p",
+			self.filter_graph,
+			"Failed to create format filter",
+		)?;
+
+		self.link_filters(&[
+			self.filter_source,
+			scale_filter,
+			format_filter,
+			self.filter_sink,
+		])
+	}
+
+	fn initialize_audio_filter_graph(
+		&mut self,
+		time_base: AVRational,
+	) -> Result<(), ThumbnailerError> {
+		self.filter_graph = unsafe { avfilter_graph_alloc() };
+		if self.filter_graph.is_null() {
+			return Err(FfmpegError::FilterGraphAllocation.into());
+		}
+
+		let mut channel_layout = [0; 64];
+		unsafe {
+			av_channel_layout_describe(
+				&(*self.decoder).ch_layout,
+				channel_layout.as_mut_ptr(),
+				channel_layout.len(),
+			)
+		};
+		let channel_layout = unsafe { CStr::from_ptr(channel_layout.as_ptr()) };
+
+		let args = unsafe {
+			format!(
+				"time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout={}",
+				time_base.num,
+				time_base.den,
+				(*self.decoder).sample_rate,
+				(*self.decoder).sample_fmt as i32,
+				channel_layout.to_string_lossy()
+			)
+		};
+
+		setup_filter(
+			&mut self.filter_source,
+			"abuffer",
+			"transcode_abuffer",
+			&args,
+			self.filter_graph,
+			"Failed to create filter source",
+		)?;
+
+		setup_filter_without_args(
+			&mut self.filter_sink,
+			"abuffersink",
+			"transcode_abuffersink",
+			self.filter_graph,
+			"Failed to create filter sink",
+		)?;
+
+		let mut format_filter = std::ptr::null_mut();
+		setup_filter(
+			&mut format_filter,
+			"aformat",
+			"transcode_aformat",
+			&format!("sample_fmts=fltp:sample_rates={AUDIO_SAMPLE_RATE}:channel_layouts=stereo"),
+			self.filter_graph,
+			"Failed to create format filter",
+		)?;
+
+		self.link_filters(&[self.filter_source, format_filter, self.filter_sink])
+	}
+
+	fn link_filters(&mut self, filters: &[*mut AVFilterContext]) -> Result<(), ThumbnailerError> {
+		for pair in filters.windows(2) {
+			check_error(
+				unsafe { avfilter_link(pair[0], 0, pair[1], 0) },
+				"Failed to link filters",
+			)?;
+		}
+
+		check_error(
+			unsafe { avfilter_graph_config(self.filter_graph, std::ptr::null_mut()) },
+			"Failed to configure filter graph",
+		)
+	}
+
+	/// Filters and encodes the frame, writing every packet the encoder has ready. A null frame drains the filters and encoder.
+	fn encode(
+		&mut self,
+		output_context: *mut AVFormatContext,
+		output: *mut AVStream,
+		packet: *mut AVPacket,
+		frame: *mut AVFrame,
+	) -> Result<(), ThumbnailerError> {
+		check_error(
+			unsafe {
+				if frame.is_null() {
+					av_buffersrc_add_frame(self.filter_source, frame)
+				} else {
+					av_buffersrc_write_frame(self.filter_source, frame)
+				}
+			},
+			"Failed to write frame to filter graph",
+		)?;
+
+		loop {
+			match unsafe { av_buffersink_get_frame(self.filter_sink, self.filtered_frame) } {
+				0 => {}
+				AVERROR_EAGAIN | AVERROR_EOF => break,
+				ret => {
+					return Err(ThumbnailerError::FfmpegWithReason(
+						FfmpegError::from(ret),
+						"Failed to get buffer from filter".to_string(),
+					))
+				}
+			}
+
+			let result = self.write_packets(output_context, output, packet, self.filtered_frame);
+			unsafe { av_frame_unref(self.filtered_frame) };
+			result?;
+		}
+
+		if frame.is_null() {
+			self.write_packets(output_context, output, packet, std::ptr::null_mut())?;
+		}
+
+		Ok(())
+	}
+
+	fn write_packets(
+		&mut self,
+		output_context: *mut AVFormatContext,
+		output: *mut AVStream,
+		packet: *mut AVPacket,
+		frame: *mut AVFrame,
+	) -> Result<(), ThumbnailerError> {
+		check_error(
+			unsafe { avcodec_send_frame(self.encoder, frame) },
+			"Failed to send frame to encoder",
+		)?;
+
+		loop {
+			match unsafe { avcodec_receive_packet(self.encoder, packet) } {
+				0 => {}
+				AVERROR_EAGAIN | AVERROR_EOF => return Ok(()),
+				ret => {
+					return Err(ThumbnailerError::FfmpegWithReason(
+						FfmpegError::from(ret),
+						"Failed to receive packet from encoder".to_string(),
+					))
+				}
+			}
+
+			write_packet(output_context, output, packet, unsafe {
+				(*self.encoder).time_base
+			})?;
+		}
+	}
+}
+
+impl Drop for StreamEncoder {
+	fn drop(&mut self) {
+		unsafe {
+			av_frame_free(&mut self.filtered_frame);
+			avfilter_graph_free(&mut self.filter_graph);
+			avcodec_free_context(&mut self.encoder);
+			avcodec_free_context(&mut self.decoder);
+		}
+	}
+}
+
+/// Writes the packet to the output stream, with its timestamps converted from `time_base`.
+fn write_packet(
+	output_context: *mut AVFormatContext,
+	output: *mut AVStream,
+	packet: *mut AVPacket,
+	time_base: AVRational,
+) -> Result<(), ThumbnailerError> {
+	unsafe {
+		(*packet).stream_index = (*output).index;
+		av_packet_rescale_ts(packet, time_base, (*output).time_base);
+	}
+
+	check_error(
+		unsafe { av_interleaved_write_frame(output_context, packet) },
+		"Failed to write packet",
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_hls_playlist() {
+		let playlist = hls_playlist(
+			Duration::from_millis(14_500),
+			HLS_SEGMENT_DURATION,
+			|index| format!("{index}.ts"),
+		);
+
+		assert_eq!(
+			playlist,
+			"#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:0\n\
+			#EXTINF:6.000,\n0.ts\n\
+			#EXTINF:6.000,\n1.ts\n\
+			#EXTINF:2.500,\n2.ts\n\
+			#EXT-X-ENDLIST\n"
+		);
+	}
+
+	#[test]
+	fn test_hls_segment_count() {
+		assert_eq!(
+			hls_segment_count(Duration::from_secs(12), HLS_SEGMENT_DURATION),
+			2
+		);
+		assert_eq!(
+			hls_segment_count(Duration::from_millis(12_001), HLS_SEGMENT_DURATION),
+			3
+		);
+	}
+}
@@ -75,6 +75,7 @@ extension_category_enum! {
 		Svg = [0x3C, 0x73, 0x76, 0x67],
 		Ico = [0x00, 0x00, 0x01, 0x00],
 		Heic = [0x00, 0x00, 0x00, 0x18, 0x66, 0x74, 0x79, 0x70, 0x68, 0x65, 0x69, 0x63],
+		Heif = [0x66, 0x74, 0x79, 0x70, 0x6D, 0x69, 0x66, 0x31] + 4,
 		Raw = [],
 		Akw = [0x41, 0x4B, 0x57, 0x42],
 		Dng = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x44, 0x4E, 0x47, 0x00],
@@ -82,12 +83,18 @@ extension_category_enum! {
 		Dcr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x44, 0x43, 0x52, 0x00],
 		Nwr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x4E, 0x57, 0x52, 0x00],
 		Nef = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x4E, 0x45, 0x46, 0x00],
+		// ARW files are TIFFs without a signature of their own
+		Arw = [],
+		Cr3 = [0x66, 0x74, 0x79, 0x70, 0x63, 0x72, 0x78, 0x20] + 4,
+		Raf = [0x46, 0x55, 0x4A, 0x49, 0x46, 0x49, 0x4C, 0x4D],
+		Orf = [0x49, 0x49, 0x52, 0x4F],
+		Rw2 = [0x49, 0x49, 0x55, 0x00],
 	}
 }
 
 // audio extensions
 extension_category_enum! {
-	AudioExtension _ALL_AUDIO_EXTENSIONS {
+	AudioExtension ALL_AUDIO_EXTENSIONS {
 		Mp3 = [0x49, 0x44, 0x33],
 		Mp2 = [0xFF, 0xFB] | [0xFF, 0xFD],
 		M4a = [0x66, 0x74, 0x79, 0x70, 0x4D, 0x34, 0x41, 0x20] + 4,
@@ -114,7 +121,7 @@ extension_category_enum! {
 
 // archive extensions
 extension_category_enum! {
-	ArchiveExtension _ALL_ARCHIVE_EXTENSIONS {
+	ArchiveExtension ALL_ARCHIVE_EXTENSIONS {
 		Zip = [0x50, 0x4B, 0x03, 0x04],
 		Rar = [0x52, 0x61, 0x72, 0x21, 0x1A, 0x07, 0x00],
 		Tar = [0x75, 0x73, 0x74, 0x61, 0x72],
@@ -129,7 +136,7 @@ extension_category_enum! {
 
 // executable extensions
 extension_category_enum! {
-	ExecutableExtension _ALL_EXECUTABLE_EXTENSIONS {
+	ExecutableExtension ALL_EXECUTABLE_EXTENSIONS {
 		Exe = [0x4D, 0x5A],
 		App = [0x4D, 0x5A],
 		Apk = [0x50, 0x4B, 0x03, 0x04],
@@ -145,7 +152,7 @@ extension_category_enum! {
 
 // document extensions
 extension_category_enum! {
-	DocumentExtension _ALL_DOCUMENT_EXTENSIONS {
+	DocumentExtension ALL_DOCUMENT_EXTENSIONS {
 		Pdf = [0x25, 0x50, 0x44, 0x46, 0x2D],
 		Key = [0x50, 0x4B, 0x03, 0x04],
 		Pages = [0x50, 0x4B, 0x03, 0x04],
@@ -166,7 +173,7 @@ extension_category_enum! {
 
 // text file extensions
 extension_category_enum! {
-	TextExtension _ALL_TEXT_EXTENSIONS {
+	TextExtension ALL_TEXT_EXTENSIONS {
 		Txt,
 		Rtf,
 		Md,
@@ -182,7 +189,7 @@ extension_category_enum! {
 
 // encrypted file extensions
 extension_category_enum! {
-	EncryptedExtension _ALL_ENCRYPTED_EXTENSIONS {
+	EncryptedExtension ALL_ENCRYPTED_EXTENSIONS {
 		// Spacedrive encrypted file
 		Bytes = [0x62, 0x61, 0x6C, 0x6C, 0x61, 0x70, 0x70],
 		// Spacedrive container
@@ -206,7 +213,7 @@ extension_category_enum! {
 
 // font extensions
 extension_category_enum! {
-	FontExtension _ALL_FONT_EXTENSIONS {
+	FontExtension ALL_FONT_EXTENSIONS {
 		Ttf = [0x00, 0x01, 0x00, 0x00, 0x00],
 		Otf = [0x4F, 0x54, 0x54, 0x4F, 0x00],
 		Woff = [0x77, 0x4F, 0x46, 0x46],
@@ -216,7 +223,7 @@ extension_category_enum! {
 
 // font extensions
 extension_category_enum! {
-	MeshExtension _ALL_MESH_EXTENSIONS {
+	MeshExtension ALL_MESH_EXTENSIONS {
 		Fbx = [0x46, 0x42, 0x58, 0x20],
 		Obj = [0x6F, 0x62, 0x6A],
 	}
@@ -224,7 +231,7 @@ extension_category_enum! {
 
 // code extensions
 extension_category_enum! {
-	CodeExtension _ALL_CODE_EXTENSIONS {
+	CodeExtension ALL_CODE_EXTENSIONS {
 		Rs,
 		Ts,
 		Tsx,
@@ -258,7 +265,7 @@ extension_category_enum! {
 
 // database extensions
 extension_category_enum! {
-	DatabaseExtension _ALL_DATABASE_EXTENSIONS {
+	DatabaseExtension ALL_DATABASE_EXTENSIONS {
 		Sqlite = [0x53, 0x51, 0x4C, 0x69, 0x74, 0x65, 0x20, 0x66, 0x6F, 0x72, 0x6D, 0x61, 0x74, 0x20, 0x33, 0x00],
 		Db = [],
 	}
@@ -290,6 +297,50 @@ mod test {
 		assert_eq!(Extension::from_str("jeff"), None);
 	}
 
+	#[test]
+	fn detect_from_header() {
+		let png = [
+			0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D,
+		];
+
+		let detected =
+			Extension::detect_from_header(Some(Extension::Image(ImageExtension::Png)), &png);
+		assert_eq!(
+			detected.verified_extension,
+			Some(Extension::Image(ImageExtension::Png))
+		);
+		assert_eq!(detected.mime_type, Some("image/png"));
+		assert!(!detected.extension_mismatch);
+
+		// a PNG which has been named as a JPEG
+		let detected =
+			Extension::detect_from_header(Some(Extension::Image(ImageExtension::Jpg)), &png);
+		assert_eq!(
+			detected.verified_extension,
+			Some(Extension::Image(ImageExtension::Png))
+		);
+		assert_eq!(detected.mime_type, Some("image/png"));
+		assert!(detected.extension_mismatch);
+
+		// a DNG which only has TIFF's magic bytes, as they usually do
+		let tiff = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x10, 0x00];
+		let detected =
+			Extension::detect_from_header(Some(Extension::Image(ImageExtension::Dng)), &tiff);
+		assert_eq!(
+			detected.verified_extension,
+			Some(Extension::Image(ImageExtension::Dng))
+		);
+		assert_eq!(detected.mime_type, Some("image/x-adobe-dng"));
+		assert!(!detected.extension_mismatch);
+
+		// text doesn't have magic bytes to verify
+		let detected =
+			Extension::detect_from_header(Some(Extension::Text(TextExtension::Txt)), b"hello");
+		assert_eq!(detected.verified_extension, None);
+		assert_eq!(detected.mime_type, Some("text/plain"));
+		assert!(!detected.extension_mismatch);
+	}
+
 	#[tokio::test]
 	async fn magic_bytes() {
 		async fn test_path(subpath: &str) -> Option<Extension> {
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use crate::extensions::{CodeExtension, Extension, VideoExtension};
+use crate::extensions::{
+	CodeExtension, Extension, ImageExtension, VideoExtension, ALL_ARCHIVE_EXTENSIONS,
+	ALL_AUDIO_EXTENSIONS, ALL_DATABASE_EXTENSIONS, ALL_DOCUMENT_EXTENSIONS,
+	ALL_ENCRYPTED_EXTENSIONS, ALL_EXECUTABLE_EXTENSIONS, ALL_FONT_EXTENSIONS, ALL_IMAGE_EXTENSIONS,
+	ALL_MESH_EXTENSIONS, ALL_VIDEO_EXTENSIONS,
+};
 use std::{ffi::OsStr, io::SeekFrom, path::Path};
 
 use tokio::{
@@ -54,7 +59,7 @@ macro_rules! extension_enum {
 		}
 	) => {
 		// construct enum
-		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, PartialEq, Eq)]
+		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 		pub enum Extension {
 			$( $variant($type), )*
 		}
@@ -105,7 +110,7 @@ macro_rules! extension_category_enum {
 			$($(#[$variant_attr:meta])* $variant:ident $(= $( [$($magic_bytes:tt),*] $(+ $offset:literal)? )|+ )? ,)*
 		}
 	) => {
-		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, ::strum::Display, Clone, Copy, PartialEq, Eq)]
+		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, ::strum::Display, Clone, Copy, PartialEq, Eq, Hash)]
 		#[serde(rename_all = "snake_case")]
 		#[strum(serialize_all = "snake_case")]
 		$(#[$enum_attr])*
@@ -228,3 +233,137 @@ impl Extension {
 		}
 	}
 }
+
+/// How much of the start of a file is read to check it against every extension's magic bytes, which is enough for the furthest of them.
+const MAGIC_BYTES_HEADER_LEN: u64 = 64;
+
+/// What a file's magic bytes say about its contents, compared to its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedType {
+	/// The extension the file's contents were verified as, which is `None` if they couldn't be (such as for text, which has no magic bytes).
+	pub verified_extension: Option<Extension>,
+	/// The MIME type of the verified extension, or of the file's extension if its contents couldn't be verified.
+	pub mime_type: Option<&'static str>,
+	/// Whether the file's magic bytes are for a different type of file than its extension.
+	pub extension_mismatch: bool,
+}
+
+/// `None` if the extension doesn't have magic bytes, otherwise whether the header has any of them.
+///
+/// Signatures shorter than `min_len` aren't used, as they'd match many files by chance.
+fn header_matches<T: MagicBytes>(ext: &T, header: &[u8], min_len: usize) -> Option<bool> {
+	let metas = ext
+		.magic_bytes_meta()
+		.into_iter()
+		.filter(|magic| magic.length >= min_len)
+		.collect::<Vec<_>>();
+
+	if metas.is_empty() {
+		return None;
+	}
+
+	Some(metas.iter().any(|magic| {
+		header
+			.get(magic.offset..magic.offset + magic.length)
+			.map_or(false, |buf| ext.has_magic_bytes(buf))
+	}))
+}
+
+/// The first extension in `exts` whose magic bytes are in the header.
+fn find_in_header<T: MagicBytes + Copy>(
+	exts: &[T],
+	header: &[u8],
+	into: fn(T) -> Extension,
+) -> Option<Extension> {
+	exts.iter()
+		.find(|ext| header_matches(*ext, header, 2) == Some(true))
+		.map(|ext| into(*ext))
+}
+
+impl Extension {
+	/// `None` if the extension doesn't have magic bytes, otherwise whether the start of a file has them.
+	pub fn matches_magic_bytes(&self, header: &[u8]) -> Option<bool> {
+		match self {
+			Self::Image(x) => header_matches(x, header, 1),
+			Self::Audio(x) => header_matches(x, header, 1),
+			Self::Video(x) => header_matches(x, header, 1),
+			Self::Archive(x) => header_matches(x, header, 1),
+			Self::Document(x) => header_matches(x, header, 1),
+			Self::Executable(x) => header_matches(x, header, 1),
+			Self::Font(x) => header_matches(x, header, 1),
+			Self::Encrypted(x) => header_matches(x, header, 1),
+			Self::Mesh(x) => header_matches(x, header, 1),
+			Self::Database(x) => header_matches(x, header, 1),
+			Self::Text(_) | Self::Key(_) | Self::Code(_) => None,
+		}
+	}
+
+	/// Finds an extension from the start of a file alone.
+	///
+	/// Many extensions share magic bytes (such as ZIP and the formats based on it), so the first match is returned, with
+	/// more common kinds of file checked first.
+	pub fn from_magic_bytes(header: &[u8]) -> Option<Extension> {
+		find_in_header(ALL_IMAGE_EXTENSIONS, header, Self::Image)
+			.or_else(|| find_in_header(ALL_AUDIO_EXTENSIONS, header, Self::Audio))
+			.or_else(|| find_in_header(ALL_VIDEO_EXTENSIONS, header, Self::Video))
+			.or_else(|| find_in_header(ALL_ARCHIVE_EXTENSIONS, header, Self::Archive))
+			.or_else(|| find_in_header(ALL_DOCUMENT_EXTENSIONS, header, Self::Document))
+			.or_else(|| find_in_header(ALL_EXECUTABLE_EXTENSIONS, header, Self::Executable))
+			.or_else(|| find_in_header(ALL_FONT_EXTENSIONS, header, Self::Font))
+			.or_else(|| find_in_header(ALL_MESH_EXTENSIONS, header, Self::Mesh))
+			.or_else(|| find_in_header(ALL_DATABASE_EXTENSIONS, header, Self::Database))
+			.or_else(|| find_in_header(ALL_ENCRYPTED_EXTENSIONS, header, Self::Encrypted))
+	}
+
+	/// Whether the extension is for a camera's RAW image which is stored as a TIFF file.
+	const fn is_tiff_based_raw(&self) -> bool {
+		use ImageExtension::*;
+		matches!(self, Self::Image(Raw | Dng | Cr2 | Dcr | Nwr | Nef | Arw))
+	}
+
+	/// Detects a file's type from its magic bytes, and whether its extension agrees with them.
+	pub async fn detect(path: impl AsRef<Path>) -> std::io::Result<DetectedType> {
+		let extension = Self::resolve_conflicting(&path, false).await;
+
+		let mut header = Vec::with_capacity(MAGIC_BYTES_HEADER_LEN as usize);
+		File::open(&path)
+			.await?
+			.take(MAGIC_BYTES_HEADER_LEN)
+			.read_to_end(&mut header)
+			.await?;
+
+		Ok(Self::detect_from_header(extension, &header))
+	}
+
+	/// Detects a file's type from the start of it, and whether its extension agrees.
+	pub fn detect_from_header(extension: Option<Extension>, header: &[u8]) -> DetectedType {
+		let matches = extension.map(|ext| (ext, ext.matches_magic_bytes(header)));
+		let (verified_extension, extension_mismatch) = match matches {
+			Some((ext, Some(true))) => (Some(ext), false),
+			// a mismatch is only flagged when we know what the file actually is, as the magic bytes of some formats vary
+			Some((ext, Some(false))) => match Self::from_magic_bytes(header) {
+				// these RAW formats are TIFF files, which often only have TIFF's magic bytes
+				Some(Self::Image(ImageExtension::Tiff)) if ext.is_tiff_based_raw() => {
+					(Some(ext), false)
+				}
+				detected => {
+					let mismatch = detected.map_or(false, |detected| {
+						detected.mime_type().is_none() || detected.mime_type() != ext.mime_type()
+					});
+					(detected, mismatch)
+				}
+			},
+			// without magic bytes for the extension, the contents can't be verified against it
+			Some((_, None)) => (None, false),
+			None => (Self::from_magic_bytes(header), false),
+		};
+
+		DetectedType {
+			verified_extension,
+			mime_type: verified_extension
+				.or(extension)
+				.and_then(|ext| ext.mime_type()),
+			extension_mismatch,
+		}
+	}
+}
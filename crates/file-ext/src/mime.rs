@@ -0,0 +1,209 @@
+use crate::extensions::{
+	ArchiveExtension, AudioExtension, CodeExtension, DatabaseExtension, DocumentExtension,
+	ExecutableExtension, Extension, FontExtension, ImageExtension, TextExtension, VideoExtension,
+};
+
+///
+/// References:
+/// https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types/Common_types
+/// https://www.iana.org/assignments/media-types/media-types.xhtml
+///
+impl Extension {
+	/// The MIME type of the file's contents, or `None` if there isn't a widely used one.
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		match self {
+			Self::Image(ext) => ext.mime_type(),
+			Self::Video(ext) => ext.mime_type(),
+			Self::Audio(ext) => ext.mime_type(),
+			Self::Document(ext) => ext.mime_type(),
+			Self::Archive(ext) => ext.mime_type(),
+			Self::Executable(ext) => ext.mime_type(),
+			Self::Text(ext) => ext.mime_type(),
+			Self::Code(ext) => ext.mime_type(),
+			Self::Font(ext) => ext.mime_type(),
+			Self::Database(ext) => ext.mime_type(),
+			Self::Encrypted(_) | Self::Key(_) | Self::Mesh(_) => None,
+		}
+	}
+}
+
+impl ImageExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use ImageExtension::*;
+		Some(match self {
+			Jpg | Jpeg => "image/jpeg",
+			Png => "image/png",
+			Apng => "image/apng",
+			Gif => "image/gif",
+			Bmp => "image/bmp",
+			Tiff => "image/tiff",
+			Webp => "image/webp",
+			Svg => "image/svg+xml",
+			Ico => "image/vnd.microsoft.icon",
+			Heic => "image/heic",
+			Heif => "image/heif",
+			Dng => "image/x-adobe-dng",
+			Cr2 => "image/x-canon-cr2",
+			Cr3 => "image/x-canon-cr3",
+			Nef => "image/x-nikon-nef",
+			Arw => "image/x-sony-arw",
+			Raf => "image/x-fuji-raf",
+			Orf => "image/x-olympus-orf",
+			Rw2 => "image/x-panasonic-rw2",
+			Raw | Akw | Dcr | Nwr => return None,
+		})
+	}
+}
+
+impl VideoExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use VideoExtension::*;
+		Some(match self {
+			Avi => "video/x-msvideo",
+			Qt | Mov => "video/quicktime",
+			Swf => "application/x-shockwave-flash",
+			Mjpeg => "video/x-motion-jpeg",
+			Ts | Mts | M2ts => "video/mp2t",
+			Mpeg | Mpg | Mpe | M2v | Vob => "video/mpeg",
+			Mxf => "application/mxf",
+			Flv => "video/x-flv",
+			Wm | Wmv => "video/x-ms-wmv",
+			Asf => "video/x-ms-asf",
+			_3gp => "video/3gpp",
+			Mp4 | M4v => "video/mp4",
+			F4v => "video/x-f4v",
+			Webm => "video/webm",
+			Mkv => "video/x-matroska",
+			Ogv => "video/ogg",
+			Hevc => "video/h265",
+			Wtv => return None,
+		})
+	}
+}
+
+impl AudioExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use AudioExtension::*;
+		Some(match self {
+			Mp3 | Mp2 => "audio/mpeg",
+			M4a => "audio/mp4",
+			Wav => "audio/wav",
+			Aiff | Aif => "audio/aiff",
+			Flac => "audio/flac",
+			Ogg | Oga => "audio/ogg",
+			Opus => "audio/opus",
+			Wma => "audio/x-ms-wma",
+			Amr => "audio/amr",
+			Aac | Adts => "audio/aac",
+			Wv => "audio/wavpack",
+			Caf => "audio/x-caf",
+			Voc | Tta | Loas | Aptx | Ast => return None,
+		})
+	}
+}
+
+impl DocumentExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use DocumentExtension::*;
+		Some(match self {
+			Pdf => "application/pdf",
+			Key => "application/vnd.apple.keynote",
+			Pages => "application/vnd.apple.pages",
+			Numbers => "application/vnd.apple.numbers",
+			Doc => "application/msword",
+			Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+			Xls => "application/vnd.ms-excel",
+			Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+			Ppt => "application/vnd.ms-powerpoint",
+			Pptx => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+			Odt => "application/vnd.oasis.opendocument.text",
+			Ods => "application/vnd.oasis.opendocument.spreadsheet",
+			Odp => "application/vnd.oasis.opendocument.presentation",
+			Ics => "text/calendar",
+			Hwp => "application/x-hwp",
+		})
+	}
+}
+
+impl ArchiveExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use ArchiveExtension::*;
+		Some(match self {
+			Zip => "application/zip",
+			Rar => "application/vnd.rar",
+			Tar => "application/x-tar",
+			Gz => "application/gzip",
+			Bz2 => "application/x-bzip2",
+			_7z => "application/x-7z-compressed",
+			Xz => "application/x-xz",
+		})
+	}
+}
+
+impl ExecutableExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use ExecutableExtension::*;
+		Some(match self {
+			Exe => "application/vnd.microsoft.portable-executable",
+			Apk => "application/vnd.android.package-archive",
+			Deb => "application/vnd.debian.binary-package",
+			Dmg => "application/x-apple-diskimage",
+			Rpm => "application/x-rpm",
+			Msi => "application/x-msi",
+			Jar => "application/java-archive",
+			Bat => "application/x-bat",
+			App | Pkg => return None,
+		})
+	}
+}
+
+impl TextExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use TextExtension::*;
+		Some(match self {
+			Txt | Cfg => "text/plain",
+			Rtf => "application/rtf",
+			Md => "text/markdown",
+			Json => "application/json",
+			Yaml | Yml => "application/yaml",
+			Toml => "application/toml",
+			Xml => "application/xml",
+			Csv => "text/csv",
+		})
+	}
+}
+
+impl CodeExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use CodeExtension::*;
+		Some(match self {
+			Html => "text/html",
+			Css => "text/css",
+			Js | Jsx => "text/javascript",
+			// source code is served as plain text, so it's shown rather than run
+			_ => "text/plain",
+		})
+	}
+}
+
+impl FontExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use FontExtension::*;
+		Some(match self {
+			Ttf => "font/ttf",
+			Otf => "font/otf",
+			Woff => "font/woff",
+			Woff2 => "font/woff2",
+		})
+	}
+}
+
+impl DatabaseExtension {
+	pub const fn mime_type(&self) -> Option<&'static str> {
+		use DatabaseExtension::*;
+		match self {
+			Sqlite => Some("application/vnd.sqlite3"),
+			Db => None,
+		}
+	}
+}
@@ -0,0 +1,348 @@
+//! Limits on how fast data is sent and received over unicast streams, so bulk transfers (such as Spacedrop) don't saturate the link.
+//!
+//! Each direction is limited by a token bucket shared by every peer, and by another bucket for each peer.
+//! A stream which goes over a limit is allowed to finish its current read or write, and then waits until the buckets are out of debt.
+
+use std::{
+	collections::HashMap,
+	future::Future,
+	pin::Pin,
+	sync::{
+		atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+		Arc, Mutex, MutexGuard, PoisonError, RwLock,
+	},
+	task::{ready, Context, Poll},
+	time::{Duration, Instant},
+};
+
+use tokio::time::Sleep;
+
+use crate::PeerId;
+
+/// How long a stream waits before trying again while a stream with a higher priority is waiting for the limit.
+const PRIORITY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// The most bytes which are sent by a single write, so a stream can't get too far ahead of its limit.
+pub(crate) const MAX_WRITE_LEN: usize = 64 * 1024;
+
+/// A limit on how many bytes are sent and received each second. `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+pub struct RateLimit {
+	pub upload: Option<u32>,
+	pub download: Option<u32>,
+}
+
+impl RateLimit {
+	fn get(&self, direction: Direction) -> Option<u32> {
+		match direction {
+			Direction::Upload => self.upload,
+			Direction::Download => self.download,
+		}
+	}
+}
+
+/// The bandwidth limits of the [`Manager`](crate::Manager).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+pub struct BandwidthLimits {
+	/// The limit for the traffic of all peers combined.
+	pub global: RateLimit,
+	/// The limit for the traffic of each peer.
+	pub per_peer: RateLimit,
+}
+
+/// How a stream's traffic is prioritised while the bandwidth is limited.
+/// A stream is held back whenever a stream with a higher priority is waiting for the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+	/// Small messages which other work is waiting on, such as sync operations.
+	High,
+	#[default]
+	Normal,
+	/// Large transfers, such as the blocks of a file.
+	Bulk,
+}
+
+/// The traffic with a peer, averaged over the time since it was last reported.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+pub struct PeerThroughput {
+	pub peer_id: PeerId,
+	/// The bytes sent to the peer each second.
+	pub upload: u64,
+	/// The bytes received from the peer each second.
+	pub download: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Direction {
+	Upload,
+	Download,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+	/// This goes negative when a read or write is larger than what's available, which the stream then waits out.
+	tokens: f64,
+	last_refill: Instant,
+}
+
+impl Default for TokenBucket {
+	fn default() -> Self {
+		Self {
+			tokens: 0.0,
+			last_refill: Instant::now(),
+		}
+	}
+}
+
+impl TokenBucket {
+	/// Adds the tokens for the time since the last refill. At most a second of tokens are kept, which limits how large a burst can be.
+	fn refill(&mut self, rate: f64, now: Instant) {
+		let elapsed = now
+			.saturating_duration_since(self.last_refill)
+			.as_secs_f64();
+		self.tokens = (self.tokens + elapsed * rate).min(rate);
+		self.last_refill = now;
+	}
+
+	/// How long until the bucket is out of debt.
+	fn delay(&mut self, rate: Option<u32>, now: Instant) -> Duration {
+		let Some(rate) = rate else {
+			return Duration::ZERO;
+		};
+
+		let rate = rate.max(1) as f64;
+		self.refill(rate, now);
+
+		if self.tokens >= 0.0 {
+			Duration::ZERO
+		} else {
+			Duration::from_secs_f64(-self.tokens / rate)
+		}
+	}
+
+	fn consume(&mut self, rate: Option<u32>, len: usize, now: Instant) {
+		if let Some(rate) = rate {
+			self.refill(rate.max(1) as f64, now);
+			self.tokens -= len as f64;
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct PeerBandwidth {
+	buckets: Mutex<[TokenBucket; 2]>,
+	/// The bytes transferred in each direction since the throughput was last sampled.
+	transferred: [AtomicU64; 2],
+	/// Whether there was any traffic when the throughput was last sampled.
+	active: AtomicBool,
+}
+
+/// The bandwidth limits and the state of them, which is shared by every stream.
+#[derive(Debug)]
+pub(crate) struct Bandwidth {
+	limits: RwLock<BandwidthLimits>,
+	global: Mutex<[TokenBucket; 2]>,
+	peers: Mutex<HashMap<PeerId, Arc<PeerBandwidth>>>,
+	/// How many streams of each priority are waiting for the limit, in each direction.
+	waiting: [[AtomicUsize; 3]; 2],
+	last_sample: Mutex<Instant>,
+}
+
+impl Bandwidth {
+	pub(crate) fn new() -> Self {
+		Self {
+			limits: Default::default(),
+			global: Default::default(),
+			peers: Default::default(),
+			waiting: Default::default(),
+			last_sample: Mutex::new(Instant::now()),
+		}
+	}
+
+	pub(crate) fn limits(&self) -> BandwidthLimits {
+		*self.limits.read().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	pub(crate) fn set_limits(&self, limits: BandwidthLimits) {
+		*self.limits.write().unwrap_or_else(PoisonError::into_inner) = limits;
+	}
+
+	pub(crate) fn limiter(self: &Arc<Self>, peer_id: PeerId) -> StreamLimiter {
+		let peer = lock(&self.peers).entry(peer_id).or_default().clone();
+
+		StreamLimiter {
+			bandwidth: self.clone(),
+			peer,
+			priority: Priority::default(),
+			sleep: Default::default(),
+			waiting: [false; 2],
+		}
+	}
+
+	/// How long a stream has to wait before it can transfer data in a direction.
+	fn delay(&self, peer: &PeerBandwidth, direction: Direction, priority: Priority) -> Duration {
+		if self.waiting[direction as usize][..priority as usize]
+			.iter()
+			.any(|waiting| waiting.load(Ordering::Relaxed) > 0)
+		{
+			return PRIORITY_BACKOFF;
+		}
+
+		let limits = self.limits();
+		let now = Instant::now();
+
+		let global =
+			lock(&self.global)[direction as usize].delay(limits.global.get(direction), now);
+		let peer =
+			lock(&peer.buckets)[direction as usize].delay(limits.per_peer.get(direction), now);
+
+		global.max(peer)
+	}
+
+	fn consume(&self, peer: &PeerBandwidth, direction: Direction, len: usize) {
+		let limits = self.limits();
+		let now = Instant::now();
+
+		lock(&self.global)[direction as usize].consume(limits.global.get(direction), len, now);
+		lock(&peer.buckets)[direction as usize].consume(limits.per_peer.get(direction), len, now);
+
+		peer.transferred[direction as usize].fetch_add(len as u64, Ordering::Relaxed);
+	}
+
+	/// The throughput of each peer since this was last called.
+	///
+	/// Idle peers are left out, except for the first time they're idle so their throughput can be shown as zero.
+	pub(crate) fn sample(&self) -> Vec<PeerThroughput> {
+		let now = Instant::now();
+		let elapsed = now
+			.saturating_duration_since(std::mem::replace(&mut lock(&self.last_sample), now))
+			.as_secs_f64()
+			.max(0.001);
+
+		let mut throughput = Vec::new();
+		lock(&self.peers).retain(|peer_id, peer| {
+			let upload = peer.transferred[Direction::Upload as usize].swap(0, Ordering::Relaxed);
+			let download =
+				peer.transferred[Direction::Download as usize].swap(0, Ordering::Relaxed);
+			let active = upload > 0 || download > 0;
+
+			if peer.active.swap(active, Ordering::Relaxed) || active {
+				throughput.push(PeerThroughput {
+					peer_id: *peer_id,
+					upload: (upload as f64 / elapsed) as u64,
+					download: (download as f64 / elapsed) as u64,
+				});
+			}
+
+			// peers without any open streams are forgotten until they open another one
+			active || Arc::strong_count(peer) > 1
+		});
+
+		throughput
+	}
+}
+
+/// Applies the bandwidth limits to a single stream.
+#[derive(Debug)]
+pub(crate) struct StreamLimiter {
+	bandwidth: Arc<Bandwidth>,
+	peer: Arc<PeerBandwidth>,
+	priority: Priority,
+	sleep: [Option<Pin<Box<Sleep>>>; 2],
+	waiting: [bool; 2],
+}
+
+impl StreamLimiter {
+	pub(crate) fn set_priority(&mut self, priority: Priority) {
+		// the streams waiting are counted by their priority, so we stop waiting until the next read or write
+		self.set_waiting(Direction::Upload, false);
+		self.set_waiting(Direction::Download, false);
+		self.priority = priority;
+	}
+
+	/// Waits until the stream is allowed to transfer data in a direction.
+	pub(crate) fn poll_ready(&mut self, cx: &mut Context<'_>, direction: Direction) -> Poll<()> {
+		loop {
+			if let Some(sleep) = &mut self.sleep[direction as usize] {
+				ready!(sleep.as_mut().poll(cx));
+				self.sleep[direction as usize] = None;
+			}
+
+			let delay = self.bandwidth.delay(&self.peer, direction, self.priority);
+			if delay.is_zero() {
+				self.set_waiting(direction, false);
+				return Poll::Ready(());
+			}
+
+			self.set_waiting(direction, true);
+			self.sleep[direction as usize] = Some(Box::pin(tokio::time::sleep(delay)));
+		}
+	}
+
+	/// Records the bytes that were transferred, which are taken from the limits.
+	pub(crate) fn record(&self, direction: Direction, len: usize) {
+		self.bandwidth.consume(&self.peer, direction, len);
+	}
+
+	fn set_waiting(&mut self, direction: Direction, waiting: bool) {
+		if self.waiting[direction as usize] == waiting {
+			return;
+		}
+
+		self.waiting[direction as usize] = waiting;
+		let count = &self.bandwidth.waiting[direction as usize][self.priority as usize];
+		if waiting {
+			count.fetch_add(1, Ordering::Relaxed);
+		} else {
+			count.fetch_sub(1, Ordering::Relaxed);
+		}
+	}
+}
+
+impl Drop for StreamLimiter {
+	fn drop(&mut self) {
+		self.set_waiting(Direction::Upload, false);
+		self.set_waiting(Direction::Download, false);
+	}
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_token_bucket() {
+		let start = Instant::now();
+		let mut bucket = TokenBucket {
+			tokens: 0.0,
+			last_refill: start,
+		};
+
+		assert_eq!(bucket.delay(None, start), Duration::ZERO);
+		assert_eq!(bucket.delay(Some(1000), start), Duration::ZERO);
+
+		bucket.consume(Some(1000), 500, start);
+		assert_eq!(bucket.delay(Some(1000), start), Duration::from_millis(500));
+		assert_eq!(
+			bucket.delay(Some(1000), start + Duration::from_millis(500)),
+			Duration::ZERO
+		);
+
+		// at most a second of tokens are kept
+		bucket.consume(Some(1000), 1500, start + Duration::from_secs(10));
+		assert_eq!(
+			bucket.delay(Some(1000), start + Duration::from_secs(10)),
+			Duration::from_millis(500)
+		);
+	}
+}
@@ -1,6 +1,8 @@
 use std::{net::SocketAddr, sync::Arc};
 
-use crate::{spacetime::SpaceTimeStream, ConnectedPeer, DiscoveredPeer, Manager, Metadata};
+use crate::{
+	spacetime::SpaceTimeStream, ConnectedPeer, DiscoveredPeer, Manager, Metadata, PeerThroughput,
+};
 
 use super::PeerId;
 
@@ -29,6 +31,8 @@ pub enum Event<TMetadata: Metadata> {
 	PeerConnected(ConnectedPeer),
 	/// communication was lost with a peer.
 	PeerDisconnected(PeerId),
+	/// the throughput of each peer which has been sending or receiving data, emitted every `THROUGHPUT_INTERVAL`.
+	Throughput { peers: Vec<PeerThroughput> },
 	/// the peer has opened a new substream
 	#[cfg_attr(any(feature = "serde", feature = "specta"), serde(skip))]
 	PeerMessage(PeerMessageEvent<TMetadata>),
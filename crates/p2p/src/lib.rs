@@ -1,5 +1,6 @@
 //! Rust Peer to Peer Networking Library
 
+mod bandwidth;
 mod event;
 mod manager;
 mod manager_stream;
@@ -10,6 +11,7 @@ pub mod spaceblock;
 pub mod spacetime;
 mod utils;
 
+pub use bandwidth::*;
 pub use event::*;
 pub use manager::*;
 pub use manager_stream::*;
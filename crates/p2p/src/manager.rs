@@ -11,8 +11,8 @@ use tracing::{debug, error, warn};
 
 use crate::{
 	spacetime::{SpaceTime, UnicastStream},
-	DiscoveredPeer, Keypair, ManagerStream, ManagerStreamAction, Mdns, MdnsState, Metadata,
-	MetadataManager, PeerId,
+	Bandwidth, BandwidthLimits, DiscoveredPeer, Keypair, ManagerStream, ManagerStreamAction, Mdns,
+	MdnsState, Metadata, MetadataManager, PeerId, REDIAL_INTERVAL, THROUGHPUT_INTERVAL,
 };
 
 /// Is the core component of the P2P system that holds the state and delegates actions to the other components
@@ -21,6 +21,7 @@ pub struct Manager<TMetadata: Metadata> {
 	pub(crate) mdns_state: Arc<MdnsState<TMetadata>>,
 	pub(crate) peer_id: PeerId,
 	pub(crate) application_name: &'static [u8],
+	pub(crate) bandwidth: Arc<Bandwidth>,
 	event_stream_tx: mpsc::Sender<ManagerStreamAction<TMetadata>>,
 }
 
@@ -52,6 +53,7 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 					.to_vec(),
 			)),
 			peer_id,
+			bandwidth: Arc::new(Bandwidth::new()),
 			event_stream_tx,
 		});
 
@@ -83,6 +85,9 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 				swarm,
 				mdns,
 				queued_events: Default::default(),
+				known_addrs: Default::default(),
+				redial_interval: tokio::time::interval(REDIAL_INTERVAL),
+				throughput_interval: tokio::time::interval(THROUGHPUT_INTERVAL),
 				shutdown: AtomicBool::new(false),
 			},
 		))
@@ -121,6 +126,26 @@ impl<TMetadata: Metadata> Manager<TMetadata> {
 		})
 	}
 
+	/// Connects to a peer at an address which can't be discovered using mDNS, such as one on another subnet or over a VPN.
+	/// The address is redialed whenever we aren't connected to it.
+	pub async fn add_known_peer(&self, addr: SocketAddr) {
+		self.emit(ManagerStreamAction::AddKnownPeer(addr)).await;
+	}
+
+	/// Stops redialing an address which was added with `add_known_peer`. We stay connected to the peer if we already are.
+	pub async fn remove_known_peer(&self, addr: SocketAddr) {
+		self.emit(ManagerStreamAction::RemoveKnownPeer(addr)).await;
+	}
+
+	pub fn bandwidth_limits(&self) -> BandwidthLimits {
+		self.bandwidth.limits()
+	}
+
+	/// Limits how fast data is sent and received over unicast streams. This applies to the streams which are already open too.
+	pub fn set_bandwidth_limits(&self, limits: BandwidthLimits) {
+		self.bandwidth.set_limits(limits);
+	}
+
 	pub async fn stream(&self, peer_id: PeerId) -> Result<UnicastStream, ()> {
 		// TODO: With this system you can send to any random peer id. Can I reduce that by requiring `.connect(peer_id).unwrap().send(data)` or something like that.
 		let (tx, rx) = oneshot::channel();
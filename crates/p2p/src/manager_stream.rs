@@ -1,14 +1,16 @@
 use std::{
-	collections::VecDeque,
+	collections::{HashMap, VecDeque},
 	fmt,
 	net::SocketAddr,
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
 	},
+	time::Duration,
 };
 
 use libp2p::{
+	core::ConnectedPoint,
 	futures::StreamExt,
 	swarm::{
 		dial_opts::{DialOpts, PeerCondition},
@@ -16,7 +18,10 @@ use libp2p::{
 	},
 	Swarm,
 };
-use tokio::sync::{mpsc, oneshot};
+use tokio::{
+	sync::{mpsc, oneshot},
+	time::Interval,
+};
 use tracing::{debug, error, info, warn};
 
 use crate::{
@@ -25,6 +30,12 @@ use crate::{
 	Event, Manager, Mdns, Metadata, PeerId,
 };
 
+/// How often known peer addresses that we aren't connected to are redialed.
+pub(crate) const REDIAL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the throughput of each peer is emitted as `Event::Throughput`.
+pub const THROUGHPUT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// TODO
 pub enum ManagerStreamAction<TMetadata: Metadata> {
 	/// Events are returned to the application via the `ManagerStream::next` method.
@@ -36,6 +47,10 @@ pub enum ManagerStreamAction<TMetadata: Metadata> {
 		peer_id: PeerId,
 		addresses: Vec<SocketAddr>,
 	},
+	/// Dial an address (without knowing the peer at it) and keep redialing it whenever it's not connected.
+	AddKnownPeer(SocketAddr),
+	/// Stop redialing an address which was added with `AddKnownPeer`.
+	RemoveKnownPeer(SocketAddr),
 	/// TODO
 	StartStream(PeerId, oneshot::Sender<UnicastStream>),
 	/// TODO
@@ -67,6 +82,10 @@ where
 	pub(crate) mdns: Mdns<TMetadata>,
 	pub(crate) queued_events: VecDeque<Event<TMetadata>>,
 	pub(crate) shutdown: AtomicBool,
+	/// The addresses added with `Manager::add_known_peer`, and the peer found at each once we've connected to it.
+	pub(crate) known_addrs: HashMap<SocketAddr, Option<libp2p::PeerId>>,
+	pub(crate) redial_interval: Interval,
+	pub(crate) throughput_interval: Interval,
 }
 
 impl<TMetadata> ManagerStream<TMetadata>
@@ -92,6 +111,24 @@ where
 					}
 					continue;
 				},
+				_ = self.redial_interval.tick() => {
+					let disconnected = self
+						.known_addrs
+						.iter()
+						.filter(|(_, peer_id)| !peer_id.map_or(false, |peer_id| self.swarm.is_connected(&peer_id)))
+						.map(|(addr, _)| *addr)
+						.collect::<Vec<_>>();
+
+					for addr in disconnected {
+						self.dial_known_addr(addr);
+					}
+				},
+				_ = self.throughput_interval.tick() => {
+					let peers = self.manager.bandwidth.sample();
+					if !peers.is_empty() {
+						return Some(Event::Throughput { peers });
+					}
+				},
 				event = self.event_stream_rx.recv() => {
 					// If the sender has shut down we return `None` to also shut down too.
 					if let Some(event) = self.handle_manager_stream_action(event?).await {
@@ -109,6 +146,13 @@ where
 								return Some(event);
 							}
 						},
+						SwarmEvent::ConnectionEstablished { peer_id, endpoint: ConnectedPoint::Dialer { address, .. }, .. } => {
+							if let Ok(addr) = quic_multiaddr_to_socketaddr(address) {
+								if let Some(known) = self.known_addrs.get_mut(&addr) {
+									*known = Some(peer_id);
+								}
+							}
+						},
 						SwarmEvent::ConnectionEstablished { .. } => {},
 						SwarmEvent::ConnectionClosed { .. } => {},
 						SwarmEvent::IncomingConnection { local_addr, .. } => debug!("incoming connection from '{}'", local_addr),
@@ -201,6 +245,15 @@ where
 					),
 				}
 			}
+			ManagerStreamAction::AddKnownPeer(addr) => {
+				let peer_id = *self.known_addrs.entry(addr).or_default();
+				if !peer_id.map_or(false, |peer_id| self.swarm.is_connected(&peer_id)) {
+					self.dial_known_addr(addr);
+				}
+			}
+			ManagerStreamAction::RemoveKnownPeer(addr) => {
+				self.known_addrs.remove(&addr);
+			}
 			ManagerStreamAction::StartStream(peer_id, rx) => {
 				self.swarm.behaviour_mut().pending_events.push_back(
 					NetworkBehaviourAction::NotifyHandler {
@@ -237,4 +290,14 @@ where
 
 		None
 	}
+	fn dial_known_addr(&mut self, addr: SocketAddr) {
+		match self.swarm.dial(
+			DialOpts::unknown_peer_id()
+				.address(socketaddr_to_quic_multiaddr(&addr))
+				.build(),
+		) {
+			Ok(_) => debug!("dialing known peer address '{}'", addr),
+			Err(err) => warn!("error dialing known peer address '{}': {}", addr, err),
+		}
+	}
 }
@@ -38,4 +38,6 @@ impl<TMetadata: Metadata> DiscoveredPeer<TMetadata> {
 pub struct ConnectedPeer {
 	/// get the peer id of the discovered peer
 	pub peer_id: PeerId,
+	/// get the address the peer was dialed at. This is `None` if the peer connected to us.
+	pub address: Option<SocketAddr>,
 }
@@ -0,0 +1,67 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{BlockSize, SpaceblockError};
+
+/// The length of a block's checksum (a BLAKE3 hash of its data).
+pub const CHECKSUM_LEN: usize = 32;
+
+/// A single block of a file, as it's sent over the wire.
+///
+/// Blocks are sent in order, and every block except the final one is exactly the transfer's `BlockSize`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block<'a> {
+	pub offset: u64,
+	pub checksum: [u8; CHECKSUM_LEN],
+	pub data: &'a [u8],
+}
+
+impl<'a> Block<'a> {
+	pub fn new(offset: u64, data: &'a [u8]) -> Self {
+		Self {
+			offset,
+			checksum: *blake3::hash(data).as_bytes(),
+			data,
+		}
+	}
+
+	/// Reads a block from the stream into `buf`, which should be able to hold an entire block.
+	///
+	/// The length of the block is checked against the `BlockSize` before anything is read into the buffer, so a peer can't make us allocate memory.
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+		block_size: BlockSize,
+		buf: &'a mut [u8],
+	) -> Result<Block<'a>, SpaceblockError> {
+		let offset = stream.read_u64_le().await?;
+		let size = stream.read_u32_le().await?;
+
+		if size > block_size.size() || size as usize > buf.len() {
+			return Err(SpaceblockError::InvalidBlockLength(size));
+		}
+
+		let mut checksum = [0u8; CHECKSUM_LEN];
+		stream.read_exact(&mut checksum).await?;
+
+		let data = &mut buf[..size as usize];
+		stream.read_exact(data).await?;
+
+		if blake3::hash(data).as_bytes() != &checksum {
+			return Err(SpaceblockError::ChecksumMismatch(offset));
+		}
+
+		Ok(Self {
+			offset,
+			checksum,
+			data,
+		})
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut buf = Vec::with_capacity(8 + 4 + CHECKSUM_LEN + self.data.len());
+		buf.extend_from_slice(&self.offset.to_le_bytes());
+		buf.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+		buf.extend_from_slice(&self.checksum);
+		buf.extend_from_slice(self.data);
+		buf
+	}
+}
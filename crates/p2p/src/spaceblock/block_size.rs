@@ -0,0 +1,59 @@
+use super::SpaceblockError;
+
+/// The smallest block size that will be used for a transfer (128 KiB).
+pub const MIN_BLOCK_SIZE: u32 = 128 * 1024;
+/// The largest block size that will be used for a transfer (16 MiB).
+pub const MAX_BLOCK_SIZE: u32 = 16 * 1024 * 1024;
+/// A file is split into fewer blocks than this, unless that would require blocks larger than `MAX_BLOCK_SIZE`.
+const DESIRED_MAX_BLOCKS: u64 = 2000;
+
+/// The size of each block within a transfer. This is always a power of 2 between `MIN_BLOCK_SIZE` and `MAX_BLOCK_SIZE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSize(u32);
+
+impl BlockSize {
+	/// Selects the block size for a file, as described in https://docs.syncthing.net/specs/bep-v1.html#selection-of-block-size
+	///
+	/// This is the smallest block size which results in fewer than 2000 blocks, up to a maximum of 16 MiB.
+	pub fn from_size(size: u64) -> Self {
+		let mut block_size = MIN_BLOCK_SIZE;
+		while block_size < MAX_BLOCK_SIZE && size / u64::from(block_size) >= DESIRED_MAX_BLOCKS {
+			block_size *= 2;
+		}
+
+		Self(block_size)
+	}
+
+	/// Validates a block size that was received from a peer.
+	pub fn from_u32(block_size: u32) -> Result<Self, SpaceblockError> {
+		if block_size.is_power_of_two() && (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+			Ok(Self(block_size))
+		} else {
+			Err(SpaceblockError::InvalidBlockSize(block_size))
+		}
+	}
+
+	pub fn size(&self) -> u32 {
+		self.0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_block_size_selection() {
+		assert_eq!(BlockSize::from_size(0).size(), MIN_BLOCK_SIZE);
+		assert_eq!(
+			BlockSize::from_size(249 * 1024 * 1024).size(),
+			MIN_BLOCK_SIZE
+		);
+		assert_eq!(BlockSize::from_size(251 * 1024 * 1024).size(), 256 * 1024);
+		assert_eq!(BlockSize::from_size(u64::MAX).size(), MAX_BLOCK_SIZE);
+
+		assert!(BlockSize::from_u32(MIN_BLOCK_SIZE).is_ok());
+		assert!(BlockSize::from_u32(MIN_BLOCK_SIZE + 1).is_err());
+		assert!(BlockSize::from_u32(MAX_BLOCK_SIZE * 2).is_err());
+	}
+}
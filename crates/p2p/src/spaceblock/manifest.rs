@@ -0,0 +1,149 @@
+use std::path::{Component, Path, PathBuf};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{SpaceblockError, TransferRequest};
+
+/// Everything that is sent as part of a single transfer.
+///
+/// Files are transferred one after another in the order they appear here, and directories are created before any files are received.
+/// Directories are only needed for empty directories, as the parents of each file are created anyway.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferManifest {
+	/// The relative paths of directories, using `/` as the separator. See [`relative_path`].
+	pub directories: Vec<String>,
+	pub files: Vec<TransferRequest>,
+}
+
+impl TransferManifest {
+	pub fn total_size(&self) -> u64 {
+		self.files.iter().map(|file| file.size).sum()
+	}
+
+	/// The first component of every path in the manifest (the files and directories that were selected by the sender), in order and without duplicates.
+	pub fn roots(&self) -> Vec<&str> {
+		let mut roots = Vec::new();
+		for name in self
+			.directories
+			.iter()
+			.chain(self.files.iter().map(|file| &file.name))
+		{
+			let root = name.split('/').next().unwrap_or(name);
+			if !roots.contains(&root) {
+				roots.push(root);
+			}
+		}
+		roots
+	}
+
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<Self, SpaceblockError> {
+		// The counts come from the peer, so nothing is preallocated from them
+		let mut directories = Vec::new();
+		for _ in 0..stream.read_u32_le().await? {
+			directories.push(read_name(stream).await?);
+		}
+
+		let mut files = Vec::new();
+		for _ in 0..stream.read_u32_le().await? {
+			files.push(TransferRequest::from_stream(stream).await?);
+		}
+
+		Ok(Self { directories, files })
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+
+		buf.extend_from_slice(&(self.directories.len() as u32).to_le_bytes());
+		for directory in &self.directories {
+			write_name(&mut buf, directory);
+		}
+
+		buf.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+		for file in &self.files {
+			buf.extend_from_slice(&file.to_bytes());
+		}
+
+		buf
+	}
+}
+
+/// Converts a name from a manifest into a path relative to wherever the transfer is being received.
+///
+/// Names come from the peer, so this rejects anything which could escape the destination (such as `..`, absolute paths or Windows drive prefixes).
+pub fn relative_path(name: &str) -> Result<PathBuf, SpaceblockError> {
+	let mut path = PathBuf::new();
+	for part in name.split('/') {
+		let mut components = Path::new(part).components();
+		match (components.next(), components.next()) {
+			(Some(Component::Normal(part)), None) => path.push(part),
+			_ => return Err(SpaceblockError::InvalidName),
+		}
+	}
+
+	Ok(path)
+}
+
+pub(super) async fn read_name(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<String, SpaceblockError> {
+	let len = stream.read_u16_le().await?;
+	let mut name = vec![0u8; len as usize];
+	stream.read_exact(&mut name).await?;
+
+	String::from_utf8(name).map_err(|_| SpaceblockError::InvalidName)
+}
+
+pub(super) fn write_name(buf: &mut Vec<u8>, name: &str) {
+	debug_assert!(name.len() <= usize::from(u16::MAX));
+
+	buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+	buf.extend_from_slice(name.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_transfer_manifest() {
+		let manifest = TransferManifest {
+			directories: vec!["photos".into(), "photos/empty".into()],
+			files: vec![
+				TransferRequest::new("photos/cat.jpg".into(), 1024).unwrap(),
+				TransferRequest::new("notes.txt".into(), 42).unwrap(),
+			],
+		};
+
+		let bytes = manifest.to_bytes();
+		let result = TransferManifest::from_stream(&mut Cursor::new(bytes))
+			.await
+			.unwrap();
+		assert_eq!(manifest, result);
+		assert_eq!(result.total_size(), 1066);
+		assert_eq!(result.roots(), vec!["photos", "notes.txt"]);
+	}
+
+	#[test]
+	fn test_relative_path() {
+		assert_eq!(
+			relative_path("photos/cat.jpg").unwrap(),
+			Path::new("photos").join("cat.jpg")
+		);
+
+		for name in [
+			"",
+			"/etc/passwd",
+			"../secret",
+			"photos/../../secret",
+			"photos//cat.jpg",
+			".",
+		] {
+			assert!(relative_path(name).is_err(), "'{name}' should be rejected");
+		}
+	}
+}
@@ -1,91 +1,318 @@
 //! Spaceblock is a file transfer protocol that uses a block based system to transfer files.
 //! This protocol is modelled after SyncThing's BEP protocol. A huge thanks to it's original authors!
 //! You can read more about it here: https://docs.syncthing.net/specs/bep-v1.html
-#![allow(unused)] // TODO: This module is still in heavy development!
+//!
+//! A transfer looks like this:
+//!  - The sender sends a [`TransferManifest`] describing every file and directory (this is normally done by the application as part of its own header).
+//!  - Each file in the manifest is then transferred in order, over the same stream. For each file:
+//!    - The receiver responds with the offset it would like to start from. This is zero, unless it's resuming a partially received file.
+//!    - The sender sends each [`Block`] from that offset onwards, each prefixed by [`BLOCK_FRAME`]. A transfer may be cancelled by sending [`CANCEL_FRAME`] instead.
+//!
+//! All integers are little endian.
 
-use std::{
-	marker::PhantomData,
-	path::{Path, PathBuf},
-};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use tokio::io::AsyncReadExt;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
-use crate::spacetime::{SpaceTimeStream, UnicastStream};
+mod block;
+mod block_size;
+mod manifest;
 
-/// TODO
-#[derive(Debug, PartialEq, Eq)]
-pub struct BlockSize(i64);
+pub use block::*;
+pub use block_size::*;
+pub use manifest::*;
 
-impl BlockSize {
-	// TODO: Validating `BlockSize` are multiple of 2, i think
+/// Precedes each block of a transfer.
+pub const BLOCK_FRAME: u8 = 0;
+/// Sent by the sender (instead of the next block) when it cancels the transfer.
+pub const CANCEL_FRAME: u8 = 1;
 
-	pub fn from_size(size: u64) -> Self {
-		// TODO: Something like: https://docs.syncthing.net/specs/bep-v1.html#selection-of-block-size
-		Self(131072) // 128 KiB
-	}
+#[derive(Debug, Error)]
+pub enum SpaceblockError {
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("the file name is not valid UTF-8, or is not a relative path")]
+	InvalidName,
+	#[error("the file name is longer than {} bytes", u16::MAX)]
+	NameTooLong,
+	#[error("invalid block size '{0}'")]
+	InvalidBlockSize(u32),
+	#[error("invalid block length '{0}'")]
+	InvalidBlockLength(u32),
+	#[error("invalid frame discriminator '{0}'")]
+	InvalidFrame(u8),
+	#[error("received block at offset '{received}' while expecting offset '{expected}'")]
+	UnexpectedBlock { expected: u64, received: u64 },
+	#[error("block at offset '{0}' failed its checksum")]
+	ChecksumMismatch(u64),
+	#[error("the transfer was cancelled")]
+	Cancelled,
+	#[error("unable to open a stream to the peer, it may be offline or unknown")]
+	Stream,
 }
 
-/// TODO
-#[derive(Debug, PartialEq, Eq)]
+/// The metadata of a file that is about to be transferred.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TransferRequest {
+	/// The path of the file relative to the root of the transfer, using `/` as the separator. See [`relative_path`].
 	pub name: String,
 	pub size: u64,
-	// TODO: Include file permissions
 	pub block_size: BlockSize,
+	/// The modification time of the file, in seconds since the UNIX epoch.
+	pub modified: Option<u64>,
+	/// The Unix permission bits of the file. These aren't sent by platforms which don't have them.
+	pub permissions: Option<u32>,
 }
 
 impl TransferRequest {
-	pub async fn from_stream(stream: &mut UnicastStream) -> Result<Self, ()> {
-		let name_len = stream.read_u8().await.map_err(|_| ())?; // TODO: Error handling
-		let mut name = vec![0u8; name_len as usize];
-		stream.read_exact(&mut name).await.map_err(|_| ())?; // TODO: Error handling
-		let name = String::from_utf8(name).map_err(|_| ())?; // TODO: Error handling
+	/// The name is limited to `u16::MAX` bytes, so it can be length-prefixed.
+	pub fn new(name: String, size: u64) -> Result<Self, SpaceblockError> {
+		if name.len() > usize::from(u16::MAX) {
+			return Err(SpaceblockError::NameTooLong);
+		}
+
+		Ok(Self {
+			name,
+			size,
+			block_size: BlockSize::from_size(size),
+			modified: None,
+			permissions: None,
+		})
+	}
+
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<Self, SpaceblockError> {
+		let name = read_name(stream).await?;
+		let size = stream.read_u64_le().await?;
+		let block_size = BlockSize::from_u32(stream.read_u32_le().await?)?;
 
-		let size = stream.read_u8().await.map_err(|_| ())? as u64; // TODO: Error handling
-		let block_size = BlockSize::from_size(size); // TODO: Get from stream: stream.read_u8().await.map_err(|_| ())?; // TODO: Error handling
+		let modified = match stream.read_u8().await? {
+			0 => None,
+			_ => Some(stream.read_u64_le().await?),
+		};
+		let permissions = match stream.read_u8().await? {
+			0 => None,
+			_ => Some(stream.read_u32_le().await?),
+		};
 
 		Ok(Self {
 			name,
 			size,
 			block_size,
+			modified,
+			permissions,
 		})
 	}
 
 	pub fn to_bytes(&self) -> Vec<u8> {
-		let mut buf = Vec::new();
-		buf.push(self.name.len() as u8); // TODO: This being a `u8` isn't going to scale to a name bigger than 255 bytes lmao
-		buf.extend(self.name.as_bytes());
-		buf.push(self.size as u8); // TODO: This being a `u8` isn't going to scale to files bigger than 255 bytes lmao
-						   // buf.push(&self.block_size.to_be_bytes()); // TODO: Do this as well
+		let mut buf = Vec::with_capacity(2 + self.name.len() + 8 + 4 + 9 + 5);
+		write_name(&mut buf, &self.name);
+		buf.extend_from_slice(&self.size.to_le_bytes());
+		buf.extend_from_slice(&self.block_size.size().to_le_bytes());
+
+		match self.modified {
+			Some(modified) => {
+				buf.push(1);
+				buf.extend_from_slice(&modified.to_le_bytes());
+			}
+			None => buf.push(0),
+		}
+		match self.permissions {
+			Some(permissions) => {
+				buf.push(1);
+				buf.extend_from_slice(&permissions.to_le_bytes());
+			}
+			None => buf.push(0),
+		}
+
 		buf
 	}
-}
 
-/// TODO
-pub struct Block<'a> {
-	// TODO: File content, checksum, source location so it can be resent!
-	pub offset: i64,
-	pub size: i64,
-	pub data: &'a [u8],
-	// TODO: Checksum?
+	/// The offset that a receiver should resume from, given how much of the file it already has.
+	///
+	/// This is rounded down to a block boundary, as a partially written block can't be trusted.
+	pub fn resume_offset(&self, received: u64) -> u64 {
+		let block_size = u64::from(self.block_size.size());
+		received.min(self.size) / block_size * block_size
+	}
 }
 
-/// TODO
-pub struct Transfer<'a> {
-	// buf: &'a mut [u8],
-	phantom: PhantomData<&'a ()>,
+/// A transfer of a single file. The same type is used by both the sender and the receiver.
+///
+/// Only a single block is held in memory at a time, regardless of the size of the file.
+pub struct Transfer<'a, F> {
+	req: &'a TransferRequest,
+	on_progress: F,
+	cancelled: &'a AtomicBool,
 }
 
-impl<'a> Transfer<'a> {
-	// TODO: Allow the user to cancel a tranfer
-	// TODO: Handle if the stream is dropped
+impl<'a, F> Transfer<'a, F>
+where
+	F: Fn(u64),
+{
+	/// `on_progress` is called with the total amount of bytes transferred after each block.
+	/// The transfer is stopped at the next block after `cancelled` is set.
+	pub fn new(req: &'a TransferRequest, on_progress: F, cancelled: &'a AtomicBool) -> Self {
+		Self {
+			req,
+			on_progress,
+			cancelled,
+		}
+	}
 
-	pub fn from_file(path: impl AsRef<Path>) -> Self {
-		// let size = std::fs::metadata(path.as_ref()).unwrap().len();
+	/// Sends the file, starting at whichever offset the receiver asks for.
+	pub async fn send(
+		&self,
+		stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+		mut file: impl AsyncRead + AsyncSeek + Unpin,
+	) -> Result<(), SpaceblockError> {
+		let mut offset = stream.read_u64_le().await?;
+		if offset > self.req.size || offset % u64::from(self.req.block_size.size()) != 0 {
+			return Err(SpaceblockError::UnexpectedBlock {
+				expected: self.req.resume_offset(offset),
+				received: offset,
+			});
+		}
+		file.seek(std::io::SeekFrom::Start(offset)).await?;
 
-		Self {
-			// buf: &mut Vec::with_capacity(42069),
-			phantom: PhantomData,
+		let mut buf = vec![0u8; self.req.block_size.size() as usize];
+		while offset < self.req.size {
+			if self.cancelled.load(Ordering::Relaxed) {
+				stream.write_u8(CANCEL_FRAME).await?;
+				stream.flush().await?;
+				return Err(SpaceblockError::Cancelled);
+			}
+
+			let len = (self.req.size - offset).min(buf.len() as u64) as usize;
+			file.read_exact(&mut buf[..len]).await?;
+
+			stream.write_u8(BLOCK_FRAME).await?;
+			stream
+				.write_all(&Block::new(offset, &buf[..len]).to_bytes())
+				.await?;
+
+			offset += len as u64;
+			(self.on_progress)(offset);
 		}
+
+		stream.flush().await?;
+
+		Ok(())
+	}
+
+	/// Receives the file into `file`, which already contains `received` bytes of it (from a previous attempt).
+	///
+	/// Blocks are only written once their checksum has been verified, so if this fails the file can be passed to a later attempt to resume it.
+	/// The caller should truncate the file to `TransferRequest::size` once this returns successfully.
+	pub async fn receive(
+		&self,
+		stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+		mut file: impl AsyncWrite + AsyncSeek + Unpin,
+		received: u64,
+	) -> Result<(), SpaceblockError> {
+		let mut offset = self.req.resume_offset(received);
+		file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+		stream.write_u64_le(offset).await?;
+		stream.flush().await?;
+		(self.on_progress)(offset);
+
+		let mut buf = vec![0u8; self.req.block_size.size() as usize];
+		while offset < self.req.size {
+			if self.cancelled.load(Ordering::Relaxed) {
+				// dropping the stream is enough to stop the sender
+				return Err(SpaceblockError::Cancelled);
+			}
+
+			match stream.read_u8().await? {
+				BLOCK_FRAME => {}
+				CANCEL_FRAME => return Err(SpaceblockError::Cancelled),
+				frame => return Err(SpaceblockError::InvalidFrame(frame)),
+			}
+
+			let expected_len = (self.req.size - offset).min(buf.len() as u64);
+			let block = Block::from_stream(stream, self.req.block_size, &mut buf).await?;
+			if block.offset != offset || block.data.len() as u64 != expected_len {
+				return Err(SpaceblockError::UnexpectedBlock {
+					expected: offset,
+					received: block.offset,
+				});
+			}
+
+			file.write_all(block.data).await?;
+
+			offset += expected_len;
+			(self.on_progress)(offset);
+		}
+
+		file.flush().await?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use tokio::io::duplex;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_transfer_request() {
+		let mut req = TransferRequest::new("Demo File.txt".into(), 42069).unwrap();
+
+		let bytes = req.to_bytes();
+		let result = TransferRequest::from_stream(&mut Cursor::new(bytes))
+			.await
+			.unwrap();
+		assert_eq!(req, result);
+
+		req.modified = Some(1678060800);
+		req.permissions = Some(0o644);
+
+		let bytes = req.to_bytes();
+		let result = TransferRequest::from_stream(&mut Cursor::new(bytes))
+			.await
+			.unwrap();
+		assert_eq!(req, result);
+	}
+
+	#[tokio::test]
+	async fn test_resumed_transfer() {
+		let data = (0..(MIN_BLOCK_SIZE as usize * 3 + 42))
+			.map(|i| i as u8)
+			.collect::<Vec<_>>();
+		let req = TransferRequest::new("demo.bin".into(), data.len() as u64).unwrap();
+
+		// the receiver already has the first block and a half
+		let mut received = data[..MIN_BLOCK_SIZE as usize + 100].to_vec();
+		received[MIN_BLOCK_SIZE as usize + 50] = 0xFF;
+		let received_len = received.len() as u64;
+
+		let (mut client, mut server) = duplex(64 * 1024);
+		let cancelled = AtomicBool::new(false);
+
+		let sender = async {
+			Transfer::new(&req, |_| {}, &cancelled)
+				.send(&mut client, Cursor::new(&data))
+				.await
+		};
+
+		let mut file = Cursor::new(&mut received);
+		let receiver = async {
+			Transfer::new(&req, |_| {}, &cancelled)
+				.receive(&mut server, &mut file, received_len)
+				.await
+		};
+
+		let (sent, recv) = tokio::join!(sender, receiver);
+		sent.unwrap();
+		recv.unwrap();
+
+		assert_eq!(received, data);
 	}
 }
@@ -16,7 +16,10 @@ use libp2p::{
 use thiserror::Error;
 use tracing::debug;
 
-use crate::{ConnectedPeer, Event, Manager, ManagerStreamAction, Metadata, PeerId};
+use crate::{
+	quic_multiaddr_to_socketaddr, ConnectedPeer, Event, Manager, ManagerStreamAction, Metadata,
+	PeerId,
+};
 
 use super::SpaceTimeConnection;
 
@@ -120,6 +123,9 @@ impl<TMetadata: Metadata> NetworkBehaviour for SpaceTime<TMetadata> {
 							.push_back(NetworkBehaviourAction::GenerateEvent(
 								ManagerStreamAction::Event(Event::PeerConnected(ConnectedPeer {
 									peer_id,
+									address: address.and_then(|address| {
+										quic_multiaddr_to_socketaddr(address).ok()
+									}),
 								})),
 							));
 					}
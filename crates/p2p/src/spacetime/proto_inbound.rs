@@ -33,7 +33,9 @@ impl<TMetadata: Metadata> InboundUpgrade<NegotiatedSubstream> for InboundProtoco
 				"stream({}, {id}): accepting inbound connection",
 				self.peer_id
 			);
-			let stream = SpaceTimeStream::from_stream(io).await;
+			let stream =
+				SpaceTimeStream::from_stream(io, self.manager.bandwidth.limiter(self.peer_id))
+					.await;
 			debug!(
 				"stream({}, {id}): stream of type {} accepted",
 				self.peer_id,
@@ -12,6 +12,8 @@ use libp2p::{
 use tokio::sync::oneshot;
 use tracing::error;
 
+use crate::StreamLimiter;
+
 use super::{SpaceTimeProtocolName, UnicastStream, BROADCAST_DISCRIMINATOR};
 
 #[derive(Debug)]
@@ -20,7 +22,11 @@ pub enum OutboundRequest {
 	Unicast(oneshot::Sender<UnicastStream>),
 }
 
-pub struct OutboundProtocol(pub(crate) &'static [u8], pub(crate) OutboundRequest);
+pub struct OutboundProtocol(
+	pub(crate) &'static [u8],
+	pub(crate) OutboundRequest,
+	pub(crate) StreamLimiter,
+);
 
 impl UpgradeInfo for OutboundProtocol {
 	type Info = SpaceTimeProtocolName;
@@ -62,7 +68,7 @@ impl OutboundUpgrade<NegotiatedSubstream> for OutboundProtocol {
 			}
 			OutboundRequest::Unicast(sender) => {
 				// We write the discriminator to the stream in the `Manager::stream` method before returning the stream to the user to make async a tad nicer.
-				sender.send(UnicastStream::new(io)).unwrap();
+				sender.send(UnicastStream::new(io, self.2)).unwrap();
 			}
 		}
 
@@ -1,7 +1,7 @@
 use std::{
 	io::{self, ErrorKind},
 	pin::Pin,
-	task::{Context, Poll},
+	task::{ready, Context, Poll},
 };
 
 use libp2p::{futures::AsyncWriteExt, swarm::NegotiatedSubstream};
@@ -11,6 +11,8 @@ use tokio::io::{
 use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt};
 use tracing::error;
 
+use crate::{Direction, Priority, StreamLimiter, MAX_WRITE_LEN};
+
 pub const BROADCAST_DISCRIMINATOR: u8 = 0;
 pub const UNICAST_DISCRIMINATOR: u8 = 1;
 
@@ -21,12 +23,12 @@ pub enum SpaceTimeStream {
 }
 
 impl SpaceTimeStream {
-	pub(crate) async fn from_stream(io: NegotiatedSubstream) -> Self {
+	pub(crate) async fn from_stream(io: NegotiatedSubstream, limiter: StreamLimiter) -> Self {
 		let mut io = io.compat();
 		let discriminator = io.read_u8().await.unwrap(); // TODO: Timeout on this
 		match discriminator {
 			BROADCAST_DISCRIMINATOR => Self::Broadcast(BroadcastStream(Some(io))),
-			UNICAST_DISCRIMINATOR => Self::Unicast(UnicastStream(io)),
+			UNICAST_DISCRIMINATOR => Self::Unicast(UnicastStream { io, limiter }),
 			_ => todo!(), // TODO: Error handling
 		}
 	}
@@ -50,7 +52,7 @@ impl SpaceTimeStream {
 					Ok(())
 				}
 			}
-			Self::Unicast(stream) => stream.0.into_inner().close().await,
+			Self::Unicast(stream) => stream.close().await,
 		}
 	}
 }
@@ -108,23 +110,35 @@ impl Drop for BroadcastStream {
 }
 
 /// A unicast stream is a direct stream to a specific peer.
+/// Reads and writes are limited by the [`BandwidthLimits`](crate::BandwidthLimits) of the [`Manager`](crate::Manager).
 #[derive(Debug)]
-pub struct UnicastStream(Compat<NegotiatedSubstream>);
+pub struct UnicastStream {
+	io: Compat<NegotiatedSubstream>,
+	limiter: StreamLimiter,
+}
 
 // TODO: Utils for sending msgpack and stuff over the stream. -> Have a max size of reading buffers so we are less susceptible to DoS attacks.
 
 impl UnicastStream {
-	pub(crate) fn new(io: NegotiatedSubstream) -> Self {
-		Self(io.compat())
+	pub(crate) fn new(io: NegotiatedSubstream, limiter: StreamLimiter) -> Self {
+		Self {
+			io: io.compat(),
+			limiter,
+		}
 	}
 
 	pub(crate) async fn write_discriminator(&mut self) -> io::Result<()> {
 		// TODO: Timeout if the peer doesn't accept the byte quick enough
-		self.0.write_all(&[UNICAST_DISCRIMINATOR]).await
+		self.io.write_all(&[UNICAST_DISCRIMINATOR]).await
+	}
+
+	/// Sets how the stream's traffic is prioritised against other streams while the bandwidth is limited. This is [`Priority::Normal`] by default.
+	pub fn set_priority(&mut self, priority: Priority) {
+		self.limiter.set_priority(priority);
 	}
 
 	pub async fn close(self) -> Result<(), io::Error> {
-		self.0.into_inner().close().await
+		self.io.into_inner().close().await
 	}
 }
 
@@ -134,7 +148,15 @@ impl AsyncRead for UnicastStream {
 		cx: &mut Context<'_>,
 		buf: &mut ReadBuf<'_>,
 	) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+		let this = self.get_mut();
+		ready!(this.limiter.poll_ready(cx, Direction::Download));
+
+		let filled = buf.filled().len();
+		ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+		this.limiter
+			.record(Direction::Download, buf.filled().len() - filled);
+
+		Poll::Ready(Ok(()))
 	}
 }
 
@@ -144,14 +166,21 @@ impl AsyncWrite for UnicastStream {
 		cx: &mut Context<'_>,
 		buf: &[u8],
 	) -> Poll<io::Result<usize>> {
-		Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+		let this = self.get_mut();
+		ready!(this.limiter.poll_ready(cx, Direction::Upload));
+
+		let len =
+			ready!(Pin::new(&mut this.io).poll_write(cx, &buf[..buf.len().min(MAX_WRITE_LEN)]))?;
+		this.limiter.record(Direction::Upload, len);
+
+		Poll::Ready(Ok(len))
 	}
 
 	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().0).poll_flush(cx)
+		Pin::new(&mut self.get_mut().io).poll_flush(cx)
 	}
 
 	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+		Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
 	}
 }
@@ -117,7 +117,11 @@ export default (props: PropsWithChildren) => {
 				<CM.Item
 					onClick={() =>
 						store.locationId &&
-						generateThumbsForLocation.mutate({ id: store.locationId, path: '' })
+						generateThumbsForLocation.mutate({
+							id: store.locationId,
+							path: '',
+							video_previews: true
+						})
 					}
 					label="Regen Thumbnails"
 					icon={Image}
@@ -1,7 +1,7 @@
 import { useState } from 'react';
 import { useLibraryMutation } from '@sd/client';
 import { Dialog, Slider, UseDialogProps, useDialog } from '@sd/ui';
-import { useZodForm, z } from '@sd/ui/src/forms';
+import { CheckBox, useZodForm, z } from '@sd/ui/src/forms';
 
 interface Props extends UseDialogProps {
 	location_id: number;
@@ -9,7 +9,9 @@ interface Props extends UseDialogProps {
 }
 
 const schema = z.object({
-	passes: z.number()
+	passes: z.number(),
+	includeCopies: z.boolean(),
+	verify: z.boolean()
 });
 
 export default (props: Props) => {
@@ -20,7 +22,9 @@ export default (props: Props) => {
 	const form = useZodForm({
 		schema,
 		defaultValues: {
-			passes: 4
+			passes: 4,
+			includeCopies: false,
+			verify: true
 		}
 	});
 
@@ -28,7 +32,9 @@ export default (props: Props) => {
 		eraseFile.mutateAsync({
 			location_id: props.location_id,
 			path_id: props.path_id,
-			passes: data.passes.toString()
+			passes: data.passes.toString(),
+			include_copies: data.includeCopies,
+			verify: data.verify
 		})
 	);
 
@@ -65,7 +71,16 @@ export default (props: Props) => {
 				</div>
 			</div>
 
-			<p>TODO: checkbox for "erase all matching files" (only if a file is selected)</p>
+			<div className="mt-4 mb-3 grid w-full grid-cols-2 gap-4">
+				<div className="flex">
+					<span className="mr-3 ml-0.5 mt-0.5 text-sm font-bold">Erase copies</span>
+					<CheckBox {...form.register('includeCopies')} />
+				</div>
+				<div className="flex">
+					<span className="mr-3 ml-0.5 mt-0.5 text-sm font-bold">Verify</span>
+					<CheckBox {...form.register('verify')} />
+				</div>
+			</div>
 		</Dialog>
 	);
 };
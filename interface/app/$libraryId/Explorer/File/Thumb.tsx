@@ -15,7 +15,7 @@ interface Props {
 }
 
 export default function Thumb({ data, size, className, loadOriginal }: Props) {
-	const { cas_id, isDir, kind, hasThumbnail, extension } = getExplorerItemData(data);
+	const { cas_id, isDir, kind, hasThumbnail, extension, dominantColor } = getExplorerItemData(data);
 	const store = useExplorerStore();
 	const platform = usePlatform();
 	const { library } = useLibraryContext();
@@ -71,7 +71,12 @@ export default function Thumb({ data, size, className, loadOriginal }: Props) {
 			)}
 		>
 			<img
-				style={{ ...imgStyle, maxWidth: size, width: size - 10 }}
+				style={{
+					...imgStyle,
+					maxWidth: size,
+					width: size - 10,
+					backgroundColor: dominantColor ?? undefined
+				}}
 				decoding="async"
 				className={clsx(
 					'z-90 pointer-events-none',
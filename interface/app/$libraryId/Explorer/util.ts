@@ -9,7 +9,8 @@ export function getExplorerItemData(data: ExplorerItem) {
 		isDir: isPath(data) && data.item.is_dir,
 		kind: ObjectKind[objectData?.kind || 0] || null,
 		hasThumbnail: data.has_thumbnail,
-		extension: filePath?.extension || null
+		extension: filePath?.extension || null,
+		dominantColor: objectData?.dominant_color || null
 	};
 }
 
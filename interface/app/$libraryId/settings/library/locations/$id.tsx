@@ -24,7 +24,8 @@ const schema = z.object({
 	hidden: z.boolean(),
 	indexerRulesIds: z.array(z.number()),
 	syncPreviewMedia: z.boolean(),
-	generatePreviewMedia: z.boolean()
+	generatePreviewMedia: z.boolean(),
+	encryptAtRest: z.boolean()
 });
 
 export const Component = () => {
@@ -78,21 +79,30 @@ export const Component = () => {
 					hidden: data.hidden,
 					indexerRulesIds: data.indexer_rules.map((i) => i.indexer_rule.id),
 					syncPreviewMedia: data.sync_preview_media,
-					generatePreviewMedia: data.generate_preview_media
+					generatePreviewMedia: data.generate_preview_media,
+					encryptAtRest: data.encrypt_at_rest
 				});
 			}
 		}
 	});
 
 	const onSubmit = form.handleSubmit(
-		({ name, hidden, indexerRulesIds, syncPreviewMedia, generatePreviewMedia }) =>
+		({
+			name,
+			hidden,
+			indexerRulesIds,
+			syncPreviewMedia,
+			generatePreviewMedia,
+			encryptAtRest
+		}) =>
 			updateLocation.mutateAsync({
 				id: locationId,
 				name,
 				hidden,
 				indexer_rules_ids: indexerRulesIds,
 				sync_preview_media: syncPreviewMedia,
-				generate_preview_media: generatePreviewMedia
+				generate_preview_media: generatePreviewMedia,
+				encrypt_at_rest: encryptAtRest
 			})
 	);
 
@@ -160,6 +170,15 @@ export const Component = () => {
 						</Label>
 						<Switch {...form.register('hidden')} size="sm" />
 					</ToggleSection>
+					<ToggleSection>
+						<Label className="grow">
+							Encrypt files in this Location at rest{' '}
+							<Tooltip label="Files are encrypted with your default key as they're added, and decrypted when they're viewed or copied out of the Location.">
+								<Info className="inline" />
+							</Tooltip>
+						</Label>
+						<Switch {...form.register('encryptAtRest')} size="sm" />
+					</ToggleSection>
 				</div>
 				<Divider />
 				<div className="flex flex-col">
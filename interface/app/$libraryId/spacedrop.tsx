@@ -2,8 +2,21 @@ import { GoogleDrive, Mega, iCloud } from '@sd/assets/images';
 import clsx from 'clsx';
 import { DeviceMobile, HardDrives, Icon, Laptop, User } from 'phosphor-react';
 import { useRef, useState } from 'react';
-import { Button, ScreenHeading, Select, SelectOption, forms, tw } from '@sd/ui';
-import { PeerMetadata, useBridgeMutation, useBridgeSubscription } from '~/../packages/client/src';
+import {
+	Button,
+	Input as TextInput,
+	ScreenHeading,
+	Select,
+	SelectOption,
+	forms,
+	tw
+} from '@sd/ui';
+import {
+	P2PEvent,
+	PeerMetadata,
+	useBridgeMutation,
+	useBridgeSubscription
+} from '~/../packages/client/src';
 import { SubtleButton, SubtleButtonContainer } from '~/components/SubtleButton';
 import { OperatingSystem } from '~/util/Platform';
 import SearchBar from './Explorer/SearchBar';
@@ -92,10 +105,26 @@ const schema = z.object({
 	file_path: z.string()
 });
 
+type SpacedropRequest = Extract<P2PEvent, { type: 'SpacedropRequest' }>;
+
+function formatBytes(bytes: string) {
+	return `${(Number(bytes) / 1024 / 1024).toFixed(2)} MB`;
+}
+
 // TODO: This will be removed and properly hooked up to the UI in the future
 function TemporarySpacedropDemo() {
 	const [[discoveredPeers], setDiscoveredPeer] = useState([new Map<string, PeerMetadata>()]);
+	const [requests, setRequests] = useState<SpacedropRequest[]>([]);
+	const [transfers, setTransfers] = useState<Record<string, string>>({});
+	const [destination, setDestination] = useState('');
 	const doSpacedrop = useBridgeMutation('p2p.spacedrop');
+	const acceptSpacedrop = useBridgeMutation('p2p.acceptSpacedrop');
+	const rejectSpacedrop = useBridgeMutation('p2p.rejectSpacedrop');
+	const cancelSpacedrop = useBridgeMutation('p2p.cancelSpacedrop');
+
+	const removeRequest = (id: string) => setRequests((r) => r.filter((req) => req.id !== id));
+	const setTransfer = (id: string, status: string) =>
+		setTransfers((t) => ({ ...t, [id]: status }));
 
 	const form = useZodForm({
 		schema
@@ -106,15 +135,32 @@ function TemporarySpacedropDemo() {
 			if (data.type === 'DiscoveredPeer') {
 				setDiscoveredPeer([discoveredPeers.set(data.peer_id, data.metadata)]);
 				// if (!form.getValues().target_peer) form.setValue('target_peer', data.peer_id);
+			} else if (data.type === 'SpacedropRequest') {
+				setRequests((r) => [...r, data]);
+			} else if (data.type === 'SpacedropProgress') {
+				setTransfer(
+					data.id,
+					`${formatBytes(data.transferred)} of ${formatBytes(data.total)} (${formatBytes(
+						data.bytes_per_second
+					)}/s)`
+				);
+			} else if (data.type === 'SpacedropCompleted') {
+				setTransfer(data.id, 'Completed');
+			} else if (data.type === 'SpacedropFailed') {
+				removeRequest(data.id);
+				setTransfer(data.id, `Failed: ${data.error}`);
 			}
 		}
 	});
 
 	const onSubmit = form.handleSubmit((data) => {
-		doSpacedrop.mutate({
-			peer_id: data.target_peer,
-			file_path: data.file_path
-		});
+		doSpacedrop.mutate(
+			{
+				peer_id: data.target_peer,
+				file_paths: [data.file_path]
+			},
+			{ onSuccess: (id) => setTransfer(id, 'Waiting for the peer to accept...') }
+		);
 	});
 
 	// TODO: Input select
@@ -122,9 +168,8 @@ function TemporarySpacedropDemo() {
 		<Form onSubmit={onSubmit} form={form}>
 			<ScreenHeading>Spacedrop Demo</ScreenHeading>
 			<p className="text-xs text-ink-dull">
-				Note: Right now the file must be less than 255 bytes long and only contain UTF-8
-				chars. Create a txt file in Vscode to test (note macOS TextEdit cause that is rtf by
-				default)
+				The receiving node must accept the Spacedrop within a minute, and choose a directory
+				to save it to.
 			</p>
 			<div className="mt-2 flex flex-row items-center space-x-4">
 				<Input
@@ -159,6 +204,54 @@ function TemporarySpacedropDemo() {
 					Send
 				</Button>
 			</div>
+			{requests.map((req) => (
+				<div key={req.id} className="mt-2 flex flex-row items-center space-x-4">
+					<p className="grow text-sm">
+						{discoveredPeers.get(req.peer_id)?.name ?? req.peer_id} would like to send{' '}
+						{req.files.join(', ')} ({formatBytes(req.total_size)})
+					</p>
+					<TextInput
+						size="sm"
+						placeholder="Destination directory"
+						value={destination}
+						onChange={(e) => setDestination(e.target.value)}
+					/>
+					<Button
+						type="button"
+						disabled={!destination}
+						variant="accent"
+						onClick={() => {
+							acceptSpacedrop.mutate({ id: req.id, destination });
+							removeRequest(req.id);
+						}}
+					>
+						Accept
+					</Button>
+					<Button
+						type="button"
+						variant="gray"
+						onClick={() => {
+							rejectSpacedrop.mutate(req.id);
+							removeRequest(req.id);
+						}}
+					>
+						Reject
+					</Button>
+				</div>
+			))}
+			{Object.entries(transfers).map(([id, status]) => (
+				<div key={id} className="mt-2 flex flex-row items-center space-x-4">
+					<p className="grow text-xs text-ink-dull">{status}</p>
+					<Button
+						type="button"
+						size="sm"
+						variant="gray"
+						onClick={() => cancelSpacedrop.mutate(id)}
+					>
+						Cancel
+					</Button>
+				</div>
+			))}
 		</Form>
 	);
 }
@@ -4,7 +4,8 @@
 export type Procedures = {
     queries: 
         { key: "buildInfo", input: never, result: BuildInfo } | 
-        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, file_paths: FilePath[], media_data: MediaData | null } | null } | 
+        { key: "files.findSimilarImages", input: LibraryArgs<FindSimilarImagesArgs>, result: ({ id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, perceptual_hash: number[] | null, blurhash: string | null, dominant_color: string | null, ipfs_id: string | null, note: string | null, date_created: string, file_paths: FilePath[], media_data: MediaData | null })[][] } | 
+        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, perceptual_hash: number[] | null, blurhash: string | null, dominant_color: string | null, ipfs_id: string | null, note: string | null, date_created: string, file_paths: FilePath[], media_data: MediaData | null } | null } | 
         { key: "jobs.getHistory", input: LibraryArgs<null>, result: JobReport[] } | 
         { key: "jobs.getRunning", input: LibraryArgs<null>, result: JobReport[] } | 
         { key: "keys.getDefault", input: LibraryArgs<null>, result: string | null } | 
@@ -16,14 +17,19 @@ export type Procedures = {
         { key: "keys.listMounted", input: LibraryArgs<null>, result: string[] } | 
         { key: "library.getStatistics", input: LibraryArgs<null>, result: Statistics } | 
         { key: "library.list", input: never, result: LibraryConfigWrapped[] } | 
+        { key: "library.pairedPeers", input: LibraryArgs<null>, result: PairedPeer[] } | 
         { key: "locations.getById", input: LibraryArgs<number>, result: location_with_indexer_rules | null } | 
         { key: "locations.getExplorerData", input: LibraryArgs<LocationExplorerArgs>, result: ExplorerData } | 
         { key: "locations.indexer_rules.get", input: LibraryArgs<number>, result: IndexerRule } | 
         { key: "locations.indexer_rules.list", input: LibraryArgs<null>, result: IndexerRule[] } | 
         { key: "locations.indexer_rules.listForLocation", input: LibraryArgs<number>, result: IndexerRule[] } | 
-        { key: "locations.list", input: LibraryArgs<null>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string, node: Node }[] } | 
+        { key: "locations.list", input: LibraryArgs<null>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, encrypt_at_rest: boolean, date_created: string, node: Node }[] } | 
         { key: "nodeState", input: never, result: NodeState } | 
+        { key: "nodes.signCustomUri", input: SignCustomUriArgs, result: string } | 
+        { key: "p2p.getRemoteExplorerData", input: RemoteExplorerArgs, result: ExplorerData } | 
+        { key: "sync.conflicts", input: LibraryArgs<null>, result: SyncConflict[] } | 
         { key: "sync.messages", input: LibraryArgs<null>, result: CRDTOperation[] } | 
+        { key: "sync.status", input: LibraryArgs<null>, result: SyncStatus } | 
         { key: "tags.get", input: LibraryArgs<number>, result: Tag | null } | 
         { key: "tags.getExplorerData", input: LibraryArgs<number>, result: ExplorerData } | 
         { key: "tags.getForObject", input: LibraryArgs<number>, result: Tag[] } | 
@@ -50,6 +56,8 @@ export type Procedures = {
         { key: "keys.changeMasterPassword", input: LibraryArgs<MasterPasswordChangeArgs>, result: null } | 
         { key: "keys.clearMasterPassword", input: LibraryArgs<null>, result: null } | 
         { key: "keys.deleteFromLibrary", input: LibraryArgs<string>, result: null } | 
+        { key: "keys.exportRecoveryKit", input: LibraryArgs<RecoveryKitExportArgs>, result: string[] } | 
+        { key: "keys.importRecoveryKit", input: LibraryArgs<RecoveryKitImportArgs>, result: number } | 
         { key: "keys.mount", input: LibraryArgs<string>, result: null } | 
         { key: "keys.restoreKeystore", input: LibraryArgs<RestoreBackupArgs>, result: number } | 
         { key: "keys.setDefault", input: LibraryArgs<string>, result: null } | 
@@ -59,8 +67,12 @@ export type Procedures = {
         { key: "keys.unmountAll", input: LibraryArgs<null>, result: null } | 
         { key: "keys.updateAutomountStatus", input: LibraryArgs<AutomountUpdateArgs>, result: null } | 
         { key: "library.create", input: CreateLibraryArgs, result: LibraryConfigWrapped } | 
+        { key: "library.createPairingCode", input: LibraryArgs<null>, result: PairingCode } | 
         { key: "library.delete", input: string, result: null } | 
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
+        { key: "library.thumbnails.cleanup", input: LibraryArgs<null>, result: null } | 
+        { key: "library.thumbnails.setCacheLimit", input: number | null, result: null } | 
+        { key: "library.unpair", input: LibraryArgs<string>, result: null } | 
         { key: "locations.addLibrary", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.create", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.delete", input: LibraryArgs<number>, result: null } | 
@@ -71,7 +83,14 @@ export type Procedures = {
         { key: "locations.relink", input: LibraryArgs<string>, result: null } | 
         { key: "locations.update", input: LibraryArgs<LocationUpdateArgs>, result: null } | 
         { key: "nodes.tokenizeSensitiveKey", input: TokenizeKeyArgs, result: TokenizeResponse } | 
-        { key: "p2p.spacedrop", input: SpacedropArgs, result: null } | 
+        { key: "p2p.acceptSpacedrop", input: AcceptSpacedropArgs, result: boolean } | 
+        { key: "p2p.cancelSpacedrop", input: string, result: boolean } | 
+        { key: "p2p.connect", input: string, result: null } | 
+        { key: "p2p.pair", input: PairArgs, result: null } | 
+        { key: "p2p.rejectSpacedrop", input: string, result: boolean } | 
+        { key: "p2p.setBandwidthLimits", input: BandwidthLimits, result: null } | 
+        { key: "p2p.spacedrop", input: SpacedropArgs, result: string } | 
+        { key: "sync.compact", input: LibraryArgs<null>, result: CompactionStats } | 
         { key: "tags.assign", input: LibraryArgs<TagAssignArgs>, result: null } | 
         { key: "tags.create", input: LibraryArgs<TagCreateArgs>, result: Tag } | 
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
@@ -84,6 +103,8 @@ export type Procedures = {
         { key: "sync.newMessage", input: LibraryArgs<null>, result: CRDTOperation }
 };
 
+export type AcceptSpacedropArgs = { id: string, destination: string }
+
 /**
  *  These are all possible algorithms that can be used for encryption and decryption
  */
@@ -93,18 +114,27 @@ export type AuthOption = { type: "Password", value: string } | { type: "Tokenize
 
 export type AutomountUpdateArgs = { uuid: string, status: boolean }
 
+/**
+ *  The bandwidth limits of the [`Manager`](crate::Manager).
+ */
+export type BandwidthLimits = { global: RateLimit, per_peer: RateLimit }
+
 export type BuildInfo = { version: string, commit: string }
 
 export type CRDTOperation = { node: string, timestamp: number, id: string, typ: CRDTOperationType }
 
 export type CRDTOperationType = SharedOperation | RelationOperation | OwnedOperation
 
+export type CompactionStats = { collapsed: number, tombstoned: number, pruned: number, reclaimed_bytes: string }
+
 /**
  *  ConfigMetadata is a part of node configuration that is loaded before the main configuration and contains information about the schema of the config.
  *  This allows us to migrate breaking changes to the config format between Spacedrive releases.
  */
 export type ConfigMetadata = { version: string | null }
 
+export type ConflictingUpdate = { node: string, value: any, timestamp: string }
+
 export type CreateLibraryArgs = { name: string, auth: AuthOption, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
 
 export type EditLibraryArgs = { id: string, name: string | null, description: string | null }
@@ -132,11 +162,13 @@ export type FileDeleterJobInit = { location_id: number, path_id: number }
 
 export type FileEncryptorJobInit = { location_id: number, path_id: number, key_uuid: string, algorithm: Algorithm, metadata: boolean, preview_media: boolean, output_path: string | null }
 
-export type FileEraserJobInit = { location_id: number, path_id: number, passes: string }
+export type FileEraserJobInit = { location_id: number, path_id: number, passes: string, include_copies: boolean, verify: boolean }
+
+export type FilePath = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, mime_type: string | null, verified_extension: string | null, extension_mismatch: boolean, size_in_bytes: string, inode: number[], device: number[], object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string }
 
-export type FilePath = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, size_in_bytes: string, inode: number[], device: number[], object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string }
+export type FindSimilarImagesArgs = { max_distance: number | null }
 
-export type GenerateThumbsForLocationArgs = { id: number, path: string }
+export type GenerateThumbsForLocationArgs = { id: number, path: string, video_previews: boolean }
 
 export type GetArgs = { id: number }
 
@@ -169,6 +201,13 @@ export type JobStatus = "Queued" | "Running" | "Completed" | "Canceled" | "Faile
 
 export type KeyAddArgs = { algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, key: string, library_sync: boolean, automount: boolean }
 
+/**
+ *  KeyringConfig selects where the secret keys of a node's libraries are stored.
+ * 
+ *  Headless nodes (e.g. `apps/server`) usually don't have an OS keyring available, so they should use one of the other backends.
+ */
+export type KeyringConfig = { backend: "os" } | { backend: "file", path: string | null, password: SecretSource } | { backend: "static", secret_key: SecretSource }
+
 /**
  *  Can wrap a query argument to require it to contain a `library_id` and provide helpers for working with libraries.
  */
@@ -183,7 +222,7 @@ export type LibraryConfigWrapped = { uuid: string, config: LibraryConfig }
 
 export type LightScanArgs = { location_id: number, sub_path: string }
 
-export type Location = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string }
+export type Location = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, encrypt_at_rest: boolean, date_created: string }
 
 /**
  *  `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
@@ -202,29 +241,34 @@ export type LocationExplorerArgs = { location_id: number, path: string, limit: n
  *  It is important to note that only the indexer rule ids in this vector will be used from now on.
  *  Old rules that aren't in this vector will be purged.
  */
-export type LocationUpdateArgs = { id: number, name: string | null, generate_preview_media: boolean | null, sync_preview_media: boolean | null, hidden: boolean | null, indexer_rules_ids: number[] }
+export type LocationUpdateArgs = { id: number, name: string | null, generate_preview_media: boolean | null, sync_preview_media: boolean | null, hidden: boolean | null, encrypt_at_rest: boolean | null, indexer_rules_ids: number[] }
 
 export type MasterPasswordChangeArgs = { password: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
 
 export type MediaData = { id: number, pixel_width: number | null, pixel_height: number | null, longitude: number | null, latitude: number | null, fps: number | null, capture_device_make: string | null, capture_device_model: string | null, capture_device_software: string | null, duration_seconds: number | null, codecs: string | null, streams: number | null }
 
-export type Node = { id: number, pub_id: number[], name: string, platform: number, version: string | null, last_seen: string, timezone: string | null, date_created: string }
+export type Node = { id: number, pub_id: number[], name: string, platform: number, version: string | null, last_seen: string, timezone: string | null, date_created: string, acknowledged_timestamp: number | null, last_synced: string | null }
 
 /**
  *  NodeConfig is the configuration for a node. This is shared between all libraries and is stored in a JSON file on disk.
  */
-export type NodeConfig = ({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null }
+export type NodeConfig = ({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null, p2p_manual_peers: string[], p2p_bandwidth_limits: BandwidthLimits, thumbnail_cache_max_mb: number | null, keyring: KeyringConfig }
 
-export type NodeState = (({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null }) & { data_path: string }
+export type NodeState = (({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null, p2p_manual_peers: string[], p2p_bandwidth_limits: BandwidthLimits, thumbnail_cache_max_mb: number | null, keyring: KeyringConfig }) & { data_path: string }
 
 /**
  *  This should be used for providing a nonce to encrypt/decrypt functions.
  * 
  *  You may also generate a nonce for a given algorithm with `Nonce::generate()`
  */
+/**
+ *  How far a node has synced a library with us.
+ */
+export type NodeSyncStatus = { node: string, name: string, clock: string | null, acknowledged_timestamp: string | null, pending_operations: number, last_synced: string | null }
+
 export type Nonce = { XChaCha20Poly1305: number[] } | { Aes256Gcm: number[] }
 
-export type Object = { id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string }
+export type Object = { id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, perceptual_hash: number[] | null, blurhash: string | null, dominant_color: string | null, ipfs_id: string | null, note: string | null, date_created: string }
 
 export type ObjectValidatorArgs = { id: number, path: string }
 
@@ -243,7 +287,16 @@ export type OwnedOperationItem = { id: any, data: OwnedOperationData }
 /**
  *  TODO: P2P event for the frontend
  */
-export type P2PEvent = { type: "DiscoveredPeer", peer_id: string, metadata: PeerMetadata } | { type: "SyncOperation", library_id: string, operations: CRDTOperation[] }
+export type P2PEvent = { type: "DiscoveredPeer", peer_id: string, metadata: PeerMetadata } | { type: "SyncOperation", library_id: string, operations: CRDTOperation[] } | { type: "SpacedropRequest", id: string, peer_id: string, files: string[], total_size: string } | { type: "SpacedropProgress", id: string, transferred: string, total: string, bytes_per_second: string } | { type: "SpacedropCompleted", id: string, received: string[] } | { type: "SpacedropFailed", id: string, error: string } | { type: "PeerThroughput", peer_id: string, upload_bytes_per_second: string, download_bytes_per_second: string }
+
+export type PairArgs = { peer_id: string, library_id: string, code: string }
+
+export type PairedPeer = { id: number, peer_id: string, date_paired: string, node_pub_id: number[] | null }
+
+/**
+ *  Everything a peer needs to join a library. This is what should be encoded into a QR code.
+ */
+export type PairingCode = { peer_id: string, library_id: string, code: string, expires_at: string }
 
 /**
  *  These parameters define the password-hashing level.
@@ -254,10 +307,25 @@ export type Params = "Standard" | "Hardened" | "Paranoid"
 
 export type PeerMetadata = { name: string, operating_system: OperatingSystem | null, version: string | null, email: string | null, img_url: string | null }
 
+/**
+ *  A limit on how many bytes are sent and received each second. `None` is unlimited.
+ */
+export type RateLimit = { upload: number | null, download: number | null }
+
+export type RecoveryKitExportArgs = { path: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, secret: RecoveryKitExportSecret }
+
+export type RecoveryKitExportSecret = { Password: string } | { Shares: { threshold: number, shares: number } }
+
+export type RecoveryKitImportArgs = { path: string, secret: RecoveryKitImportSecret }
+
+export type RecoveryKitImportSecret = { Password: string } | { Shares: string[] }
+
 export type RelationOperation = { relation_item: string, relation_group: string, relation: string, data: RelationOperationData }
 
 export type RelationOperationData = "Create" | { Update: { field: string, value: any } } | "Delete"
 
+export type RemoteExplorerArgs = { peer_id: string, library_id: string, location_id: number, path: string }
+
 export type RenameFileArgs = { location_id: number, file_name: string, new_file_name: string }
 
 export type RestoreBackupArgs = { password: string, secret_key: string, path: string }
@@ -271,6 +339,11 @@ export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChil
  */
 export type Salt = number[]
 
+/**
+ *  SecretSource is where a secret is read from when the node starts.
+ */
+export type SecretSource = { source: "environment", variable: string } | { source: "stdin" }
+
 export type SetFavoriteArgs = { id: number, favorite: boolean }
 
 export type SetNoteArgs = { id: number, note: string | null }
@@ -281,7 +354,9 @@ export type SharedOperationCreateData = { u: { [key: string]: any } } | "a"
 
 export type SharedOperationData = SharedOperationCreateData | { field: string, value: any } | null
 
-export type SpacedropArgs = { peer_id: string, file_path: string }
+export type SignCustomUriArgs = { path: string, expires_in_secs: number | null }
+
+export type SpacedropArgs = { peer_id: string, file_paths: string[] }
 
 export type Statistics = { id: number, date_captured: string, total_object_count: number, library_db_size: string, total_bytes_used: string, total_bytes_capacity: string, total_unique_bytes: string, total_bytes_free: string, preview_media_bytes: string }
 
@@ -302,6 +377,13 @@ export type StoredKeyType = "User" | "Root"
  */
 export type StoredKeyVersion = "V1"
 
+/**
+ *  A conflict between updates to the same field, as recorded by [`resolve_update`].
+ */
+export type SyncConflict = { id: number, model: string, record_id: any, field: string, winner: ConflictingUpdate, loser: ConflictingUpdate, date_resolved: string }
+
+export type SyncStatus = { node: string, nodes: NodeSyncStatus[] }
+
 export type Tag = { id: number, pub_id: number[], name: string | null, color: string | null, total_objects: number | null, redundancy_goal: number | null, date_created: string, date_modified: string }
 
 export type TagAssignArgs = { object_id: number, tag_id: number, unassign: boolean }
@@ -318,8 +400,8 @@ export type UnlockKeyManagerArgs = { password: string, secret_key: string }
 
 export type Volume = { name: string, mount_point: string, total_capacity: string, available_capacity: string, is_removable: boolean, disk_type: string | null, file_system: string | null, is_root_filesystem: boolean }
 
-export type file_path_with_object = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, size_in_bytes: string, inode: number[], device: number[], object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null }
+export type file_path_with_object = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, mime_type: string | null, verified_extension: string | null, extension_mismatch: boolean, size_in_bytes: string, inode: number[], device: number[], object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null }
 
-export type location_with_indexer_rules = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string, indexer_rules: { indexer_rule: IndexerRule }[] }
+export type location_with_indexer_rules = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, encrypt_at_rest: boolean, date_created: string, indexer_rules: { indexer_rule: IndexerRule }[] }
 
-export type object_with_file_paths = { id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, file_paths: FilePath[] }
+export type object_with_file_paths = { id: number, pub_id: number[], kind: number, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, perceptual_hash: number[] | null, blurhash: string | null, dominant_color: string | null, ipfs_id: string | null, note: string | null, date_created: string, file_paths: FilePath[] }